@@ -0,0 +1,236 @@
+/// This module contains analyses of which variables an expression binds and
+/// uses, shared by compiler passes like closure conversion, and useful for
+/// tools like editors (e.g. to rename a variable and all of its uses).
+use crate::common::{ExprKind, ExprMeta, Prog};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The location of a subexpression, as the indices of the children (see
+/// `children`) to follow from the root expression to reach it.
+pub type Path = Vec<usize>;
+
+/// A variable binding, identified by the expression which binds it and the
+/// position of the binding within that expression: the index of the let
+/// binding, the lambda parameter, or the typecase clause (always 0 for
+/// unpack).
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Binder {
+    pub node: Path,
+    pub index: usize,
+    pub name: String,
+}
+
+/// Returns the direct subexpressions of an expression, in the order they
+/// appear in the source.
+pub fn children<E: ExprMeta>(kind: &ExprKind<E>) -> Vec<&E> {
+    match kind {
+        ExprKind::Binop(_op, arg1, arg2) => vec![arg1, arg2],
+        ExprKind::Unop(_op, arg) => vec![arg],
+        ExprKind::If(pred, cons, alt) => vec![pred, cons, alt],
+        ExprKind::Let(bindings, body) => bindings
+            .iter()
+            .map(|(_name, val)| val)
+            .chain(std::iter::once(body))
+            .collect(),
+        ExprKind::Lambda(_params, _ret_type, body) => vec![body],
+        ExprKind::Begin(exps) => exps.iter().collect(),
+        ExprKind::Set(_var, val) => vec![val],
+        ExprKind::Cons(first, rest) => vec![first, rest],
+        ExprKind::Car(val) | ExprKind::Cdr(val) | ExprKind::IsNull(val) => vec![val],
+        ExprKind::FnApp(func, args) => std::iter::once(func).chain(args.iter()).collect(),
+        ExprKind::Tuple(exps) => exps.iter().collect(),
+        ExprKind::TupleGet(tuple, _key) => vec![tuple],
+        ExprKind::TupleSet(tuple, _key, val) => vec![tuple, val],
+        ExprKind::Pack(val, _sub, _exist) => vec![val],
+        ExprKind::Unpack(_var, package, _type_var, body) => vec![package, body],
+        ExprKind::Record(bindings) => bindings.iter().map(|(_label, val)| val).collect(),
+        ExprKind::RecordGet(record, _label) => vec![record],
+        ExprKind::Inject(val, _union_type) => vec![val],
+        ExprKind::TypeCase(val, clauses) => std::iter::once(val)
+            .chain(clauses.iter().map(|(_typ, _var, body)| body))
+            .collect(),
+        ExprKind::Assert(val, _message) => vec![val],
+        ExprKind::While(pred, body) => vec![pred, body],
+        ExprKind::Case(key, clauses, else_body) => std::iter::once(key)
+            .chain(clauses.iter().map(|(_datums, body)| body))
+            .chain(std::iter::once(else_body))
+            .collect(),
+        ExprKind::Curry(func, args) => std::iter::once(func).chain(args.iter()).collect(),
+        ExprKind::Compose(outer, inner) => vec![outer, inner],
+        ExprKind::Delay(val) => vec![val],
+        ExprKind::Force(promise) => vec![promise],
+        ExprKind::Format(_control, args) => args.iter().collect(),
+        ExprKind::Builtin(_builtin, args) => args.iter().collect(),
+        ExprKind::Extern(_module, _name, _typ, args) => args.iter().collect(),
+        ExprKind::WasmAsm(_code, _typ, args) => args.iter().collect(),
+        ExprKind::Null(_) | ExprKind::Id(_) | ExprKind::Num(_) | ExprKind::Bool(_) => vec![],
+        ExprKind::Float(_) => vec![],
+        ExprKind::Str(_) => vec![],
+    }
+}
+
+/// Returns the variables bound by an expression, along with the indices (into
+/// `children`) of the subexpressions they are in scope for.
+fn bound_vars<E: ExprMeta>(kind: &ExprKind<E>) -> Vec<(String, Vec<usize>)> {
+    match kind {
+        // let bindings are in scope in the body, but not in each other
+        ExprKind::Let(bindings, _body) => bindings
+            .iter()
+            .map(|(name, _val)| (name.clone(), vec![bindings.len()]))
+            .collect(),
+        ExprKind::Lambda(params, _ret_type, _body) => params
+            .iter()
+            .map(|(name, _typ)| (name.clone(), vec![0]))
+            .collect(),
+        ExprKind::Unpack(var, _package, _type_var, _body) => vec![(var.clone(), vec![1])],
+        ExprKind::TypeCase(_val, clauses) => clauses
+            .iter()
+            .enumerate()
+            .map(|(i, (_typ, var, _body))| (var.clone(), vec![i + 1]))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Returns the variables which are used in an expression (including being
+/// assigned with set!) without being bound by it.
+pub fn free_vars<E: ExprMeta>(exp: &E) -> BTreeSet<String> {
+    let mut vars = BTreeSet::new();
+    collect_free_vars(exp, &BTreeSet::new(), &mut vars);
+    vars
+}
+
+fn collect_free_vars<E: ExprMeta>(exp: &E, bound: &BTreeSet<String>, vars: &mut BTreeSet<String>) {
+    match exp.kind() {
+        ExprKind::Id(var) | ExprKind::Set(var, _) if !bound.contains(var) => {
+            vars.insert(var.clone());
+        }
+        _ => (),
+    }
+    let binders = bound_vars(exp.kind());
+    for (i, child) in children(exp.kind()).into_iter().enumerate() {
+        let mut child_bound = bound.clone();
+        for (name, scope) in binders.iter() {
+            if scope.contains(&i) {
+                child_bound.insert(name.clone());
+            }
+        }
+        collect_free_vars(child, &child_bound, vars);
+    }
+}
+
+/// Returns the variables which are assigned with set! anywhere within an
+/// expression, including variables it binds itself.
+pub fn assigned_vars<E: ExprMeta>(exp: &E) -> BTreeSet<String> {
+    let mut vars = BTreeSet::new();
+    collect_assigned_vars(exp, &mut vars);
+    vars
+}
+
+fn collect_assigned_vars<E: ExprMeta>(exp: &E, vars: &mut BTreeSet<String>) {
+    if let ExprKind::Set(var, _) = exp.kind() {
+        vars.insert(var.clone());
+    }
+    for child in children(exp.kind()) {
+        collect_assigned_vars(child, vars);
+    }
+}
+
+/// Returns the variables which are free in some closure within an
+/// expression: a lambda, or a delayed expression (which becomes a closure
+/// during closure conversion). These variables are copied into the
+/// closure's environment when it is created.
+pub fn captured_vars<E: ExprMeta>(exp: &E) -> BTreeSet<String> {
+    let mut vars = BTreeSet::new();
+    collect_captured_vars(exp, &mut vars);
+    vars
+}
+
+fn collect_captured_vars<E: ExprMeta>(exp: &E, vars: &mut BTreeSet<String>) {
+    match exp.kind() {
+        ExprKind::Lambda(_, _, _) | ExprKind::Delay(_) => vars.extend(free_vars(exp)),
+        _ => (),
+    }
+    for child in children(exp.kind()) {
+        collect_captured_vars(child, vars);
+    }
+}
+
+/// Returns the names of the (lambda-lifted) functions of a program which its
+/// expression can reach: the functions it refers to, and the functions which
+/// they refer to in turn.
+pub fn reachable_fns<E: ExprMeta>(prog: &Prog<E>) -> BTreeSet<String> {
+    let mut reachable = BTreeSet::new();
+    let mut pending: Vec<String> = free_vars(&prog.exp).into_iter().collect();
+    while let Some(name) = pending.pop() {
+        let lambda = match prog.fns.iter().find(|(other, _lambda)| *other == name) {
+            Some((_name, lambda)) => lambda,
+            None => continue,
+        };
+        if reachable.insert(name) {
+            pending.extend(free_vars(lambda));
+        }
+    }
+    reachable
+}
+
+/// Returns the number of expressions within an expression, including itself.
+pub fn node_count<E: ExprMeta>(exp: &E) -> usize {
+    1 + children(exp.kind())
+        .into_iter()
+        .map(node_count)
+        .sum::<usize>()
+}
+
+/// Finds every binding in an expression, and the locations of the
+/// expressions which refer to it (identifiers, and set! expressions which
+/// assign to it). Uses of free variables aren't included (see `free_vars`).
+pub fn binding_occurrences<E: ExprMeta>(exp: &E) -> BTreeMap<Binder, Vec<Path>> {
+    let mut occurrences = BTreeMap::new();
+    collect_occurrences(exp, &mut vec![], &BTreeMap::new(), &mut occurrences);
+    occurrences
+}
+
+fn collect_occurrences<E: ExprMeta>(
+    exp: &E,
+    path: &mut Path,
+    scope: &BTreeMap<String, Binder>,
+    occurrences: &mut BTreeMap<Binder, Vec<Path>>,
+) {
+    match exp.kind() {
+        ExprKind::Id(var) | ExprKind::Set(var, _) => {
+            if let Some(binder) = scope.get(var) {
+                occurrences
+                    .get_mut(binder)
+                    .expect("binders are added before their uses")
+                    .push(path.clone());
+            }
+        }
+        _ => (),
+    }
+    let binders: Vec<(Binder, Vec<usize>)> = bound_vars(exp.kind())
+        .into_iter()
+        .enumerate()
+        .map(|(index, (name, children_in_scope))| {
+            let binder = Binder {
+                node: path.clone(),
+                index,
+                name,
+            };
+            (binder, children_in_scope)
+        })
+        .collect();
+    for (binder, _) in binders.iter() {
+        occurrences.insert(binder.clone(), vec![]);
+    }
+    for (i, child) in children(exp.kind()).into_iter().enumerate() {
+        let mut child_scope = scope.clone();
+        for (binder, children_in_scope) in binders.iter() {
+            if children_in_scope.contains(&i) {
+                child_scope.insert(binder.name.clone(), binder.clone());
+            }
+        }
+        path.push(i);
+        collect_occurrences(child, path, &child_scope, occurrences);
+        path.pop();
+    }
+}
@@ -0,0 +1,175 @@
+/// This module performs assignment conversion: every variable which is both
+/// assigned with set! and captured by a closure is stored in a box (a tuple
+/// with one element), so that assignments made inside or outside of the
+/// closure are seen by both.
+///
+/// Without this, closure conversion would copy the variable's value into the
+/// closure's environment, and assignments to the variable inside a lambda
+/// would refer to a variable which no longer exists after lambda lifting.
+///
+/// TODO: code generation stores each tuple at a fixed address rather than
+/// allocating one each time it's made, so in compiled code the closures
+/// returned by different calls to a function share (and each call resets)
+/// the same box.
+use crate::analysis::{assigned_vars, captured_vars};
+use crate::common::{generate_var_name, Expr, ExprKind};
+use crate::types::Type;
+use im_rc::{vector, Vector};
+use std::collections::BTreeSet;
+
+/// Boxes each variable which is assigned and captured. Binding such a
+/// variable wraps its value in a tuple, each use of it reads the tuple's
+/// element, and each set! writes the element instead (see
+/// `ExprKind::TupleSet`).
+///
+/// Variables are matched by name, so binders which shadow a boxed variable
+/// are boxed too. This is always correct, but only boxes exactly the
+/// variables which need it after `resolve::resolve` has given every binder a
+/// unique name.
+pub fn assignment_convert(exp: &Expr) -> Expr {
+    let boxed = assigned_vars(exp)
+        .intersection(&captured_vars(exp))
+        .cloned()
+        .collect();
+    ac(exp, &boxed)
+}
+
+fn ac_array(exps: &Vector<Expr>, boxed: &BTreeSet<String>) -> Vector<Expr> {
+    exps.iter().map(|exp| ac(exp, boxed)).collect()
+}
+
+fn make_box(val: Expr) -> Expr {
+    Expr::new(ExprKind::Tuple(vector![val]))
+}
+
+/// If `var` is boxed, renames it to a new variable and returns the new name,
+/// along with `body` wrapped in a let expression which boxes the new variable
+/// under the original name.
+fn box_binder(var: &str, body: Expr, boxed: &BTreeSet<String>) -> (String, Expr) {
+    if !boxed.contains(var) {
+        return (String::from(var), body);
+    }
+    let unboxed_var = generate_var_name();
+    let unboxed_id = Expr::new(ExprKind::Id(unboxed_var.clone()));
+    let boxed_body = Expr::new(ExprKind::Let(
+        vector![(String::from(var), make_box(unboxed_id))],
+        body,
+    ));
+    (unboxed_var, boxed_body)
+}
+
+fn ac_lambda(
+    params: &Vector<(String, Type)>,
+    ret_type: &Type,
+    body: &Expr,
+    boxed: &BTreeSet<String>,
+) -> Expr {
+    let mut abody = ac(body, boxed);
+    let mut aparams = Vector::new();
+    for (name, typ) in params.iter().rev() {
+        let (param, new_body) = box_binder(name, abody, boxed);
+        abody = new_body;
+        aparams.push_front((param, typ.clone()));
+    }
+    Expr::new(ExprKind::Lambda(aparams, ret_type.clone(), abody))
+}
+
+fn ac(exp: &Expr, boxed: &BTreeSet<String>) -> Expr {
+    let kind = match &*exp.kind {
+        ExprKind::Num(_)
+        | ExprKind::Float(_)
+        | ExprKind::Bool(_)
+        | ExprKind::Str(_)
+        | ExprKind::Null(_) => return exp.clone(),
+        ExprKind::Id(name) if boxed.contains(name) => ExprKind::TupleGet(exp.clone(), 0),
+        ExprKind::Id(_name) => return exp.clone(),
+        ExprKind::Binop(op, arg1, arg2) => ExprKind::Binop(*op, ac(arg1, boxed), ac(arg2, boxed)),
+        ExprKind::Unop(op, arg) => ExprKind::Unop(*op, ac(arg, boxed)),
+        ExprKind::If(pred, cons, alt) => {
+            ExprKind::If(ac(pred, boxed), ac(cons, boxed), ac(alt, boxed))
+        }
+        ExprKind::Let(bindings, body) => ExprKind::Let(
+            bindings
+                .iter()
+                .map(|(name, val)| {
+                    let aval = ac(val, boxed);
+                    if boxed.contains(name) {
+                        (name.clone(), make_box(aval))
+                    } else {
+                        (name.clone(), aval)
+                    }
+                })
+                .collect(),
+            ac(body, boxed),
+        ),
+        ExprKind::Lambda(params, ret_type, body) => {
+            return ac_lambda(params, ret_type, body, boxed)
+        }
+        ExprKind::Begin(exps) => ExprKind::Begin(ac_array(exps, boxed)),
+        ExprKind::Set(name, val) if boxed.contains(name) => {
+            ExprKind::TupleSet(Expr::new(ExprKind::Id(name.clone())), 0, ac(val, boxed))
+        }
+        ExprKind::Set(name, val) => ExprKind::Set(name.clone(), ac(val, boxed)),
+        ExprKind::Cons(first, rest) => ExprKind::Cons(ac(first, boxed), ac(rest, boxed)),
+        ExprKind::Car(val) => ExprKind::Car(ac(val, boxed)),
+        ExprKind::Cdr(val) => ExprKind::Cdr(ac(val, boxed)),
+        ExprKind::IsNull(val) => ExprKind::IsNull(ac(val, boxed)),
+        ExprKind::FnApp(func, args) => ExprKind::FnApp(ac(func, boxed), ac_array(args, boxed)),
+        ExprKind::Tuple(exps) => ExprKind::Tuple(ac_array(exps, boxed)),
+        ExprKind::TupleGet(tuple, key) => ExprKind::TupleGet(ac(tuple, boxed), *key),
+        ExprKind::TupleSet(tuple, key, val) => {
+            ExprKind::TupleSet(ac(tuple, boxed), *key, ac(val, boxed))
+        }
+        ExprKind::Pack(val, sub, exist) => {
+            ExprKind::Pack(ac(val, boxed), sub.clone(), exist.clone())
+        }
+        ExprKind::Unpack(var, package, type_var, body) => {
+            let (var, abody) = box_binder(var, ac(body, boxed), boxed);
+            ExprKind::Unpack(var, ac(package, boxed), *type_var, abody)
+        }
+        ExprKind::Record(bindings) => ExprKind::Record(
+            bindings
+                .iter()
+                .map(|(label, val)| (label.clone(), ac(val, boxed)))
+                .collect(),
+        ),
+        ExprKind::RecordGet(record, label) => ExprKind::RecordGet(ac(record, boxed), label.clone()),
+        ExprKind::Inject(val, union_type) => ExprKind::Inject(ac(val, boxed), union_type.clone()),
+        ExprKind::TypeCase(val, clauses) => ExprKind::TypeCase(
+            ac(val, boxed),
+            clauses
+                .iter()
+                .map(|(typ, var, body)| {
+                    let (var, abody) = box_binder(var, ac(body, boxed), boxed);
+                    (typ.clone(), var, abody)
+                })
+                .collect(),
+        ),
+        ExprKind::Assert(val, message) => ExprKind::Assert(ac(val, boxed), message.clone()),
+        ExprKind::While(pred, body) => ExprKind::While(ac(pred, boxed), ac(body, boxed)),
+        ExprKind::Case(key, clauses, else_body) => ExprKind::Case(
+            ac(key, boxed),
+            clauses
+                .iter()
+                .map(|(datums, body)| (datums.clone(), ac(body, boxed)))
+                .collect(),
+            ac(else_body, boxed),
+        ),
+        ExprKind::Curry(func, args) => ExprKind::Curry(ac(func, boxed), ac_array(args, boxed)),
+        ExprKind::Compose(outer, inner) => ExprKind::Compose(ac(outer, boxed), ac(inner, boxed)),
+        ExprKind::Delay(val) => ExprKind::Delay(ac(val, boxed)),
+        ExprKind::Force(promise) => ExprKind::Force(ac(promise, boxed)),
+        ExprKind::Format(control, args) => ExprKind::Format(control.clone(), ac_array(args, boxed)),
+        ExprKind::Builtin(builtin, args) => ExprKind::Builtin(*builtin, ac_array(args, boxed)),
+        ExprKind::Extern(module, name, typ, args) => ExprKind::Extern(
+            module.clone(),
+            name.clone(),
+            typ.clone(),
+            ac_array(args, boxed),
+        ),
+        ExprKind::WasmAsm(code, typ, args) => {
+            ExprKind::WasmAsm(code.clone(), typ.clone(), ac_array(args, boxed))
+        }
+    };
+    Expr::new(kind)
+}
@@ -2,8 +2,9 @@
 /// Expr, and TypedExpr structs that aim to eliminate the need for
 /// re-implementing recursion on these data structures.
 use crate::common::{ExprKind, Prog, TypedExpr};
+use crate::effects::lambda_effect;
 use crate::type_check::validate_lambda_type;
-use crate::types::Type;
+use crate::types::{forced_type, unit_type, Type};
 
 use im_rc::Vector;
 
@@ -26,21 +27,30 @@ where
     }
     match typ {
         Type::Int => Ok(Type::Int),
+        Type::Float => Ok(Type::Float),
         Type::Bool => Ok(Type::Bool),
         Type::Str => Ok(Type::Str),
         Type::List(base_type) => {
             let tbase_type = transform_type_recursive(base_type, transform_type)?;
             Ok(Type::List(Box::new(tbase_type)))
         }
-        Type::Func(in_types, ret_type) => {
+        Type::Promise(base_type) => {
+            let tbase_type = transform_type_recursive(base_type, transform_type)?;
+            Ok(Type::Promise(Box::new(tbase_type)))
+        }
+        Type::Func(in_types, ret_type, effect) => {
             let tin_types = transform_type_array(in_types, transform_type)?;
             let tret_type = transform_type_recursive(ret_type, transform_type)?;
-            Ok(Type::Func(tin_types, Box::new(tret_type)))
+            Ok(Type::Func(tin_types, Box::new(tret_type), *effect))
         }
         Type::Tuple(types) => {
             let ttypes = transform_type_array(types, transform_type)?;
             Ok(Type::Tuple(ttypes))
         }
+        Type::Union(types) => {
+            let ttypes = transform_type_array(types, transform_type)?;
+            Ok(Type::Union(ttypes))
+        }
         Type::Record(bindings) => {
             let tbindings = bindings
                 .iter()
@@ -53,6 +63,18 @@ where
                 .collect::<Result<Vector<(String, Type)>, E>>()?;
             Ok(Type::Record(tbindings))
         }
+        Type::OpenRecord(bindings, row_var) => {
+            let tbindings = bindings
+                .iter()
+                .map(|(name, inner_type)| {
+                    Ok((
+                        name.clone(),
+                        transform_type_recursive(inner_type, transform_type)?,
+                    ))
+                })
+                .collect::<Result<Vector<(String, Type)>, E>>()?;
+            Ok(Type::OpenRecord(tbindings, *row_var))
+        }
         Type::Exists(type_var, base_type) => {
             let tbase_type = transform_type_recursive(base_type, transform_type)?;
             Ok(Type::Exists(*type_var, Box::new(tbase_type)))
@@ -114,6 +136,7 @@ where
     // Otherwise, recurse normally according to the individual structures.
     match &*exp.kind {
         ExprKind::Num(x) => Ok(TypedExpr::new(Type::Int, ExprKind::Num(*x))),
+        ExprKind::Float(x) => Ok(TypedExpr::new(Type::Float, ExprKind::Float(*x))),
         ExprKind::Bool(x) => Ok(TypedExpr::new(Type::Bool, ExprKind::Bool(*x))),
         ExprKind::Str(x) => Ok(TypedExpr::new(Type::Str, ExprKind::Str(x.clone()))),
         ExprKind::Id(x) => Ok(TypedExpr::new(
@@ -131,6 +154,13 @@ where
                 ExprKind::Binop(*op, targ1, targ2),
             ))
         }
+        ExprKind::Unop(op, arg) => {
+            let targ = transform_typed_exp_recursive(arg, transform_exp, transform_type)?;
+            Ok(TypedExpr::new(
+                transform_type_recursive(&exp.typ, transform_type)?,
+                ExprKind::Unop(*op, targ),
+            ))
+        }
         ExprKind::If(pred, cons, alt) => {
             let tpred = transform_typed_exp_recursive(pred, transform_exp, transform_type)?;
             let tcons = transform_typed_exp_recursive(cons, transform_exp, transform_type)?;
@@ -167,7 +197,11 @@ where
             let tret_type = transform_type_recursive(ret_type, transform_type)?;
             let param_types: Vector<Type> =
                 tparams.iter().map(|(_name, typ)| typ.clone()).collect();
-            let lambda_type = Type::Func(param_types, Box::new(tret_type.clone()));
+            let lambda_type = Type::Func(
+                param_types,
+                Box::new(tret_type.clone()),
+                lambda_effect(&tparams, &tbody),
+            );
             Ok(TypedExpr::new(
                 lambda_type,
                 ExprKind::Lambda(tparams, tret_type, tbody),
@@ -252,6 +286,14 @@ where
                 _ => Err(E::from("First expression in tuple-ref is not a tuple.")),
             }
         }
+        ExprKind::TupleSet(tuple, key, val) => {
+            let ttuple = transform_typed_exp_recursive(tuple, transform_exp, transform_type)?;
+            let tval = transform_typed_exp_recursive(val, transform_exp, transform_type)?;
+            Ok(TypedExpr::new(
+                tval.typ.clone(),
+                ExprKind::TupleSet(ttuple, *key, tval),
+            ))
+        }
         ExprKind::Record(bindings) => {
             let tbindings = bindings
                 .iter()
@@ -273,7 +315,7 @@ where
         ExprKind::RecordGet(record, key) => {
             let trecord = transform_typed_exp_recursive(record, transform_exp, transform_type)?;
             let tkey_type = match trecord.typ.clone() {
-                Type::Record(fields) => {
+                Type::Record(fields) | Type::OpenRecord(fields, _) => {
                     let matches: Vector<(String, Type)> = fields
                         .iter()
                         .cloned()
@@ -308,6 +350,155 @@ where
                 ExprKind::Unpack(var.clone(), tpackage, *type_sub, tbody),
             ))
         }
+        ExprKind::Inject(val, union_type) => {
+            let tval = transform_typed_exp_recursive(val, transform_exp, transform_type)?;
+            let tunion_type = transform_type_recursive(union_type, transform_type)?;
+            Ok(TypedExpr::new(
+                tunion_type.clone(),
+                ExprKind::Inject(tval, tunion_type),
+            ))
+        }
+        ExprKind::Assert(val, message) => {
+            let tval = transform_typed_exp_recursive(val, transform_exp, transform_type)?;
+            Ok(TypedExpr::new(
+                Type::Bool,
+                ExprKind::Assert(tval, message.clone()),
+            ))
+        }
+        ExprKind::While(pred, body) => {
+            let tpred = transform_typed_exp_recursive(pred, transform_exp, transform_type)?;
+            let tbody = transform_typed_exp_recursive(body, transform_exp, transform_type)?;
+            Ok(TypedExpr::new(unit_type(), ExprKind::While(tpred, tbody)))
+        }
+        ExprKind::Case(key, clauses, else_body) => {
+            let tkey = transform_typed_exp_recursive(key, transform_exp, transform_type)?;
+            let tclauses = clauses
+                .iter()
+                .map(|(datums, body)| {
+                    let tbody = transform_typed_exp_recursive(body, transform_exp, transform_type)?;
+                    Ok((datums.clone(), tbody))
+                })
+                .collect::<Result<Vector<(Vector<i32>, TypedExpr)>, E>>()?;
+            let telse = transform_typed_exp_recursive(else_body, transform_exp, transform_type)?;
+            Ok(TypedExpr::new(
+                telse.typ.clone(),
+                ExprKind::Case(tkey, tclauses, telse),
+            ))
+        }
+        ExprKind::Curry(func, args) => {
+            let tfunc = transform_typed_exp_recursive(func, transform_exp, transform_type)?;
+            let targs = args
+                .iter()
+                .map(|arg| transform_typed_exp_recursive(arg, transform_exp, transform_type))
+                .collect::<Result<Vector<TypedExpr>, E>>()?;
+            match tfunc.typ.clone() {
+                Type::Func(mut param_types, ret_type, effect)
+                    if targs.len() <= param_types.len() =>
+                {
+                    let residual_params = param_types.slice(targs.len()..);
+                    Ok(TypedExpr::new(
+                        Type::Func(residual_params, ret_type, effect),
+                        ExprKind::Curry(tfunc, targs),
+                    ))
+                }
+                _ => Err(E::from(
+                    "First expression in curry is not a function which takes enough arguments.",
+                )),
+            }
+        }
+        ExprKind::Compose(outer, inner) => {
+            let touter = transform_typed_exp_recursive(outer, transform_exp, transform_type)?;
+            let tinner = transform_typed_exp_recursive(inner, transform_exp, transform_type)?;
+            match (touter.typ.clone(), tinner.typ.clone()) {
+                (
+                    Type::Func(_, ret_type, outer_effect),
+                    Type::Func(param_types, _, inner_effect),
+                ) => Ok(TypedExpr::new(
+                    Type::Func(param_types, ret_type, outer_effect.max(inner_effect)),
+                    ExprKind::Compose(touter, tinner),
+                )),
+                _ => Err(E::from("Expressions in compose are not functions.")),
+            }
+        }
+        ExprKind::Delay(val) => {
+            let tval = transform_typed_exp_recursive(val, transform_exp, transform_type)?;
+            Ok(TypedExpr::new(
+                Type::Promise(Box::new(tval.typ.clone())),
+                ExprKind::Delay(tval),
+            ))
+        }
+        ExprKind::Format(control, args) => {
+            let targs = args
+                .iter()
+                .map(|arg| transform_typed_exp_recursive(arg, transform_exp, transform_type))
+                .collect::<Result<Vector<TypedExpr>, E>>()?;
+            Ok(TypedExpr::new(
+                Type::Str,
+                ExprKind::Format(control.clone(), targs),
+            ))
+        }
+        ExprKind::Builtin(builtin, args) => {
+            let targs = args
+                .iter()
+                .map(|arg| transform_typed_exp_recursive(arg, transform_exp, transform_type))
+                .collect::<Result<Vector<TypedExpr>, E>>()?;
+            Ok(TypedExpr::new(
+                builtin.signature().1,
+                ExprKind::Builtin(*builtin, targs),
+            ))
+        }
+        ExprKind::Extern(module, name, typ, args) => {
+            let targs = args
+                .iter()
+                .map(|arg| transform_typed_exp_recursive(arg, transform_exp, transform_type))
+                .collect::<Result<Vector<TypedExpr>, E>>()?;
+            let ttyp = transform_type_recursive(typ, transform_type)?;
+            let ret_type = match &ttyp {
+                Type::Func(_param_types, ret_type, _effect) => (**ret_type).clone(),
+                _ => exp.typ.clone(),
+            };
+            Ok(TypedExpr::new(
+                ret_type,
+                ExprKind::Extern(module.clone(), name.clone(), ttyp, targs),
+            ))
+        }
+        ExprKind::WasmAsm(code, typ, args) => {
+            let targs = args
+                .iter()
+                .map(|arg| transform_typed_exp_recursive(arg, transform_exp, transform_type))
+                .collect::<Result<Vector<TypedExpr>, E>>()?;
+            let ttyp = transform_type_recursive(typ, transform_type)?;
+            Ok(TypedExpr::new(
+                ttyp.clone(),
+                ExprKind::WasmAsm(code.clone(), ttyp, targs),
+            ))
+        }
+        ExprKind::Force(promise) => {
+            let tpromise = transform_typed_exp_recursive(promise, transform_exp, transform_type)?;
+            match forced_type(&tpromise.typ) {
+                Some(typ) => Ok(TypedExpr::new(typ, ExprKind::Force(tpromise))),
+                None => Err(E::from("Expression in force is not a promise type.")),
+            }
+        }
+        ExprKind::TypeCase(val, clauses) => {
+            let tval = transform_typed_exp_recursive(val, transform_exp, transform_type)?;
+            let tclauses = clauses
+                .iter()
+                .map(|(typ, var, body)| {
+                    let ttype = transform_type_recursive(typ, transform_type)?;
+                    let tbody = transform_typed_exp_recursive(body, transform_exp, transform_type)?;
+                    Ok((ttype, var.clone(), tbody))
+                })
+                .collect::<Result<Vector<(Type, String, TypedExpr)>, E>>()?;
+            let body_type = match tclauses.front() {
+                Some((_, _, tbody)) => tbody.typ.clone(),
+                None => return Err(E::from("Typecase expression has no clauses.")),
+            };
+            Ok(TypedExpr::new(
+                body_type,
+                ExprKind::TypeCase(tval, tclauses),
+            ))
+        }
         ExprKind::FnApp(func, args) => {
             let tfunc = transform_typed_exp_recursive(func, transform_exp, transform_type)?;
             let targs = args
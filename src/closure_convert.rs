@@ -1,6 +1,7 @@
+use crate::analysis::free_vars;
 use crate::common::{generate_env_name, generate_id, generate_var_name, Expr, ExprKind, TypeEnv};
 use crate::type_check::tc_with_env;
-use crate::types::Type;
+use crate::types::{Effect, Type};
 use im_rc::{vector, Vector};
 
 #[derive(Clone, Debug)]
@@ -24,20 +25,30 @@ impl std::fmt::Display for ClosureConvertError {
 fn cc_type(typ: &Type) -> Result<Type, ClosureConvertError> {
     match typ {
         Type::Int => Ok(Type::Int),
+        Type::Float => Ok(Type::Float),
         Type::Bool => Ok(Type::Bool),
         Type::Str => Ok(Type::Str),
         Type::List(base_typ) => {
             let cc_base_typ = cc_type(base_typ)?;
             Ok(Type::List(Box::new(cc_base_typ)))
         }
-        Type::Func(in_typs, ret_typ) => {
+        // Promises become a flag for whether they have been forced yet, and
+        // a closure which computes their value (see `cc_delay`)
+        Type::Promise(base_typ) => Ok(Type::Tuple(vector![
+            Type::Bool,
+            cc_type(&Type::Func(vector![], base_typ.clone(), Effect::Mutates))?
+        ])),
+        // Closures are packed without knowing their effects (see
+        // `cc_lambda`), so every function type may mutate after closure
+        // conversion, and later passes don't rely on effects.
+        Type::Func(in_typs, ret_typ, _effect) => {
             let mut cc_in_typs = cc_type_array(in_typs)?;
             let cc_ret_typ = cc_type(ret_typ)?;
             let typ_var_id = generate_id();
             let typ_var = Type::TypeVar(typ_var_id);
             cc_in_typs.push_front(typ_var.clone());
             let base_typ = Type::Tuple(vector![
-                Type::Func(cc_in_typs, Box::new(cc_ret_typ),),
+                Type::Func(cc_in_typs, Box::new(cc_ret_typ), Effect::Mutates),
                 typ_var
             ]);
             Ok(Type::Exists(typ_var_id, Box::new(base_typ)))
@@ -46,6 +57,10 @@ fn cc_type(typ: &Type) -> Result<Type, ClosureConvertError> {
             let cc_typs = cc_type_array(typs)?;
             Ok(Type::Tuple(cc_typs))
         }
+        Type::Union(typs) => {
+            let cc_typs = cc_type_array(typs)?;
+            Ok(Type::Union(cc_typs))
+        }
         Type::Record(bindings) => {
             let cc_bindings = bindings
                 .iter()
@@ -53,6 +68,13 @@ fn cc_type(typ: &Type) -> Result<Type, ClosureConvertError> {
                 .collect::<Result<Vector<(String, Type)>, ClosureConvertError>>()?;
             Ok(Type::Record(cc_bindings))
         }
+        Type::OpenRecord(bindings, row_var) => {
+            let cc_bindings = bindings
+                .iter()
+                .map(|pair| Ok((pair.0.clone(), cc_type(&pair.1)?)))
+                .collect::<Result<Vector<(String, Type)>, ClosureConvertError>>()?;
+            Ok(Type::OpenRecord(cc_bindings, *row_var))
+        }
         Type::Exists(typ_var, base_typ) => {
             let cc_base_typ = cc_type(base_typ)?;
             Ok(Type::Exists(*typ_var, Box::new(cc_base_typ)))
@@ -76,6 +98,22 @@ fn cc_bindings(
         .collect()
 }
 
+/// Converts a lambda into a closure: a tuple of a function which takes the
+/// closure's environment as its first parameter, and the environment itself,
+/// a record holding the values of the lambda's free variables.
+///
+/// (lambda ((x : int)) : int (+ x y)) becomes
+///
+/// (pack (make-tuple (lambda ((env : (record (y : int))) (x : int)) : int
+///                     (+ x (record-ref env y)))
+///                   (make-record (y y)))
+///       (record (y : int))
+///       (exists T (tuple (-> T int int) T)))
+///
+/// The tuple is packed into an existential type, so that closures with the
+/// same parameter and return types have the same type no matter what they
+/// capture. Applications unpack the closure and pass the environment to the
+/// function (see `cc_fn_app`).
 fn cc_lambda(
     params: &Vector<(String, Type)>,
     ret_type: &Type,
@@ -87,7 +125,10 @@ fn cc_lambda(
 
     // Calculate the set of free variables in the lambda
     // which is the free variables in the body, minus the variables bound by the parameters
-    let free_vars = get_free_vars_lambda(&params, &new_body)?;
+    let mut free_vars = free_vars(&new_body);
+    for (param, _typ) in params.iter() {
+        free_vars.remove(param);
+    }
 
     // Construct the environment name
     let env_name: String = generate_env_name();
@@ -145,7 +186,14 @@ fn cc_lambda(
     let new_lambda = Expr::new(ExprKind::Lambda(new_params, new_ret_typ, new_body));
 
     let orig_param_typs = params.clone().iter().map(|pair| pair.1.clone()).collect();
-    let new_lambda_typ = cc_type(&Type::Func(orig_param_typs, Box::new(ret_type.clone())))?;
+    // The lambda's effect can't be inferred without types, but this is only
+    // checked during the first round of type checking (before closure
+    // conversion), so we conservatively assume it may have any effect.
+    let new_lambda_typ = cc_type(&Type::Func(
+        orig_param_typs,
+        Box::new(ret_type.clone()),
+        Effect::Mutates,
+    ))?;
 
     let new_closure = Expr::new(ExprKind::Tuple(vector![new_lambda, new_env]));
     Ok(Expr::new(ExprKind::Pack(
@@ -155,6 +203,120 @@ fn cc_lambda(
     )))
 }
 
+/// Converts (delay e) into a tuple of a flag for whether the promise has been
+/// forced yet, and a closure with no arguments which computes e.
+///
+/// When the promise is forced, the closure is called and its result is
+/// stored in place of it (see `generate_code::gen_instr_force`).
+fn cc_delay(exp: &Expr, env: &TypeEnv) -> Result<Expr, ClosureConvertError> {
+    let typ = match tc_with_env(exp, env) {
+        Ok(typed_exp) => typed_exp.typ,
+        Err(e) => {
+            return Err(ClosureConvertError(format!(
+                "Type checking error during closure conversion: {}",
+                e
+            )))
+        }
+    };
+    let thunk = cc_lambda(&vector![], &typ, exp, env)?;
+    Ok(Expr::new(ExprKind::Tuple(vector![
+        Expr::new(ExprKind::Bool(false)),
+        thunk
+    ])))
+}
+
+/// Converts (curry f a b) into a closure which calls f with a, b and its own
+/// arguments, by building the equivalent let and lambda expressions:
+///
+/// (let ((temp1 f) (temp2 a) (temp3 b))
+///   (lambda ((temp4 : T4) ...) : R (temp1 temp2 temp3 temp4 ...)))
+///
+/// The function and arguments are evaluated once, when the closure is
+/// created, rather than each time it's called.
+fn cc_curry(
+    exp: &Expr,
+    func: &Expr,
+    args: &Vector<Expr>,
+    env: &TypeEnv,
+) -> Result<Expr, ClosureConvertError> {
+    let bindings = std::iter::once(func)
+        .chain(args.iter())
+        .map(|val| (generate_var_name(), val.clone()))
+        .collect::<Vector<(String, Expr)>>();
+    let bound_ids = bindings
+        .iter()
+        .map(|(name, _val)| Expr::new(ExprKind::Id(name.clone())))
+        .collect::<Vector<Expr>>();
+    let (param_types, ret_type) = wrapper_type(exp, env)?;
+    let params = param_types
+        .iter()
+        .map(|typ| (generate_var_name(), typ.clone()))
+        .collect::<Vector<(String, Type)>>();
+    let param_ids = params
+        .iter()
+        .map(|(name, _typ)| Expr::new(ExprKind::Id(name.clone())))
+        .collect::<Vector<Expr>>();
+    let call = Expr::new(ExprKind::FnApp(
+        bound_ids[0].clone(),
+        bound_ids.skip(1) + param_ids,
+    ));
+    let lambda = Expr::new(ExprKind::Lambda(params, ret_type, call));
+    cc(&Expr::new(ExprKind::Let(bindings, lambda)), env)
+}
+
+/// Converts (compose f g) into a closure which calls g with its arguments
+/// and then f with the result, in the same way as `cc_curry`:
+///
+/// (let ((temp1 f) (temp2 g))
+///   (lambda ((temp3 : T3) ...) : R (temp1 (temp2 temp3 ...))))
+fn cc_compose(
+    exp: &Expr,
+    outer: &Expr,
+    inner: &Expr,
+    env: &TypeEnv,
+) -> Result<Expr, ClosureConvertError> {
+    let outer_name = generate_var_name();
+    let inner_name = generate_var_name();
+    let (param_types, ret_type) = wrapper_type(exp, env)?;
+    let params = param_types
+        .iter()
+        .map(|typ| (generate_var_name(), typ.clone()))
+        .collect::<Vector<(String, Type)>>();
+    let param_ids = params
+        .iter()
+        .map(|(name, _typ)| Expr::new(ExprKind::Id(name.clone())))
+        .collect::<Vector<Expr>>();
+    let inner_call = Expr::new(ExprKind::FnApp(
+        Expr::new(ExprKind::Id(inner_name.clone())),
+        param_ids,
+    ));
+    let call = Expr::new(ExprKind::FnApp(
+        Expr::new(ExprKind::Id(outer_name.clone())),
+        vector![inner_call],
+    ));
+    let lambda = Expr::new(ExprKind::Lambda(params, ret_type, call));
+    let bindings = vector![(outer_name, outer.clone()), (inner_name, inner.clone())];
+    cc(&Expr::new(ExprKind::Let(bindings, lambda)), env)
+}
+
+/// Returns the parameter types and return type of the function produced by
+/// a curry or compose expression.
+fn wrapper_type(exp: &Expr, env: &TypeEnv) -> Result<(Vector<Type>, Type), ClosureConvertError> {
+    match tc_with_env(exp, env) {
+        Ok(typed_exp) => match typed_exp.typ {
+            Type::Func(param_types, ret_type, _effect) => Ok((param_types, *ret_type)),
+            typ => Err(ClosureConvertError(format!(
+                "Expected {} to have a function type, instead found {}",
+                exp, typ
+            ))),
+        },
+        Err(e) => Err(ClosureConvertError(format!(
+            "Type checking error during closure conversion: {}",
+            e
+        ))),
+    }
+}
+
 fn cc_fn_app(func: &Expr, args: &Vector<Expr>, env: &TypeEnv) -> Result<Expr, ClosureConvertError> {
     let tuple_name = generate_var_name();
     let tuple_name_id = Expr::new(ExprKind::Id(tuple_name.clone()));
@@ -195,6 +357,8 @@ fn substitute(
                     .and_then(|sarg2| Ok(Expr::new(ExprKind::Binop(*op, sarg1, sarg2))))
             })
         }
+        ExprKind::Unop(op, arg) => substitute(&arg, match_exp, replace_with)
+            .and_then(|sarg| Ok(Expr::new(ExprKind::Unop(*op, sarg)))),
         ExprKind::If(pred, cons, alt) => {
             substitute(&pred, match_exp, replace_with).and_then(|spred| {
                 substitute(&cons, match_exp, replace_with).and_then(|scons| {
@@ -218,7 +382,7 @@ fn substitute(
         ExprKind::Lambda(params, ret_type, body) => {
             let param_names: Vector<String> = params.iter().map(|pair| pair.0.clone()).collect();
             if !param_names.contains(&String::from(match_exp)) {
-                let sub_free_vars = get_free_vars(&replace_with)?;
+                let sub_free_vars = free_vars(replace_with);
                 for param in param_names {
                     if sub_free_vars.contains(&param) {
                         return Err(ClosureConvertError::from("Tried to substitute an expression with free variables into a lambda which will result in said free variables getting captured!"));
@@ -274,6 +438,11 @@ fn substitute(
             .and_then(|svals| Ok(Expr::new(ExprKind::Tuple(svals)))),
         ExprKind::TupleGet(tuple, key) => substitute(&tuple, match_exp, replace_with)
             .and_then(|stuple| Ok(Expr::new(ExprKind::TupleGet(stuple, *key)))),
+        ExprKind::TupleSet(tuple, key, val) => Ok(Expr::new(ExprKind::TupleSet(
+            substitute(&tuple, match_exp, replace_with)?,
+            *key,
+            substitute(&val, match_exp, replace_with)?,
+        ))),
         ExprKind::Pack(val, sub, exist) => substitute(&val, match_exp, replace_with)
             .and_then(|sval| Ok(Expr::new(ExprKind::Pack(sval, sub.clone(), exist.clone())))),
         ExprKind::Unpack(var, package, type_sub, body) => {
@@ -288,6 +457,79 @@ fn substitute(
                 })
             })
         }
+        ExprKind::Inject(val, union_typ) => substitute(&val, match_exp, replace_with)
+            .and_then(|sval| Ok(Expr::new(ExprKind::Inject(sval, union_typ.clone())))),
+        ExprKind::Assert(val, message) => substitute(&val, match_exp, replace_with)
+            .and_then(|sval| Ok(Expr::new(ExprKind::Assert(sval, message.clone())))),
+        ExprKind::While(pred, body) => {
+            substitute(&pred, match_exp, replace_with).and_then(|spred| {
+                substitute(&body, match_exp, replace_with)
+                    .and_then(|sbody| Ok(Expr::new(ExprKind::While(spred, sbody))))
+            })
+        }
+        ExprKind::Case(key, clauses, else_body) => {
+            let skey = substitute(key, match_exp, replace_with)?;
+            let sclauses = clauses
+                .iter()
+                .map(|(datums, body)| {
+                    Ok((datums.clone(), substitute(body, match_exp, replace_with)?))
+                })
+                .collect::<Result<Vector<(Vector<i32>, Expr)>, ClosureConvertError>>()?;
+            let selse = substitute(else_body, match_exp, replace_with)?;
+            Ok(Expr::new(ExprKind::Case(skey, sclauses, selse)))
+        }
+        ExprKind::Curry(func, args) => Ok(Expr::new(ExprKind::Curry(
+            substitute(&func, match_exp, replace_with)?,
+            substitute_array(&args, match_exp, replace_with)?,
+        ))),
+        ExprKind::Compose(outer, inner) => Ok(Expr::new(ExprKind::Compose(
+            substitute(&outer, match_exp, replace_with)?,
+            substitute(&inner, match_exp, replace_with)?,
+        ))),
+        ExprKind::Delay(val) => substitute(&val, match_exp, replace_with)
+            .and_then(|sval| Ok(Expr::new(ExprKind::Delay(sval)))),
+        ExprKind::Force(promise) => substitute(&promise, match_exp, replace_with)
+            .and_then(|spromise| Ok(Expr::new(ExprKind::Force(spromise)))),
+        ExprKind::Format(control, args) => substitute_array(&args, match_exp, replace_with)
+            .and_then(|sargs| Ok(Expr::new(ExprKind::Format(control.clone(), sargs)))),
+        ExprKind::Builtin(builtin, args) => substitute_array(&args, match_exp, replace_with)
+            .and_then(|sargs| Ok(Expr::new(ExprKind::Builtin(*builtin, sargs)))),
+        ExprKind::Extern(module, name, typ, args) => {
+            substitute_array(&args, match_exp, replace_with).and_then(|sargs| {
+                Ok(Expr::new(ExprKind::Extern(
+                    module.clone(),
+                    name.clone(),
+                    typ.clone(),
+                    sargs,
+                )))
+            })
+        }
+        ExprKind::WasmAsm(code, typ, args) => substitute_array(&args, match_exp, replace_with)
+            .and_then(|sargs| {
+                Ok(Expr::new(ExprKind::WasmAsm(
+                    code.clone(),
+                    typ.clone(),
+                    sargs,
+                )))
+            }),
+        ExprKind::TypeCase(val, clauses) => {
+            let sval = substitute(&val, match_exp, replace_with)?;
+            let sub_free_vars = free_vars(replace_with);
+            let sclauses = clauses
+                .iter()
+                .map(|(typ, var, body)| {
+                    if var == match_exp {
+                        return Ok((typ.clone(), var.clone(), body.clone()));
+                    }
+                    if sub_free_vars.contains(var) {
+                        return Err(ClosureConvertError::from("Tried to substitute an expression with free variables into a typecase clause which will result in said free variables getting captured!"));
+                    }
+                    let sbody = substitute(&body, match_exp, replace_with)?;
+                    Ok((typ.clone(), var.clone(), sbody))
+                })
+                .collect::<Result<Vector<(Type, String, Expr)>, ClosureConvertError>>()?;
+            Ok(Expr::new(ExprKind::TypeCase(sval, sclauses)))
+        }
         ExprKind::IsNull(val) => substitute(&val, match_exp, replace_with)
             .and_then(|sval| Ok(Expr::new(ExprKind::IsNull(sval)))),
         ExprKind::Null(_) => Ok(exp.clone()),
@@ -299,75 +541,12 @@ fn substitute(
             }
         }
         ExprKind::Num(_) => Ok(exp.clone()),
+        ExprKind::Float(_) => Ok(exp.clone()),
         ExprKind::Bool(_) => Ok(exp.clone()),
         ExprKind::Str(_) => Ok(exp.clone()),
     }
 }
 
-fn get_free_vars_array(exps: &Vector<Expr>) -> Result<Vector<String>, ClosureConvertError> {
-    let var_vecs: Result<Vector<Vector<String>>, ClosureConvertError> =
-        exps.iter().map(|val| get_free_vars(val)).collect();
-    var_vecs.and_then(|vecs: Vector<Vector<String>>| {
-        Ok(vecs
-            .iter()
-            .fold(vector![], |vec1, vec2| vec1 + vec2.clone()))
-    })
-}
-
-fn get_free_vars(exp: &Expr) -> Result<Vector<String>, ClosureConvertError> {
-    match &*exp.kind {
-        ExprKind::Binop(_op, arg1, arg2) => get_free_vars(&arg1)
-            .and_then(|vars1| get_free_vars(&arg2).and_then(|vars2| Ok(vars1 + vars2))),
-        ExprKind::If(pred, cons, alt) => get_free_vars(&pred).and_then(|vars1| {
-            get_free_vars(&cons)
-                .and_then(|vars2| get_free_vars(&alt).and_then(|vars3| Ok(vars1 + vars2 + vars3)))
-        }),
-        ExprKind::Let(bindings, body) => {
-            let binding_exps: Vector<Expr> = bindings.iter().map(|pair| pair.1.clone()).collect();
-            let binding_vars: Vector<String> = bindings.iter().map(|pair| pair.0.clone()).collect();
-            let mut body_vars = get_free_vars(&body)?;
-            body_vars.retain(|var| !binding_vars.contains(var));
-            Ok(body_vars + get_free_vars_array(&binding_exps)?)
-        }
-        ExprKind::Lambda(params, _ret_type, body) => get_free_vars_lambda(&params, &body),
-        ExprKind::FnApp(func, args) => get_free_vars_array(&(vector![func.clone()] + args.clone())),
-        ExprKind::Record(bindings) => {
-            get_free_vars_array(&bindings.iter().map(|pair| pair.1.clone()).collect())
-        }
-        ExprKind::RecordGet(record, _key) => get_free_vars(&record),
-        ExprKind::Begin(exps) => get_free_vars_array(&exps),
-        ExprKind::Set(_var, val) => get_free_vars(&val),
-        ExprKind::Cons(first, second) => get_free_vars(&first)
-            .and_then(|vars1| get_free_vars(&second).and_then(|vars2| Ok(vars1 + vars2))),
-        ExprKind::Car(val) => get_free_vars(&val),
-        ExprKind::Cdr(val) => get_free_vars(&val),
-        ExprKind::Tuple(vals) => get_free_vars_array(&vals),
-        ExprKind::TupleGet(tuple, _key) => get_free_vars(&tuple),
-        ExprKind::Pack(val, _sub, _exist) => get_free_vars(&val),
-        ExprKind::Unpack(var, package, _type_sub, body) => {
-            let mut free_vars = get_free_vars(&package)? + get_free_vars(&body)?;
-            free_vars.retain(|free_var| free_var != var);
-            Ok(free_vars)
-        }
-        ExprKind::IsNull(val) => get_free_vars(&val),
-        ExprKind::Null(_) => Ok(vector![]),
-        ExprKind::Id(x) => Ok(vector![x.clone()]),
-        ExprKind::Num(_) => Ok(vector![]),
-        ExprKind::Bool(_) => Ok(vector![]),
-        ExprKind::Str(_) => Ok(vector![]),
-    }
-}
-
-fn get_free_vars_lambda(
-    params: &Vector<(String, Type)>,
-    body: &Expr,
-) -> Result<Vector<String>, ClosureConvertError> {
-    let param_vars: Vector<String> = params.iter().map(|pair| pair.0.clone()).collect();
-    let mut free_vars: Vector<String> = get_free_vars(body)?;
-    free_vars.retain(|var| !param_vars.contains(var));
-    Ok(free_vars)
-}
-
 pub fn closure_convert(exp: &Expr) -> Result<Expr, ClosureConvertError> {
     cc(exp, &TypeEnv::new())
 }
@@ -382,12 +561,16 @@ pub fn closure_convert(exp: &Expr) -> Result<Expr, ClosureConvertError> {
 fn cc(exp: &Expr, env: &TypeEnv) -> Result<Expr, ClosureConvertError> {
     match &*exp.kind {
         ExprKind::Num(x) => Ok(Expr::new(ExprKind::Num(*x))),
+        ExprKind::Float(x) => Ok(Expr::new(ExprKind::Float(*x))),
         ExprKind::Bool(x) => Ok(Expr::new(ExprKind::Bool(*x))),
         ExprKind::Str(x) => Ok(Expr::new(ExprKind::Str(x.clone()))),
         ExprKind::Id(x) => Ok(Expr::new(ExprKind::Id(x.clone()))),
         ExprKind::Binop(op, arg1, arg2) => cc(&arg1, env).and_then(|carg1| {
             cc(&arg2, env).and_then(|carg2| Ok(Expr::new(ExprKind::Binop(*op, carg1, carg2))))
         }),
+        ExprKind::Unop(op, arg) => {
+            cc(&arg, env).and_then(|carg| Ok(Expr::new(ExprKind::Unop(*op, carg))))
+        }
         ExprKind::If(pred, cons, alt) => cc(&pred, env).and_then(|cpred| {
             cc(&cons, env).and_then(|ccons| {
                 cc(&alt, env).and_then(|calt| Ok(Expr::new(ExprKind::If(cpred, ccons, calt))))
@@ -395,9 +578,11 @@ fn cc(exp: &Expr, env: &TypeEnv) -> Result<Expr, ClosureConvertError> {
         }),
         ExprKind::Let(bindings, body) => {
             // We need a map of the types for the bindings to ensure that we can properly
-            // closure convert the body of the let expression
+            // closure convert the body of the let expression. Like lambda
+            // parameters, these are the types from before closure conversion
+            // (which `cc_lambda` converts when building environments).
             let cbindings = cc_bindings(&bindings, env)?;
-            let binding_type_map = cbindings
+            let binding_type_map = bindings
                 .iter()
                 .map(|pair| match tc_with_env(&pair.1, env) {
                     Ok(typed_exp) => Ok((pair.0.clone(), typed_exp.typ)),
@@ -436,6 +621,11 @@ fn cc(exp: &Expr, env: &TypeEnv) -> Result<Expr, ClosureConvertError> {
         ExprKind::TupleGet(tuple, key) => {
             cc(&tuple, env).and_then(|ctuple| Ok(Expr::new(ExprKind::TupleGet(ctuple, *key))))
         }
+        ExprKind::TupleSet(tuple, key, val) => Ok(Expr::new(ExprKind::TupleSet(
+            cc(&tuple, env)?,
+            *key,
+            cc(&val, env)?,
+        ))),
         ExprKind::Record(bindings) => cc_bindings(&bindings, env)
             .and_then(|cbindings| Ok(Expr::new(ExprKind::Record(cbindings)))),
         ExprKind::RecordGet(record, key) => cc(&record, env)
@@ -451,6 +641,82 @@ fn cc(exp: &Expr, env: &TypeEnv) -> Result<Expr, ClosureConvertError> {
             *type_sub,
             cc(&body, env)?,
         ))),
+        ExprKind::Inject(val, union_typ) => Ok(Expr::new(ExprKind::Inject(
+            cc(&val, env)?,
+            cc_type(&union_typ)?,
+        ))),
+        ExprKind::Assert(val, message) => {
+            Ok(Expr::new(ExprKind::Assert(cc(&val, env)?, message.clone())))
+        }
+        ExprKind::While(pred, body) => {
+            Ok(Expr::new(ExprKind::While(cc(&pred, env)?, cc(&body, env)?)))
+        }
+        ExprKind::Case(key, clauses, else_body) => {
+            let ckey = cc(key, env)?;
+            let cclauses = clauses
+                .iter()
+                .map(|(datums, body)| Ok((datums.clone(), cc(body, env)?)))
+                .collect::<Result<Vector<(Vector<i32>, Expr)>, ClosureConvertError>>()?;
+            Ok(Expr::new(ExprKind::Case(
+                ckey,
+                cclauses,
+                cc(else_body, env)?,
+            )))
+        }
+        ExprKind::Curry(func, args) => cc_curry(exp, &func, &args, env),
+        ExprKind::Compose(outer, inner) => cc_compose(exp, &outer, &inner, env),
+        ExprKind::Delay(val) => cc_delay(&val, env),
+        ExprKind::Force(promise) => Ok(Expr::new(ExprKind::Force(cc(&promise, env)?))),
+        ExprKind::Format(control, args) => {
+            let cargs = args
+                .iter()
+                .map(|arg| cc(&arg, env))
+                .collect::<Result<Vector<Expr>, ClosureConvertError>>()?;
+            Ok(Expr::new(ExprKind::Format(control.clone(), cargs)))
+        }
+        ExprKind::Builtin(builtin, args) => {
+            let cargs = args
+                .iter()
+                .map(|arg| cc(&arg, env))
+                .collect::<Result<Vector<Expr>, ClosureConvertError>>()?;
+            Ok(Expr::new(ExprKind::Builtin(*builtin, cargs)))
+        }
+        // the function type of an extern is kept as-is, since the host
+        // function is called directly rather than through a closure
+        ExprKind::Extern(module, name, typ, args) => {
+            let cargs = args
+                .iter()
+                .map(|arg| cc(&arg, env))
+                .collect::<Result<Vector<Expr>, ClosureConvertError>>()?;
+            Ok(Expr::new(ExprKind::Extern(
+                module.clone(),
+                name.clone(),
+                typ.clone(),
+                cargs,
+            )))
+        }
+        ExprKind::WasmAsm(code, typ, args) => {
+            let cargs = args
+                .iter()
+                .map(|arg| cc(&arg, env))
+                .collect::<Result<Vector<Expr>, ClosureConvertError>>()?;
+            Ok(Expr::new(ExprKind::WasmAsm(
+                code.clone(),
+                typ.clone(),
+                cargs,
+            )))
+        }
+        ExprKind::TypeCase(val, clauses) => {
+            let cval = cc(&val, env)?;
+            let cclauses = clauses
+                .iter()
+                .map(|(typ, var, body)| {
+                    let cbody = cc(&body, &env.add_binding((var.clone(), typ.clone())))?;
+                    Ok((cc_type(&typ)?, var.clone(), cbody))
+                })
+                .collect::<Result<Vector<(Type, String, Expr)>, ClosureConvertError>>()?;
+            Ok(Expr::new(ExprKind::TypeCase(cval, cclauses)))
+        }
         ExprKind::FnApp(func, args) => cc_fn_app(&func, &args, env),
     }
 }
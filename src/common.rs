@@ -1,54 +1,54 @@
-use crate::types::Type;
-use crate::util::format_vector;
-use im_rc::Vector;
+use crate::types::{bytes_type, option_type, Type};
+use crate::util::{escape_string, format_float, format_vector};
+use im_rc::{vector, Vector};
 use std::fmt::Debug;
 use std::fmt::Display;
 
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::cell::Cell;
 
-// "global variable" usage derived from https://stackoverflow.com/a/27826181
-static GENSYM_COUNT: AtomicU64 = AtomicU64::new(0);
+thread_local! {
+    // The counter is per thread, so that compilations running in parallel
+    // (e.g. in tests) don't change each other's generated names.
+    static GENSYM_COUNT: Cell<u64> = const { Cell::new(0) };
+}
+
+fn next_gensym_count() -> u64 {
+    GENSYM_COUNT.with(|count| {
+        let val = count.get();
+        count.set(val + 1);
+        val
+    })
+}
 
 pub fn generate_env_name() -> String {
-    let name = format!("env{}", GENSYM_COUNT.load(Ordering::SeqCst));
-    GENSYM_COUNT.fetch_add(1, Ordering::SeqCst);
-    name
+    format!("env{}", next_gensym_count())
 }
 
 pub fn generate_record_name() -> String {
-    let name = format!("Record{}", GENSYM_COUNT.load(Ordering::SeqCst));
-    GENSYM_COUNT.fetch_add(1, Ordering::SeqCst);
-    name
+    format!("Record{}", next_gensym_count())
 }
 
 pub fn generate_var_name() -> String {
-    let name = format!("temp{}", GENSYM_COUNT.load(Ordering::SeqCst));
-    GENSYM_COUNT.fetch_add(1, Ordering::SeqCst);
-    name
+    format!("temp{}", next_gensym_count())
 }
 
 pub fn generate_func_name() -> String {
-    let name = format!("func{}", GENSYM_COUNT.load(Ordering::SeqCst));
-    GENSYM_COUNT.fetch_add(1, Ordering::SeqCst);
-    name
+    format!("func{}", next_gensym_count())
 }
 
 pub fn generate_id() -> u64 {
-    let val = GENSYM_COUNT.load(Ordering::SeqCst);
-    GENSYM_COUNT.fetch_add(1, Ordering::SeqCst);
-    val
+    next_gensym_count()
 }
 
-/// Only use this for testing purposes!
+/// Restarts the numbering of generated names from zero.
 ///
-/// If tests aren't being run sequentially / in series, then it's entirely
-/// possible that the GENSYM_COUNT counter will get desynchronized. The primary
-/// issue with this isn't that it will generate inconsistent programs, but
-/// that the programs may have temporary variable names etc. that vary
-/// from one compilation to another, which makes validating tests more
-/// difficult.
+/// `compile::compile_exp` does this before each compilation, so that
+/// compiling the same program twice produces identical output. Otherwise,
+/// this should only be used in tests, since names generated after the reset
+/// can be the same as names generated before it, and so the two must never
+/// end up in the same program.
 pub fn dangerously_reset_gensym_count() {
-    GENSYM_COUNT.store(0, Ordering::SeqCst);
+    GENSYM_COUNT.with(|count| count.set(0));
 }
 
 // struct BaseExpr {
@@ -129,13 +129,38 @@ impl TypedExpr {
 /// and any other expression types that are developed later.
 /// TODO: add (not x) operation
 /// TODO: add (string-equal? x y) operation
-/// TODO: add (str-len x) operation
 /// TODO: add (incr x) and (decr x) operations
 /// TODO: add (set-car! x) and (set-cdr! x) operations
+/// TODO: support arbitrary-precision integers, e.g. as an opt-in mode where
+/// ints are pointers to digits in linear memory. This needs memory to be
+/// allocated at runtime, since the size of a result isn't known statically
+/// TODO: add (letrec ((x e) ...) body) once closures can refer to themselves,
+/// and statically reject bindings whose values evaluate another binding of
+/// the same letrec outside of a lambda (use before initialization)
+/// TODO: add delimited continuations, (reset e) and (shift k e), once there
+/// is a CPS conversion pass to generate code for them
+/// TODO: add (match e (pattern body) ...) over tuples, records, and unions,
+/// and compile its patterns into a decision tree, so that each tag or field is
+/// tested at most once on any path (unlike the chain of tag checks generated
+/// for typecase), with tests comparing the number of generated branches
+/// TODO: add streams (stream-cons, stream-car, stream-cdr, stream-take) on top
+/// of delay and force. Infinite streams need recursion (see letrec above), and
+/// memory which is allocated at runtime, since every stream cell created by
+/// the same expression is currently stored at the same address
+/// TODO: add (await e) for calling async host imports (e.g. fetch or timers)
+/// without blocking. A module suspended at an await needs its state saved
+/// until the host resumes it, either with the CPS conversion pass needed for
+/// shift/reset, or with the stack switching proposal for wasm
+/// TODO: add (parallel-map f vec) for CPU-bound numeric code, running f on
+/// wasm threads over shared memory (with atomics for the work queue) when
+/// the threads feature is enabled, and falling back to a sequential map
+/// otherwise. Even the sequential map needs vectors, and lists whose cells
+/// are allocated at runtime rather than at a fixed address per expression
 #[allow(clippy::large_enum_variant)]
 #[derive(Clone, Debug, PartialEq)]
 pub enum ExprKind<E: ExprMeta> {
     Binop(BinOp, E, E),                      // operator, arg1, arg2
+    Unop(UnOp, E),                           // operator, arg
     If(E, E, E),                             // pred, consequent, alternate
     Let(Vector<(String, E)>, E),             // variable bindings, body
     Lambda(Vector<(String, Type)>, Type, E), // arg names/types, return type, body
@@ -146,15 +171,30 @@ pub enum ExprKind<E: ExprMeta> {
     Cdr(E),
     IsNull(E),
     Null(Type),
-    FnApp(E, Vector<E>),         // func, arguments
-    Tuple(Vector<E>),            // list of expressions, type annotation
-    TupleGet(E, u32),            // env, index - index must explicitly be a number
-    Pack(E, Type, Type),         // exp, type substitution, existential type
-    Unpack(String, E, u64, E),   // new var, package, type var, body
-    Record(Vector<(String, E)>), // map from values to labels
-    RecordGet(E, String),        // record, label
+    FnApp(E, Vector<E>),                     // func, arguments
+    Tuple(Vector<E>),                        // list of expressions, type annotation
+    TupleGet(E, u32),                        // env, index - index must explicitly be a number
+    TupleSet(E, u32, E),                     // tuple, index, new value
+    Pack(E, Type, Type),                     // exp, type substitution, existential type
+    Unpack(String, E, u64, E),               // new var, package, type var, body
+    Record(Vector<(String, E)>),             // map from values to labels
+    RecordGet(E, String),                    // record, label
+    Inject(E, Type),                         // exp, union type
+    TypeCase(E, Vector<(Type, String, E)>),  // union exp, clauses (type, var, body)
+    Case(E, Vector<(Vector<i32>, E)>, E),    // key, clauses (datums, body), else body
+    Assert(E, String),                       // condition, message
+    While(E, E),                             // condition, body
+    Curry(E, Vector<E>),                     // func, first arguments
+    Compose(E, E),                           // outer func, inner func
+    Delay(E),                                // exp (evaluated when forced)
+    Force(E),                                // promise
+    Format(String, Vector<E>),               // control string, arguments
+    Builtin(Builtin, Vector<E>),             // builtin, arguments
+    Extern(String, String, Type, Vector<E>), // host module, name, function type, arguments
+    WasmAsm(String, Type, Vector<E>),        // wasm instructions, result type, arguments
     Id(String),
     Num(i32),
+    Float(f32),
     Bool(bool),
     Str(String),
 }
@@ -177,6 +217,7 @@ impl<E: ExprMeta> Display for ExprKind<E> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ExprKind::Binop(op, exp1, exp2) => write!(f, "({} {} {})", op, exp1, exp2),
+            ExprKind::Unop(op, exp) => write!(f, "({} {})", op, exp),
             ExprKind::If(pred, cons, alt) => write!(f, "(if {} {} {})", pred, cons, alt),
             ExprKind::Let(bindings, body) => {
                 let bindings_str_vec = bindings
@@ -222,15 +263,100 @@ impl<E: ExprMeta> Display for ExprKind<E> {
                 _ => write!(f, "(make-tuple {})", format_vector(exps.clone())),
             },
             ExprKind::TupleGet(tup, key) => write!(f, "(tuple-ref {} {})", tup, key),
+            ExprKind::TupleSet(tup, key, val) => {
+                write!(f, "(tuple-set! {} {} {})", tup, key, val)
+            }
             // TODO: change to (pack type_sub val : exist)?
             ExprKind::Pack(val, sub, exist) => write!(f, "(pack {} {} {})", val, sub, exist),
             ExprKind::Unpack(var, package, type_sub, body) => {
                 write!(f, "(unpack ({} {} T{}) {})", var, package, type_sub, body)
             }
+            ExprKind::Inject(exp, typ) => write!(f, "(inject {} {})", exp, typ),
+            ExprKind::Assert(exp, message) => {
+                write!(f, "(assert {} {})", exp, escape_string(message))
+            }
+            ExprKind::While(pred, body) => write!(f, "(while {} {})", pred, body),
+            ExprKind::Curry(func, args) => {
+                if args.is_empty() {
+                    write!(f, "(curry {})", func)
+                } else {
+                    write!(f, "(curry {} {})", func, format_vector(args.clone()))
+                }
+            }
+            ExprKind::Compose(outer, inner) => write!(f, "(compose {} {})", outer, inner),
+            ExprKind::Delay(exp) => write!(f, "(delay {})", exp),
+            ExprKind::Force(exp) => write!(f, "(force {})", exp),
+            ExprKind::Builtin(builtin, args) => {
+                if args.is_empty() {
+                    write!(f, "({})", builtin)
+                } else {
+                    write!(f, "({} {})", builtin, format_vector(args.clone()))
+                }
+            }
+            ExprKind::Extern(module, name, typ, args) => {
+                if args.is_empty() {
+                    write!(
+                        f,
+                        "(call-extern {} {} {})",
+                        escape_string(module),
+                        escape_string(name),
+                        typ
+                    )
+                } else {
+                    write!(
+                        f,
+                        "(call-extern {} {} {} {})",
+                        escape_string(module),
+                        escape_string(name),
+                        typ,
+                        format_vector(args.clone())
+                    )
+                }
+            }
+            ExprKind::WasmAsm(code, typ, args) => {
+                if args.is_empty() {
+                    write!(f, "(wasm-asm {} : {})", escape_string(code), typ)
+                } else {
+                    write!(
+                        f,
+                        "(wasm-asm {} : {} {})",
+                        escape_string(code),
+                        typ,
+                        format_vector(args.clone())
+                    )
+                }
+            }
+            ExprKind::Format(control, args) => {
+                if args.is_empty() {
+                    write!(f, "(format {})", escape_string(control))
+                } else {
+                    write!(
+                        f,
+                        "(format {} {})",
+                        escape_string(control),
+                        format_vector(args.clone())
+                    )
+                }
+            }
+            ExprKind::TypeCase(exp, clauses) => {
+                let clauses_str_vec = clauses
+                    .iter()
+                    .map(|(typ, var, body)| format!("(({} {}) {})", typ, var, body))
+                    .collect();
+                write!(f, "(typecase {} {})", exp, format_vector(clauses_str_vec))
+            }
+            ExprKind::Case(key, clauses, else_body) => {
+                write!(f, "(case {}", key)?;
+                for (datums, body) in clauses {
+                    write!(f, " (({}) {})", format_vector(datums.clone()), body)?;
+                }
+                write!(f, " (else {}))", else_body)
+            }
             ExprKind::Id(val) => write!(f, "{}", val),
             ExprKind::Num(val) => write!(f, "{}", val),
+            ExprKind::Float(val) => write!(f, "{}", format_float(*val)),
             ExprKind::Bool(val) => write!(f, "{}", if *val { "true" } else { "false" }),
-            ExprKind::Str(val) => write!(f, "\"{}\"", val),
+            ExprKind::Str(val) => write!(f, "{}", escape_string(val)),
         }
     }
 }
@@ -249,6 +375,12 @@ pub enum BinOp {
     And,
     Or,
     Concat,
+    Expt, // raises the first argument to the power of the second
+    // the unsigned variants treat both ints as 32-bit unsigned integers
+    UnsignedLessThan,
+    UnsignedGreaterThan,
+    UnsignedDivide,
+    UnsignedRemainder,
 }
 
 impl std::fmt::Display for BinOp {
@@ -266,6 +398,197 @@ impl std::fmt::Display for BinOp {
             BinOp::And => write!(f, "and"),
             BinOp::Or => write!(f, "or"),
             BinOp::Concat => write!(f, "concat"),
+            BinOp::Expt => write!(f, "expt"),
+            BinOp::UnsignedLessThan => write!(f, "u<"),
+            BinOp::UnsignedGreaterThan => write!(f, "u>"),
+            BinOp::UnsignedDivide => write!(f, "u/"),
+            BinOp::UnsignedRemainder => write!(f, "u%"),
+        }
+    }
+}
+
+/// Operations on a single value, which are compiled to wasm instructions.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum UnOp {
+    ExactToInexact, // int to float, rounding to the nearest float
+    InexactToExact, // float to int, which must be an integer within range
+    IntToFloat,     // same as exact->inexact
+    FloatToInt,     // float to int, truncating towards zero; NaN and out of range values trap
+    Round,          // float to the nearest integral float, with ties to even
+    Floor,          // float to the integral float below
+    Ceiling,        // float to the integral float above
+    Truncate,       // float to the integral float towards zero
+    Sqrt,           // the square root of a float, which is NaN for negative floats
+    IsNan,          // whether a float is NaN
+    IsInfinite,     // whether a float is positive or negative infinity
+}
+
+impl UnOp {
+    /// The type of the operation's argument, and its result type.
+    pub fn signature(self) -> (Type, Type) {
+        match self {
+            UnOp::ExactToInexact | UnOp::IntToFloat => (Type::Int, Type::Float),
+            UnOp::InexactToExact | UnOp::FloatToInt => (Type::Float, Type::Int),
+            UnOp::Round | UnOp::Floor | UnOp::Ceiling | UnOp::Truncate | UnOp::Sqrt => {
+                (Type::Float, Type::Float)
+            }
+            UnOp::IsNan | UnOp::IsInfinite => (Type::Float, Type::Bool),
+        }
+    }
+}
+
+impl std::fmt::Display for UnOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnOp::ExactToInexact => write!(f, "exact->inexact"),
+            UnOp::InexactToExact => write!(f, "inexact->exact"),
+            UnOp::IntToFloat => write!(f, "int->float"),
+            UnOp::FloatToInt => write!(f, "float->int"),
+            UnOp::Round => write!(f, "round"),
+            UnOp::Floor => write!(f, "floor"),
+            UnOp::Ceiling => write!(f, "ceiling"),
+            UnOp::Truncate => write!(f, "truncate"),
+            UnOp::Sqrt => write!(f, "sqrt"),
+            UnOp::IsNan => write!(f, "nan?"),
+            UnOp::IsInfinite => write!(f, "infinite?"),
+        }
+    }
+}
+
+/// Functions which are provided by the host environment, and imported into
+/// compiled modules from the "env" module under the builtin's name.
+///
+/// Modules only import the builtins which their programs use.
+///
+/// Bytes are represented by lists of ints from 0 to 255.
+///
+/// Strings are indexed by Unicode scalar values (Rust's `char`), not by bytes
+/// of their UTF-8 encoding or by grapheme clusters, so every index within a
+/// string's length refers to a whole character. Since there is no character
+/// type, characters are strings containing a single scalar value. Indexes
+/// outside a string are errors. Grapheme clusters (what a reader would
+/// consider a single character, e.g. a letter and its combining accents) are
+/// found with `string-graphemes`.
+///
+/// TODO: use a bytevector type for bytes, once there is one
+/// TODO: add (json-parse s) and (json-stringify v). A JSON value needs a
+/// recursive type (arrays and objects contain other JSON values), e.g. a
+/// union of int, bool, string, (tuple) for null, and lists of JSON values and
+/// of (string, JSON) pairs, but unions can't refer to themselves yet, and
+/// there is no dynamic type to use instead
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Builtin {
+    Random,        // (random n): a random int from 0 to n - 1
+    RandomFloat,   // (random-float): a random float from 0 up to (but not including) 1
+    CurrentMillis, // (current-millis): milliseconds elapsed since a fixed point
+    ReadFile,      // (read-file path): the contents of a file
+    WriteFile,     // (write-file path contents): whether the file was written
+    CommandLine,   // (command-line): the program's arguments
+    GetEnv,        // (getenv name): the value of an environment variable, if set
+    StringMatch,   // (string-match pattern s): the first match and its groups, if any
+    StringReplace, // (string-replace pattern s repl): s with each match replaced
+    StringToUtf8,  // (string->utf8 s): the bytes of s in UTF-8
+    Utf8ToString,  // (utf8->string bytes): the string encoded by bytes, if valid UTF-8
+    HexEncode,     // (hex-encode bytes): bytes as hexadecimal digits
+    HexDecode,     // (hex-decode s): the bytes written in hexadecimal by s, if valid
+    Base64Encode,  // (base64-encode bytes): bytes in base64
+    Base64Decode,  // (base64-decode s): the bytes written in base64 by s, if valid
+    StringLength,  // (string-length s): the number of characters in s
+    StringRef,     // (string-ref s k): the character at index k of s
+    Substring,     // (substring s start end): the characters of s from start to end
+    StringToList,  // (string->list s): the characters of s
+    Graphemes,     // (string-graphemes s): the grapheme clusters of s
+    Exp,           // (exp x): e to the power of x
+    Log,           // (log x): the natural logarithm of x
+    Sin,           // (sin x): the sine of x (in radians)
+    Cos,           // (cos x): the cosine of x
+    Tan,           // (tan x): the tangent of x
+    Atan2,         // (atan2 y x): the angle from the positive x axis to the point (x, y)
+    FloatToString, // (float->string x): x printed as by display (see `util::format_float`)
+}
+
+impl Builtin {
+    /// The types of the builtin's parameters, and its return type.
+    pub fn signature(self) -> (Vector<Type>, Type) {
+        match self {
+            Builtin::Random => (Vector::unit(Type::Int), Type::Int),
+            Builtin::RandomFloat => (Vector::new(), Type::Float),
+            // The time since the epoch doesn't fit in an int, so hosts are
+            // expected to measure from some other point, e.g. when the module
+            // was instantiated.
+            Builtin::CurrentMillis => (Vector::new(), Type::Int),
+            Builtin::ReadFile => (Vector::unit(Type::Str), Type::Str),
+            Builtin::WriteFile => (vector![Type::Str, Type::Str], Type::Bool),
+            Builtin::CommandLine => (Vector::new(), Type::List(Box::new(Type::Str))),
+            Builtin::GetEnv => (Vector::unit(Type::Str), option_type(Type::Str)),
+            Builtin::StringMatch => (
+                vector![Type::Str, Type::Str],
+                option_type(Type::List(Box::new(Type::Str))),
+            ),
+            Builtin::StringReplace => (vector![Type::Str, Type::Str, Type::Str], Type::Str),
+            Builtin::StringToUtf8 => (Vector::unit(Type::Str), bytes_type()),
+            Builtin::Utf8ToString => (Vector::unit(bytes_type()), option_type(Type::Str)),
+            Builtin::HexEncode | Builtin::Base64Encode => (Vector::unit(bytes_type()), Type::Str),
+            Builtin::HexDecode | Builtin::Base64Decode => {
+                (Vector::unit(Type::Str), option_type(bytes_type()))
+            }
+            Builtin::StringLength => (Vector::unit(Type::Str), Type::Int),
+            Builtin::StringRef => (vector![Type::Str, Type::Int], Type::Str),
+            Builtin::Substring => (vector![Type::Str, Type::Int, Type::Int], Type::Str),
+            Builtin::StringToList | Builtin::Graphemes => {
+                (Vector::unit(Type::Str), Type::List(Box::new(Type::Str)))
+            }
+            Builtin::Exp | Builtin::Log | Builtin::Sin | Builtin::Cos | Builtin::Tan => {
+                (Vector::unit(Type::Float), Type::Float)
+            }
+            Builtin::Atan2 => (vector![Type::Float, Type::Float], Type::Float),
+            Builtin::FloatToString => (Vector::unit(Type::Float), Type::Str),
+        }
+    }
+
+    /// Whether programs which use the builtin can be compiled to WebAssembly.
+    /// Modules have no access to the file system, or to the arguments and
+    /// environment variables they were run with, so builtins which use them
+    /// only run in the interpreter. (Importing them from WASI would also need strings, which
+    /// can't be compiled yet.)
+    pub fn supported_by_wasm(self) -> bool {
+        !matches!(
+            self,
+            Builtin::ReadFile | Builtin::WriteFile | Builtin::CommandLine | Builtin::GetEnv
+        )
+    }
+}
+
+impl std::fmt::Display for Builtin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Builtin::Random => write!(f, "random"),
+            Builtin::RandomFloat => write!(f, "random-float"),
+            Builtin::CurrentMillis => write!(f, "current-millis"),
+            Builtin::ReadFile => write!(f, "read-file"),
+            Builtin::WriteFile => write!(f, "write-file"),
+            Builtin::CommandLine => write!(f, "command-line"),
+            Builtin::GetEnv => write!(f, "getenv"),
+            Builtin::StringMatch => write!(f, "string-match"),
+            Builtin::StringReplace => write!(f, "string-replace"),
+            Builtin::StringToUtf8 => write!(f, "string->utf8"),
+            Builtin::Utf8ToString => write!(f, "utf8->string"),
+            Builtin::HexEncode => write!(f, "hex-encode"),
+            Builtin::HexDecode => write!(f, "hex-decode"),
+            Builtin::Base64Encode => write!(f, "base64-encode"),
+            Builtin::Base64Decode => write!(f, "base64-decode"),
+            Builtin::StringLength => write!(f, "string-length"),
+            Builtin::StringRef => write!(f, "string-ref"),
+            Builtin::Substring => write!(f, "substring"),
+            Builtin::StringToList => write!(f, "string->list"),
+            Builtin::Graphemes => write!(f, "string-graphemes"),
+            Builtin::Exp => write!(f, "exp"),
+            Builtin::Log => write!(f, "log"),
+            Builtin::Sin => write!(f, "sin"),
+            Builtin::Cos => write!(f, "cos"),
+            Builtin::Tan => write!(f, "tan"),
+            Builtin::Atan2 => write!(f, "atan2"),
+            Builtin::FloatToString => write!(f, "float->string"),
         }
     }
 }
@@ -284,9 +607,14 @@ impl std::fmt::Display for BinOp {
 /// Thus, to type-check the body of the let-expression, the type checker needs
 /// to remember this binding and be able to find it as needed. This is achieved
 /// by passing a TypeEnv between different type-checker calls.
-#[derive(Default, Debug)]
+///
+/// A TypeEnv also carries flags which change how strictly types are checked,
+/// so that they are available to every type checker call.
+#[derive(Clone, Default, Debug)]
 pub struct TypeEnv {
     bindings: Vector<(String, Type)>,
+    tuple_width_subtyping: bool,
+    wasm_target: bool,
 }
 
 // New values are appended to the front of the frame
@@ -294,14 +622,49 @@ impl TypeEnv {
     pub fn new() -> Self {
         TypeEnv {
             bindings: Vector::new(),
+            tuple_width_subtyping: false,
+            wasm_target: false,
+        }
+    }
+
+    /// Returns a new environment in which a tuple with extra elements may be
+    /// passed to a function expecting a shorter tuple.
+    pub fn with_tuple_width_subtyping(&self, enabled: bool) -> TypeEnv {
+        TypeEnv {
+            bindings: self.bindings.clone(),
+            tuple_width_subtyping: enabled,
+            wasm_target: self.wasm_target,
         }
     }
 
+    pub fn tuple_width_subtyping(&self) -> bool {
+        self.tuple_width_subtyping
+    }
+
+    /// Returns a new environment for checking a program which will be
+    /// compiled to WebAssembly, in which builtins that only the interpreter
+    /// supports are errors (see `Builtin::supported_by_wasm`).
+    pub fn with_wasm_target(&self, enabled: bool) -> TypeEnv {
+        TypeEnv {
+            bindings: self.bindings.clone(),
+            tuple_width_subtyping: self.tuple_width_subtyping,
+            wasm_target: enabled,
+        }
+    }
+
+    pub fn wasm_target(&self) -> bool {
+        self.wasm_target
+    }
+
     /// Returns a new environment extended with the provided binding.
     pub fn add_binding(&self, new_binding: (String, Type)) -> TypeEnv {
         let mut bindings = self.bindings.clone();
         bindings.push_front(new_binding);
-        TypeEnv { bindings }
+        TypeEnv {
+            bindings,
+            tuple_width_subtyping: self.tuple_width_subtyping,
+            wasm_target: self.wasm_target,
+        }
     }
 
     /// Returns a new environment extended with the provided bindings.
@@ -310,7 +673,11 @@ impl TypeEnv {
         for binding in new_bindings {
             bindings.push_front(binding);
         }
-        TypeEnv { bindings }
+        TypeEnv {
+            bindings,
+            tuple_width_subtyping: self.tuple_width_subtyping,
+            wasm_target: self.wasm_target,
+        }
     }
 
     pub fn find(&self, key: &str) -> Option<&Type> {
@@ -325,7 +692,11 @@ impl TypeEnv {
 
 impl From<Vector<(String, Type)>> for TypeEnv {
     fn from(bindings: Vector<(String, Type)>) -> Self {
-        TypeEnv { bindings }
+        TypeEnv {
+            bindings,
+            tuple_width_subtyping: false,
+            wasm_target: false,
+        }
     }
 }
 
@@ -1,26 +1,179 @@
+use crate::analysis::node_count;
+use crate::assignment_convert::assignment_convert;
 use crate::closure_convert::closure_convert;
-use crate::common::{Expr, Prog, TypedExpr};
+use crate::common::{dangerously_reset_gensym_count, Expr, ExprMeta, Prog, TypeEnv, TypedExpr};
 use crate::lambda_lift::lambda_lift;
+use crate::optimize::{optimize_prog, OptLevel};
 use crate::record_elim::record_elim_prog;
-use crate::type_check::{type_check, type_check_prog};
+use crate::resolve::resolve;
+use crate::type_check::{tc_with_env, type_check_prog_with_env};
+use std::time::Duration;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+use std::time::Instant;
+
+/// Options which change how a program is compiled.
+#[derive(Clone, Debug, Default)]
+pub struct CompileOptions {
+    /// Allow tuples with extra elements to be passed to functions expecting
+    /// shorter tuples. The extra elements are dropped at the call site.
+    pub tuple_width_subtyping: bool,
+    /// Which optimizer passes to run after all other passes.
+    pub opt_level: OptLevel,
+    /// Print the time taken by each compiler pass, and the size of the
+    /// program it produced, to stderr (see `CompileStats`).
+    pub verbose: bool,
+}
+
+/// The time taken by a compiler pass, and the number of expressions in the
+/// program it produced.
+#[derive(Clone, Debug)]
+pub struct PassStats {
+    pub name: &'static str,
+    pub duration: Duration,
+    pub nodes: usize,
+}
+
+/// Statistics about each pass of a compilation, in the order they ran, for
+/// finding which passes are slow on large programs.
+#[derive(Clone, Debug, Default)]
+pub struct CompileStats {
+    pub passes: Vec<PassStats>,
+}
+
+impl CompileStats {
+    /// Runs a compiler pass, recording how long it took and the size of its
+    /// output.
+    fn record<T, E>(
+        &mut self,
+        name: &'static str,
+        size: impl Fn(&T) -> usize,
+        pass: impl FnOnce() -> Result<T, E>,
+    ) -> Result<T, E> {
+        let _span = tracing::info_span!("pass", name).entered();
+        let (output, duration) = time(pass);
+        let output = output?;
+        self.passes.push(PassStats {
+            name,
+            duration,
+            nodes: size(&output),
+        });
+        Ok(output)
+    }
+
+    pub fn total_duration(&self) -> Duration {
+        self.passes.iter().map(|pass| pass.duration).sum()
+    }
+}
+
+impl std::fmt::Display for CompileStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for pass in self.passes.iter() {
+            writeln!(
+                f,
+                "{:<20} {:>8}us {:>8} nodes",
+                pass.name,
+                pass.duration.as_micros(),
+                pass.nodes
+            )?;
+        }
+        write!(
+            f,
+            "{:<20} {:>8}us",
+            "total",
+            self.total_duration().as_micros()
+        )
+    }
+}
+
+/// Runs `f`, returning its result and how long it took.
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+fn time<T>(f: impl FnOnce() -> T) -> (T, Duration) {
+    let start = Instant::now();
+    let output = f();
+    (output, start.elapsed())
+}
+
+/// There's no clock on wasm32-unknown-unknown (`Instant::now` panics), so
+/// everything takes no time.
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+fn time<T>(f: impl FnOnce() -> T) -> (T, Duration) {
+    (f(), Duration::default())
+}
+
+fn prog_node_count<E: ExprMeta>(prog: &Prog<E>) -> usize {
+    prog.fns
+        .iter()
+        .map(|(_name, func)| node_count(func))
+        .sum::<usize>()
+        + node_count(&prog.exp)
+}
 
 /// Perform a complete compilation from an Expr to a Prog - in other words, all
 /// all compiler passes before code generation.
 ///
 /// Parsing the original input string (code) into an Expr must be handled
-/// separately, using `parse::parse()`.
+/// separately, using `parse::parse()` (after expanding any macros with
+/// `macro_expand::macro_expand()`).
+///
+/// Compiling the same expression always produces the same program, and so
+/// the same module after code generation.
 ///
 /// TODO: We could return a custom "wrapping" error type instead of
 /// Box<dyn Error>, but I'm not sure if this is necessary or what is
 /// best form.
 pub fn compile_exp(exp: &Expr) -> Result<Prog<TypedExpr>, Box<dyn std::error::Error>> {
+    compile_exp_with_options(exp, &CompileOptions::default())
+}
+
+/// Like `compile_exp`, but with the given `CompileOptions`.
+pub fn compile_exp_with_options(
+    exp: &Expr,
+    options: &CompileOptions,
+) -> Result<Prog<TypedExpr>, Box<dyn std::error::Error>> {
+    compile_exp_with_stats(exp, options).map(|(prog, _stats)| prog)
+}
+
+/// Like `compile_exp_with_options`, but also returns statistics about each
+/// compiler pass. Code generation isn't included, since it happens
+/// separately (see `generate_code::size_report` for the size of its output).
+pub fn compile_exp_with_stats(
+    exp: &Expr,
+    options: &CompileOptions,
+) -> Result<(Prog<TypedExpr>, CompileStats), Box<dyn std::error::Error>> {
+    let env = TypeEnv::new()
+        .with_tuple_width_subtyping(options.tuple_width_subtyping)
+        .with_wasm_target(true);
+    let mut stats = CompileStats::default();
+    // number generated names from zero, so that the output only depends on
+    // the program. Names generated before compiling (e.g. by macro expansion)
+    // are renamed by resolve, so they can't clash with the new ones.
+    dangerously_reset_gensym_count();
+
     // the type information is not currently used for closure conversion, but
     // we want to type check just to catch errors early on
-    type_check(&exp)?;
+    stats.record("type check", node_count, || tc_with_env(&exp, &env))?;
 
-    let cc_exp = closure_convert(&exp)?;
-    let prog = lambda_lift(&cc_exp)?;
-    let typed_prog = type_check_prog(&prog)?;
-    let re_typed_prog = record_elim_prog(&typed_prog)?;
-    Ok(re_typed_prog)
+    // give every variable a unique name, so later passes (and the locals in
+    // code generation) don't need to handle shadowing
+    let resolved_exp = stats.record("resolve", node_count, || resolve(&exp))?;
+    // box variables which closures assign to or see assignments to, since
+    // closure conversion copies captured variables into environments
+    let ac_exp = stats.record("assignment convert", node_count, || {
+        Ok::<_, Box<dyn std::error::Error>>(assignment_convert(&resolved_exp))
+    })?;
+    let cc_exp = stats.record("closure convert", node_count, || closure_convert(&ac_exp))?;
+    let prog = stats.record("lambda lift", prog_node_count, || lambda_lift(&cc_exp))?;
+    let typed_prog = stats.record("type check prog", prog_node_count, || {
+        type_check_prog_with_env(&prog, &env)
+    })?;
+    let re_typed_prog = stats.record("record elim", prog_node_count, || {
+        record_elim_prog(&typed_prog)
+    })?;
+    let opt_prog = stats.record("optimize", prog_node_count, || {
+        optimize_prog(&re_typed_prog, options.opt_level)
+    })?;
+    if options.verbose {
+        eprintln!("{}", stats);
+    }
+    Ok((opt_prog, stats))
 }
@@ -0,0 +1,464 @@
+/// This module rewrites syntactic sugar into the core forms which the parser
+/// understands. It runs on s-expressions, after macro expansion (see
+/// `macro_expand::macro_expand`) and before parsing.
+use lexpr::Value;
+use std::collections::BTreeSet;
+
+#[derive(Clone, Debug)]
+pub struct DesugarError(String);
+
+// Allows other errors to wrap this one
+impl std::error::Error for DesugarError {}
+
+impl From<&str> for DesugarError {
+    fn from(message: &str) -> Self {
+        DesugarError(String::from(message))
+    }
+}
+
+impl std::fmt::Display for DesugarError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "DesugarError: {}", self.0)
+    }
+}
+
+/// Rewrites all of the syntactic sugar within a program:
+///
+/// - `(let* ((x a) (y b)) body)` becomes nested let expressions.
+/// - `(cond (pred exp) ... (else exp))` becomes nested if expressions. The
+///   else clause is required, since every expression must have a value.
+/// - `(begin0 first rest ...)` evaluates each expression in order, returning
+///   the value of the first, by binding it to a variable in a let expression.
+/// - `(for-each f lst)` calls `f` on each element of `lst` in order, using a
+///   while loop rather than recursion, and returns unit. Like in Scheme, the
+///   results of `f` are ignored, so it can return unit or any other type.
+/// - `+`, `*`, `-`, `and`, `or` and `concat` can be given any number of
+///   arguments, and are folded from the left, e.g. `(+ 1 2 3)` becomes
+///   `(+ (+ 1 2) 3)`. With one argument, `(- x)` negates `x` and the others
+///   return `x`; with none, `(+)`, `(*)`, `(and)` and `(or)` return their
+///   identity values.
+/// - `(cond-expand (requirement exp) ... (else exp))` becomes the expression
+///   of the first clause whose feature requirement is met (see
+///   `desugar_with_features`). Expressions in the other clauses are dropped
+///   without being desugared or checked.
+/// - `(when pred exp ...)` and `(unless pred exp ...)` become if expressions
+///   which evaluate the expressions in order when `pred` is true (or false).
+///   Like while loops, they produce unit (see `types::unit_type`).
+/// - `(let name ((x : type init) ...) : ret-type body)` (a named let) calls a
+///   function `name` with the initial values, where `name` can call itself
+///   in `body` to loop with new values. Like other recursive functions, it is
+///   bound to a placeholder first and then assigned with set!, so its type is
+///   `(-> type ... ret-type)` (which may mutate).
+pub fn desugar(value: &Value) -> Result<Value, DesugarError> {
+    desugar_with_features(value, &BTreeSet::new())
+}
+
+/// The features which are always available to `cond-expand`, from the
+/// target which is compiled to.
+pub const TARGET_FEATURES: [&str; 1] = ["wasm"];
+
+/// Like `desugar`, but `cond-expand` expressions can also select clauses
+/// based on the given user-defined features (e.g. "wasi" or "browser"), in
+/// addition to the `TARGET_FEATURES`.
+///
+/// A feature requirement is a feature's name, or `(and req ...)`,
+/// `(or req ...)` or `(not req)`, like in R7RS Scheme.
+pub fn desugar_with_features(
+    value: &Value,
+    features: &BTreeSet<String>,
+) -> Result<Value, DesugarError> {
+    let lst = match value.to_vec() {
+        Some(lst) if !lst.is_empty() => lst,
+        _ => return Ok(value.clone()),
+    };
+    // only the selected clause is desugared, since the others may only make
+    // sense for other targets
+    if lst[0].as_symbol() == Some("cond-expand") {
+        let selected = desugar_cond_expand(&lst[1..], features)?;
+        return desugar_with_features(&selected, features);
+    }
+    let args = lst[1..]
+        .iter()
+        .map(|arg| desugar_with_features(arg, features))
+        .collect::<Result<Vec<Value>, DesugarError>>()?;
+    match lst[0].as_symbol() {
+        Some("let*") => desugar_let_star(&args),
+        Some("cond") => desugar_cond(&args),
+        Some("begin0") => desugar_begin0(args),
+        Some("for-each") => desugar_for_each(&args),
+        Some("when") => desugar_when(true, args),
+        Some("unless") => desugar_when(false, args),
+        Some("let") if args.first().and_then(|arg| arg.as_symbol()).is_some() => {
+            desugar_named_let(&args)
+        }
+        Some(op @ "+") | Some(op @ "*") | Some(op @ "-") | Some(op @ "and") | Some(op @ "or")
+        | Some(op @ "concat")
+            if args.len() != 2 =>
+        {
+            desugar_variadic(op, args)
+        }
+        _ => {
+            let head = desugar_with_features(&lst[0], features)?;
+            Ok(Value::list(
+                std::iter::once(head).chain(args).collect::<Vec<Value>>(),
+            ))
+        }
+    }
+}
+
+fn desugar_let_star(args: &[Value]) -> Result<Value, DesugarError> {
+    if args.len() != 2 {
+        return Err(DesugarError::from(
+            "Let* expression has incorrect number of arguments.",
+        ));
+    }
+    let bindings = args[0]
+        .to_vec()
+        .ok_or_else(|| DesugarError::from("Let* expression bindings are not in a list."))?;
+    if bindings.is_empty() {
+        return Ok(Value::list(vec![
+            Value::symbol("let"),
+            Value::Null,
+            args[1].clone(),
+        ]));
+    }
+    let mut body = args[1].clone();
+    for binding in bindings.into_iter().rev() {
+        body = Value::list(vec![Value::symbol("let"), Value::list(vec![binding]), body]);
+    }
+    Ok(body)
+}
+
+fn desugar_cond(args: &[Value]) -> Result<Value, DesugarError> {
+    let (last, clauses) = match args.split_last() {
+        Some(split) => split,
+        None => return Err(DesugarError::from("Cond expression has no clauses.")),
+    };
+    let parse_clause = |clause: &Value| -> Result<(Value, Value), DesugarError> {
+        match clause.to_vec() {
+            Some(clause) if clause.len() == 2 => Ok((clause[0].clone(), clause[1].clone())),
+            _ => Err(DesugarError::from(
+                "Cond clause must contain a condition and an expression.",
+            )),
+        }
+    };
+    let (else_symbol, mut result) = parse_clause(last)?;
+    if else_symbol.as_symbol() != Some("else") {
+        return Err(DesugarError::from(
+            "Cond expression must end with an else clause.",
+        ));
+    }
+    for clause in clauses.iter().rev() {
+        let (pred, exp) = parse_clause(clause)?;
+        if pred.as_symbol() == Some("else") {
+            return Err(DesugarError::from(
+                "Else clause must be the last clause in a cond expression.",
+            ));
+        }
+        result = Value::list(vec![Value::symbol("if"), pred, exp, result]);
+    }
+    Ok(result)
+}
+
+/// Returns the expression of the first cond-expand clause whose requirement
+/// is met by the features.
+fn desugar_cond_expand(
+    clauses: &[Value],
+    features: &BTreeSet<String>,
+) -> Result<Value, DesugarError> {
+    for (i, clause) in clauses.iter().enumerate() {
+        let (requirement, exp) = match clause.to_vec() {
+            Some(clause) if clause.len() == 2 => (clause[0].clone(), clause[1].clone()),
+            _ => {
+                return Err(DesugarError::from(
+                    "Cond-expand clause must contain a feature requirement and an expression.",
+                ))
+            }
+        };
+        if requirement.as_symbol() == Some("else") {
+            if i != clauses.len() - 1 {
+                return Err(DesugarError::from(
+                    "Else clause must be the last clause in a cond-expand expression.",
+                ));
+            }
+            return Ok(exp);
+        }
+        if has_features(&requirement, features)? {
+            return Ok(exp);
+        }
+    }
+    Err(DesugarError::from(
+        "No clause of cond-expand expression matches the enabled features.",
+    ))
+}
+
+/// Returns whether a cond-expand feature requirement is met.
+fn has_features(requirement: &Value, features: &BTreeSet<String>) -> Result<bool, DesugarError> {
+    if let Some(feature) = requirement.as_symbol() {
+        return Ok(TARGET_FEATURES.contains(&feature) || features.contains(feature));
+    }
+    let lst = requirement.to_vec().unwrap_or_default();
+    let reqs = lst.get(1..).unwrap_or_default();
+    match lst.first().and_then(|head| head.as_symbol()) {
+        Some("and") => {
+            for req in reqs {
+                if !has_features(req, features)? {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        }
+        Some("or") => {
+            for req in reqs {
+                if has_features(req, features)? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+        Some("not") if reqs.len() == 1 => Ok(!has_features(&reqs[0], features)?),
+        _ => Err(DesugarError(format!(
+            "Invalid feature requirement in cond-expand expression: {}",
+            requirement
+        ))),
+    }
+}
+
+fn desugar_begin0(args: Vec<Value>) -> Result<Value, DesugarError> {
+    let mut args = args.into_iter();
+    let first = match args.next() {
+        Some(first) => first,
+        None => {
+            return Err(DesugarError::from(
+                "Begin0 expression must have at least one argument.",
+            ))
+        }
+    };
+    let rest: Vec<Value> = args.collect();
+    if rest.is_empty() {
+        return Ok(first);
+    }
+    let var = fresh_symbol("begin0-value", &rest);
+    Ok(Value::list(vec![
+        Value::symbol("let"),
+        Value::list(vec![Value::list(vec![var.clone(), first])]),
+        Value::list(
+            std::iter::once(Value::symbol("begin"))
+                .chain(rest)
+                .chain(std::iter::once(var))
+                .collect::<Vec<Value>>(),
+        ),
+    ]))
+}
+
+fn desugar_for_each(args: &[Value]) -> Result<Value, DesugarError> {
+    if args.len() != 2 {
+        return Err(DesugarError::from(
+            "For-each expression has incorrect number of arguments.",
+        ));
+    }
+    let func = fresh_symbol("for-each-func", args);
+    let lst = fresh_symbol("for-each-list", args);
+    let list = |items: Vec<Value>| Value::list(items);
+    // (let ((func f) (lst l))
+    //   (while (if (null? lst) false true)
+    //     (begin (func (car lst)) (set! lst (cdr lst)))))
+    Ok(list(vec![
+        Value::symbol("let"),
+        list(vec![
+            list(vec![func.clone(), args[0].clone()]),
+            list(vec![lst.clone(), args[1].clone()]),
+        ]),
+        list(vec![
+            Value::symbol("while"),
+            list(vec![
+                Value::symbol("if"),
+                list(vec![Value::symbol("null?"), lst.clone()]),
+                Value::symbol("false"),
+                Value::symbol("true"),
+            ]),
+            list(vec![
+                Value::symbol("begin"),
+                list(vec![func, list(vec![Value::symbol("car"), lst.clone()])]),
+                list(vec![
+                    Value::symbol("set!"),
+                    lst.clone(),
+                    list(vec![Value::symbol("cdr"), lst]),
+                ]),
+            ]),
+        ]),
+    ]))
+}
+
+fn desugar_when(when: bool, args: Vec<Value>) -> Result<Value, DesugarError> {
+    let op = if when { "When" } else { "Unless" };
+    let mut args = args.into_iter();
+    let pred = match args.next() {
+        Some(pred) if args.len() > 0 => pred,
+        _ => {
+            return Err(DesugarError(format!(
+                "{} expression must have a condition and at least one expression.",
+                op
+            )))
+        }
+    };
+    let unit = || Value::list(vec![Value::symbol("make-tuple")]);
+    let body = Value::list(
+        std::iter::once(Value::symbol("begin"))
+            .chain(args)
+            .chain(std::iter::once(unit()))
+            .collect::<Vec<Value>>(),
+    );
+    let (cons, alt) = if when { (body, unit()) } else { (unit(), body) };
+    Ok(Value::list(vec![Value::symbol("if"), pred, cons, alt]))
+}
+
+fn desugar_named_let(args: &[Value]) -> Result<Value, DesugarError> {
+    if args.len() != 5 || args[2].as_symbol() != Some(":") {
+        return Err(DesugarError::from(
+            "Named let expression must have a name, bindings, : and a return type, and a body.",
+        ));
+    }
+    let name = &args[0];
+    let ret_type = &args[3];
+    let bindings = args[1]
+        .to_vec()
+        .ok_or_else(|| DesugarError::from("Named let expression bindings are not in a list."))?;
+    let mut vars = vec![];
+    let mut types = vec![];
+    let mut inits = vec![];
+    for binding in bindings {
+        match binding.to_vec() {
+            Some(binding)
+                if binding.len() == 4
+                    && binding[0].as_symbol().is_some()
+                    && binding[1].as_symbol() == Some(":") =>
+            {
+                if binding[0] == *name {
+                    return Err(DesugarError(format!(
+                        "Named let binding has the same name as the let, {}.",
+                        name
+                    )));
+                }
+                vars.push(binding[0].clone());
+                types.push(binding[2].clone());
+                inits.push(binding[3].clone());
+            }
+            _ => {
+                return Err(DesugarError::from(
+                    "Named let binding must contain a name, : and a type, and a value.",
+                ))
+            }
+        }
+    }
+    let list = |items: Vec<Value>| Value::list(items);
+    let params = vars
+        .iter()
+        .zip(types.iter())
+        .map(|(var, typ)| list(vec![var.clone(), Value::symbol(":"), typ.clone()]))
+        .collect::<Vec<Value>>();
+    let lambda = |body: Value| {
+        list(vec![
+            Value::symbol("lambda"),
+            list(params.clone()),
+            Value::symbol(":"),
+            ret_type.clone(),
+            body,
+        ])
+    };
+    let car_of_null = |typ: Value| {
+        list(vec![
+            Value::symbol("car"),
+            list(vec![Value::symbol("null"), typ]),
+        ])
+    };
+    let func_type = list(
+        std::iter::once(Value::symbol("->"))
+            .chain(types)
+            .chain(std::iter::once(ret_type.clone()))
+            .collect::<Vec<Value>>(),
+    );
+    // (let ((x init) ...)
+    //   (let ((name (if true
+    //                   (lambda ((x : type) ...) : ret-type
+    //                     (car (null ret-type)))
+    //                   (car (null (-> type ... ret-type))))))
+    //     (begin (set! name (lambda ((x : type) ...) : ret-type body))
+    //            (name x ...))))
+    // The initial values are bound first so that they can't refer to `name`.
+    // The placeholder is never called, and the branch which isn't taken only
+    // gives it a type which may mutate, so that `body` can.
+    let placeholder = list(vec![
+        Value::symbol("if"),
+        Value::symbol("true"),
+        lambda(car_of_null(ret_type.clone())),
+        car_of_null(func_type),
+    ]);
+    Ok(list(vec![
+        Value::symbol("let"),
+        list(
+            vars.iter()
+                .zip(inits)
+                .map(|(var, init)| list(vec![var.clone(), init]))
+                .collect::<Vec<Value>>(),
+        ),
+        list(vec![
+            Value::symbol("let"),
+            list(vec![list(vec![name.clone(), placeholder])]),
+            list(vec![
+                Value::symbol("begin"),
+                list(vec![
+                    Value::symbol("set!"),
+                    name.clone(),
+                    lambda(args[4].clone()),
+                ]),
+                list(
+                    std::iter::once(name.clone())
+                        .chain(vars)
+                        .collect::<Vec<Value>>(),
+                ),
+            ]),
+        ]),
+    ]))
+}
+
+/// Returns a symbol starting with `prefix` which doesn't appear anywhere in
+/// `values`, so that binding it can't capture any of their variables.
+fn fresh_symbol(prefix: &str, values: &[Value]) -> Value {
+    fn contains_symbol(value: &Value, name: &str) -> bool {
+        match value.to_vec() {
+            Some(items) => items.iter().any(|item| contains_symbol(item, name)),
+            None => value.as_symbol() == Some(name),
+        }
+    }
+    let mut name = String::from(prefix);
+    let mut count = 0;
+    while values.iter().any(|value| contains_symbol(value, &name)) {
+        count += 1;
+        name = format!("{}{}", prefix, count);
+    }
+    Value::symbol(name)
+}
+
+fn desugar_variadic(op: &str, args: Vec<Value>) -> Result<Value, DesugarError> {
+    let mut args = args.into_iter();
+    let first = match (op, args.next()) {
+        ("-", Some(arg)) if args.len() == 0 => {
+            return Ok(Value::list(vec![Value::symbol("-"), Value::from(0), arg]))
+        }
+        (_, Some(arg)) => arg,
+        ("+", None) => return Ok(Value::from(0)),
+        ("*", None) => return Ok(Value::from(1)),
+        ("and", None) => return Ok(Value::symbol("true")),
+        ("or", None) => return Ok(Value::symbol("false")),
+        (_, None) => {
+            return Err(DesugarError(format!(
+                "{} expression must have at least one argument.",
+                op
+            )))
+        }
+    };
+    Ok(args.fold(first, |acc, arg| {
+        Value::list(vec![Value::symbol(op), acc, arg])
+    }))
+}
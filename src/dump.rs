@@ -0,0 +1,110 @@
+/// This module prints the program at different stages of the front end, so
+/// that users can see how their source was interpreted.
+use crate::desugar::desugar;
+use crate::limits::CompilerLimits;
+use crate::macro_expand::macro_expand_with_limits;
+use crate::parse::parse_with_limits;
+use crate::pretty::{pretty_print, DEFAULT_WIDTH};
+use crate::read::read;
+use crate::type_check::type_check;
+use lexpr::Value;
+
+/// How far to process the source before printing it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DumpStage {
+    Expanded,  // after macro expansion
+    Desugared, // after rewriting syntactic sugar (see `desugar::desugar`)
+    Parsed,    // after parsing, as the parser understood each form
+    Typed,     // after type checking, with the type of the whole expression
+}
+
+/// The format to print the program in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DumpFormat {
+    SExpr, // formatted s-expressions (see `pretty::format_source`)
+    Json,  // lists as arrays, symbols as strings, strings as {"string": ...}
+}
+
+/// Reads and processes source code up to the given stage, and prints the
+/// resulting program.
+///
+/// At the typed stage, the program is printed as `(: exp type)` (or as
+/// `{"exp": ..., "type": ...}` in JSON).
+pub fn dump_ast(
+    source: &str,
+    stage: DumpStage,
+    format: DumpFormat,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let limits = CompilerLimits::default();
+    let expanded = macro_expand_with_limits(&read(source)?, &limits)?;
+    let (value, typ) = match stage {
+        DumpStage::Expanded => (expanded, None),
+        DumpStage::Desugared => (desugar(&expanded)?, None),
+        DumpStage::Parsed => (
+            reread(&parse_with_limits(&desugar(&expanded)?, &limits)?)?,
+            None,
+        ),
+        DumpStage::Typed => {
+            let typed_exp = type_check(&parse_with_limits(&desugar(&expanded)?, &limits)?)?;
+            (reread(&typed_exp)?, Some(reread(&typed_exp.typ)?))
+        }
+    };
+    let output = match (format, typ) {
+        (DumpFormat::SExpr, None) => print_sexpr(&value),
+        (DumpFormat::SExpr, Some(typ)) => {
+            print_sexpr(&Value::list(vec![Value::symbol(":"), value, typ]))
+        }
+        (DumpFormat::Json, None) => format!("{}\n", print_json(&value)),
+        (DumpFormat::Json, Some(typ)) => format!(
+            "{{\"exp\": {}, \"type\": {}}}\n",
+            print_json(&value),
+            print_json(&typ)
+        ),
+    };
+    Ok(output)
+}
+
+/// Reads back the printed form of an expression or type.
+fn reread<T: std::fmt::Display>(val: &T) -> Result<Value, Box<dyn std::error::Error>> {
+    Ok(read(&val.to_string())?)
+}
+
+fn print_sexpr(value: &Value) -> String {
+    let mut output = String::new();
+    pretty_print(value, DEFAULT_WIDTH, &mut output);
+    output.push('\n');
+    output
+}
+
+fn print_json(value: &Value) -> String {
+    match value {
+        Value::Bool(x) => format!("{}", x),
+        Value::Number(x) => format!("{}", x),
+        Value::String(x) => format!("{{\"string\": {}}}", escape_json(x)),
+        Value::Symbol(x) => escape_json(x),
+        _ => match value.to_vec() {
+            Some(items) => {
+                let items: Vec<String> = items.iter().map(print_json).collect();
+                format!("[{}]", items.join(", "))
+            }
+            None => escape_json(&value.to_string()),
+        },
+    }
+}
+
+fn escape_json(val: &str) -> String {
+    let mut result = String::from("\"");
+    for c in val.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\t' => result.push_str("\\t"),
+            '\r' => result.push_str("\\r"),
+            c if c.is_control() => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
@@ -0,0 +1,167 @@
+use crate::common::{BinOp, ExprKind, TypedExpr};
+use crate::types::{Effect, Type};
+use im_rc::{vector, Vector};
+
+/// Infers the effect of evaluating a (typed) expression.
+///
+/// Assigning with `set!` to a variable not bound within the expression counts
+/// as a mutation, as does calling a function whose type has the
+/// `Effect::Mutates` effect, and dividing ints by a divisor which may trap
+/// (see `may_trap`), since removing the division would remove the trap.
+/// Creating a lambda is pure, since its body is not evaluated until it is
+/// called.
+pub fn infer_effect(exp: &TypedExpr) -> Effect {
+    exp_effect(exp, &vector![])
+}
+
+/// Returns whether evaluating the expression has no observable side effects,
+/// e.g. so that it can be safely removed or reordered.
+pub fn is_pure(exp: &TypedExpr) -> bool {
+    infer_effect(exp) == Effect::Pure
+}
+
+/// Infers the effect of calling a lambda with the provided parameters and
+/// body.
+///
+/// Assigning to the lambda's own parameters, or to variables bound within its
+/// body, cannot be observed by the caller, so these are not counted as
+/// mutations.
+pub fn lambda_effect(params: &Vector<(String, Type)>, body: &TypedExpr) -> Effect {
+    let locals: Vector<String> = params.iter().map(|pair| pair.0.clone()).collect();
+    exp_effect(body, &locals)
+}
+
+fn exp_array_effect(exps: &Vector<TypedExpr>, locals: &Vector<String>) -> Effect {
+    exps.iter()
+        .map(|exp| exp_effect(exp, locals))
+        .max()
+        .unwrap_or(Effect::Pure)
+}
+
+/// Returns whether an int division or remainder may trap: when the divisor is
+/// zero, or (for signed division) when -2147483648 is divided by -1. Only a
+/// constant divisor which is neither of these is known not to.
+fn may_trap(op: BinOp, arg1: &TypedExpr, arg2: &TypedExpr) -> bool {
+    if arg1.typ != Type::Int {
+        return false;
+    }
+    match (op, &*arg2.kind) {
+        (BinOp::Divide, ExprKind::Num(divisor)) => *divisor == 0 || *divisor == -1,
+        (BinOp::UnsignedDivide, ExprKind::Num(divisor))
+        | (BinOp::UnsignedRemainder, ExprKind::Num(divisor)) => *divisor == 0,
+        (BinOp::Divide, _) | (BinOp::UnsignedDivide, _) | (BinOp::UnsignedRemainder, _) => true,
+        _ => false,
+    }
+}
+
+fn exp_effect(exp: &TypedExpr, locals: &Vector<String>) -> Effect {
+    match &*exp.kind {
+        ExprKind::Set(var, val) => {
+            let set_effect = if locals.contains(var) {
+                Effect::Pure
+            } else {
+                Effect::Mutates
+            };
+            set_effect.max(exp_effect(val, locals))
+        }
+        ExprKind::FnApp(func, args) => {
+            let call_effect = match &func.typ {
+                Type::Func(_, _, effect) => *effect,
+                _ => Effect::Mutates,
+            };
+            call_effect
+                .max(exp_effect(func, locals))
+                .max(exp_array_effect(args, locals))
+        }
+        ExprKind::Lambda(_params, _ret_type, _body) => Effect::Pure,
+        ExprKind::Let(bindings, body) => {
+            let binding_exps: Vector<TypedExpr> =
+                bindings.iter().map(|pair| pair.1.clone()).collect();
+            let body_locals = locals.clone() + bindings.iter().map(|pair| pair.0.clone()).collect();
+            exp_array_effect(&binding_exps, locals).max(exp_effect(body, &body_locals))
+        }
+        ExprKind::Unpack(var, package, _type_sub, body) => {
+            let mut body_locals = locals.clone();
+            body_locals.push_back(var.clone());
+            exp_effect(package, locals).max(exp_effect(body, &body_locals))
+        }
+        ExprKind::TypeCase(val, clauses) => clauses
+            .iter()
+            .map(|(_typ, var, body)| {
+                let mut body_locals = locals.clone();
+                body_locals.push_back(var.clone());
+                exp_effect(body, &body_locals)
+            })
+            .fold(exp_effect(val, locals), Effect::max),
+        ExprKind::Binop(op, arg1, arg2) => {
+            let op_effect = if may_trap(*op, arg1, arg2) {
+                Effect::Mutates
+            } else {
+                Effect::Pure
+            };
+            op_effect
+                .max(exp_effect(arg1, locals))
+                .max(exp_effect(arg2, locals))
+        }
+        ExprKind::Unop(_op, arg) => exp_effect(arg, locals),
+        ExprKind::If(pred, cons, alt) => exp_effect(pred, locals)
+            .max(exp_effect(cons, locals))
+            .max(exp_effect(alt, locals)),
+        ExprKind::Begin(exps) => exp_array_effect(exps, locals),
+        ExprKind::Cons(first, rest) => exp_effect(first, locals).max(exp_effect(rest, locals)),
+        ExprKind::Car(val) => exp_effect(val, locals),
+        ExprKind::Cdr(val) => exp_effect(val, locals),
+        ExprKind::IsNull(val) => exp_effect(val, locals),
+        ExprKind::Tuple(exps) => exp_array_effect(exps, locals),
+        ExprKind::TupleGet(tuple, _key) => exp_effect(tuple, locals),
+        // the tuple may be shared with other code (e.g. as a boxed variable,
+        // see `assignment_convert`), so the write is always observable
+        ExprKind::TupleSet(_tuple, _key, _val) => Effect::Mutates,
+        ExprKind::Record(bindings) => {
+            let binding_exps: Vector<TypedExpr> =
+                bindings.iter().map(|pair| pair.1.clone()).collect();
+            exp_array_effect(&binding_exps, locals)
+        }
+        ExprKind::RecordGet(record, _key) => exp_effect(record, locals),
+        ExprKind::Pack(val, _sub, _exist) => exp_effect(val, locals),
+        ExprKind::Inject(val, _union_typ) => exp_effect(val, locals),
+        // a failed assert traps rather than having an effect which the rest of
+        // the program could observe
+        ExprKind::Assert(val, _message) => exp_effect(val, locals),
+        ExprKind::While(pred, body) => exp_effect(pred, locals).max(exp_effect(body, locals)),
+        ExprKind::Case(key, clauses, else_body) => clauses
+            .iter()
+            .map(|(_datums, body)| exp_effect(body, locals))
+            .fold(
+                exp_effect(key, locals).max(exp_effect(else_body, locals)),
+                Effect::max,
+            ),
+        // like a lambda, the closure for a curried or composed function
+        // doesn't call anything when it is created
+        ExprKind::Curry(func, args) => exp_effect(func, locals).max(exp_array_effect(args, locals)),
+        ExprKind::Compose(outer, inner) => exp_effect(outer, locals).max(exp_effect(inner, locals)),
+        // a delayed expression isn't evaluated until the promise is forced,
+        // and the effects of doing so aren't tracked by promise types
+        ExprKind::Delay(_val) => Effect::Pure,
+        ExprKind::Force(_promise) => Effect::Mutates,
+        ExprKind::Format(_control, args) => exp_array_effect(args, locals),
+        // host functions may have any effect (e.g. advancing a random number
+        // generator), so calls to them can't be removed or reordered
+        ExprKind::Builtin(_builtin, _args) => Effect::Mutates,
+        // extern functions are trusted to have the effect they're declared with
+        ExprKind::Extern(_module, _name, typ, args) => match typ {
+            Type::Func(_param_types, _ret_type, effect) => {
+                std::cmp::max(*effect, exp_array_effect(args, locals))
+            }
+            _ => Effect::Mutates,
+        },
+        // the instructions can do anything, e.g. write to memory
+        ExprKind::WasmAsm(_code, _typ, _args) => Effect::Mutates,
+        ExprKind::Null(_)
+        | ExprKind::Id(_)
+        | ExprKind::Num(_)
+        | ExprKind::Float(_)
+        | ExprKind::Bool(_)
+        | ExprKind::Str(_) => Effect::Pure,
+    }
+}
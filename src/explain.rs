@@ -0,0 +1,240 @@
+/// This module gives each kind of error found by the compiler a stable code,
+/// such as "T001", along with a longer explanation of what causes errors with
+/// that code and how to fix them, modeled on `rustc --explain`.
+///
+/// Parse, type, and code generation errors report their codes with `code()`
+/// (e.g. `TypeCheckError::code`), and errors from the earlier passes each
+/// have a single code (see `error_code`). Codes are never reused for a
+/// different kind of error, so they can be searched for and referred to in
+/// docs.
+use crate::desugar::DesugarError;
+use crate::generate_code::CodeGenerateError;
+use crate::limits::LimitError;
+use crate::macro_expand::MacroExpandError;
+use crate::parse::ParseError;
+use crate::read::ReadError;
+use crate::type_check::TypeCheckError;
+use std::error::Error;
+use std::fmt::Display;
+
+/// A longer description of the errors which have a particular code.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Explanation {
+    pub code: &'static str,
+    pub title: &'static str,
+    pub description: &'static str,
+    /// A program which causes the error.
+    pub example: &'static str,
+    /// How the example can be fixed.
+    pub fix: &'static str,
+}
+
+impl Display for Explanation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {}\n\n{}\n\nFor example:\n\n    {}\n\n{}",
+            self.code, self.title, self.description, self.example, self.fix
+        )
+    }
+}
+
+pub const EXPLANATIONS: [Explanation; 24] = [
+    Explanation {
+        code: "R001",
+        title: "Invalid syntax",
+        description: "The source can't be read as an s-expression, e.g. a list, string literal, or block comment isn't closed, or a string contains an invalid escape.",
+        example: "(+ 1 (* 2 3)",
+        fix: "Close every list: (+ 1 (* 2 3))",
+    },
+    Explanation {
+        code: "M001",
+        title: "Invalid macro",
+        description: "A macro or constant definition is malformed, or a use of a macro doesn't match any of its rules.",
+        example: "(begin (define-syntax twice (syntax-rules () ((twice e) (+ e e)))) (twice 1 2))",
+        fix: "Use the macro the way one of its rules expects: (begin (define-syntax twice (syntax-rules () ((twice e) (+ e e)))) (twice 1))",
+    },
+    Explanation {
+        code: "P001",
+        title: "Malformed expression",
+        description: "An expression doesn't have the shape its form requires, e.g. it has the wrong number of arguments, or a binding list isn't a list of pairs. This includes syntactic sugar such as cond and let*.",
+        example: "(if true 1)",
+        fix: "Give the form the parts it expects; an if expression needs a predicate, a consequent, and an alternate: (if true 1 0)",
+    },
+    Explanation {
+        code: "P002",
+        title: "Invalid number",
+        description: "Integer literals must fit in 32 bits, from -2147483648 to 2147483647. Other numbers must be written as floats.",
+        example: "(+ 2147483648 1)",
+        fix: "Use a smaller integer, or a float if the precision isn't needed: (+ 2147483648.0 1.0)",
+    },
+    Explanation {
+        code: "P003",
+        title: "Reserved word used as a name",
+        description: "The names of the language's forms and builtins (such as if, car, and random) can't be bound as variables, since uses of them would be ambiguous.",
+        example: "(let ((car 3)) car)",
+        fix: "Choose another name: (let ((car-count 3)) car-count)",
+    },
+    Explanation {
+        code: "P004",
+        title: "Invalid type annotation",
+        description: "A type annotation isn't one of the types of the language, e.g. it names an unknown type or a list type is missing its element type.",
+        example: "(lambda ((x : integer)) : int x)",
+        fix: "Use one of the types int, float, bool, string, or a compound type such as (list int): (lambda ((x : int)) : int x)",
+    },
+    Explanation {
+        code: "P005",
+        title: "Invalid constant expression",
+        description: "Where a constant integer is required (e.g. tuple indexes), only integer literals and +, -, *, and / of constant expressions can be used, and they must not divide by zero or overflow.",
+        example: "(tuple-ref (make-tuple 1 2) x)",
+        fix: "Use a literal index: (tuple-ref (make-tuple 1 2) 1)",
+    },
+    Explanation {
+        code: "T001",
+        title: "Type mismatch",
+        description: "An expression has a different type from the one required where it is used. The error shows the expected type, the type which was found, and the expression.",
+        example: "(if 1 2 3)",
+        fix: "Use an expression of the expected type, here a bool: (if (> 1 0) 2 3)",
+    },
+    Explanation {
+        code: "T002",
+        title: "Ints and floats mixed",
+        description: "Ints are never implicitly converted to floats (or floats to ints), since the conversion can lose precision, so arithmetic and comparisons need two ints or two floats.",
+        example: "(+ 1 2.5)",
+        fix: "Convert one of the numbers explicitly: (+ (exact->inexact 1) 2.5)",
+    },
+    Explanation {
+        code: "T003",
+        title: "Wrong number of arguments",
+        description: "A function, builtin, or format string is given more or fewer arguments than it takes.",
+        example: "(let ((f (lambda ((x : int)) : int x))) (f 1 2))",
+        fix: "Pass one argument for each parameter: (let ((f (lambda ((x : int)) : int x))) (f 1))",
+    },
+    Explanation {
+        code: "T004",
+        title: "Unbound variable",
+        description: "A variable is used outside the scope of any binding for it (a let, a lambda parameter, or a top-level definition).",
+        example: "(let ((x 1)) y)",
+        fix: "Bind the variable first, or fix the misspelled name: (let ((x 1)) x)",
+    },
+    Explanation {
+        code: "T005",
+        title: "Not a function",
+        description: "A value which isn't a function is applied to arguments, or passed to curry or compose.",
+        example: "(let ((x 1)) (x 2))",
+        fix: "Only apply functions: (let ((x (lambda ((y : int)) : int y))) (x 2))",
+    },
+    Explanation {
+        code: "T006",
+        title: "Division by zero",
+        description: "Dividing an int by zero traps at runtime, so division by a literal zero is rejected.",
+        example: "(/ 5 0)",
+        fix: "Divide by a nonzero number, or check the divisor first: (let ((y 0)) (if (= y 0) 0 (/ 5 y)))",
+    },
+    Explanation {
+        code: "T007",
+        title: "Invalid use of a form",
+        description: "A form is used in a way its types don't allow, e.g. a record is missing the field being read, a tuple index is out of range, or a typecase doesn't cover every type in its union.",
+        example: "(record-ref (make-record (x 1)) y)",
+        fix: "Read a field which the record has: (record-ref (make-record (x 1)) x)",
+    },
+    Explanation {
+        code: "T008",
+        title: "Builtin not supported by the wasm target",
+        description: "Builtins which need the host's operating system, such as reading files or getting the program's arguments or environment variables, can be run by the interpreter, but not compiled to WebAssembly, since modules don't have access to it.",
+        example: "(read-file \"notes.txt\")",
+        fix: "Run the program with the interpreter, or have the host pass the data to the program instead.",
+    },
+    Explanation {
+        code: "T009",
+        title: "Missing record field",
+        description: "A record is passed where a record type with a field it doesn't have is required. Records can have more fields than required, but not fewer.",
+        example: "(let ((get-x (lambda ((r : (record (x : int)))) : int (record-ref r x)))) (get-x (make-record (y 1))))",
+        fix: "Pass a record with the field: (let ((get-x (lambda ((r : (record (x : int)))) : int (record-ref r x)))) (get-x (make-record (x 1))))",
+    },
+    Explanation {
+        code: "T010",
+        title: "Function may mutate",
+        description: "A function which may mutate variables (or calls a function which may) is used where a pure function, with a (pure-> ...) type, is required.",
+        example: "(let ((count 0)) (let ((call (lambda ((f : (pure-> int))) : int (f)))) (call (lambda () : int (set! count 1)))))",
+        fix: "Pass a function which doesn't mutate anything: (let ((count 0)) (let ((call (lambda ((f : (pure-> int))) : int (f)))) (call (lambda () : int count))))",
+    },
+    Explanation {
+        code: "T011",
+        title: "Infinite record type",
+        description: "A row variable (the T0 in (record (x : int) ... T0)) would be bound to fields which include itself, which would make an infinitely large record type.",
+        example: "(lambda ((f : (-> (record ... T0) int)) (r : (record (a : int) ... T0))) : int (f r))",
+        fix: "Use a separate row variable for the argument: (lambda ((f : (-> (record ... T0) int)) (r : (record (a : int) ... T1))) : int (f r))",
+    },
+    Explanation {
+        code: "T012",
+        title: "Conflicting row variable",
+        description: "A row variable which appears in several parameter types stands for the same fields in each, but the arguments have different fields besides the ones listed.",
+        example: "(let ((f (lambda ((a : (record (x : int) ... T1)) (b : (record (x : int) ... T1))) : int (record-ref a x)))) (f (make-record (x 1) (y 2)) (make-record (x 1) (z 2))))",
+        fix: "Pass records with the same other fields: (let ((f (lambda ((a : (record (x : int) ... T1)) (b : (record (x : int) ... T1))) : int (record-ref a x)))) (f (make-record (x 1) (y 2)) (make-record (x 1) (y 3))))",
+    },
+    Explanation {
+        code: "C001",
+        title: "Unsupported construct",
+        description: "The program uses a construct which can't be compiled to WebAssembly yet (e.g. strings), though it can still be run by the interpreter.",
+        example: "(concat \"a\" \"b\")",
+        fix: "Avoid the construct in compiled programs, or run the program with the interpreter.",
+    },
+    Explanation {
+        code: "C002",
+        title: "Invalid identifier",
+        description: "Code generation found a variable which isn't bound to a local or a function. This indicates a bug in an earlier compiler pass, since type checking rejects unbound variables.",
+        example: "(generated by an earlier pass)",
+        fix: "Please report the program which caused the error.",
+    },
+    Explanation {
+        code: "C003",
+        title: "Unexpected type",
+        description: "Code generation found a type it didn't expect at that point, e.g. a member type which isn't part of a union. This indicates a bug in an earlier compiler pass.",
+        example: "(generated by an earlier pass)",
+        fix: "Please report the program which caused the error.",
+    },
+    Explanation {
+        code: "C004",
+        title: "Compiler limit exceeded",
+        description: "The program, or the module compiled from it, is larger than one of the configured compiler limits allows.",
+        example: "a module larger than `max_output_size`",
+        fix: "Split the program into smaller functions, or raise the limit (see `CompilerLimits`).",
+    },
+    Explanation {
+        code: "C005",
+        title: "Code generation failed",
+        description: "Code generation failed for another reason, e.g. invalid options, or a function with more parameters than are supported.",
+        example: "an extern function declared with more than 8 parameters",
+        fix: "The error message describes the specific problem.",
+    },
+];
+
+/// Returns the explanation of the errors with the given code, if there is
+/// one.
+pub fn explain(code: &str) -> Option<&'static Explanation> {
+    EXPLANATIONS
+        .iter()
+        .find(|explanation| explanation.code.eq_ignore_ascii_case(code))
+}
+
+/// Returns the code of an error from the compiler, if it has one.
+pub fn error_code(err: &(dyn Error + 'static)) -> Option<&'static str> {
+    if err.is::<ReadError>() {
+        Some("R001")
+    } else if err.is::<MacroExpandError>() {
+        Some("M001")
+    } else if err.is::<DesugarError>() {
+        Some("P001")
+    } else if let Some(err) = err.downcast_ref::<ParseError>() {
+        Some(err.code())
+    } else if let Some(err) = err.downcast_ref::<TypeCheckError>() {
+        Some(err.code())
+    } else if let Some(err) = err.downcast_ref::<CodeGenerateError>() {
+        Some(err.code())
+    } else if err.is::<LimitError>() {
+        Some("C004")
+    } else {
+        None
+    }
+}
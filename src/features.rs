@@ -0,0 +1,96 @@
+/// This module handles user-defined feature flags, as given to the compiler
+/// with `--define-feature name[=value]`.
+///
+/// Every defined feature can be tested for by `cond-expand` expressions (see
+/// `desugar::desugar_with_features`). A feature which is given a value is
+/// also defined as a constant with that name, as if by `define-constant`, so
+/// uses of it are replaced by the value and folded by the optimizer (e.g.
+/// `(if (> log-level 2) ...)` with `log-level=1`). Values which are integers
+/// are ints, and other values are strings.
+use lexpr::Value;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Clone, Debug)]
+pub struct FeatureError(String);
+
+// Allows other errors to wrap this one
+impl std::error::Error for FeatureError {}
+
+impl From<&str> for FeatureError {
+    fn from(message: &str) -> Self {
+        FeatureError(String::from(message))
+    }
+}
+
+impl std::fmt::Display for FeatureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "FeatureError: {}", self.0)
+    }
+}
+
+/// The features defined by the user, and their values if they have one.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FeatureFlags {
+    flags: BTreeMap<String, Option<Value>>,
+}
+
+impl FeatureFlags {
+    pub fn new() -> Self {
+        FeatureFlags::default()
+    }
+
+    /// Defines a feature from a definition of the form `name` or
+    /// `name=value`. Defining a feature again replaces its value.
+    pub fn define(&mut self, definition: &str) -> Result<(), FeatureError> {
+        let (name, value) = match definition.find('=') {
+            Some(index) => (&definition[..index], Some(&definition[index + 1..])),
+            None => (definition, None),
+        };
+        match lexpr::from_str(name) {
+            Ok(Value::Symbol(sym)) if &*sym == name => (),
+            _ => {
+                return Err(FeatureError(format!(
+                    "Feature name is not an identifier: {}",
+                    name
+                )))
+            }
+        }
+        let value = value.map(|value| match value.parse::<i32>() {
+            Ok(num) => Value::from(num),
+            Err(_) => Value::string(value),
+        });
+        self.flags.insert(String::from(name), value);
+        Ok(())
+    }
+
+    /// Returns the value of a feature, or None if the feature has no value
+    /// or isn't defined.
+    pub fn value(&self, name: &str) -> Option<&Value> {
+        self.flags.get(name).and_then(|value| value.as_ref())
+    }
+
+    /// The names of all defined features, for `cond-expand`.
+    pub fn features(&self) -> BTreeSet<String> {
+        self.flags.keys().cloned().collect()
+    }
+
+    /// Defines the features which have values as constants within a (read,
+    /// but not yet macro expanded) program.
+    pub fn bind(&self, program: &Value) -> Value {
+        let mut exps = vec![Value::symbol("begin")];
+        for (name, value) in self.flags.iter() {
+            if let Some(value) = value {
+                exps.push(Value::list(vec![
+                    Value::symbol("define-constant"),
+                    Value::symbol(name.as_str()),
+                    value.clone(),
+                ]));
+            }
+        }
+        if exps.len() == 1 {
+            return program.clone();
+        }
+        exps.push(program.clone());
+        Value::list(exps)
+    }
+}
@@ -1,27 +1,81 @@
-use crate::common::{BinOp, ExprKind, Prog, TypedExpr};
+use crate::analysis::reachable_fns;
+use crate::ast_transform::transform_typed_exp_recursive;
+use crate::common::{BinOp, Builtin, ExprKind, Prog, TypedExpr, UnOp};
+use crate::limits::{check_output_size, CompilerLimits, LimitError};
+use crate::resolve::original_name;
 use crate::types::Type;
+use crate::util::format_vector;
 
+use std::cell::RefCell;
 use std::collections::BTreeMap;
+use std::convert::TryFrom;
 
-use im_rc::Vector;
+use im_rc::{vector, Vector};
 use parity_wasm::builder;
-use parity_wasm::elements::{BlockType, Instruction, Instructions, Local, Module, ValueType};
+use parity_wasm::elements::{
+    BlockType, BrTableData, CustomSection, FunctionNameSubsection, ImportCountType, Instruction,
+    Instructions, Local, LocalNameSubsection, Module, NameMap, NameSection, Section, ValueType,
+};
 
+/// An error during code generation.
+///
+/// Expressions don't keep track of where they appeared in the source, so
+/// errors carry the offending expression (or type) itself instead.
 #[derive(Clone, Debug)]
-pub struct CodeGenerateError(String);
+pub enum CodeGenerateError {
+    /// The expression uses a construct which code generation doesn't support
+    /// (e.g. strings), or which an earlier pass should have removed.
+    UnsupportedConstruct(String, TypedExpr),
+    /// A variable which isn't bound to a local or a function.
+    InvalidIdentifier(String),
+    /// A type which code generation didn't expect at that point, e.g. a
+    /// member type that isn't part of a union.
+    TypeUnknownAtCodegen(String, Type),
+    /// The compiled module exceeds one of the `CompilerLimits`.
+    LimitExceeded(LimitError),
+    /// Any other error, e.g. from invalid options.
+    Other(String),
+}
 
 // Allows other errors to wrap this one
 impl std::error::Error for CodeGenerateError {}
 
 impl From<&str> for CodeGenerateError {
     fn from(message: &str) -> Self {
-        CodeGenerateError(String::from(message))
+        CodeGenerateError::Other(String::from(message))
+    }
+}
+
+impl CodeGenerateError {
+    /// The code for the kind of error this is (see `explain::explain`).
+    pub fn code(&self) -> &'static str {
+        match self {
+            CodeGenerateError::UnsupportedConstruct(_, _) => "C001",
+            CodeGenerateError::InvalidIdentifier(_) => "C002",
+            CodeGenerateError::TypeUnknownAtCodegen(_, _) => "C003",
+            CodeGenerateError::LimitExceeded(_) => "C004",
+            CodeGenerateError::Other(_) => "C005",
+        }
     }
 }
 
 impl std::fmt::Display for CodeGenerateError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "CodeGenerateError: {}", self.0)
+        match self {
+            CodeGenerateError::UnsupportedConstruct(message, exp) => {
+                write!(f, "CodeGenerateError: {} In expression: {}", message, exp)
+            }
+            CodeGenerateError::InvalidIdentifier(name) => write!(
+                f,
+                "CodeGenerateError: Symbol {} not found in locals or function table.",
+                name
+            ),
+            CodeGenerateError::TypeUnknownAtCodegen(message, typ) => {
+                write!(f, "CodeGenerateError: {} Found type: {}", message, typ)
+            }
+            CodeGenerateError::LimitExceeded(err) => write!(f, "CodeGenerateError: {}", err),
+            CodeGenerateError::Other(message) => write!(f, "CodeGenerateError: {}", message),
+        }
     }
 }
 
@@ -38,6 +92,14 @@ type LocalsMap = BTreeMap<String, u32>;
 /// which is associated with a particular identifier (string).
 type FuncsMap = BTreeMap<String, u32>;
 
+/// A key-value map for finding the index of an imported WebAssembly function,
+/// given the module and name it is imported from. Builtins are imported from
+/// the "env" module under their own names.
+///
+/// Imported functions come before all other functions in a module, so the
+/// indices of the lambda-lifted functions are offset by the number of imports.
+type ImportsMap = BTreeMap<(String, String), u32>;
+
 /// A key-value map for finding the index of the signature associated with a
 /// particular type signature.
 ///
@@ -46,6 +108,79 @@ type FuncsMap = BTreeMap<String, u32>;
 /// basic.
 type SignaturesMap = BTreeMap<u32, u32>;
 
+/// Options which change how code is generated.
+///
+/// There is no option for the width of ints: they are always compiled to
+/// i32 values, and the parser rejects int literals outside the i32 range.
+/// Every value (including pointers and the bits of floats) is an i32, and
+/// function signatures only depend on the number of parameters, so an i64
+/// mode would need types threaded through all of code generation.
+///
+/// TODO: a C-ABI export mode (`#[no_mangle] extern "C"` wrappers) would need
+/// a backend which emits Rust source; this module only emits wasm directly,
+/// and the only function exported from modules is the main function.
+#[derive(Clone, Debug)]
+pub struct CodeGenerateOptions {
+    /// Whether `assert` expressions are compiled into runtime checks. If
+    /// false, asserts are stripped: their conditions are not evaluated, and
+    /// they always produce true.
+    pub asserts: bool,
+    /// Constants (names and values) to export from the module as immutable
+    /// globals, e.g. those returned by
+    /// `macro_expand::macro_expand_with_constants`.
+    pub exported_constants: Vector<(String, i32)>,
+    /// Runtime instrumentation to add to the generated code.
+    pub instrument: Instrumentation,
+    /// Limits on the compiled module. Only `max_output_size` applies to code
+    /// generation; the other limits are checked by the parser.
+    pub limits: CompilerLimits,
+    /// Export the module's memory, as `memory`, and functions for allocating
+    /// within it, so hosts can write data into the module:
+    ///
+    /// - `alloc(len: i32) -> i32` returns the address of `len` bytes of
+    ///   unused memory, aligned to 8 bytes, growing the memory if needed. It
+    ///   traps if the memory can't grow.
+    /// - `free(ptr: i32, len: i32)` releases memory returned by `alloc`.
+    ///   Memory is allocated from a stack, so only the most recent allocation
+    ///   is actually reclaimed; freeing any other allocation does nothing.
+    ///
+    /// Strings and byte buffers are passed as two i32s, the address and the
+    /// length in bytes, with strings encoded as UTF-8. The host allocates the
+    /// memory, writes the bytes, and frees the memory once the call which it
+    /// was passed to returns.
+    ///
+    /// TODO: the main function is the only function which modules export,
+    /// and it takes no arguments, so there is nothing to pass data to yet.
+    pub host_memory: bool,
+}
+
+/// Kinds of runtime instrumentation which can be added to a module, so that
+/// programs can be profiled without external tooling.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Instrumentation {
+    None,
+    /// Count the number of times each function is called. The counts are
+    /// exported as mutable globals named `$$CALLS$$.<function name>`, using
+    /// the same function names as the name section.
+    Calls,
+    /// Count the number of times each branch of every if, typecase and case
+    /// expression is taken. The counts are exported as mutable globals named
+    /// `$$COVERAGE$$.<id>`, and can be summarized with `coverage_report`.
+    Coverage,
+}
+
+impl Default for CodeGenerateOptions {
+    fn default() -> Self {
+        CodeGenerateOptions {
+            asserts: true,
+            exported_constants: Vector::new(),
+            instrument: Instrumentation::None,
+            limits: CompilerLimits::default(),
+            host_memory: false,
+        }
+    }
+}
+
 /// Maintains metadata used by code-generating functions.
 ///
 /// The code-generating functions (gen_instr_*) recursively call each other,
@@ -56,25 +191,115 @@ type SignaturesMap = BTreeMap<u32, u32>;
 ///    compiled
 /// b) the first free index within WebAssembly's linear memory safe to allocate
 ///    new data (tuples, records, etc.) to
+///
+/// TODO: every tuple expression (including closures and their environments,
+/// after closure conversion) is given a memory address at compile time, so
+/// nothing is allocated at runtime, and each evaluation of the expression
+/// overwrites the same memory. This is wrong for closures which escape the
+/// function that creates them (e.g. two counters returned by two calls to
+/// the same function share one environment). Once tuples which may escape
+/// are allocated at runtime instead, an escape analysis over the closure
+/// converted program (see `analysis::children` for walking it) could keep
+/// environments which never outlive their creating call at fixed addresses,
+/// or in locals, to avoid allocating in callback-heavy code.
 #[derive(Default)]
 pub struct CodeGenerateState {
     locals: LocalsMap,
     funcs: FuncsMap,
+    imports: ImportsMap,
     sigs: SignaturesMap,
     mem_index: u32,
+    options: CodeGenerateOptions,
+    coverage: Vec<String>,
 }
 
 impl CodeGenerateState {
     pub fn new() -> Self {
+        CodeGenerateState::with_options(CodeGenerateOptions::default())
+    }
+
+    pub fn with_options(options: CodeGenerateOptions) -> Self {
         CodeGenerateState {
             locals: LocalsMap::new(),
             funcs: FuncsMap::new(),
+            imports: ImportsMap::new(),
             sigs: SignaturesMap::new(),
             mem_index: 0,
+            options,
+            coverage: vec![],
         }
     }
 }
 
+/// Reserves a new local for a value which generated code stores temporarily,
+/// and returns its index. The local is named after its index rather than with
+/// `generate_var_name`, so that modules don't depend on how many names were
+/// generated before code generation started.
+fn add_temp_local(state: &mut CodeGenerateState) -> u32 {
+    let index = state.locals.len() as u32;
+    state.locals.insert(format!("$$temp{}$$", index), index);
+    index
+}
+
+/// Generate instructions which add one to the i32 global with the given
+/// index, e.g. for counting how many times some code runs.
+fn gen_instr_increment_global(index: u32) -> Vec<Instruction> {
+    vec![
+        Instruction::GetGlobal(index),
+        Instruction::I32Const(1),
+        Instruction::I32Add,
+        Instruction::SetGlobal(index),
+    ]
+}
+
+/// Generate instructions which count how many times the code following them
+/// runs, if compiling with `Instrumentation::Coverage`. Otherwise, no
+/// instructions are generated.
+///
+/// Coverage counters are stored in globals after the exported constants, and
+/// `description` is saved to identify the counter in coverage reports.
+fn gen_instr_coverage(description: String, state: &mut CodeGenerateState) -> Vec<Instruction> {
+    if state.options.instrument != Instrumentation::Coverage {
+        return vec![];
+    }
+    let counter = (state.options.exported_constants.len() + state.coverage.len()) as u32;
+    // descriptions are stored one per line in the module
+    state.coverage.push(description.replace('\n', " "));
+    gen_instr_increment_global(counter)
+}
+
+/// Prints an expression for a coverage description, using the names which
+/// variables had in the source (see `resolve::original_name`).
+fn describe_exp(exp: &TypedExpr) -> String {
+    fn restore_names(exp: &TypedExpr) -> Option<Result<TypedExpr, CodeGenerateError>> {
+        let keep_type = |_typ: &Type| -> Option<Result<Type, CodeGenerateError>> { None };
+        let kind = match &*exp.kind {
+            ExprKind::Id(name) => ExprKind::Id(String::from(original_name(name))),
+            ExprKind::Set(name, val) => ExprKind::Set(
+                String::from(original_name(name)),
+                transform_typed_exp_recursive(val, restore_names, keep_type).ok()?,
+            ),
+            ExprKind::Let(bindings, body) => ExprKind::Let(
+                bindings
+                    .iter()
+                    .map(|(name, val)| {
+                        let val = transform_typed_exp_recursive(val, restore_names, keep_type);
+                        Some((String::from(original_name(name)), val.ok()?))
+                    })
+                    .collect::<Option<Vector<(String, TypedExpr)>>>()?,
+                transform_typed_exp_recursive(body, restore_names, keep_type).ok()?,
+            ),
+            _ => return None,
+        };
+        Some(Ok(TypedExpr::new(exp.typ.clone(), kind)))
+    }
+    let keep_type = |_typ: &Type| -> Option<Result<Type, CodeGenerateError>> { None };
+    match transform_typed_exp_recursive(exp, restore_names, keep_type) {
+        Ok(restored) => restored.to_string(),
+        Err(_) => exp.to_string(),
+    }
+}
+
 /// Generate instructions for a binop (binary operation) expression.
 fn gen_instr_binop(
     op: BinOp,
@@ -82,12 +307,18 @@ fn gen_instr_binop(
     arg2: &TypedExpr,
     state: &mut CodeGenerateState,
 ) -> Result<Vec<Instruction>, CodeGenerateError> {
+    if arg1.typ == Type::Float {
+        return gen_instr_float_binop(op, arg1, arg2, state);
+    }
     let arg1_instr = gen_instr(arg1, state)?;
     let arg2_instr = gen_instr(arg2, state)?;
     match op {
         BinOp::Add => Ok([arg1_instr, arg2_instr, vec![Instruction::I32Add]].concat()),
         BinOp::Subtract => Ok([arg1_instr, arg2_instr, vec![Instruction::I32Sub]].concat()),
         BinOp::Multiply => Ok([arg1_instr, arg2_instr, vec![Instruction::I32Mul]].concat()),
+        // Division by zero (and overflowing division) traps, since there is no
+        // way to recover from it. Division by a literal zero is rejected
+        // during type checking instead.
         BinOp::Divide => Ok([arg1_instr, arg2_instr, vec![Instruction::I32DivS]].concat()),
         BinOp::LessThan => Ok([arg1_instr, arg2_instr, vec![Instruction::I32LtS]].concat()),
         BinOp::GreaterThan => Ok([arg1_instr, arg2_instr, vec![Instruction::I32GtS]].concat()),
@@ -97,7 +328,201 @@ fn gen_instr_binop(
         BinOp::And => Ok([arg1_instr, arg2_instr, vec![Instruction::I32And]].concat()),
         BinOp::Or => Ok([arg1_instr, arg2_instr, vec![Instruction::I32Or]].concat()),
         BinOp::Concat => Err(CodeGenerateError::from("Unhandled binop: concat.")),
+        BinOp::Expt => Ok([arg1_instr, arg2_instr, gen_instr_int_expt(state)].concat()),
+        BinOp::UnsignedLessThan => Ok([arg1_instr, arg2_instr, vec![Instruction::I32LtU]].concat()),
+        BinOp::UnsignedGreaterThan => {
+            Ok([arg1_instr, arg2_instr, vec![Instruction::I32GtU]].concat())
+        }
+        BinOp::UnsignedDivide => Ok([arg1_instr, arg2_instr, vec![Instruction::I32DivU]].concat()),
+        BinOp::UnsignedRemainder => {
+            Ok([arg1_instr, arg2_instr, vec![Instruction::I32RemU]].concat())
+        }
+    }
+}
+
+/// Generate instructions which raise an int to the power of another int (both
+/// on the stack), by repeated squaring. Like the other arithmetic operations
+/// on ints, the result wraps around on overflow. Negative powers trap, since
+/// their results generally aren't ints.
+fn gen_instr_int_expt(state: &mut CodeGenerateState) -> Vec<Instruction> {
+    let base = add_temp_local(state);
+    let power = add_temp_local(state);
+    let result = add_temp_local(state);
+    vec![
+        Instruction::SetLocal(power),
+        Instruction::SetLocal(base),
+        Instruction::GetLocal(power),
+        Instruction::I32Const(0),
+        Instruction::I32LtS,
+        Instruction::If(BlockType::NoResult),
+        Instruction::Unreachable,
+        Instruction::End,
+        Instruction::I32Const(1),
+        Instruction::SetLocal(result),
+        Instruction::Block(BlockType::NoResult),
+        Instruction::Loop(BlockType::NoResult),
+        Instruction::GetLocal(power),
+        Instruction::I32Eqz,
+        Instruction::BrIf(1),
+        // multiply the result by the base for each set bit of the power
+        Instruction::GetLocal(power),
+        Instruction::I32Const(1),
+        Instruction::I32And,
+        Instruction::If(BlockType::NoResult),
+        Instruction::GetLocal(result),
+        Instruction::GetLocal(base),
+        Instruction::I32Mul,
+        Instruction::SetLocal(result),
+        Instruction::End,
+        Instruction::GetLocal(base),
+        Instruction::GetLocal(base),
+        Instruction::I32Mul,
+        Instruction::SetLocal(base),
+        Instruction::GetLocal(power),
+        Instruction::I32Const(1),
+        Instruction::I32ShrU,
+        Instruction::SetLocal(power),
+        Instruction::Br(0),
+        Instruction::End,
+        Instruction::End,
+        Instruction::GetLocal(result),
+    ]
+}
+
+/// Generate instructions for a binary operation on two floats.
+///
+/// Like all other values, floats are stored in i32s (as their bits), so that
+/// locals, tuples, and function parameters don't depend on the types of their
+/// values. They are reinterpreted as f32s to operate on them.
+fn gen_instr_float_binop(
+    op: BinOp,
+    arg1: &TypedExpr,
+    arg2: &TypedExpr,
+    state: &mut CodeGenerateState,
+) -> Result<Vec<Instruction>, CodeGenerateError> {
+    // wasm has no instruction for powers of floats, so they are computed by
+    // the host, like builtins (see `find_imports`)
+    if op == BinOp::Expt {
+        return gen_instr_import_call("env", "expt", &vector![arg1.clone(), arg2.clone()], state);
+    }
+    let (op_instr, returns_float) = match op {
+        BinOp::Add => (Instruction::F32Add, true),
+        BinOp::Subtract => (Instruction::F32Sub, true),
+        BinOp::Multiply => (Instruction::F32Mul, true),
+        // division by zero produces an infinity or NaN, instead of trapping
+        BinOp::Divide => (Instruction::F32Div, true),
+        BinOp::LessThan => (Instruction::F32Lt, false),
+        BinOp::GreaterThan => (Instruction::F32Gt, false),
+        BinOp::LessOrEqual => (Instruction::F32Le, false),
+        BinOp::GreaterOrEqual => (Instruction::F32Ge, false),
+        BinOp::EqualTo => (Instruction::F32Eq, false),
+        BinOp::And
+        | BinOp::Or
+        | BinOp::Concat
+        | BinOp::Expt
+        | BinOp::UnsignedLessThan
+        | BinOp::UnsignedGreaterThan
+        | BinOp::UnsignedDivide
+        | BinOp::UnsignedRemainder => {
+            return Err(CodeGenerateError::Other(format!(
+                "Unhandled binop on floats: {}.",
+                op
+            )))
+        }
+    };
+    let mut instr = gen_instr(arg1, state)?;
+    instr.push(Instruction::F32ReinterpretI32);
+    instr.extend(gen_instr(arg2, state)?);
+    instr.push(Instruction::F32ReinterpretI32);
+    instr.push(op_instr);
+    if returns_float {
+        instr.push(Instruction::I32ReinterpretF32);
     }
+    Ok(instr)
+}
+
+/// Generate instructions for an operation on a single value.
+fn gen_instr_unop(
+    op: UnOp,
+    arg: &TypedExpr,
+    state: &mut CodeGenerateState,
+) -> Result<Vec<Instruction>, CodeGenerateError> {
+    let mut instr = gen_instr(arg, state)?;
+    match op {
+        UnOp::ExactToInexact | UnOp::IntToFloat => {
+            instr.extend(vec![
+                Instruction::F32ConvertSI32,
+                Instruction::I32ReinterpretF32,
+            ]);
+        }
+        // The conversion traps if the float isn't an integer (including NaN
+        // and infinities), and i32.trunc_s itself traps if it is out of range.
+        UnOp::InexactToExact => {
+            let temp = add_temp_local(state);
+            instr.extend(vec![
+                Instruction::SetLocal(temp),
+                Instruction::GetLocal(temp),
+                Instruction::F32ReinterpretI32,
+                Instruction::F32Trunc,
+                Instruction::GetLocal(temp),
+                Instruction::F32ReinterpretI32,
+                Instruction::F32Ne,
+                Instruction::If(BlockType::NoResult),
+                Instruction::Unreachable,
+                Instruction::End,
+                Instruction::GetLocal(temp),
+                Instruction::F32ReinterpretI32,
+                Instruction::I32TruncSF32,
+            ]);
+        }
+        // i32.trunc_s traps on NaN and out of range values
+        UnOp::FloatToInt => {
+            instr.extend(vec![
+                Instruction::F32ReinterpretI32,
+                Instruction::I32TruncSF32,
+            ]);
+        }
+        UnOp::Round | UnOp::Floor | UnOp::Ceiling | UnOp::Truncate => {
+            let rounding = match op {
+                UnOp::Round => Instruction::F32Nearest,
+                UnOp::Floor => Instruction::F32Floor,
+                UnOp::Ceiling => Instruction::F32Ceil,
+                _ => Instruction::F32Trunc,
+            };
+            instr.extend(vec![
+                Instruction::F32ReinterpretI32,
+                rounding,
+                Instruction::I32ReinterpretF32,
+            ]);
+        }
+        UnOp::Sqrt => {
+            instr.extend(vec![
+                Instruction::F32ReinterpretI32,
+                Instruction::F32Sqrt,
+                Instruction::I32ReinterpretF32,
+            ]);
+        }
+        // NaN is the only float which isn't equal to itself
+        UnOp::IsNan => {
+            let temp = add_temp_local(state);
+            instr.extend(vec![
+                Instruction::TeeLocal(temp),
+                Instruction::F32ReinterpretI32,
+                Instruction::GetLocal(temp),
+                Instruction::F32ReinterpretI32,
+                Instruction::F32Ne,
+            ]);
+        }
+        UnOp::IsInfinite => {
+            instr.extend(vec![
+                Instruction::F32ReinterpretI32,
+                Instruction::F32Abs,
+                Instruction::F32Const(f32::INFINITY.to_bits()),
+                Instruction::F32Eq,
+            ]);
+        }
+    }
+    Ok(instr)
 }
 
 /// Generate instructions for an if expression.
@@ -108,7 +533,15 @@ fn gen_instr_if(
     state: &mut CodeGenerateState,
 ) -> Result<Vec<Instruction>, CodeGenerateError> {
     let pred_instr = gen_instr(pred, state)?;
+    let cons_coverage = gen_instr_coverage(
+        format!("then branch of (if {} ...)", describe_exp(pred)),
+        state,
+    );
     let cons_instr = gen_instr(cons, state)?;
+    let alt_coverage = gen_instr_coverage(
+        format!("else branch of (if {} ...)", describe_exp(pred)),
+        state,
+    );
     let alt_instr = gen_instr(alt, state)?;
 
     // In WebAssembly, if-expressions must be given a type annotation of the
@@ -122,8 +555,10 @@ fn gen_instr_if(
     Ok([
         pred_instr,
         vec![Instruction::If(block_type)],
+        cons_coverage,
         cons_instr,
         vec![Instruction::Else],
+        alt_coverage,
         alt_instr,
         vec![Instruction::End],
     ]
@@ -185,6 +620,396 @@ fn gen_instr_begin(
     Ok(begin_instr)
 }
 
+/// Generate instructions for an assert expression.
+///
+/// If the condition is false, execution traps. WebAssembly traps cannot carry
+/// a message, so the assert's message is not reported at runtime.
+fn gen_instr_assert(
+    exp: &TypedExpr,
+    state: &mut CodeGenerateState,
+) -> Result<Vec<Instruction>, CodeGenerateError> {
+    if !state.options.asserts {
+        return Ok(vec![Instruction::I32Const(1)]);
+    }
+    let exp_instr = gen_instr(exp, state)?;
+    Ok([
+        exp_instr,
+        vec![
+            Instruction::I32Eqz,
+            Instruction::If(BlockType::NoResult),
+            Instruction::Unreachable,
+            Instruction::End,
+            Instruction::I32Const(1),
+        ],
+    ]
+    .concat())
+}
+
+/// Generate instructions for a while loop.
+///
+/// The loop is a block containing a loop, so that branching to the block
+/// exits the loop and branching to the loop starts the next iteration. The
+/// body's value is dropped, and the loop produces a placeholder value for the
+/// unit type (which is never inspected).
+fn gen_instr_while(
+    pred: &TypedExpr,
+    body: &TypedExpr,
+    state: &mut CodeGenerateState,
+) -> Result<Vec<Instruction>, CodeGenerateError> {
+    let pred_instr = gen_instr(pred, state)?;
+    let body_instr = gen_instr(body, state)?;
+    Ok([
+        vec![
+            Instruction::Block(BlockType::NoResult),
+            Instruction::Loop(BlockType::NoResult),
+        ],
+        pred_instr,
+        vec![Instruction::I32Eqz, Instruction::BrIf(1)],
+        body_instr,
+        vec![
+            Instruction::Drop,
+            Instruction::Br(0),
+            Instruction::End,
+            Instruction::End,
+            Instruction::I32Const(0),
+        ],
+    ]
+    .concat())
+}
+
+/// Generate instructions for forcing a promise.
+///
+/// After closure conversion, a promise is a tuple of a flag for whether it has
+/// been forced already, and a closure which computes its value (see
+/// `closure_convert::cc_delay`). The first time a promise is forced, the
+/// closure is called, and its result is stored in place of the closure so
+/// that later forces just load the result.
+fn gen_instr_force(
+    promise: &TypedExpr,
+    state: &mut CodeGenerateState,
+) -> Result<Vec<Instruction>, CodeGenerateError> {
+    let mut force_instr = gen_instr(promise, state)?;
+    let promise_local_index = add_temp_local(state);
+    let result_local_index = add_temp_local(state);
+    let sig_index = match state.sigs.get(&1) {
+        Some(val) => *val,
+        None => return Err(CodeGenerateError::from("Signature index not found!")),
+    };
+
+    force_instr.append(&mut vec![
+        Instruction::SetLocal(promise_local_index),
+        Instruction::GetLocal(promise_local_index),
+        Instruction::I32Load(0, 0),
+        Instruction::If(BlockType::Value(ValueType::I32)),
+        Instruction::GetLocal(promise_local_index),
+        Instruction::I32Load(0, 4),
+        Instruction::Else,
+        // call the closure with its environment as the only argument
+        Instruction::GetLocal(promise_local_index),
+        Instruction::I32Load(0, 4),
+        Instruction::I32Load(0, 4),
+        Instruction::GetLocal(promise_local_index),
+        Instruction::I32Load(0, 4),
+        Instruction::I32Load(0, 0),
+        Instruction::CallIndirect(sig_index, 0),
+        Instruction::SetLocal(result_local_index),
+        // memoize the result
+        Instruction::GetLocal(promise_local_index),
+        Instruction::GetLocal(result_local_index),
+        Instruction::I32Store(0, 4),
+        Instruction::GetLocal(promise_local_index),
+        Instruction::I32Const(1),
+        Instruction::I32Store(0, 0),
+        Instruction::GetLocal(result_local_index),
+        Instruction::End,
+    ]);
+    Ok(force_instr)
+}
+
+/// Generate instructions for calling a builtin, which is imported from the
+/// host environment.
+fn gen_instr_builtin(
+    builtin: Builtin,
+    args: &Vector<TypedExpr>,
+    state: &mut CodeGenerateState,
+) -> Result<Vec<Instruction>, CodeGenerateError> {
+    let (param_types, ret_type) = builtin.signature();
+    if param_types
+        .iter()
+        .chain(std::iter::once(&ret_type))
+        .any(contains_strings)
+    {
+        return Err(CodeGenerateError::UnsupportedConstruct(
+            String::from("Builtins which use strings are not supported."),
+            TypedExpr::new(ret_type, ExprKind::Builtin(builtin, args.clone())),
+        ));
+    }
+    gen_instr_import_call("env", &builtin.to_string(), args, state)
+}
+
+/// Generate instructions for calling a function declared with
+/// `declare-extern`, which is imported from the host environment.
+fn gen_instr_extern(
+    module: &str,
+    name: &str,
+    typ: &Type,
+    args: &Vector<TypedExpr>,
+    state: &mut CodeGenerateState,
+) -> Result<Vec<Instruction>, CodeGenerateError> {
+    if contains_strings(typ) {
+        let ret_type = match typ {
+            Type::Func(_param_types, ret_type, _effect) => (**ret_type).clone(),
+            _ => Type::Unknown,
+        };
+        return Err(CodeGenerateError::UnsupportedConstruct(
+            String::from("Extern functions which use strings are not supported."),
+            TypedExpr::new(
+                ret_type,
+                ExprKind::Extern(
+                    String::from(module),
+                    String::from(name),
+                    typ.clone(),
+                    args.clone(),
+                ),
+            ),
+        ));
+    }
+    gen_instr_import_call(module, name, args, state)
+}
+
+fn gen_instr_import_call(
+    module: &str,
+    name: &str,
+    args: &Vector<TypedExpr>,
+    state: &mut CodeGenerateState,
+) -> Result<Vec<Instruction>, CodeGenerateError> {
+    let mut call_instr: Vec<Instruction> = vec![];
+    for arg in args {
+        call_instr.append(&mut gen_instr(arg, state)?);
+    }
+    let func_index = match state
+        .imports
+        .get(&(String::from(module), String::from(name)))
+    {
+        Some(val) => *val,
+        None => {
+            return Err(CodeGenerateError::Other(format!(
+                "Function {}.{} was not imported.",
+                module, name
+            )))
+        }
+    };
+    call_instr.push(Instruction::Call(func_index));
+    Ok(call_instr)
+}
+
+/// Generate instructions for a wasm-asm expression: the arguments are pushed
+/// onto the stack in order, followed by the expression's own instructions
+/// (see `parse_wasm_asm`). The instructions should leave a single i32 on the
+/// stack; this isn't checked here, but when the module is validated.
+///
+/// TODO: an inline Rust form like `(rust "expr using {x}" : type x)` would
+/// need a backend which emits Rust source. Only wasm is emitted, so wasm-asm
+/// and extern functions are the only ways to reach the host.
+fn gen_instr_wasm_asm(
+    code: &str,
+    args: &Vector<TypedExpr>,
+    state: &mut CodeGenerateState,
+) -> Result<Vec<Instruction>, CodeGenerateError> {
+    let mut instructions = vec![];
+    for arg in args.iter() {
+        instructions.append(&mut gen_instr(arg, state)?);
+    }
+    instructions.append(&mut parse_wasm_asm(code)?);
+    Ok(instructions)
+}
+
+/// Parses the instructions of a wasm-asm expression, written in the wasm
+/// text format. Instructions can be written one after another (e.g.
+/// `"i64.extend_i32_s i64.const 3 i64.mul i32.wrap_i64"`), or folded, with
+/// their operands inside parentheses after them (e.g.
+/// `"(i32.add (i32.const 1))"`).
+///
+/// Only instructions which operate on the stack are supported: i32 and i64
+/// arithmetic and comparisons, conversions between the two, `drop`,
+/// `select`, `nop`, `unreachable`, i32 loads and stores (with no offset),
+/// `memory.size` and `memory.grow`. Locals, globals, and control flow can't
+/// be used, since the instructions can't know how the surrounding code uses
+/// them.
+pub fn parse_wasm_asm(code: &str) -> Result<Vec<Instruction>, CodeGenerateError> {
+    let value = lexpr::from_str(&format!("({})", code)).map_err(|err| {
+        CodeGenerateError::Other(format!("Invalid wasm-asm instructions: {}", err))
+    })?;
+    let mut instructions = vec![];
+    parse_wasm_asm_sequence(&value.to_vec().unwrap_or_default(), &mut instructions)?;
+    Ok(instructions)
+}
+
+/// Parses a sequence of (flat or folded) instructions, appending them to
+/// `instructions`.
+fn parse_wasm_asm_sequence(
+    values: &[lexpr::Value],
+    instructions: &mut Vec<Instruction>,
+) -> Result<(), CodeGenerateError> {
+    let mut i = 0;
+    while i < values.len() {
+        match &values[i] {
+            lexpr::Value::Symbol(name) => {
+                let (instruction, num_immediates) =
+                    parse_wasm_asm_instruction(name, values.get(i + 1))?;
+                instructions.push(instruction);
+                i += 1 + num_immediates;
+            }
+            lexpr::Value::Cons(_) => {
+                // a folded instruction's operands come before it
+                let lst = values[i].to_vec().unwrap_or_default();
+                let name = lst.first().and_then(|val| val.as_symbol()).ok_or_else(|| {
+                    CodeGenerateError::Other(format!(
+                        "Folded wasm-asm instruction does not start with a name: {}",
+                        values[i]
+                    ))
+                })?;
+                let (instruction, num_immediates) = parse_wasm_asm_instruction(name, lst.get(1))?;
+                parse_wasm_asm_sequence(&lst[1 + num_immediates..], instructions)?;
+                instructions.push(instruction);
+                i += 1;
+            }
+            other => {
+                return Err(CodeGenerateError::Other(format!(
+                    "Unexpected value in wasm-asm instructions: {}",
+                    other
+                )))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Parses a single instruction given its name and the value after it,
+/// returning the instruction and how many immediate values it used.
+fn parse_wasm_asm_instruction(
+    name: &str,
+    next: Option<&lexpr::Value>,
+) -> Result<(Instruction, usize), CodeGenerateError> {
+    let instruction = match name {
+        "i32.const" | "i64.const" => {
+            let val = next.and_then(|val| val.as_i64()).ok_or_else(|| {
+                CodeGenerateError::Other(format!("Wasm-asm {} needs an integer.", name))
+            })?;
+            let instruction = if name == "i32.const" {
+                let val = i32::try_from(val).map_err(|_| {
+                    CodeGenerateError::Other(format!("Wasm-asm constant {} is too large.", val))
+                })?;
+                Instruction::I32Const(val)
+            } else {
+                Instruction::I64Const(val)
+            };
+            return Ok((instruction, 1));
+        }
+        "i32.add" => Instruction::I32Add,
+        "i32.sub" => Instruction::I32Sub,
+        "i32.mul" => Instruction::I32Mul,
+        "i32.div_s" => Instruction::I32DivS,
+        "i32.div_u" => Instruction::I32DivU,
+        "i32.rem_s" => Instruction::I32RemS,
+        "i32.rem_u" => Instruction::I32RemU,
+        "i32.and" => Instruction::I32And,
+        "i32.or" => Instruction::I32Or,
+        "i32.xor" => Instruction::I32Xor,
+        "i32.shl" => Instruction::I32Shl,
+        "i32.shr_s" => Instruction::I32ShrS,
+        "i32.shr_u" => Instruction::I32ShrU,
+        "i32.rotl" => Instruction::I32Rotl,
+        "i32.rotr" => Instruction::I32Rotr,
+        "i32.clz" => Instruction::I32Clz,
+        "i32.ctz" => Instruction::I32Ctz,
+        "i32.popcnt" => Instruction::I32Popcnt,
+        "i32.eqz" => Instruction::I32Eqz,
+        "i32.eq" => Instruction::I32Eq,
+        "i32.ne" => Instruction::I32Ne,
+        "i32.lt_s" => Instruction::I32LtS,
+        "i32.lt_u" => Instruction::I32LtU,
+        "i32.gt_s" => Instruction::I32GtS,
+        "i32.gt_u" => Instruction::I32GtU,
+        "i32.le_s" => Instruction::I32LeS,
+        "i32.le_u" => Instruction::I32LeU,
+        "i32.ge_s" => Instruction::I32GeS,
+        "i32.ge_u" => Instruction::I32GeU,
+        "i64.add" => Instruction::I64Add,
+        "i64.sub" => Instruction::I64Sub,
+        "i64.mul" => Instruction::I64Mul,
+        "i64.div_s" => Instruction::I64DivS,
+        "i64.div_u" => Instruction::I64DivU,
+        "i64.rem_s" => Instruction::I64RemS,
+        "i64.rem_u" => Instruction::I64RemU,
+        "i64.and" => Instruction::I64And,
+        "i64.or" => Instruction::I64Or,
+        "i64.xor" => Instruction::I64Xor,
+        "i64.shl" => Instruction::I64Shl,
+        "i64.shr_s" => Instruction::I64ShrS,
+        "i64.shr_u" => Instruction::I64ShrU,
+        "i64.rotl" => Instruction::I64Rotl,
+        "i64.rotr" => Instruction::I64Rotr,
+        "i64.clz" => Instruction::I64Clz,
+        "i64.ctz" => Instruction::I64Ctz,
+        "i64.popcnt" => Instruction::I64Popcnt,
+        "i64.eqz" => Instruction::I64Eqz,
+        "i64.eq" => Instruction::I64Eq,
+        "i64.ne" => Instruction::I64Ne,
+        "i64.lt_s" => Instruction::I64LtS,
+        "i64.lt_u" => Instruction::I64LtU,
+        "i64.gt_s" => Instruction::I64GtS,
+        "i64.gt_u" => Instruction::I64GtU,
+        "i64.le_s" => Instruction::I64LeS,
+        "i64.le_u" => Instruction::I64LeU,
+        "i64.ge_s" => Instruction::I64GeS,
+        "i64.ge_u" => Instruction::I64GeU,
+        "i32.wrap_i64" => Instruction::I32WrapI64,
+        "i64.extend_i32_s" => Instruction::I64ExtendSI32,
+        "i64.extend_i32_u" => Instruction::I64ExtendUI32,
+        "i32.load" => Instruction::I32Load(2, 0),
+        "i32.load8_s" => Instruction::I32Load8S(0, 0),
+        "i32.load8_u" => Instruction::I32Load8U(0, 0),
+        "i32.load16_s" => Instruction::I32Load16S(1, 0),
+        "i32.load16_u" => Instruction::I32Load16U(1, 0),
+        "i32.store" => Instruction::I32Store(2, 0),
+        "i32.store8" => Instruction::I32Store8(0, 0),
+        "i32.store16" => Instruction::I32Store16(1, 0),
+        "memory.size" => Instruction::CurrentMemory(0),
+        "memory.grow" => Instruction::GrowMemory(0),
+        "drop" => Instruction::Drop,
+        "select" => Instruction::Select,
+        "nop" => Instruction::Nop,
+        "unreachable" => Instruction::Unreachable,
+        _ => {
+            return Err(CodeGenerateError::Other(format!(
+                "Unsupported wasm-asm instruction: {}",
+                name
+            )))
+        }
+    };
+    Ok((instruction, 0))
+}
+
+/// Returns whether values of the given type contain strings, which can't be
+/// represented in generated code yet.
+fn contains_strings(typ: &Type) -> bool {
+    match typ {
+        Type::Str => true,
+        Type::List(base_typ) | Type::Promise(base_typ) | Type::Exists(_, base_typ) => {
+            contains_strings(base_typ)
+        }
+        Type::Func(in_typs, ret_typ, _) => {
+            in_typs.iter().any(contains_strings) || contains_strings(ret_typ)
+        }
+        Type::Tuple(typs) | Type::Union(typs) => typs.iter().any(contains_strings),
+        Type::Record(fields) | Type::OpenRecord(fields, _) => {
+            fields.iter().any(|field| contains_strings(&field.1))
+        }
+        Type::Int | Type::Float | Type::Bool | Type::TypeVar(_) | Type::Unknown => false,
+    }
+}
+
 /// Generate instructions for a set! expression.
 fn gen_instr_set(
     sym: &str,
@@ -309,14 +1134,46 @@ fn gen_instr_tuple_get(
         // of the tuple.
         Type::Tuple(_inner_types) => tuple_get_instr.push(Instruction::I32Load(0, 4 * key)),
         _ => {
-            return Err(CodeGenerateError::from(
-                "get_instr_tuple_get called with non-tuple expression.",
+            return Err(CodeGenerateError::TypeUnknownAtCodegen(
+                String::from("Expected a tuple in tuple-ref."),
+                tuple.typ.clone(),
             ))
         }
     }
     Ok([tuple_instr, tuple_get_instr].concat())
 }
 
+/// Generate instructions for a tuple-set! expression.
+///
+/// The new value is stored in a local, so that it can be left on the stack
+/// as the value of the expression after it's written to the tuple.
+fn gen_instr_tuple_set(
+    tuple: &TypedExpr,
+    key: u32,
+    val: &TypedExpr,
+    state: &mut CodeGenerateState,
+) -> Result<Vec<Instruction>, CodeGenerateError> {
+    match &tuple.typ {
+        Type::Tuple(_inner_types) => (),
+        _ => {
+            return Err(CodeGenerateError::TypeUnknownAtCodegen(
+                String::from("Expected a tuple in tuple-set!."),
+                tuple.typ.clone(),
+            ))
+        }
+    }
+    let tuple_instr = gen_instr(tuple, state)?;
+    let val_instr = gen_instr(val, state)?;
+    let val_local_index = add_temp_local(state);
+    let set_instr = vec![
+        Instruction::SetLocal(val_local_index),
+        Instruction::GetLocal(val_local_index),
+        Instruction::I32Store(0, 4 * key),
+        Instruction::GetLocal(val_local_index),
+    ];
+    Ok([tuple_instr, val_instr, set_instr].concat())
+}
+
 /// Generate instructions for a cons expression.
 ///
 /// A List expression is stored as simply a pair of values: a car (sometimes
@@ -481,6 +1338,208 @@ fn gen_instr_unpack(
     Ok([let_instr, body_instr].concat())
 }
 
+/// Generate instructions for an inject expression.
+///
+/// Values of a union type are stored as a pair of a tag and the value itself,
+/// where the tag is the index of the value's type within the union type. We
+/// construct this pair the same way as a two-element tuple.
+fn gen_instr_inject(
+    val: &TypedExpr,
+    union_typ: &Type,
+    state: &mut CodeGenerateState,
+) -> Result<Vec<Instruction>, CodeGenerateError> {
+    let tag = get_union_tag(union_typ, &val.typ)?;
+    let tag_exp = TypedExpr::new(Type::Int, ExprKind::Num(tag));
+    gen_instr_tuple(&vector![tag_exp, val.clone()], state)
+}
+
+/// Generate instructions for a typecase expression.
+///
+/// The union value is stored in a fresh local variable, and then its tag is
+/// compared against the tag of each clause's type in a chain of if-else
+/// blocks. Once a clause matches, the value inside the union is bound to the
+/// clause's variable and the body is evaluated.
+///
+/// We can assume from the type checking that every tag is handled by some
+/// clause, so the last clause does not need to check the tag.
+fn gen_instr_typecase(
+    val: &TypedExpr,
+    clauses: &Vector<(Type, String, TypedExpr)>,
+    state: &mut CodeGenerateState,
+) -> Result<Vec<Instruction>, CodeGenerateError> {
+    let mut typecase_instr = gen_instr(val, state)?;
+    let union_local_index = add_temp_local(state);
+    typecase_instr.push(Instruction::SetLocal(union_local_index));
+
+    let block_type = BlockType::Value(ValueType::I32);
+    for (i, (typ, var, body)) in clauses.iter().enumerate() {
+        let is_last_clause = i == clauses.len() - 1;
+        if !is_last_clause {
+            let tag = get_union_tag(&val.typ, typ)?;
+            typecase_instr.append(&mut vec![
+                Instruction::GetLocal(union_local_index),
+                Instruction::I32Load(0, 0),
+                Instruction::I32Const(tag),
+                Instruction::I32Eq,
+                Instruction::If(block_type),
+            ]);
+        }
+        // Clauses often reuse the same variable name, so each clause gets its
+        // own local slot (reserved under a fresh name), and any outer binding
+        // of the variable is restored after the clause body.
+        let var_local_index = add_temp_local(state);
+        let outer_local_index = state.locals.insert(var.clone(), var_local_index);
+        typecase_instr.append(&mut vec![
+            Instruction::GetLocal(union_local_index),
+            Instruction::I32Load(0, 4),
+            Instruction::SetLocal(var_local_index),
+        ]);
+        let description = format!("{} clause of (typecase {} ...)", typ, describe_exp(val));
+        typecase_instr.append(&mut gen_instr_coverage(description, state));
+        typecase_instr.append(&mut gen_instr(body, state)?);
+        match outer_local_index {
+            Some(index) => state.locals.insert(var.clone(), index),
+            None => state.locals.remove(var),
+        };
+        if !is_last_clause {
+            typecase_instr.push(Instruction::Else);
+        }
+    }
+    for _ in 1..clauses.len() {
+        typecase_instr.push(Instruction::End);
+    }
+    Ok(typecase_instr)
+}
+
+/// The fewest datums for which a case expression is compiled to a jump table.
+const MIN_JUMP_TABLE_DATUMS: usize = 3;
+
+/// The most entries in a case expression's jump table.
+const MAX_JUMP_TABLE_SIZE: i64 = 1024;
+
+/// Generate instructions for a case expression.
+///
+/// The key is stored in a fresh local variable. If the datums are dense
+/// enough (they fill at least half of the range from the smallest to the
+/// largest), the clause is chosen with a single `br_table` indexed by the key
+/// minus the smallest datum, otherwise the key is compared against the datums
+/// of each clause in a chain of if-else blocks, like a typecase.
+fn gen_instr_case(
+    key: &TypedExpr,
+    clauses: &Vector<(Vector<i32>, TypedExpr)>,
+    else_body: &TypedExpr,
+    state: &mut CodeGenerateState,
+) -> Result<Vec<Instruction>, CodeGenerateError> {
+    let mut case_instr = gen_instr(key, state)?;
+    let key_local_index = add_temp_local(state);
+    case_instr.push(Instruction::SetLocal(key_local_index));
+
+    let datums: Vec<i32> = clauses
+        .iter()
+        .flat_map(|(datums, _body)| datums.iter().copied())
+        .collect();
+    let min = datums.iter().copied().min().unwrap_or(0);
+    let max = datums.iter().copied().max().unwrap_or(0);
+    let size = i64::from(max) - i64::from(min) + 1;
+    let is_dense = datums.len() >= MIN_JUMP_TABLE_DATUMS
+        && size <= MAX_JUMP_TABLE_SIZE
+        && size <= 2 * datums.len() as i64;
+
+    let mut clause_instrs = vec![];
+    for (datums, body) in clauses.iter() {
+        let description = format!(
+            "({}) clause of (case {} ...)",
+            format_vector(datums.clone()),
+            describe_exp(key)
+        );
+        let mut clause_instr = gen_instr_coverage(description, state);
+        clause_instr.append(&mut gen_instr(body, state)?);
+        clause_instrs.push(clause_instr);
+    }
+    let description = format!("else clause of (case {} ...)", describe_exp(key));
+    let mut else_instr = gen_instr_coverage(description, state);
+    else_instr.append(&mut gen_instr(else_body, state)?);
+
+    let block_type = BlockType::Value(ValueType::I32);
+    if is_dense {
+        // Each clause's body follows the end of its own block, so branching
+        // to the i-th block runs the i-th body, and branching past all of them
+        // runs the else body. Clause bodies then branch to the end of the
+        // outermost block, which produces the value of the case expression.
+        let mut table = vec![clauses.len() as u32; size as usize];
+        for (i, (datums, _body)) in clauses.iter().enumerate() {
+            for datum in datums {
+                table[(i64::from(*datum) - i64::from(min)) as usize] = i as u32;
+            }
+        }
+        case_instr.push(Instruction::Block(block_type));
+        for _ in 0..=clauses.len() {
+            case_instr.push(Instruction::Block(BlockType::NoResult));
+        }
+        // keys outside of the range wrap around to large indices, which are
+        // out of bounds of the table and so go to the else body
+        case_instr.append(&mut vec![
+            Instruction::GetLocal(key_local_index),
+            Instruction::I32Const(min),
+            Instruction::I32Sub,
+            Instruction::BrTable(Box::new(BrTableData {
+                table: table.into_boxed_slice(),
+                default: clauses.len() as u32,
+            })),
+        ]);
+        for (i, mut clause_instr) in clause_instrs.into_iter().enumerate() {
+            case_instr.push(Instruction::End);
+            case_instr.append(&mut clause_instr);
+            case_instr.push(Instruction::Br((clauses.len() - i) as u32));
+        }
+        case_instr.push(Instruction::End);
+        case_instr.append(&mut else_instr);
+        case_instr.push(Instruction::End);
+    } else {
+        for ((datums, _body), mut clause_instr) in clauses.iter().zip(clause_instrs) {
+            for (j, datum) in datums.iter().enumerate() {
+                case_instr.append(&mut vec![
+                    Instruction::GetLocal(key_local_index),
+                    Instruction::I32Const(*datum),
+                    Instruction::I32Eq,
+                ]);
+                if j > 0 {
+                    case_instr.push(Instruction::I32Or);
+                }
+            }
+            case_instr.push(Instruction::If(block_type));
+            case_instr.append(&mut clause_instr);
+            case_instr.push(Instruction::Else);
+        }
+        case_instr.append(&mut else_instr);
+        for _ in 0..clauses.len() {
+            case_instr.push(Instruction::End);
+        }
+    }
+    Ok(case_instr)
+}
+
+/// Returns the tag used to represent values of type `typ` within the union
+/// type `union_typ`.
+fn get_union_tag(union_typ: &Type, typ: &Type) -> Result<i32, CodeGenerateError> {
+    match union_typ {
+        Type::Union(types) => types
+            .iter()
+            .position(|member_typ| member_typ == typ)
+            .map(|index| index as i32)
+            .ok_or_else(|| {
+                CodeGenerateError::TypeUnknownAtCodegen(
+                    format!("Type not found in union type {}.", union_typ),
+                    typ.clone(),
+                )
+            }),
+        _ => Err(CodeGenerateError::TypeUnknownAtCodegen(
+            String::from("Expected a union type when computing a union tag."),
+            union_typ.clone(),
+        )),
+    }
+}
+
 /// Generate instructions for a function application expression.
 ///
 /// Recall that as a result of lambda lifting, all lambda expressions will be
@@ -490,6 +1549,17 @@ fn gen_instr_unpack(
 /// our arguments onto the stack followed by the function index, and then use
 /// WebAssembly's CallIndirect to call the appropriate function in our table,
 /// consuming all of the arguments we provided.
+// TODO: every application uses a wasm call, so non-tail recursion that is
+// deep enough (e.g. over a long list) overflows the wasm call stack. An
+// opt-in trampolined mode would need continuations to be allocated in linear
+// memory, which first needs a CPS conversion pass and a garbage collector.
+//
+// TODO: compile self-recursive tail calls into a loop, branching back to the
+// start of the function after storing the new arguments in its parameters.
+// Functions can only recurse by calling a closure read from a variable which
+// they assign to themselves with set!, so a call never statically refers to
+// the function it's in; this needs letrec, so that lambda lifting can turn
+// calls to a letrec-bound lambda from its own body into direct calls.
 fn gen_instr_fn_app(
     func: &TypedExpr,
     args: &Vector<TypedExpr>,
@@ -518,30 +1588,40 @@ pub fn gen_instr(
 ) -> Result<Vec<Instruction>, CodeGenerateError> {
     let instructions: Result<Vec<Instruction>, CodeGenerateError> = match &*exp.kind {
         ExprKind::Num(x) => Ok(vec![Instruction::I32Const(*x)]),
+        ExprKind::Float(x) => Ok(vec![Instruction::I32Const(x.to_bits() as i32)]),
         ExprKind::Bool(x) => Ok(vec![Instruction::I32Const(*x as i32)]),
-        ExprKind::Str(_) => Err(CodeGenerateError::from("Unhandled gen_instr case: Str")),
+        ExprKind::Str(_) => Err(CodeGenerateError::UnsupportedConstruct(
+            String::from("Strings are not supported."),
+            exp.clone(),
+        )),
         ExprKind::Id(sym) => match state.locals.get(sym) {
             Some(local_idx) => Ok(vec![Instruction::GetLocal(*local_idx)]),
             None => match state.funcs.get(sym) {
                 Some(func_idx) => Ok(vec![Instruction::I32Const(*func_idx as i32)]),
-                None => {
-                    return Err(CodeGenerateError::from(
-                        "Symbol not found in locals or function table.",
-                    ))
-                }
+                None => return Err(CodeGenerateError::InvalidIdentifier(sym.clone())),
             },
         },
+        ExprKind::Binop(BinOp::Concat, _arg1, _arg2) => {
+            Err(CodeGenerateError::UnsupportedConstruct(
+                String::from("Strings are not supported."),
+                exp.clone(),
+            ))
+        }
         ExprKind::Binop(op, arg1, arg2) => Ok(gen_instr_binop(*op, &arg1, &arg2, state)?),
+        ExprKind::Unop(op, arg) => Ok(gen_instr_unop(*op, &arg, state)?),
         ExprKind::If(pred, cons, alt) => Ok(gen_instr_if(&pred, &cons, &alt, state)?),
         ExprKind::Let(bindings, body) => Ok(gen_instr_let(&bindings, &body, state)?),
-        ExprKind::Lambda(_params, _ret_type, _body) => Err(CodeGenerateError::from(
-            "Lambda expressions should have been hoisted to the top level via lambda lifting pass.",
+        ExprKind::Lambda(_params, _ret_type, _body) => Err(CodeGenerateError::UnsupportedConstruct(
+            String::from("Lambda expressions should have been hoisted to the top level via lambda lifting pass."),
+            exp.clone(),
         )),
-        ExprKind::Record(_bindings) => Err(CodeGenerateError::from(
-            "Record expressions should be removed via record conversion pass.",
+        ExprKind::Record(_bindings) => Err(CodeGenerateError::UnsupportedConstruct(
+            String::from("Record expressions should be removed via record conversion pass."),
+            exp.clone(),
         )),
-        ExprKind::RecordGet(_record, _key) => Err(CodeGenerateError::from(
-            "Record expressions should be removed via record conversion pass.",
+        ExprKind::RecordGet(_record, _key) => Err(CodeGenerateError::UnsupportedConstruct(
+            String::from("Record expressions should be removed via record conversion pass."),
+            exp.clone(),
         )),
         ExprKind::Begin(exps) => Ok(gen_instr_begin(&exps, state)?),
         ExprKind::Set(sym, exp) => Ok(gen_instr_set(&sym, &exp, state)?),
@@ -552,12 +1632,46 @@ pub fn gen_instr(
         ExprKind::Null(typ) => Ok(gen_instr_null(&typ, state)?),
         ExprKind::Tuple(exps) => Ok(gen_instr_tuple(&exps, state)?),
         ExprKind::TupleGet(tup, key) => Ok(gen_instr_tuple_get(&tup, *key, state)?),
+        ExprKind::TupleSet(tup, key, val) => Ok(gen_instr_tuple_set(&tup, *key, &val, state)?),
         ExprKind::Pack(val, sub, exist) => Ok(gen_instr_pack(&val, &sub, &exist, state)?),
         ExprKind::Unpack(var, package, type_sub, body) => {
             Ok(gen_instr_unpack(&var, &package, *type_sub, &body, state)?)
         }
+        ExprKind::Inject(val, union_typ) => Ok(gen_instr_inject(&val, &union_typ, state)?),
+        ExprKind::TypeCase(val, clauses) => Ok(gen_instr_typecase(&val, &clauses, state)?),
+        ExprKind::Assert(val, _message) => Ok(gen_instr_assert(&val, state)?),
+        ExprKind::While(pred, body) => Ok(gen_instr_while(&pred, &body, state)?),
+        ExprKind::Case(key, clauses, else_body) => {
+            Ok(gen_instr_case(key, clauses, else_body, state)?)
+        }
+        ExprKind::Curry(_func, _args) => Err(CodeGenerateError::UnsupportedConstruct(
+            String::from("Curry expressions should be converted to closures via closure conversion pass."),
+            exp.clone(),
+        )),
+        ExprKind::Compose(_outer, _inner) => Err(CodeGenerateError::UnsupportedConstruct(
+            String::from("Compose expressions should be converted to closures via closure conversion pass."),
+            exp.clone(),
+        )),
+        ExprKind::Delay(_val) => Err(CodeGenerateError::UnsupportedConstruct(
+            String::from("Delay expressions should be converted to closures via closure conversion pass."),
+            exp.clone(),
+        )),
+        ExprKind::Force(promise) => Ok(gen_instr_force(&promise, state)?),
+        ExprKind::Builtin(builtin, args) => Ok(gen_instr_builtin(*builtin, &args, state)?),
+        ExprKind::WasmAsm(code, _typ, args) => gen_instr_wasm_asm(code, &args, state),
+        ExprKind::Extern(module, name, typ, args) => {
+            Ok(gen_instr_extern(module, name, typ, &args, state)?)
+        }
+        ExprKind::Format(_control, _args) => Err(CodeGenerateError::UnsupportedConstruct(
+            String::from("Format expressions are not supported."),
+            exp.clone(),
+        )),
         ExprKind::FnApp(func, args) => Ok(gen_instr_fn_app(&func, &args, state)?),
     };
+    #[cfg(feature = "trace-exprs")]
+    if let Ok(instructions) = &instructions {
+        tracing::debug!(%exp, ?instructions, "generated");
+    }
     Ok(instructions?)
 }
 
@@ -604,13 +1718,48 @@ pub fn construct_module(
         .build()
 }
 
+/// Generates a wasm module from a compiled program, whose `$$MAIN$$` function
+/// evaluates the program's expression.
+///
+/// Modules are self-contained: no runtime library is linked into them (values
+/// are i32s, and tuples are laid out in the module's own memory), and they
+/// only import the host functions which the program uses (see
+/// `find_imports`). So they can run on any wasm host, including embedded ones
+/// without WASI or an allocator.
+///
+/// TODO: a backend which emits Rust source would need a runtime for its value
+/// types, lists and strings; that runtime should build with `no_std` and
+/// `alloc` to keep this property.
 pub fn construct_module_from_prog(prog: &Prog<TypedExpr>) -> Result<Module, CodeGenerateError> {
+    construct_module_from_prog_with_options(prog, &CodeGenerateOptions::default())
+}
+
+/// Like `construct_module_from_prog`, but with the given
+/// `CodeGenerateOptions`.
+pub fn construct_module_from_prog_with_options(
+    prog: &Prog<TypedExpr>,
+    options: &CodeGenerateOptions,
+) -> Result<Module, CodeGenerateError> {
+    let _span = tracing::info_span!("generate_code").entered();
+    // The main function is the only one exported, so functions which it
+    // can't reach (e.g. closures which are never created, once unused
+    // bindings have been optimized away) are left out of the module.
+    let reachable = reachable_fns(prog);
+    let prog = &Prog {
+        fns: prog
+            .fns
+            .iter()
+            .filter(|(name, _lambda)| reachable.contains(name))
+            .cloned()
+            .collect(),
+        exp: prog.exp.clone(),
+    };
     let mut module_builder = builder::module()
         .memory()
         .with_min(32)
         .with_max(None)
         .build();
-    let mut state = CodeGenerateState::new();
+    let mut state = CodeGenerateState::with_options(options.clone());
 
     // We need to know the index of type signatures in WebAssembly's type
     // signature table at any time when compiling a function in case we need
@@ -637,14 +1786,44 @@ pub fn construct_module_from_prog(prog: &Prog<TypedExpr>) -> Result<Module, Code
         state.sigs.insert(i as u32, sig_index);
     }
 
+    // Builtins and extern functions used by the program are imported from
+    // the host. Imported functions take the first function indices, so the
+    // lambda-lifted functions are numbered after them.
+    for ((module, name), num_params) in find_imports(prog)? {
+        let sig_index = match state.sigs.get(&num_params) {
+            Some(val) => *val,
+            None => {
+                return Err(CodeGenerateError::Other(format!(
+                    "Function {}.{} has too many parameters.",
+                    module, name
+                )))
+            }
+        };
+        let func_index = state.imports.len() as u32;
+        module_builder = module_builder
+            .import()
+            .path(&module, &name)
+            .external()
+            .func(sig_index)
+            .build();
+        state.imports.insert((module, name), func_index);
+    }
+    let num_imports = state.imports.len() as u32;
+
+    // The names of each function's locals, kept for the name section
+    let mut local_names: Vec<LocalsMap> = vec![];
+
+    // Call counters are stored in globals after the exported constants, in
+    // the same order as the functions.
+    let counters_start = options.exported_constants.len() as u32;
+
     // First, the lambda-lifted functions within `prog` will get compiled.
     // This is necessary for populating state.funcs, which maps the names of
     // functions to indices within the WebAssembly store. For reference, see:
     // https://webassembly.github.io/spec/core/exec/instructions.html#function-calls
     // https://webassembly.github.io/spec/core/exec/runtime.html#syntax-store
-    prog.fns
-        .iter()
-        .for_each(|(name, lambda)| match &*lambda.kind {
+    for (name, lambda) in prog.fns.iter() {
+        match &*lambda.kind {
             ExprKind::Lambda(params, _ret_type, body) => {
                 let param_types = params
                     .iter()
@@ -656,7 +1835,13 @@ pub fn construct_module_from_prog(prog: &Prog<TypedExpr>) -> Result<Module, Code
                     state.locals.insert(name.clone(), local_index);
                 });
 
-                let func_instructions = gen_instr(&body, &mut state).unwrap();
+                let mut func_instructions = gen_instr(&body, &mut state)?;
+                if options.instrument == Instrumentation::Calls {
+                    let counter = counters_start + state.funcs.len() as u32;
+                    let mut counter_instructions = gen_instr_increment_global(counter);
+                    counter_instructions.append(&mut func_instructions);
+                    func_instructions = counter_instructions;
+                }
                 let wasm_function = construct_function(
                     param_types,
                     Instructions::new(func_instructions),
@@ -675,12 +1860,14 @@ pub fn construct_module_from_prog(prog: &Prog<TypedExpr>) -> Result<Module, Code
                 // code generate can look at state.funcs to see that foo
                 // maps to 2, so we just need to put 5 on the stack and add
                 // Instruction::Call(2) to perform the function application.
-                let func_index = state.funcs.len() as u32;
+                let func_index = num_imports + state.funcs.len() as u32;
                 state.funcs.insert(name.to_string(), func_index);
 
                 // Add the function to the module
                 module_builder.push_function(wasm_function);
 
+                local_names.push(state.locals.clone());
+
                 // Reset state.locals so that the locals don't carry on
                 // when compiling the next function...
                 // Having to remember this kind of thing is a bit of a flaw
@@ -688,22 +1875,90 @@ pub fn construct_module_from_prog(prog: &Prog<TypedExpr>) -> Result<Module, Code
                 state.locals.clear();
             }
             _ => panic!("Function inside prog.fns is not a lambda."),
-        });
+        }
+    }
 
     // Construct a dummy table to make Instruction::CallIndirect work.
     let mut module_builder = module_builder.table().with_min(32).with_max(None);
-    for i in 0..prog.fns.len() {
-        module_builder = module_builder.with_element(i as u32, vec![i as u32]);
+    for i in num_imports..(num_imports + prog.fns.len() as u32) {
+        module_builder = module_builder.with_element(i, vec![i]);
     }
-    let module_builder = module_builder.build();
+    let mut module_builder = module_builder.build();
 
-    // Finally, the body of the program is compiled. We will just give it a
-    // fancy name like $$MAIN$$ and hope that nobody else uses it. :-)
-    let mut main_instructions = gen_instr(&prog.exp, &mut state).unwrap();
+    // The body of the program is compiled before any globals are declared,
+    // since it may need coverage counters.
+    let mut main_instructions = gen_instr(&prog.exp, &mut state)?;
     main_instructions.push(Instruction::End);
+
+    // Exported constants don't need to be used by the program itself, since
+    // all uses of them have already been replaced by their values.
+    for (i, (name, val)) in options.exported_constants.iter().enumerate() {
+        if options
+            .exported_constants
+            .iter()
+            .take(i)
+            .any(|pair| pair.0 == *name)
+        {
+            return Err(CodeGenerateError::Other(format!(
+                "Constant {} is exported more than once.",
+                name
+            )));
+        }
+        module_builder = module_builder
+            .global()
+            .value_type()
+            .i32()
+            .init_expr(Instruction::I32Const(*val))
+            .build()
+            .export()
+            .field(name)
+            .internal()
+            .global(i as u32)
+            .build();
+    }
+
+    if options.instrument == Instrumentation::Calls {
+        let display_names = function_display_names(prog, &state.funcs)?;
+        for func_index in 0..prog.fns.len() as u32 {
+            module_builder = module_builder
+                .global()
+                .value_type()
+                .i32()
+                .mutable()
+                .init_expr(Instruction::I32Const(0))
+                .build()
+                .export()
+                .field(&format!(
+                    "$$CALLS$$.{}",
+                    display_names[&(num_imports + func_index)]
+                ))
+                .internal()
+                .global(counters_start + func_index)
+                .build();
+        }
+    }
+
+    for (id, _description) in state.coverage.iter().enumerate() {
+        module_builder = module_builder
+            .global()
+            .value_type()
+            .i32()
+            .mutable()
+            .init_expr(Instruction::I32Const(0))
+            .build()
+            .export()
+            .field(&format!("$$COVERAGE$$.{}", id))
+            .internal()
+            .global(counters_start + id as u32)
+            .build();
+    }
+
+    // Finally, the body of the program is added. We will just give it a
+    // fancy name like $$MAIN$$ and hope that nobody else uses it. :-)
     let wasm_locals = construct_locals(&state.locals);
-    let func_index = state.funcs.len() as u32;
-    Ok(module_builder
+    let func_index = num_imports + state.funcs.len() as u32;
+    local_names.push(state.locals.clone());
+    let mut module_builder = module_builder
         .function()
         .signature()
         .with_params(vec![])
@@ -718,8 +1973,397 @@ pub fn construct_module_from_prog(prog: &Prog<TypedExpr>) -> Result<Module, Code
         .field("$$MAIN$$")
         .internal()
         .func(func_index)
-        .build()
-        .build())
+        .build();
+
+    if options.host_memory {
+        let num_counters = match options.instrument {
+            Instrumentation::Calls => prog.fns.len() as u32,
+            _ => state.coverage.len() as u32,
+        };
+        let heap_global = counters_start + num_counters;
+        // everything the program stores in memory has a fixed address (see
+        // `CodeGenerateState`), so the heap starts after the last of them
+        let heap_start = (state.mem_index + 7) & !7;
+        module_builder = module_builder
+            .global()
+            .value_type()
+            .i32()
+            .mutable()
+            .init_expr(Instruction::I32Const(heap_start as i32))
+            .build()
+            .export()
+            .field("memory")
+            .internal()
+            .memory(0)
+            .build()
+            .function()
+            .signature()
+            .with_params(vec![ValueType::I32])
+            .with_return_type(Some(ValueType::I32))
+            .build()
+            .body()
+            .with_locals(vec![Local::new(1, ValueType::I32)])
+            .with_instructions(Instructions::new(gen_instr_alloc(heap_global)))
+            .build()
+            .build()
+            .export()
+            .field("alloc")
+            .internal()
+            .func(func_index + 1)
+            .build()
+            .function()
+            .signature()
+            .with_params(vec![ValueType::I32, ValueType::I32])
+            .with_return_type(None)
+            .build()
+            .body()
+            .with_instructions(Instructions::new(gen_instr_free(heap_global)))
+            .build()
+            .build()
+            .export()
+            .field("free")
+            .internal()
+            .func(func_index + 2)
+            .build();
+    }
+    let mut module = module_builder.build();
+
+    let name_section = construct_name_section(prog, &state.funcs, &state.imports, &local_names)?;
+    module.sections_mut().push(Section::Name(name_section));
+    if options.instrument == Instrumentation::Coverage {
+        module
+            .sections_mut()
+            .push(Section::Custom(CustomSection::new(
+                String::from(COVERAGE_SECTION),
+                state.coverage.join("\n").into_bytes(),
+            )));
+    }
+    if options.limits.max_output_size != usize::MAX {
+        let size = parity_wasm::serialize(module.clone())
+            .map_err(|err| CodeGenerateError::Other(err.to_string()))?
+            .len();
+        check_output_size(size, &options.limits).map_err(CodeGenerateError::LimitExceeded)?;
+    }
+    Ok(module)
+}
+
+/// Generates the body of the `alloc` function (see
+/// `CodeGenerateOptions::host_memory`), which bumps the heap pointer stored in
+/// the given global. Its parameter is the number of bytes to allocate, and its
+/// local holds the address returned.
+fn gen_instr_alloc(heap_global: u32) -> Vec<Instruction> {
+    let mut instructions = vec![
+        Instruction::GetGlobal(heap_global),
+        Instruction::SetLocal(1),
+    ];
+    // the heap pointer is kept aligned to 8 bytes
+    instructions.extend(gen_instr_align_end(0, 1));
+    instructions.push(Instruction::SetGlobal(heap_global));
+    // grow the memory (by whole pages of 64KiB) if the heap no longer fits
+    instructions.extend(vec![
+        Instruction::GetGlobal(heap_global),
+        Instruction::CurrentMemory(0),
+        Instruction::I32Const(16),
+        Instruction::I32Shl,
+        Instruction::I32GtU,
+        Instruction::If(BlockType::NoResult),
+        Instruction::GetGlobal(heap_global),
+        Instruction::CurrentMemory(0),
+        Instruction::I32Const(16),
+        Instruction::I32Shl,
+        Instruction::I32Sub,
+        Instruction::I32Const(0xffff),
+        Instruction::I32Add,
+        Instruction::I32Const(16),
+        Instruction::I32ShrU,
+        Instruction::GrowMemory(0),
+        Instruction::I32Const(-1),
+        Instruction::I32Eq,
+        Instruction::If(BlockType::NoResult),
+        Instruction::Unreachable,
+        Instruction::End,
+        Instruction::End,
+        Instruction::GetLocal(1),
+        Instruction::End,
+    ]);
+    instructions
+}
+
+/// Generates the body of the `free` function (see
+/// `CodeGenerateOptions::host_memory`), which moves the heap pointer in the
+/// given global back to the start of the freed memory, if it was the last
+/// memory allocated.
+fn gen_instr_free(heap_global: u32) -> Vec<Instruction> {
+    let mut instructions = gen_instr_align_end(1, 0);
+    instructions.extend(vec![
+        Instruction::GetGlobal(heap_global),
+        Instruction::I32Eq,
+        Instruction::If(BlockType::NoResult),
+        Instruction::GetLocal(0),
+        Instruction::SetGlobal(heap_global),
+        Instruction::End,
+        Instruction::End,
+    ]);
+    instructions
+}
+
+/// Generates instructions which compute the end of an allocation, rounded up
+/// to a multiple of 8, given the locals holding its length and its address.
+fn gen_instr_align_end(len_local: u32, ptr_local: u32) -> Vec<Instruction> {
+    vec![
+        Instruction::GetLocal(ptr_local),
+        Instruction::GetLocal(len_local),
+        Instruction::I32Add,
+        Instruction::I32Const(7),
+        Instruction::I32Add,
+        Instruction::I32Const(-8),
+        Instruction::I32And,
+    ]
+}
+
+/// The name of the custom section which describes each coverage counter.
+const COVERAGE_SECTION: &str = "coverage";
+
+/// Produces a coverage report for a module compiled with
+/// `Instrumentation::Coverage`, given the values of its `$$COVERAGE$$.<id>`
+/// globals after running it (keyed by id).
+///
+/// Each line of the report has the number of times a branch was taken,
+/// followed by a description of the branch. Since code is generated from the
+/// program after closure conversion and lambda lifting, descriptions refer to
+/// the converted program rather than the original source.
+pub fn coverage_report(
+    module: &Module,
+    counts: &BTreeMap<u32, i32>,
+) -> Result<String, CodeGenerateError> {
+    let section = module
+        .custom_sections()
+        .find(|section| section.name() == COVERAGE_SECTION)
+        .ok_or_else(|| CodeGenerateError::from("Module was not compiled with coverage."))?;
+    let payload = String::from_utf8_lossy(section.payload());
+    let mut report = String::new();
+    for (id, description) in payload.lines().enumerate() {
+        let count = counts.get(&(id as u32)).ok_or_else(|| {
+            CodeGenerateError::Other(format!("Missing the count of coverage counter {}.", id))
+        })?;
+        report.push_str(&format!("{:>8}  {}\n", count, description));
+    }
+    Ok(report)
+}
+
+/// Produces a report of where the bytes of a module go, so that it's possible
+/// to see which parts of a program make the module large.
+///
+/// The report starts with the total size of the module, followed by the size
+/// of each section, and then the size of each function's body, largest
+/// first. Functions are named using the module's name section (see
+/// `construct_name_section`), so closures are listed under the variable they
+/// are bound to where possible. Records don't get their own lines, since
+/// they have been turned into tuples by `record_elim`, and tuples are
+/// created by the code of the functions which use them.
+pub fn size_report(module: &Module) -> Result<String, CodeGenerateError> {
+    let serialized_size = |section: Section| -> Result<usize, CodeGenerateError> {
+        parity_wasm::serialize(section)
+            .map(|bytes| bytes.len())
+            .map_err(|err| CodeGenerateError::Other(err.to_string()))
+    };
+    let total = parity_wasm::serialize(module.clone())
+        .map_err(|err| CodeGenerateError::Other(err.to_string()))?
+        .len();
+    let mut report = format!("{:>8}  total\n", total);
+    for section in module.sections() {
+        let name = match section {
+            Section::Type(_) => String::from("type section"),
+            Section::Import(_) => String::from("import section"),
+            Section::Function(_) => String::from("function section"),
+            Section::Table(_) => String::from("table section"),
+            Section::Memory(_) => String::from("memory section"),
+            Section::Global(_) => String::from("global section"),
+            Section::Export(_) => String::from("export section"),
+            Section::Start(_) => String::from("start section"),
+            Section::Element(_) => String::from("element section"),
+            Section::Code(_) => String::from("code section"),
+            Section::Data(_) => String::from("data section"),
+            Section::Name(_) => String::from("name section"),
+            Section::Custom(custom) => format!("custom section {}", custom.name()),
+            _ => String::from("other section"),
+        };
+        report.push_str(&format!(
+            "{:>8}  {}\n",
+            serialized_size(section.clone())?,
+            name
+        ));
+    }
+
+    let num_imports = module.import_count(ImportCountType::Function) as u32;
+    let function_names = module
+        .names_section()
+        .and_then(|names| names.functions())
+        .map(|functions| functions.names());
+    let mut func_sizes = vec![];
+    for (index, body) in module
+        .code_section()
+        .map_or(&[][..], |code| code.bodies())
+        .iter()
+        .enumerate()
+    {
+        let func_index = num_imports + index as u32;
+        let name = function_names
+            .and_then(|names| names.get(func_index))
+            .cloned()
+            .unwrap_or_else(|| format!("function {}", func_index));
+        let size = parity_wasm::serialize(body.clone())
+            .map_err(|err| CodeGenerateError::Other(err.to_string()))?
+            .len();
+        func_sizes.push((size, name));
+    }
+    func_sizes.sort_by(|(size1, _), (size2, _)| size2.cmp(size1));
+    for (size, name) in func_sizes {
+        report.push_str(&format!("{:>8}  function {}\n", size, name));
+    }
+    Ok(report)
+}
+
+/// Construct a name section, which gives readable names to the functions in
+/// the module and their local variables, for debuggers and profilers.
+///
+/// Lambda-lifted functions only have generated names, so where possible,
+/// functions are named after the variable which their closure is bound to
+/// by a let expression. If several functions would get the same name, the
+/// generated name is appended to keep them apart (e.g. `loop.func3`).
+/// Imported builtins are named after the builtin. `local_names` contains the
+/// locals of each function in order, followed by the locals of the main
+/// function.
+fn construct_name_section(
+    prog: &Prog<TypedExpr>,
+    funcs: &FuncsMap,
+    imports: &ImportsMap,
+    local_names: &[LocalsMap],
+) -> Result<NameSection, CodeGenerateError> {
+    let mut function_names = FunctionNameSubsection::default();
+    for ((_module, name), func_index) in imports.iter() {
+        function_names.names_mut().insert(*func_index, name.clone());
+    }
+    for (func_index, display_name) in function_display_names(prog, funcs)? {
+        function_names.names_mut().insert(func_index, display_name);
+    }
+    let num_imports = imports.len() as u32;
+    function_names
+        .names_mut()
+        .insert(num_imports + funcs.len() as u32, String::from("$$MAIN$$"));
+
+    let mut locals_names = LocalNameSubsection::default();
+    for (func_index, locals) in local_names.iter().enumerate() {
+        let mut names = NameMap::default();
+        for (name, local_index) in locals.iter() {
+            names.insert(*local_index, String::from(original_name(name)));
+        }
+        locals_names
+            .local_names_mut()
+            .insert(num_imports + func_index as u32, names);
+    }
+
+    Ok(NameSection::new(
+        None,
+        Some(function_names),
+        Some(locals_names),
+    ))
+}
+
+/// Finds readable names for each of the lambda-lifted functions in `funcs`,
+/// keyed by function index (see `construct_name_section`).
+fn function_display_names(
+    prog: &Prog<TypedExpr>,
+    funcs: &FuncsMap,
+) -> Result<BTreeMap<u32, String>, CodeGenerateError> {
+    let binder_names = RefCell::new(BTreeMap::<String, String>::new());
+    let find_binder_names = |exp: &TypedExpr| -> Option<Result<TypedExpr, CodeGenerateError>> {
+        if let ExprKind::Let(bindings, _body) = &*exp.kind {
+            for (var, val) in bindings.iter() {
+                if let Some(func_name) = closure_func_name(val) {
+                    binder_names
+                        .borrow_mut()
+                        .entry(func_name)
+                        .or_insert_with(|| String::from(original_name(var)));
+                }
+            }
+        }
+        None
+    };
+    let keep_type = |_typ: &Type| -> Option<Result<Type, CodeGenerateError>> { None };
+    for (_name, lambda) in prog.fns.iter() {
+        transform_typed_exp_recursive(lambda, find_binder_names, keep_type)?;
+    }
+    transform_typed_exp_recursive(&prog.exp, find_binder_names, keep_type)?;
+    let binder_names = binder_names.into_inner();
+
+    let mut display_names = BTreeMap::new();
+    for (name, func_index) in funcs.iter() {
+        let display_name = match binder_names.get(name) {
+            Some(binder) if binder_names.values().filter(|val| *val == binder).count() == 1 => {
+                binder.clone()
+            }
+            Some(binder) => format!("{}.{}", binder, name),
+            None => name.clone(),
+        };
+        display_names.insert(*func_index, display_name);
+    }
+    Ok(display_names)
+}
+
+/// Finds all of the functions which a program imports from the host (its
+/// builtins, extern functions, and `expt` on floats), along with their
+/// numbers of parameters.
+fn find_imports(
+    prog: &Prog<TypedExpr>,
+) -> Result<BTreeMap<(String, String), u32>, CodeGenerateError> {
+    let imports = RefCell::new(BTreeMap::new());
+    let conflict = RefCell::new(None);
+    let find = |exp: &TypedExpr| -> Option<Result<TypedExpr, CodeGenerateError>> {
+        let (key, num_params) = match &*exp.kind {
+            ExprKind::Builtin(builtin, args) => {
+                ((String::from("env"), builtin.to_string()), args.len())
+            }
+            ExprKind::Extern(module, name, _typ, args) => {
+                ((module.clone(), name.clone()), args.len())
+            }
+            ExprKind::Binop(BinOp::Expt, arg1, _arg2) if arg1.typ == Type::Float => {
+                ((String::from("env"), BinOp::Expt.to_string()), 2)
+            }
+            _ => return None,
+        };
+        let num_params = num_params as u32;
+        match imports.borrow_mut().insert(key.clone(), num_params) {
+            Some(other) if other != num_params => *conflict.borrow_mut() = Some(key),
+            _ => (),
+        }
+        None
+    };
+    let keep_type = |_typ: &Type| -> Option<Result<Type, CodeGenerateError>> { None };
+    for (_name, lambda) in prog.fns.iter() {
+        transform_typed_exp_recursive(lambda, find, keep_type)?;
+    }
+    transform_typed_exp_recursive(&prog.exp, find, keep_type)?;
+    if let Some((module, name)) = conflict.into_inner() {
+        return Err(CodeGenerateError::Other(format!(
+            "Function {}.{} is declared with different numbers of parameters.",
+            module, name
+        )));
+    }
+    Ok(imports.into_inner())
+}
+
+/// If `exp` constructs a closure (see `closure_convert`), returns the name
+/// of the lambda-lifted function which it contains.
+fn closure_func_name(exp: &TypedExpr) -> Option<String> {
+    match &*exp.kind {
+        ExprKind::Pack(val, _sub, _exist) => closure_func_name(val),
+        ExprKind::Tuple(vals) if !vals.is_empty() => match &*vals[0].kind {
+            ExprKind::Id(name) => Some(name.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
 }
 
 /// Construct a WebAssembly `FunctionDefinition`, a format for a function which
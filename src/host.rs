@@ -0,0 +1,337 @@
+/// This module provides a typed API for running compiled programs from Rust
+/// applications, using wasmer as the WebAssembly runtime. It is only
+/// available with the "host" feature.
+use crate::common::{Prog, TypedExpr};
+use crate::generate_code::construct_module_from_prog;
+use crate::types::Type;
+use crate::util::{random_float, random_state, xorshift};
+use im_rc::{vector, Vector};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use wasmer_runtime::{func, imports, ImportObject, Value};
+
+#[derive(Clone, Debug)]
+pub struct HostError(String);
+
+// Allows other errors to wrap this one
+impl std::error::Error for HostError {}
+
+impl From<&str> for HostError {
+    fn from(message: &str) -> Self {
+        HostError(String::from(message))
+    }
+}
+
+impl std::fmt::Display for HostError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "HostError: {}", self.0)
+    }
+}
+
+/// A Rust type which can be passed to or returned from compiled code.
+pub trait HostValue: Sized {
+    /// The type of the corresponding values in the source language.
+    fn host_type() -> Type;
+    fn from_wasm(value: &Value) -> Option<Self>;
+    fn into_wasm(self) -> Value;
+}
+
+impl HostValue for i32 {
+    fn host_type() -> Type {
+        Type::Int
+    }
+
+    fn from_wasm(value: &Value) -> Option<Self> {
+        match value {
+            Value::I32(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    fn into_wasm(self) -> Value {
+        Value::I32(self)
+    }
+}
+
+// floats are passed as the bits of an i32, like all other values
+impl HostValue for f32 {
+    fn host_type() -> Type {
+        Type::Float
+    }
+
+    fn from_wasm(value: &Value) -> Option<Self> {
+        match value {
+            Value::I32(x) => Some(f32::from_bits(*x as u32)),
+            _ => None,
+        }
+    }
+
+    fn into_wasm(self) -> Value {
+        Value::I32(self.to_bits() as i32)
+    }
+}
+
+impl HostValue for bool {
+    fn host_type() -> Type {
+        Type::Bool
+    }
+
+    fn from_wasm(value: &Value) -> Option<Self> {
+        match value {
+            Value::I32(x) => Some(*x != 0),
+            _ => None,
+        }
+    }
+
+    fn into_wasm(self) -> Value {
+        Value::I32(self as i32)
+    }
+}
+
+/// A tuple of Rust values which can be passed as the arguments of a call to
+/// compiled code.
+pub trait HostArgs {
+    fn host_types() -> Vector<Type>;
+    fn into_wasm(self) -> Vec<Value>;
+}
+
+macro_rules! impl_host_args {
+    ($($name:ident),*) => {
+        impl<$($name: HostValue),*> HostArgs for ($($name,)*) {
+            fn host_types() -> Vector<Type> {
+                vector![$($name::host_type()),*]
+            }
+
+            #[allow(non_snake_case)]
+            fn into_wasm(self) -> Vec<Value> {
+                let ($($name,)*) = self;
+                vec![$($name.into_wasm()),*]
+            }
+        }
+    };
+}
+
+impl_host_args!();
+impl_host_args!(A);
+impl_host_args!(A, B);
+impl_host_args!(A, B, C);
+impl_host_args!(A, B, C, D);
+
+/// Wall-clock timings of repeated runs of a program (see `Instance::bench`).
+///
+/// Instruction counts aren't reported, since the runtime only supports
+/// metering with a backend which isn't used here. To count how often each
+/// function is called instead, compile the program with
+/// `generate_code::Instrumentation::Calls`.
+#[derive(Clone, Debug)]
+pub struct BenchReport {
+    pub runs: usize,
+    pub mean: Duration,
+    pub median: Duration,
+    pub p95: Duration,
+}
+
+/// An instantiated program, whose exported functions can be called with
+/// arguments and return values that are checked against the program's types.
+///
+/// Currently the only function which programs export is the main function,
+/// `$$MAIN$$`, which takes no arguments.
+pub struct Instance {
+    instance: wasmer_runtime::Instance,
+    signatures: BTreeMap<String, (Vector<Type>, Type)>,
+}
+
+impl Instance {
+    /// Generates code for a compiled program (see `compile::compile_exp`),
+    /// and instantiates it. `import_object` must provide any builtins and
+    /// extern functions which the program uses.
+    pub fn new(prog: &Prog<TypedExpr>, import_object: &ImportObject) -> Result<Self, HostError> {
+        let module = construct_module_from_prog(prog)
+            .map_err(|err| HostError(format!("Could not generate code: {}", err)))?;
+        let binary = parity_wasm::serialize(module)
+            .map_err(|err| HostError(format!("Could not serialize module: {}", err)))?;
+        let instance = wasmer_runtime::instantiate(&binary, import_object)
+            .map_err(|err| HostError(format!("Could not instantiate module: {}", err)))?;
+        let mut signatures = BTreeMap::new();
+        signatures.insert(
+            String::from("$$MAIN$$"),
+            (Vector::new(), prog.exp.typ.clone()),
+        );
+        Ok(Instance {
+            instance,
+            signatures,
+        })
+    }
+
+    /// Calls an exported function, e.g.
+    /// `instance.call::<(), i32>("$$MAIN$$", ())`.
+    ///
+    /// An error is returned if the argument or return types don't match the
+    /// function's signature, or if the call traps.
+    pub fn call<A: HostArgs, R: HostValue>(&self, name: &str, args: A) -> Result<R, HostError> {
+        let (param_types, ret_type) = match self.signatures.get(name) {
+            Some(signature) => signature,
+            None => {
+                return Err(HostError(format!(
+                    "No function named {} is exported.",
+                    name
+                )))
+            }
+        };
+        if *param_types != A::host_types() || *ret_type != R::host_type() {
+            return Err(HostError(format!(
+                "Function {} has parameter types {:?} and return type {}, but was called with parameter types {:?} and return type {}.",
+                name,
+                param_types,
+                ret_type,
+                A::host_types(),
+                R::host_type()
+            )));
+        }
+        let func = self
+            .instance
+            .dyn_func(name)
+            .map_err(|err| HostError(format!("Could not find function {}: {}", name, err)))?;
+        let values = func
+            .call(&args.into_wasm())
+            .map_err(|err| HostError(format!("Call to {} failed: {}", name, err)))?;
+        values
+            .first()
+            .and_then(R::from_wasm)
+            .ok_or_else(|| HostError(format!("Function {} returned an invalid value.", name)))
+    }
+
+    /// Runs the program's main function `runs` times, timing each run, e.g.
+    /// to measure the effect of optimizer passes.
+    pub fn bench(&self, runs: usize) -> Result<BenchReport, HostError> {
+        if runs == 0 {
+            return Err(HostError::from("Benchmark must have at least one run."));
+        }
+        let func = self
+            .instance
+            .dyn_func("$$MAIN$$")
+            .map_err(|err| HostError(format!("Could not find function $$MAIN$$: {}", err)))?;
+        let mut times = Vec::with_capacity(runs);
+        for _ in 0..runs {
+            let start = Instant::now();
+            func.call(&[])
+                .map_err(|err| HostError(format!("Call to $$MAIN$$ failed: {}", err)))?;
+            times.push(start.elapsed());
+        }
+        times.sort();
+        // percentiles use the nearest-rank method
+        let percentile = |p: usize| times[(p * runs).div_ceil(100).max(1) - 1];
+        Ok(BenchReport {
+            runs,
+            mean: times.iter().sum::<Duration>() / runs as u32,
+            median: percentile(50),
+            p95: percentile(95),
+        })
+    }
+}
+
+/// Returns the standard implementations of the builtins which compiled
+/// programs import from the host: `random`, `random-float`, `current-millis`,
+/// `exp`, `log`, `sin`, `cos`, `tan`, `atan2`, and `expt` on floats. These
+/// behave the same as in the interpreter, so most hosts can use them instead
+/// of providing their own, and fixes to them apply without recompiling
+/// programs.
+///
+/// Programs which use extern functions need an import object which also
+/// provides those.
+pub fn runtime_imports() -> ImportObject {
+    imports! {
+        "env" => {
+            "random" => func!(runtime_random),
+            "random-float" => func!(runtime_random_float),
+            "current-millis" => func!(runtime_current_millis),
+            "exp" => func!(runtime_exp),
+            "log" => func!(runtime_log),
+            "sin" => func!(runtime_sin),
+            "cos" => func!(runtime_cos),
+            "tan" => func!(runtime_tan),
+            "atan2" => func!(runtime_atan2),
+            "expt" => func!(runtime_expt),
+        },
+    }
+}
+
+// the state of an xorshift generator, shared by every instance, like the
+// interpreter's generator; zero until it's seeded, by `seed_random` or by the
+// first call
+static RANDOM_STATE: AtomicU64 = AtomicU64::new(0);
+
+// when current-millis was first called
+static START: OnceLock<Instant> = OnceLock::new();
+
+/// Seeds the generator used by the `random` and `random-float` functions in
+/// `runtime_imports`, so that runs can be reproduced. A seed gives the same
+/// numbers as in the interpreter (see `interpret::EvalOptions::seed`).
+pub fn seed_random(seed: u64) {
+    RANDOM_STATE.store(random_state(seed), Ordering::Relaxed);
+}
+
+fn next_random() -> u64 {
+    let mut state = RANDOM_STATE.load(Ordering::Relaxed);
+    if state == 0 {
+        state = random_state(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_nanos() as u64)
+                .unwrap_or(0),
+        );
+    }
+    state = xorshift(state);
+    RANDOM_STATE.store(state, Ordering::Relaxed);
+    state
+}
+
+fn runtime_random(n: i32) -> Result<i32, String> {
+    if n <= 0 {
+        return Err(String::from("Random expects a positive int."));
+    }
+    Ok((next_random() % n as u64) as i32)
+}
+
+fn runtime_random_float() -> i32 {
+    random_float(next_random()).to_bits() as i32
+}
+
+fn runtime_current_millis() -> i32 {
+    START.get_or_init(Instant::now).elapsed().as_millis() as i32
+}
+
+// floats are passed as the bits of an i32, like all other values
+fn float_op(x: i32, op: impl Fn(f32) -> f32) -> i32 {
+    op(f32::from_bits(x as u32)).to_bits() as i32
+}
+
+fn runtime_exp(x: i32) -> i32 {
+    float_op(x, f32::exp)
+}
+
+fn runtime_log(x: i32) -> i32 {
+    float_op(x, f32::ln)
+}
+
+fn runtime_sin(x: i32) -> i32 {
+    float_op(x, f32::sin)
+}
+
+fn runtime_cos(x: i32) -> i32 {
+    float_op(x, f32::cos)
+}
+
+fn runtime_tan(x: i32) -> i32 {
+    float_op(x, f32::tan)
+}
+
+fn runtime_atan2(y: i32, x: i32) -> i32 {
+    float_op(y, |y| y.atan2(f32::from_bits(x as u32)))
+}
+
+fn runtime_expt(x: i32, y: i32) -> i32 {
+    float_op(x, |x| x.powf(f32::from_bits(y as u32)))
+}
@@ -0,0 +1,803 @@
+/// This module evaluates programs directly, without compiling them, so that
+/// it can serve as the reference semantics for the compiled code. It runs on
+/// type checked programs, since inject and typecase expressions need the
+/// types of the values they operate on.
+use crate::common::{BinOp, Builtin, ExprKind, TypedExpr, UnOp};
+use crate::desugar::desugar;
+use crate::limits::CompilerLimits;
+use crate::macro_expand::macro_expand_with_limits;
+use crate::parse::parse_with_limits;
+use crate::read::read;
+use crate::regex::Regex;
+use crate::type_check::type_check;
+use crate::types::{bytes_type, unit_type, Type};
+use crate::util::{
+    decode_base64, decode_hex, encode_base64, encode_hex, escape_string, format_float,
+    random_float, random_state, xorshift,
+};
+use im_rc::{HashMap, Vector};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use unicode_segmentation::UnicodeSegmentation;
+
+#[derive(Clone, Debug)]
+pub struct EvalError(String);
+
+// Allows other errors to wrap this one
+impl std::error::Error for EvalError {}
+
+impl From<&str> for EvalError {
+    fn from(message: &str) -> Self {
+        EvalError(String::from(message))
+    }
+}
+
+impl std::fmt::Display for EvalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "EvalError: {}", self.0)
+    }
+}
+
+/// A runtime value. Values which can be mutated (tuples and promises), or
+/// which are expensive to copy, are shared by reference.
+#[derive(Clone, Debug)]
+pub enum Value {
+    Int(i32),
+    Float(f32),
+    Bool(bool),
+    Str(Rc<String>),
+    Null,
+    Cons(Rc<(Value, Value)>),
+    Tuple(Rc<RefCell<Vec<Value>>>),
+    Record(Rc<Vec<(String, Value)>>),
+    Inject(Type, Rc<Value>), // type of the value within the union, and the value
+    Func(Rc<Function>),
+    Promise(Rc<RefCell<Promise>>),
+}
+
+#[derive(Debug)]
+pub enum Function {
+    Lambda(Vector<String>, TypedExpr, Env), // params, body, captured variables
+    Curry(Value, Vec<Value>),               // func, first arguments
+    Compose(Value, Value),                  // outer func, inner func
+}
+
+#[derive(Debug)]
+pub enum Promise {
+    Delayed(TypedExpr, Env),
+    Forced(Value),
+}
+
+/// Maps each variable in scope to its value. Closures share the cells of the
+/// variables they capture, so assignments are seen both inside and outside
+/// of them.
+pub type Env = HashMap<String, Rc<RefCell<Value>>>;
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Value::Int(x) => write!(f, "{}", x),
+            Value::Float(x) => write!(f, "{}", format_float(*x)),
+            Value::Bool(x) => write!(f, "{}", x),
+            Value::Str(x) => write!(f, "{}", escape_string(x)),
+            Value::Null | Value::Cons(_) => {
+                let mut items = vec![];
+                let mut rest = self;
+                while let Value::Cons(pair) = rest {
+                    items.push(pair.0.to_string());
+                    rest = &pair.1;
+                }
+                write!(f, "({})", items.join(" "))
+            }
+            Value::Tuple(vals) => {
+                let items: Vec<String> = vals.borrow().iter().map(Value::to_string).collect();
+                if items.is_empty() {
+                    write!(f, "(make-tuple)")
+                } else {
+                    write!(f, "(make-tuple {})", items.join(" "))
+                }
+            }
+            Value::Record(bindings) => {
+                let items: Vec<String> = bindings
+                    .iter()
+                    .map(|(label, val)| format!("({} {})", label, val))
+                    .collect();
+                if items.is_empty() {
+                    write!(f, "(make-record)")
+                } else {
+                    write!(f, "(make-record {})", items.join(" "))
+                }
+            }
+            Value::Inject(_typ, val) => write!(f, "{}", val),
+            Value::Func(_) => write!(f, "#<procedure>"),
+            Value::Promise(_) => write!(f, "#<promise>"),
+        }
+    }
+}
+
+/// Options which change how a program is evaluated.
+#[derive(Clone, Debug, Default)]
+pub struct EvalOptions {
+    /// Record each expression which is evaluated (other than literals and
+    /// variables) along with its value, once its value is known.
+    pub trace: bool,
+    /// The arguments returned by `(command-line)`.
+    pub args: Vec<String>,
+    /// The seed for `random` and `random-float`, so that runs can be
+    /// reproduced. If not set, the current time is used.
+    pub seed: Option<u64>,
+    /// Limits on the size of the program, checked before it is evaluated.
+    pub limits: CompilerLimits,
+}
+
+/// Evaluates a type checked expression with the default options.
+pub fn eval(exp: &TypedExpr) -> Result<Value, EvalError> {
+    Interpreter::new(EvalOptions::default()).eval(exp)
+}
+
+/// Reads, expands, parses, type checks and evaluates a program, returning
+/// the printed value (preceded by the trace, one step per line, if enabled).
+pub fn eval_source(
+    source: &str,
+    options: &EvalOptions,
+) -> Result<String, Box<dyn std::error::Error>> {
+    eval_program(&read(source)?, options)
+}
+
+/// Like `eval_source`, but for a program which has already been read.
+pub fn eval_program(
+    program: &lexpr::Value,
+    options: &EvalOptions,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let value = desugar(&macro_expand_with_limits(program, &options.limits)?)?;
+    let typed_exp = type_check(&parse_with_limits(&value, &options.limits)?)?;
+    let mut interpreter = Interpreter::new(options.clone());
+    let result = interpreter.eval(&typed_exp)?;
+    let mut output = interpreter.trace().join("\n");
+    if !output.is_empty() {
+        output.push('\n');
+    }
+    output.push_str(&result.to_string());
+    Ok(output)
+}
+
+/// The state of an evaluation: the steps traced so far, and the state of the
+/// builtins.
+pub struct Interpreter {
+    options: EvalOptions,
+    trace: Vec<String>,
+    depth: usize,
+    start: Instant,
+    random_state: u64,
+}
+
+impl Interpreter {
+    pub fn new(options: EvalOptions) -> Self {
+        let seed = options.seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_nanos() as u64)
+                .unwrap_or(0)
+        });
+        Interpreter {
+            options,
+            trace: vec![],
+            depth: 0,
+            start: Instant::now(),
+            random_state: random_state(seed),
+        }
+    }
+
+    /// Evaluates an expression with no variables in scope.
+    pub fn eval(&mut self, exp: &TypedExpr) -> Result<Value, EvalError> {
+        self.eval_with_env(exp, &Env::new())
+    }
+
+    /// The steps traced so far, indented by how deeply each expression is
+    /// nested within the expressions being evaluated.
+    pub fn trace(&self) -> &[String] {
+        &self.trace
+    }
+
+    fn eval_with_env(&mut self, exp: &TypedExpr, env: &Env) -> Result<Value, EvalError> {
+        let traced = self.options.trace
+            && !matches!(
+                &*exp.kind,
+                ExprKind::Num(_)
+                    | ExprKind::Float(_)
+                    | ExprKind::Bool(_)
+                    | ExprKind::Str(_)
+                    | ExprKind::Id(_)
+            );
+        if !traced {
+            return self.eval_kind(exp, env);
+        }
+        let index = self.trace.len();
+        self.trace.push(String::new());
+        self.depth += 1;
+        let result = self.eval_kind(exp, env);
+        self.depth -= 1;
+        let step = match &result {
+            Ok(value) => format!("{}{} => {}", "  ".repeat(self.depth), exp, value),
+            Err(e) => format!("{}{} => {}", "  ".repeat(self.depth), exp, e),
+        };
+        // steps are listed in the order their evaluation started
+        self.trace[index] = step;
+        result
+    }
+
+    fn eval_array(&mut self, exps: &Vector<TypedExpr>, env: &Env) -> Result<Vec<Value>, EvalError> {
+        exps.iter()
+            .map(|exp| self.eval_with_env(exp, env))
+            .collect()
+    }
+
+    fn eval_kind(&mut self, exp: &TypedExpr, env: &Env) -> Result<Value, EvalError> {
+        match &*exp.kind {
+            ExprKind::Num(x) => Ok(Value::Int(*x)),
+            ExprKind::Float(x) => Ok(Value::Float(*x)),
+            ExprKind::Bool(x) => Ok(Value::Bool(*x)),
+            ExprKind::Str(x) => Ok(Value::Str(Rc::new(x.clone()))),
+            ExprKind::Null(_typ) => Ok(Value::Null),
+            ExprKind::Id(name) => match env.get(name) {
+                Some(cell) => Ok(cell.borrow().clone()),
+                None => Err(EvalError(format!("Identifier {} is not bound.", name))),
+            },
+            ExprKind::Binop(op, arg1, arg2) => {
+                let val1 = self.eval_with_env(arg1, env)?;
+                let val2 = self.eval_with_env(arg2, env)?;
+                eval_binop(*op, &val1, &val2)
+            }
+            ExprKind::Unop(op, arg) => {
+                let val = self.eval_with_env(arg, env)?;
+                eval_unop(*op, &val)
+            }
+            ExprKind::If(pred, cons, alt) => match self.eval_with_env(pred, env)? {
+                Value::Bool(true) => self.eval_with_env(cons, env),
+                Value::Bool(false) => self.eval_with_env(alt, env),
+                val => Err(EvalError(format!("Condition of if is not a bool: {}", val))),
+            },
+            ExprKind::Let(bindings, body) => {
+                // bindings are evaluated in the outer scope
+                let mut body_env = env.clone();
+                for (name, val) in bindings.iter() {
+                    let val = self.eval_with_env(val, env)?;
+                    body_env.insert(name.clone(), Rc::new(RefCell::new(val)));
+                }
+                self.eval_with_env(body, &body_env)
+            }
+            ExprKind::Lambda(params, _ret_type, body) => {
+                let names = params.iter().map(|(name, _typ)| name.clone()).collect();
+                Ok(Value::Func(Rc::new(Function::Lambda(
+                    names,
+                    body.clone(),
+                    env.clone(),
+                ))))
+            }
+            ExprKind::Begin(exps) => self
+                .eval_array(exps, env)?
+                .pop()
+                .ok_or_else(|| EvalError::from("Begin expression has no arguments.")),
+            ExprKind::Set(name, val) => {
+                let val = self.eval_with_env(val, env)?;
+                match env.get(name) {
+                    Some(cell) => {
+                        *cell.borrow_mut() = val.clone();
+                        Ok(val)
+                    }
+                    None => Err(EvalError(format!("Identifier {} is not bound.", name))),
+                }
+            }
+            ExprKind::Cons(first, rest) => {
+                let first = self.eval_with_env(first, env)?;
+                let rest = self.eval_with_env(rest, env)?;
+                Ok(Value::Cons(Rc::new((first, rest))))
+            }
+            ExprKind::Car(val) => match self.eval_with_env(val, env)? {
+                Value::Cons(pair) => Ok(pair.0.clone()),
+                _ => Err(EvalError::from("Car of an empty list.")),
+            },
+            ExprKind::Cdr(val) => match self.eval_with_env(val, env)? {
+                Value::Cons(pair) => Ok(pair.1.clone()),
+                _ => Err(EvalError::from("Cdr of an empty list.")),
+            },
+            ExprKind::IsNull(val) => match self.eval_with_env(val, env)? {
+                Value::Null => Ok(Value::Bool(true)),
+                _ => Ok(Value::Bool(false)),
+            },
+            ExprKind::FnApp(func, args) => {
+                let func = self.eval_with_env(func, env)?;
+                let args = self.eval_array(args, env)?;
+                self.apply(&func, args)
+            }
+            ExprKind::Tuple(exps) => Ok(Value::Tuple(Rc::new(RefCell::new(
+                self.eval_array(exps, env)?,
+            )))),
+            ExprKind::TupleGet(tuple, key) => match self.eval_with_env(tuple, env)? {
+                Value::Tuple(vals) => vals
+                    .borrow()
+                    .get(*key as usize)
+                    .cloned()
+                    .ok_or_else(|| EvalError::from("Index in tuple-ref is out of bounds.")),
+                val => Err(EvalError(format!("Expected a tuple, found {}", val))),
+            },
+            ExprKind::TupleSet(tuple, key, val) => {
+                let tuple = self.eval_with_env(tuple, env)?;
+                let val = self.eval_with_env(val, env)?;
+                match tuple {
+                    Value::Tuple(vals) => match vals.borrow_mut().get_mut(*key as usize) {
+                        Some(elem) => {
+                            *elem = val.clone();
+                            Ok(val)
+                        }
+                        None => Err(EvalError::from("Index in tuple-set! is out of bounds.")),
+                    },
+                    val => Err(EvalError(format!("Expected a tuple, found {}", val))),
+                }
+            }
+            // packing only changes the type of a value
+            ExprKind::Pack(val, _sub, _exist) => self.eval_with_env(val, env),
+            ExprKind::Unpack(var, package, _type_var, body) => {
+                let val = self.eval_with_env(package, env)?;
+                let body_env = env.update(var.clone(), Rc::new(RefCell::new(val)));
+                self.eval_with_env(body, &body_env)
+            }
+            ExprKind::Record(bindings) => {
+                let vals = bindings
+                    .iter()
+                    .map(|(label, val)| Ok((label.clone(), self.eval_with_env(val, env)?)))
+                    .collect::<Result<Vec<(String, Value)>, EvalError>>()?;
+                Ok(Value::Record(Rc::new(vals)))
+            }
+            ExprKind::RecordGet(record, label) => match self.eval_with_env(record, env)? {
+                Value::Record(bindings) => bindings
+                    .iter()
+                    .find(|(field, _val)| field == label)
+                    .map(|(_field, val)| val.clone())
+                    .ok_or_else(|| EvalError(format!("Record has no field {}.", label))),
+                val => Err(EvalError(format!("Expected a record, found {}", val))),
+            },
+            ExprKind::Inject(val, _union_type) => {
+                let typ = val.typ.clone();
+                Ok(Value::Inject(typ, Rc::new(self.eval_with_env(val, env)?)))
+            }
+            ExprKind::TypeCase(val, clauses) => match self.eval_with_env(val, env)? {
+                Value::Inject(typ, inner) => {
+                    match clauses
+                        .iter()
+                        .find(|(clause_typ, _var, _body)| *clause_typ == typ)
+                    {
+                        Some((_typ, var, body)) => {
+                            let cell = Rc::new(RefCell::new(inner.as_ref().clone()));
+                            self.eval_with_env(body, &env.update(var.clone(), cell))
+                        }
+                        None => Err(EvalError(format!(
+                            "No typecase clause matches type {}.",
+                            typ
+                        ))),
+                    }
+                }
+                val => Err(EvalError(format!("Expected a union value, found {}", val))),
+            },
+            ExprKind::Assert(val, message) => match self.eval_with_env(val, env)? {
+                Value::Bool(true) => Ok(Value::Bool(true)),
+                _ => Err(EvalError(format!("Assertion failed: {}", message))),
+            },
+            ExprKind::While(pred, body) => {
+                while let Value::Bool(true) = self.eval_with_env(pred, env)? {
+                    self.eval_with_env(body, env)?;
+                }
+                Ok(Value::Tuple(Rc::new(RefCell::new(vec![]))))
+            }
+            ExprKind::Case(key, clauses, else_body) => match self.eval_with_env(key, env)? {
+                Value::Int(key) => {
+                    match clauses.iter().find(|(datums, _body)| datums.contains(&key)) {
+                        Some((_datums, body)) => self.eval_with_env(body, env),
+                        None => self.eval_with_env(else_body, env),
+                    }
+                }
+                val => Err(EvalError(format!("Expected an int, found {}", val))),
+            },
+            ExprKind::Curry(func, args) => {
+                let func = self.eval_with_env(func, env)?;
+                let args = self.eval_array(args, env)?;
+                Ok(Value::Func(Rc::new(Function::Curry(func, args))))
+            }
+            ExprKind::Compose(outer, inner) => {
+                let outer = self.eval_with_env(outer, env)?;
+                let inner = self.eval_with_env(inner, env)?;
+                Ok(Value::Func(Rc::new(Function::Compose(outer, inner))))
+            }
+            ExprKind::Delay(val) => Ok(Value::Promise(Rc::new(RefCell::new(Promise::Delayed(
+                val.clone(),
+                env.clone(),
+            ))))),
+            ExprKind::Force(promise) => match self.eval_with_env(promise, env)? {
+                Value::Promise(promise) => self.force(&promise),
+                val => Err(EvalError(format!("Expected a promise, found {}", val))),
+            },
+            ExprKind::Format(control, args) => {
+                let args = self.eval_array(args, env)?;
+                Ok(Value::Str(Rc::new(format_values(control, &args))))
+            }
+            ExprKind::Builtin(builtin, args) => {
+                let args = self.eval_array(args, env)?;
+                self.eval_builtin(*builtin, &args)
+            }
+            ExprKind::Extern(module, name, _typ, _args) => Err(EvalError(format!(
+                "Extern function {}.{} cannot be called by the interpreter.",
+                module, name
+            ))),
+            ExprKind::WasmAsm(_code, _typ, _args) => Err(EvalError::from(
+                "Wasm-asm expressions cannot be evaluated by the interpreter.",
+            )),
+        }
+    }
+
+    fn apply(&mut self, func: &Value, args: Vec<Value>) -> Result<Value, EvalError> {
+        let func = match func {
+            Value::Func(func) => func,
+            val => return Err(EvalError(format!("Expected a function, found {}", val))),
+        };
+        match func.as_ref() {
+            Function::Lambda(params, body, closure_env) => {
+                if params.len() != args.len() {
+                    return Err(EvalError(format!(
+                        "Function takes {} arguments, but {} were given.",
+                        params.len(),
+                        args.len()
+                    )));
+                }
+                let mut body_env = closure_env.clone();
+                for (param, arg) in params.iter().zip(args) {
+                    body_env.insert(param.clone(), Rc::new(RefCell::new(arg)));
+                }
+                self.eval_with_env(body, &body_env)
+            }
+            Function::Curry(func, first_args) => {
+                let all_args = first_args.iter().cloned().chain(args).collect();
+                self.apply(func, all_args)
+            }
+            Function::Compose(outer, inner) => {
+                let result = self.apply(inner, args)?;
+                self.apply(outer, vec![result])
+            }
+        }
+    }
+
+    fn force(&mut self, promise: &Rc<RefCell<Promise>>) -> Result<Value, EvalError> {
+        let (exp, env) = match &*promise.borrow() {
+            Promise::Forced(val) => return Ok(val.clone()),
+            Promise::Delayed(exp, env) => (exp.clone(), env.clone()),
+        };
+        let val = self.eval_with_env(&exp, &env)?;
+        *promise.borrow_mut() = Promise::Forced(val.clone());
+        Ok(val)
+    }
+
+    fn eval_builtin(&mut self, builtin: Builtin, args: &[Value]) -> Result<Value, EvalError> {
+        let string = |val: &Value| match val {
+            Value::Str(x) => Ok(x.as_ref().clone()),
+            val => Err(EvalError(format!("Expected a string, found {}", val))),
+        };
+        match builtin {
+            Builtin::Random => match args {
+                [Value::Int(n)] if *n > 0 => {
+                    self.random_state = xorshift(self.random_state);
+                    Ok(Value::Int((self.random_state % (*n as u64)) as i32))
+                }
+                _ => Err(EvalError::from("Random expects a positive int.")),
+            },
+            Builtin::RandomFloat => {
+                self.random_state = xorshift(self.random_state);
+                Ok(Value::Float(random_float(self.random_state)))
+            }
+            Builtin::CurrentMillis => Ok(Value::Int(self.start.elapsed().as_millis() as i32)),
+            Builtin::ReadFile => {
+                let path = string(&args[0])?;
+                std::fs::read_to_string(&path)
+                    .map(|contents| Value::Str(Rc::new(contents)))
+                    .map_err(|e| EvalError(format!("Could not read {}: {}", path, e)))
+            }
+            Builtin::WriteFile => {
+                let path = string(&args[0])?;
+                let contents = string(&args[1])?;
+                Ok(Value::Bool(std::fs::write(path, contents).is_ok()))
+            }
+            Builtin::CommandLine => Ok(list_value(
+                self.options
+                    .args
+                    .iter()
+                    .map(|arg| Value::Str(Rc::new(arg.clone())))
+                    .collect(),
+            )),
+            Builtin::GetEnv => {
+                let name = string(&args[0])?;
+                let val = std::env::var(name).ok();
+                Ok(option_value(
+                    Type::Str,
+                    val.map(|val| Value::Str(Rc::new(val))),
+                ))
+            }
+            Builtin::StringMatch => {
+                let regex = Regex::new(&string(&args[0])?).map_err(|e| EvalError(e.to_string()))?;
+                let groups = regex.captures(&string(&args[1])?).map(|groups| {
+                    list_value(
+                        groups
+                            .into_iter()
+                            .map(|group| Value::Str(Rc::new(group)))
+                            .collect(),
+                    )
+                });
+                Ok(option_value(Type::List(Box::new(Type::Str)), groups))
+            }
+            Builtin::StringReplace => {
+                let regex = Regex::new(&string(&args[0])?).map_err(|e| EvalError(e.to_string()))?;
+                let replaced = regex.replace_all(&string(&args[1])?, &string(&args[2])?);
+                Ok(Value::Str(Rc::new(replaced)))
+            }
+            Builtin::StringToUtf8 => Ok(bytes_value(string(&args[0])?.as_bytes())),
+            Builtin::Utf8ToString => {
+                let val = String::from_utf8(value_bytes(&args[0])?).ok();
+                Ok(option_value(
+                    Type::Str,
+                    val.map(|val| Value::Str(Rc::new(val))),
+                ))
+            }
+            Builtin::HexEncode => Ok(Value::Str(Rc::new(encode_hex(&value_bytes(&args[0])?)))),
+            Builtin::HexDecode => {
+                let bytes = decode_hex(&string(&args[0])?);
+                Ok(option_value(
+                    bytes_type(),
+                    bytes.map(|bytes| bytes_value(&bytes)),
+                ))
+            }
+            Builtin::Base64Encode => {
+                Ok(Value::Str(Rc::new(encode_base64(&value_bytes(&args[0])?))))
+            }
+            Builtin::Base64Decode => {
+                let bytes = decode_base64(&string(&args[0])?);
+                Ok(option_value(
+                    bytes_type(),
+                    bytes.map(|bytes| bytes_value(&bytes)),
+                ))
+            }
+            Builtin::StringLength => Ok(Value::Int(string(&args[0])?.chars().count() as i32)),
+            Builtin::StringRef => {
+                let chars: Vec<char> = string(&args[0])?.chars().collect();
+                let index = string_index(&args[1], chars.len())?;
+                if index == chars.len() {
+                    return Err(EvalError(format!(
+                        "Index {} is out of range for a string of length {}.",
+                        index,
+                        chars.len()
+                    )));
+                }
+                Ok(Value::Str(Rc::new(chars[index].to_string())))
+            }
+            Builtin::Substring => {
+                let chars: Vec<char> = string(&args[0])?.chars().collect();
+                let start = string_index(&args[1], chars.len())?;
+                let end = string_index(&args[2], chars.len())?;
+                if end < start {
+                    return Err(EvalError(format!(
+                        "Substring end {} is before its start {}.",
+                        end, start
+                    )));
+                }
+                Ok(Value::Str(Rc::new(chars[start..end].iter().collect())))
+            }
+            Builtin::StringToList => Ok(list_value(
+                string(&args[0])?
+                    .chars()
+                    .map(|c| Value::Str(Rc::new(c.to_string())))
+                    .collect(),
+            )),
+            Builtin::Graphemes => Ok(list_value(
+                string(&args[0])?
+                    .graphemes(true)
+                    .map(|grapheme| Value::Str(Rc::new(String::from(grapheme))))
+                    .collect(),
+            )),
+            Builtin::Exp | Builtin::Log | Builtin::Sin | Builtin::Cos | Builtin::Tan => {
+                match args {
+                    [Value::Float(x)] => Ok(Value::Float(match builtin {
+                        Builtin::Exp => x.exp(),
+                        Builtin::Log => x.ln(),
+                        Builtin::Sin => x.sin(),
+                        Builtin::Cos => x.cos(),
+                        _ => x.tan(),
+                    })),
+                    _ => Err(EvalError(format!("{} expects a float.", builtin))),
+                }
+            }
+            Builtin::FloatToString => match args {
+                [Value::Float(x)] => Ok(Value::Str(Rc::new(format_float(*x)))),
+                _ => Err(EvalError::from("float->string expects a float.")),
+            },
+            Builtin::Atan2 => match args {
+                [Value::Float(y), Value::Float(x)] => Ok(Value::Float(y.atan2(*x))),
+                _ => Err(EvalError::from("atan2 expects two floats.")),
+            },
+        }
+    }
+}
+
+/// Builds a list from its elements.
+fn list_value(vals: Vec<Value>) -> Value {
+    vals.into_iter()
+        .rev()
+        .fold(Value::Null, |rest, val| Value::Cons(Rc::new((val, rest))))
+}
+
+/// Builds a value of type `(option typ)`: either a value of type `typ`, or
+/// an empty tuple if there is none.
+fn option_value(typ: Type, val: Option<Value>) -> Value {
+    match val {
+        Some(val) => Value::Inject(typ, Rc::new(val)),
+        None => Value::Inject(
+            unit_type(),
+            Rc::new(Value::Tuple(Rc::new(RefCell::new(vec![])))),
+        ),
+    }
+}
+
+/// Builds a list of ints from bytes (see `types::bytes_type`).
+fn bytes_value(bytes: &[u8]) -> Value {
+    list_value(
+        bytes
+            .iter()
+            .map(|byte| Value::Int(i32::from(*byte)))
+            .collect(),
+    )
+}
+
+/// Returns an index into a string of `len` characters, which must be from 0
+/// to `len` (inclusive, as the end of a substring).
+fn string_index(val: &Value, len: usize) -> Result<usize, EvalError> {
+    match val {
+        Value::Int(index) if *index >= 0 && *index as usize <= len => Ok(*index as usize),
+        val => Err(EvalError(format!(
+            "Index {} is out of range for a string of length {}.",
+            val, len
+        ))),
+    }
+}
+
+/// Returns the bytes in a list of ints, which must all be from 0 to 255.
+fn value_bytes(val: &Value) -> Result<Vec<u8>, EvalError> {
+    let mut bytes = vec![];
+    let mut rest = val;
+    while let Value::Cons(pair) = rest {
+        match pair.0 {
+            Value::Int(byte) if (0..256).contains(&byte) => bytes.push(byte as u8),
+            ref val => return Err(EvalError(format!("Expected a byte, found {}", val))),
+        }
+        rest = &pair.1;
+    }
+    Ok(bytes)
+}
+
+/// Evaluates a binary operation, using the same (wrapping) semantics as the
+/// generated wasm code. Operations which trap in wasm (like division by
+/// zero) are errors.
+fn eval_binop(op: BinOp, val1: &Value, val2: &Value) -> Result<Value, EvalError> {
+    match (op, val1, val2) {
+        (BinOp::Add, Value::Int(a), Value::Int(b)) => Ok(Value::Int(a.wrapping_add(*b))),
+        (BinOp::Subtract, Value::Int(a), Value::Int(b)) => Ok(Value::Int(a.wrapping_sub(*b))),
+        (BinOp::Multiply, Value::Int(a), Value::Int(b)) => Ok(Value::Int(a.wrapping_mul(*b))),
+        (BinOp::Divide, Value::Int(a), Value::Int(b)) => match a.checked_div(*b) {
+            Some(x) => Ok(Value::Int(x)),
+            None => Err(EvalError(format!("Cannot divide {} by {}.", a, b))),
+        },
+        (BinOp::LessThan, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a < b)),
+        (BinOp::GreaterThan, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a > b)),
+        (BinOp::LessOrEqual, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a <= b)),
+        (BinOp::GreaterOrEqual, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a >= b)),
+        (BinOp::EqualTo, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a == b)),
+        (BinOp::EqualTo, Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(a == b)),
+        (BinOp::Expt, Value::Int(a), Value::Int(b)) if *b >= 0 => {
+            Ok(Value::Int(a.wrapping_pow(*b as u32)))
+        }
+        (BinOp::Expt, Value::Int(a), Value::Int(b)) => Err(EvalError(format!(
+            "Cannot raise {} to a negative power {}.",
+            a, b
+        ))),
+        (BinOp::UnsignedLessThan, Value::Int(a), Value::Int(b)) => {
+            Ok(Value::Bool((*a as u32) < (*b as u32)))
+        }
+        (BinOp::UnsignedGreaterThan, Value::Int(a), Value::Int(b)) => {
+            Ok(Value::Bool((*a as u32) > (*b as u32)))
+        }
+        (BinOp::UnsignedDivide, Value::Int(a), Value::Int(b)) => {
+            match (*a as u32).checked_div(*b as u32) {
+                Some(x) => Ok(Value::Int(x as i32)),
+                None => Err(EvalError(format!("Cannot divide {} by {}.", a, b))),
+            }
+        }
+        (BinOp::UnsignedRemainder, Value::Int(a), Value::Int(b)) => {
+            match (*a as u32).checked_rem(*b as u32) {
+                Some(x) => Ok(Value::Int(x as i32)),
+                None => Err(EvalError(format!("Cannot divide {} by {}.", a, b))),
+            }
+        }
+        (BinOp::Add, Value::Float(a), Value::Float(b)) => Ok(Value::Float(a + b)),
+        (BinOp::Subtract, Value::Float(a), Value::Float(b)) => Ok(Value::Float(a - b)),
+        (BinOp::Multiply, Value::Float(a), Value::Float(b)) => Ok(Value::Float(a * b)),
+        (BinOp::Divide, Value::Float(a), Value::Float(b)) => Ok(Value::Float(a / b)),
+        (BinOp::LessThan, Value::Float(a), Value::Float(b)) => Ok(Value::Bool(a < b)),
+        (BinOp::GreaterThan, Value::Float(a), Value::Float(b)) => Ok(Value::Bool(a > b)),
+        (BinOp::LessOrEqual, Value::Float(a), Value::Float(b)) => Ok(Value::Bool(a <= b)),
+        (BinOp::GreaterOrEqual, Value::Float(a), Value::Float(b)) => Ok(Value::Bool(a >= b)),
+        (BinOp::EqualTo, Value::Float(a), Value::Float(b)) => Ok(Value::Bool(a == b)),
+        (BinOp::Expt, Value::Float(a), Value::Float(b)) => Ok(Value::Float(a.powf(*b))),
+        // like in the generated code, both arguments are always evaluated
+        (BinOp::And, Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(*a && *b)),
+        (BinOp::Or, Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(*a || *b)),
+        (BinOp::Concat, Value::Str(a), Value::Str(b)) => {
+            Ok(Value::Str(Rc::new(format!("{}{}", a, b))))
+        }
+        _ => Err(EvalError(format!(
+            "Invalid arguments to {}: {} and {}",
+            op, val1, val2
+        ))),
+    }
+}
+
+/// Evaluates an operation on a single value. Like in the generated code,
+/// converting a float which isn't an integer within range to an int is an
+/// error.
+fn eval_unop(op: UnOp, val: &Value) -> Result<Value, EvalError> {
+    match (op, val) {
+        (UnOp::ExactToInexact, Value::Int(x)) | (UnOp::IntToFloat, Value::Int(x)) => {
+            Ok(Value::Float(*x as f32))
+        }
+        (UnOp::InexactToExact, Value::Float(x))
+            if x.trunc() == *x && *x >= -2147483648.0 && *x < 2147483648.0 =>
+        {
+            Ok(Value::Int(*x as i32))
+        }
+        // matches wasm's i32.trunc_s, which traps rather than saturating
+        (UnOp::FloatToInt, Value::Float(x)) if *x >= -2147483648.0 && *x < 2147483648.0 => {
+            Ok(Value::Int(*x as i32))
+        }
+        (UnOp::Round, Value::Float(x)) => Ok(Value::Float(x.round_ties_even())),
+        (UnOp::Floor, Value::Float(x)) => Ok(Value::Float(x.floor())),
+        (UnOp::Ceiling, Value::Float(x)) => Ok(Value::Float(x.ceil())),
+        (UnOp::Truncate, Value::Float(x)) => Ok(Value::Float(x.trunc())),
+        (UnOp::Sqrt, Value::Float(x)) => Ok(Value::Float(x.sqrt())),
+        (UnOp::IsNan, Value::Float(x)) => Ok(Value::Bool(x.is_nan())),
+        (UnOp::IsInfinite, Value::Float(x)) => Ok(Value::Bool(x.is_infinite())),
+        _ => Err(EvalError(format!("Invalid argument to {}: {}", op, val))),
+    }
+}
+
+/// Fills in the directives of a format control string (see
+/// `util::format_directives`). `~a` displays strings without quotes, and
+/// `~s` writes them as literals.
+fn format_values(control: &str, args: &[Value]) -> String {
+    let mut result = String::new();
+    let mut args = args.iter();
+    let mut chars = control.chars();
+    while let Some(c) = chars.next() {
+        if c != '~' {
+            result.push(c);
+            continue;
+        }
+        match (chars.next(), args.clone().next()) {
+            (Some('a'), Some(Value::Str(x))) => {
+                result.push_str(x);
+                args.next();
+            }
+            (Some('a'), Some(val)) | (Some('s'), Some(val)) | (Some('d'), Some(val)) => {
+                result.push_str(&val.to_string());
+                args.next();
+            }
+            (Some('%'), _) => result.push('\n'),
+            (Some(other), _) => result.push(other),
+            (None, _) => (),
+        }
+    }
+    result
+}
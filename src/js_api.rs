@@ -0,0 +1,103 @@
+/// This module exposes the compiler to JavaScript with wasm-bindgen, for
+/// running it in a browser (e.g. in a playground) once it's built for
+/// wasm32-unknown-unknown with the "js-api" feature:
+///
+/// ```js
+/// import { compile } from "scheme-to-wasm";
+///
+/// try {
+///   const output = compile("(+ 1 2)");
+///   const { instance } = await WebAssembly.instantiate(output.wasm);
+///   console.log(instance.exports.$$MAIN$$(), output.type);
+/// } catch (diagnostics) {
+///   console.error(diagnostics.code, diagnostics.message);
+/// }
+/// ```
+///
+/// Building for that target also needs the default "fs" feature turned off,
+/// since there's no file system to read projects from.
+use crate::compile::compile_exp;
+use crate::desugar::desugar;
+use crate::explain::error_code;
+use crate::generate_code::construct_module_from_prog;
+use crate::limits::CompilerLimits;
+use crate::macro_expand::macro_expand_with_limits;
+use crate::parse::parse_with_limits;
+use crate::read::read;
+use std::error::Error;
+use wasm_bindgen::prelude::*;
+
+/// A compiled program.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct CompiledOutput {
+    wasm: Vec<u8>,
+    typ: String,
+}
+
+#[wasm_bindgen]
+impl CompiledOutput {
+    /// The binary of the compiled module.
+    #[wasm_bindgen(getter)]
+    pub fn wasm(&self) -> Vec<u8> {
+        self.wasm.clone()
+    }
+
+    /// The type of the value returned by the module's `$$MAIN$$` function.
+    #[wasm_bindgen(getter, js_name = type)]
+    pub fn typ(&self) -> String {
+        self.typ.clone()
+    }
+}
+
+/// Why a program couldn't be compiled.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct JsDiagnostics {
+    message: String,
+    code: Option<String>,
+}
+
+#[wasm_bindgen]
+impl JsDiagnostics {
+    #[wasm_bindgen(getter)]
+    pub fn message(&self) -> String {
+        self.message.clone()
+    }
+
+    /// The code of the error, which `explain` describes in more detail, if it
+    /// has one.
+    #[wasm_bindgen(getter)]
+    pub fn code(&self) -> Option<String> {
+        self.code.clone()
+    }
+}
+
+impl From<Box<dyn Error>> for JsDiagnostics {
+    fn from(err: Box<dyn Error>) -> Self {
+        JsDiagnostics {
+            message: err.to_string(),
+            code: error_code(err.as_ref()).map(String::from),
+        }
+    }
+}
+
+/// Compiles a program's source code into a wasm module.
+#[wasm_bindgen]
+pub fn compile(source: &str) -> Result<CompiledOutput, JsDiagnostics> {
+    compile_source(source).map_err(JsDiagnostics::from)
+}
+
+fn compile_source(source: &str) -> Result<CompiledOutput, Box<dyn Error>> {
+    let limits = CompilerLimits::default();
+    let exp = parse_with_limits(
+        &desugar(&macro_expand_with_limits(&read(source)?, &limits)?)?,
+        &limits,
+    )?;
+    let prog = compile_exp(&exp)?;
+    let wasm = parity_wasm::serialize(construct_module_from_prog(&prog)?)?;
+    Ok(CompiledOutput {
+        wasm,
+        typ: prog.exp.typ.to_string(),
+    })
+}
@@ -1,4 +1,5 @@
 use crate::common::{generate_func_name, Expr, ExprKind, Prog};
+use crate::types::Type;
 use im_rc::{vector, Vector};
 
 #[derive(Clone, Debug)]
@@ -31,6 +32,7 @@ fn ll_array(
 fn ll(exp: &Expr, fns: &mut Vector<(String, Expr)>) -> Result<Expr, LambdaLiftError> {
     match &*exp.kind {
         ExprKind::Num(_) => Ok(exp.clone()),
+        ExprKind::Float(_) => Ok(exp.clone()),
         ExprKind::Bool(_) => Ok(exp.clone()),
         ExprKind::Str(_) => Ok(exp.clone()),
         ExprKind::Id(_) => Ok(exp.clone()),
@@ -39,6 +41,10 @@ fn ll(exp: &Expr, fns: &mut Vector<(String, Expr)>) -> Result<Expr, LambdaLiftEr
             let lexp2 = ll(&exp2, fns)?;
             Ok(Expr::new(ExprKind::Binop(*op, lexp1, lexp2)))
         }
+        ExprKind::Unop(op, exp) => {
+            let lexp = ll(&exp, fns)?;
+            Ok(Expr::new(ExprKind::Unop(*op, lexp)))
+        }
         ExprKind::If(pred, cons, alt) => {
             let lpred = ll(&pred, fns)?;
             let lcons = ll(&cons, fns)?;
@@ -116,10 +122,84 @@ fn ll(exp: &Expr, fns: &mut Vector<(String, Expr)>) -> Result<Expr, LambdaLiftEr
             let ltup = ll(&tup, fns)?;
             Ok(Expr::new(ExprKind::TupleGet(ltup, *key)))
         }
+        ExprKind::TupleSet(tup, key, val) => {
+            let ltup = ll(&tup, fns)?;
+            let lval = ll(&val, fns)?;
+            Ok(Expr::new(ExprKind::TupleSet(ltup, *key, lval)))
+        }
         ExprKind::Pack(val, sub, exist) => {
             let lval = ll(&val, fns)?;
             Ok(Expr::new(ExprKind::Pack(lval, sub.clone(), exist.clone())))
         }
+        ExprKind::Inject(val, union_typ) => {
+            let lval = ll(&val, fns)?;
+            Ok(Expr::new(ExprKind::Inject(lval, union_typ.clone())))
+        }
+        ExprKind::Assert(val, message) => {
+            let lval = ll(&val, fns)?;
+            Ok(Expr::new(ExprKind::Assert(lval, message.clone())))
+        }
+        ExprKind::While(pred, body) => {
+            let lpred = ll(&pred, fns)?;
+            let lbody = ll(&body, fns)?;
+            Ok(Expr::new(ExprKind::While(lpred, lbody)))
+        }
+        ExprKind::Case(key, clauses, else_body) => {
+            let lkey = ll(key, fns)?;
+            let lclauses = clauses
+                .iter()
+                .map(|(datums, body)| Ok((datums.clone(), ll(body, fns)?)))
+                .collect::<Result<Vector<(Vector<i32>, Expr)>, LambdaLiftError>>()?;
+            let lelse = ll(else_body, fns)?;
+            Ok(Expr::new(ExprKind::Case(lkey, lclauses, lelse)))
+        }
+        ExprKind::Curry(_func, _args) => Err(LambdaLiftError::from(
+            "Curry expressions should be converted to closures via closure conversion pass.",
+        )),
+        ExprKind::Compose(_outer, _inner) => Err(LambdaLiftError::from(
+            "Compose expressions should be converted to closures via closure conversion pass.",
+        )),
+        ExprKind::Delay(val) => {
+            let lval = ll(&val, fns)?;
+            Ok(Expr::new(ExprKind::Delay(lval)))
+        }
+        ExprKind::Force(promise) => {
+            let lpromise = ll(&promise, fns)?;
+            Ok(Expr::new(ExprKind::Force(lpromise)))
+        }
+        ExprKind::Format(control, args) => {
+            let largs = ll_array(&args, fns)?;
+            Ok(Expr::new(ExprKind::Format(control.clone(), largs)))
+        }
+        ExprKind::Builtin(builtin, args) => {
+            let largs = ll_array(&args, fns)?;
+            Ok(Expr::new(ExprKind::Builtin(*builtin, largs)))
+        }
+        ExprKind::Extern(module, name, typ, args) => {
+            let largs = ll_array(&args, fns)?;
+            Ok(Expr::new(ExprKind::Extern(
+                module.clone(),
+                name.clone(),
+                typ.clone(),
+                largs,
+            )))
+        }
+        ExprKind::WasmAsm(code, typ, args) => {
+            let largs = ll_array(&args, fns)?;
+            Ok(Expr::new(ExprKind::WasmAsm(
+                code.clone(),
+                typ.clone(),
+                largs,
+            )))
+        }
+        ExprKind::TypeCase(val, clauses) => {
+            let lval = ll(&val, fns)?;
+            let lclauses = clauses
+                .iter()
+                .map(|(typ, var, body)| Ok((typ.clone(), var.clone(), ll(&body, fns)?)))
+                .collect::<Result<Vector<(Type, String, Expr)>, LambdaLiftError>>()?;
+            Ok(Expr::new(ExprKind::TypeCase(lval, lclauses)))
+        }
         ExprKind::Unpack(var, package, type_sub, body) => {
             let lpackage = ll(&package, fns)?;
             let lbody = ll(&body, fns)?;
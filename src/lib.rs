@@ -1,11 +1,33 @@
+pub mod analysis;
+pub mod assignment_convert;
 pub mod ast_transform;
 pub mod closure_convert;
 pub mod common;
 pub mod compile;
+pub mod desugar;
+pub mod dump;
+pub mod effects;
+pub mod explain;
+pub mod features;
 pub mod generate_code;
+#[cfg(feature = "host")]
+pub mod host;
+pub mod interpret;
+#[cfg(feature = "js-api")]
+pub mod js_api;
 pub mod lambda_lift;
+pub mod limits;
+pub mod macro_expand;
+pub mod optimize;
 pub mod parse;
+pub mod pretty;
+#[cfg(feature = "fs")]
+pub mod project;
+pub mod read;
 pub mod record_elim;
+pub mod regex;
+pub mod repl;
+pub mod resolve;
 pub mod type_check;
 pub mod types;
 pub mod util;
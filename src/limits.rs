@@ -0,0 +1,143 @@
+/// This module defines limits on the size of programs which the compiler
+/// will accept, so that compiling untrusted input (e.g. in a web playground)
+/// can't exhaust the compiler's memory or stack.
+///
+/// Limits are checked during macro expansion (see
+/// `macro_expand::macro_expand_with_limits`), on the s-expressions given to
+/// the parser (see `parse::parse_with_limits`), which bounds the work of
+/// every later pass, and on the size of the module produced by code
+/// generation (see `generate_code::CodeGenerateOptions`).
+use lexpr::Value;
+
+/// The default `CompilerLimits::max_depth`. The compiler's passes recurse
+/// over expressions, and (in release builds) a program nested this deeply
+/// compiles within a 1 MiB stack, the default for wasm. Debug builds use much
+/// more stack for each level.
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// Limits on the size of a program and of its compiled output. The default
+/// limits are far larger than hand-written programs need, but small enough
+/// that compiling untrusted input within them is safe; `unlimited` removes
+/// them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CompilerLimits {
+    /// The deepest that s-expressions can be nested, counting the top level
+    /// expression as depth 1.
+    pub max_depth: usize,
+    /// The largest number of s-expressions (atoms and lists) in a program,
+    /// and in all of the code produced by expanding macros.
+    pub max_nodes: usize,
+    /// The largest number of macro uses which are expanded.
+    pub max_expansions: usize,
+    /// The largest size of a compiled module, in bytes.
+    pub max_output_size: usize,
+}
+
+impl CompilerLimits {
+    /// Limits which are never exceeded.
+    pub fn unlimited() -> Self {
+        CompilerLimits {
+            max_depth: usize::MAX,
+            max_nodes: usize::MAX,
+            max_expansions: usize::MAX,
+            max_output_size: usize::MAX,
+        }
+    }
+}
+
+impl Default for CompilerLimits {
+    fn default() -> Self {
+        CompilerLimits {
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_nodes: 1_000_000,
+            max_expansions: 100_000,
+            max_output_size: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// An error from a program exceeding one of the `CompilerLimits`. Each
+/// variant contains the limit which was exceeded.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LimitError {
+    TooDeep(usize),
+    TooManyNodes(usize),
+    TooManyExpansions(usize),
+    OutputTooLarge(usize),
+}
+
+// Allows other errors to wrap this one
+impl std::error::Error for LimitError {}
+
+impl std::fmt::Display for LimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LimitError::TooDeep(max) => write!(
+                f,
+                "LimitError: Program is nested more than {} levels deep.",
+                max
+            ),
+            LimitError::TooManyNodes(max) => {
+                write!(f, "LimitError: Program has more than {} expressions.", max)
+            }
+            LimitError::TooManyExpansions(max) => write!(
+                f,
+                "LimitError: Program expands more than {} macro uses.",
+                max
+            ),
+            LimitError::OutputTooLarge(max) => write!(
+                f,
+                "LimitError: Compiled module is larger than {} bytes.",
+                max
+            ),
+        }
+    }
+}
+
+/// Checks that an s-expression is within the depth and node limits.
+///
+/// The s-expression is traversed with an explicit stack rather than by
+/// recursion, so that checking a deeply nested program can't overflow the
+/// stack itself.
+pub fn check_value(value: &Value, limits: &CompilerLimits) -> Result<(), LimitError> {
+    count_nodes(value, limits).map(|_nodes| ())
+}
+
+/// Like `check_value`, but returns the number of s-expressions in `value`.
+pub fn count_nodes(value: &Value, limits: &CompilerLimits) -> Result<usize, LimitError> {
+    let mut nodes = 0;
+    let mut stack = vec![(value, 1)];
+    while let Some((value, depth)) = stack.pop() {
+        nodes += 1;
+        if nodes > limits.max_nodes {
+            return Err(LimitError::TooManyNodes(limits.max_nodes));
+        }
+        if depth > limits.max_depth {
+            return Err(LimitError::TooDeep(limits.max_depth));
+        }
+        match value {
+            Value::Cons(_) => {
+                let mut rest = value;
+                while let Value::Cons(pair) = rest {
+                    stack.push((pair.car(), depth + 1));
+                    rest = pair.cdr();
+                }
+                if !rest.is_null() {
+                    stack.push((rest, depth + 1));
+                }
+            }
+            Value::Vector(vals) => stack.extend(vals.iter().map(|val| (val, depth + 1))),
+            _ => (),
+        }
+    }
+    Ok(nodes)
+}
+
+/// Checks that a compiled module, serialized to `size` bytes, is within the
+/// output size limit.
+pub fn check_output_size(size: usize, limits: &CompilerLimits) -> Result<(), LimitError> {
+    if size > limits.max_output_size {
+        return Err(LimitError::OutputTooLarge(limits.max_output_size));
+    }
+    Ok(())
+}
@@ -0,0 +1,1050 @@
+use crate::common::generate_id;
+use crate::limits::{check_value, count_nodes, CompilerLimits, LimitError};
+use im_rc::{HashMap, Vector};
+use lexpr::Value;
+
+#[derive(Clone, Debug)]
+pub struct MacroExpandError(String);
+
+// Allows other errors to wrap this one
+impl std::error::Error for MacroExpandError {}
+
+impl From<&str> for MacroExpandError {
+    fn from(message: &str) -> Self {
+        MacroExpandError(String::from(message))
+    }
+}
+
+impl std::fmt::Display for MacroExpandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "MacroExpandError: {}", self.0)
+    }
+}
+
+/// A macro defined with `syntax-rules`: a list of literal identifiers, and a
+/// list of (pattern, template) rules which are tried in order.
+#[derive(Clone, Debug)]
+struct SyntaxRules {
+    literals: Vec<String>,
+    rules: Vec<(Value, Value)>,
+}
+
+/// A macro defined with `define-macro`: the names of its parameters, the
+/// name of a parameter bound to any remaining arguments (if any), and a body
+/// which computes the expansion.
+#[derive(Clone, Debug)]
+struct ProcMacro {
+    params: Vec<String>,
+    rest_param: Option<String>,
+    body: Value,
+}
+
+/// A function declared with `declare-extern`: the host module and name it is
+/// imported from, and its type annotation.
+#[derive(Clone, Debug)]
+struct ExternDecl {
+    module: Value,
+    name: Value,
+    typ: Value,
+}
+
+/// A name which the pass replaces: a macro, a constant defined with
+/// `define-constant` (whose value is a number, bool or string), or an extern
+/// function.
+#[derive(Clone, Debug)]
+enum Macro {
+    Rules(SyntaxRules),
+    Proc(ProcMacro),
+    Constant(Value),
+    Extern(ExternDecl),
+}
+
+/// The macros and constants in scope, in order of definition. Later
+/// definitions shadow earlier ones with the same name.
+type MacroEnv = Vector<(String, Macro)>;
+
+/// The variables bound while evaluating the body of a `define-macro` macro.
+type EvalEnv = Vector<(String, Value)>;
+
+/// The values matched by the variables in a pattern. Variables which appear
+/// under an ellipsis match a sequence of values (one per repetition).
+#[derive(Clone, Debug)]
+enum Match {
+    One(Value),
+    Many(Vec<Match>),
+}
+
+type Bindings = HashMap<String, Match>;
+
+/// The state of an expansion: the constants defined so far, and how much
+/// expansion has been done, which is bounded by the `CompilerLimits`.
+struct ExpandState {
+    constants: Vector<(String, Value)>,
+    limits: CompilerLimits,
+    depth: usize,
+    expansions: usize,
+    nodes: usize,
+    /// The limit which was exceeded, if expansion stopped because of one.
+    limit_error: Option<LimitError>,
+}
+
+impl ExpandState {
+    fn new(limits: CompilerLimits) -> Self {
+        ExpandState {
+            constants: Vector::new(),
+            limits,
+            depth: 0,
+            expansions: 0,
+            nodes: 0,
+            limit_error: None,
+        }
+    }
+
+    /// Records that a limit was exceeded, returning an error which stops the
+    /// expansion.
+    fn exceeded(&mut self, err: LimitError) -> MacroExpandError {
+        let message = err.to_string();
+        self.limit_error = Some(err);
+        MacroExpandError(message)
+    }
+
+    /// Counts the expansion of a macro use into `expanded`, which must not
+    /// take the total expansions or the total size of all expanded code over
+    /// their limits.
+    fn count_expansion(&mut self, expanded: &Value) -> Result<(), MacroExpandError> {
+        self.expansions += 1;
+        if self.expansions > self.limits.max_expansions {
+            return Err(self.exceeded(LimitError::TooManyExpansions(self.limits.max_expansions)));
+        }
+        let remaining = CompilerLimits {
+            max_nodes: self.limits.max_nodes - self.nodes,
+            ..self.limits
+        };
+        match count_nodes(expanded, &remaining) {
+            Ok(nodes) => {
+                self.nodes += nodes;
+                Ok(())
+            }
+            Err(LimitError::TooManyNodes(_)) => {
+                Err(self.exceeded(LimitError::TooManyNodes(self.limits.max_nodes)))
+            }
+            Err(err) => Err(self.exceeded(err)),
+        }
+    }
+}
+
+/// Expands all macro uses within a (read, but not yet parsed) program.
+///
+/// Macros are defined with `(define-syntax name (syntax-rules (literal ...)
+/// (pattern template) ...))` as one of the expressions in a begin
+/// expression, and can be used in all of the expressions after it. The
+/// definitions themselves are removed from the output.
+///
+/// Expansion is hygienic with respect to the variables that a template
+/// binds (with let, lambda, unpack or typecase): these are renamed on each
+/// expansion, so they can never capture variables from the code passed into
+/// the macro. Other identifiers in a template refer to whatever is in scope
+/// where the macro is used, so expansion is not hygienic for them: a free
+/// identifier in a template (e.g. a variable bound outside of the macro) is
+/// captured by a local variable with the same name around the macro use.
+/// The forms which templates use, like if and let, can't be captured this
+/// way, since their names are reserved words which can't be bound.
+///
+/// Macros can also be defined with `(define-macro (name param ...) body)`,
+/// in which case the body is evaluated with the (unevaluated) arguments bound
+/// to the parameters, and its result replaces the macro use. These macros are
+/// not hygienic, but can use `(gensym)` to create fresh variable names. See
+/// `eval_macro_body` for the forms available within the body.
+///
+/// Finally, `(define-constant name exp)` defines a constant which is
+/// replaced by its value everywhere it is used. The value must be a string,
+/// or computable at compile time from numbers, booleans, other constants,
+/// operators and if expressions. Constants cannot be rebound or assigned.
+///
+/// `(declare-extern name "module" "field" (-> param ... ret))` declares a
+/// function provided by the host, which is imported from the given module and
+/// field of the WebAssembly module's imports. Calls `(name arg ...)` are
+/// replaced by `(call-extern "module" "field" (-> param ... ret) arg ...)`, so
+/// that they are type checked against the declared signature. Other uses of
+/// `name` (e.g. passing it as a callback to another function) are replaced by
+/// a lambda which calls the extern function.
+pub fn macro_expand(value: &Value) -> Result<Value, MacroExpandError> {
+    Ok(macro_expand_with_constants(value)?.0)
+}
+
+/// Like `macro_expand`, but first checks that the s-expression is within the
+/// depth and node limits, and stops expanding with a `LimitError` if
+/// expansion nests too deeply, expands too many macro uses, or produces too
+/// much code in total (e.g. a macro whose expansion uses itself forever).
+pub fn macro_expand_with_limits(
+    value: &Value,
+    limits: &CompilerLimits,
+) -> Result<Value, Box<dyn std::error::Error>> {
+    check_value(value, limits)?;
+    let mut state = ExpandState::new(*limits);
+    match expand(value, &Vector::new(), &mut state) {
+        Ok(expanded) => Ok(expanded),
+        Err(err) => match state.limit_error {
+            Some(limit_err) => Err(Box::new(limit_err)),
+            None => Err(Box::new(err)),
+        },
+    }
+}
+
+/// Like `macro_expand`, but also returns the names and values of all
+/// constants defined with `define-constant`, e.g. so that they can be
+/// exported from the WebAssembly module (see
+/// `generate_code::CodeGenerateOptions`). As in compiled code, booleans are
+/// represented by 0 and 1, and floats by their bits. String constants are not
+/// included.
+pub fn macro_expand_with_constants(
+    value: &Value,
+) -> Result<(Value, Vector<(String, i32)>), MacroExpandError> {
+    let mut state = ExpandState::new(CompilerLimits::unlimited());
+    let expanded = expand(value, &Vector::new(), &mut state)?;
+    let constants = state
+        .constants
+        .into_iter()
+        .filter_map(|(name, val)| match val {
+            Value::Bool(x) => Some((name, x as i32)),
+            Value::Number(_) => match val.as_i64() {
+                Some(x) => Some((name, x as i32)),
+                None => val.as_f64().map(|x| (name, (x as f32).to_bits() as i32)),
+            },
+            _ => None,
+        })
+        .collect();
+    Ok((expanded, constants))
+}
+
+fn find_macro<'a>(name: &str, macros: &'a MacroEnv) -> Option<&'a Macro> {
+    macros
+        .iter()
+        .rev()
+        .find(|pair| pair.0 == name)
+        .map(|pair| &pair.1)
+}
+
+fn expand(
+    value: &Value,
+    macros: &MacroEnv,
+    state: &mut ExpandState,
+) -> Result<Value, MacroExpandError> {
+    // expansion recurses into each subexpression, and into the result of
+    // each macro use, so its depth is limited to keep it from overflowing the
+    // stack
+    if state.depth >= state.limits.max_depth {
+        return Err(state.exceeded(LimitError::TooDeep(state.limits.max_depth)));
+    }
+    state.depth += 1;
+    let result = expand_nested(value, macros, state);
+    state.depth -= 1;
+    result
+}
+
+fn expand_nested(
+    value: &Value,
+    macros: &MacroEnv,
+    state: &mut ExpandState,
+) -> Result<Value, MacroExpandError> {
+    if let Value::Symbol(sym) = value {
+        return match find_macro(sym, macros) {
+            Some(Macro::Constant(val)) => Ok(val.clone()),
+            Some(Macro::Extern(decl)) => extern_lambda(sym, decl),
+            _ => Ok(value.clone()),
+        };
+    }
+    let lst = match value.to_vec() {
+        Some(lst) if !lst.is_empty() => lst,
+        _ => return Ok(value.clone()),
+    };
+    check_constants_not_rebound(&lst, macros)?;
+    match lst[0].as_symbol() {
+        Some("begin") => expand_begin(&lst[1..], macros, state),
+        Some("define-syntax") | Some("define-macro") | Some("define-constant") => {
+            Err(MacroExpandError::from(
+                "Macros and constants can only be defined within a begin expression.",
+            ))
+        }
+        Some("declare-extern") => Err(MacroExpandError::from(
+            "Extern functions can only be declared within a begin expression.",
+        )),
+        Some(name) => match find_macro(name, macros) {
+            Some(Macro::Rules(syntax_rules)) => {
+                let expanded = expand_macro_use(name, syntax_rules, value)?;
+                state.count_expansion(&expanded)?;
+                expand(&expanded, macros, state)
+            }
+            Some(Macro::Proc(proc_macro)) => {
+                let expanded = expand_proc_macro_use(name, proc_macro, &lst[1..])?;
+                state.count_expansion(&expanded)?;
+                expand(&expanded, macros, state)
+            }
+            Some(Macro::Extern(decl)) => {
+                let mut call = vec![
+                    Value::symbol("call-extern"),
+                    decl.module.clone(),
+                    decl.name.clone(),
+                    decl.typ.clone(),
+                ];
+                for arg in &lst[1..] {
+                    call.push(expand(arg, macros, state)?);
+                }
+                Ok(Value::list(call))
+            }
+            _ => expand_list(&lst, macros, state),
+        },
+        None => expand_list(&lst, macros, state),
+    }
+}
+
+fn expand_list(
+    lst: &[Value],
+    macros: &MacroEnv,
+    state: &mut ExpandState,
+) -> Result<Value, MacroExpandError> {
+    let expanded = lst
+        .iter()
+        .map(|val| expand(val, macros, state))
+        .collect::<Result<Vec<Value>, MacroExpandError>>()?;
+    Ok(Value::list(expanded))
+}
+
+fn expand_begin(
+    rest: &[Value],
+    macros: &MacroEnv,
+    state: &mut ExpandState,
+) -> Result<Value, MacroExpandError> {
+    let mut macros = macros.clone();
+    let mut exps = vec![Value::symbol("begin")];
+    for val in rest {
+        let definition_kind = match val.to_vec() {
+            Some(lst) if !lst.is_empty() => lst[0].as_symbol().map(String::from),
+            _ => None,
+        };
+        match definition_kind.as_deref() {
+            Some("define-syntax") => macros.push_back(parse_define_syntax(val)?),
+            Some("define-macro") => macros.push_back(parse_define_macro(val)?),
+            Some("declare-extern") => macros.push_back(parse_declare_extern(val)?),
+            Some("define-constant") => {
+                let (name, constant) = parse_define_constant(val, &macros)?;
+                state.constants.push_back((name.clone(), constant.clone()));
+                macros.push_back((name, Macro::Constant(constant)));
+            }
+            _ => exps.push(expand(val, &macros, state)?),
+        }
+    }
+    if exps.len() == 1 {
+        return Err(MacroExpandError::from(
+            "Begin expression has no expressions besides macro, constant, and extern definitions.",
+        ));
+    }
+    Ok(Value::list(exps))
+}
+
+/// Returns an error if the list `lst` is a form which binds or assigns to a
+/// constant.
+fn check_constants_not_rebound(lst: &[Value], macros: &MacroEnv) -> Result<(), MacroExpandError> {
+    let mut names = form_binders(lst);
+    if lst.len() == 3 && lst[0].as_symbol() == Some("set!") {
+        names.extend(lst[1].as_symbol().map(String::from));
+    }
+    for name in names {
+        if let Some(Macro::Constant(_)) = find_macro(&name, macros) {
+            return Err(MacroExpandError(format!(
+                "Constant {} cannot be rebound or assigned.",
+                name
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn parse_define_constant(
+    value: &Value,
+    macros: &MacroEnv,
+) -> Result<(String, Value), MacroExpandError> {
+    let lst = value.to_vec().unwrap();
+    if lst.len() != 3 {
+        return Err(MacroExpandError::from(
+            "Define-constant expression has incorrect number of arguments.",
+        ));
+    }
+    let name = lst[1]
+        .as_symbol()
+        .ok_or("Define-constant expression does not have a symbol as its name.")?;
+    let constant = eval_constant(&lst[2], macros)?;
+    Ok((String::from(name), constant))
+}
+
+fn parse_declare_extern(value: &Value) -> Result<(String, Macro), MacroExpandError> {
+    let lst = value.to_vec().unwrap();
+    if lst.len() != 5 {
+        return Err(MacroExpandError::from(
+            "Declare-extern expression has incorrect number of arguments.",
+        ));
+    }
+    let name = lst[1]
+        .as_symbol()
+        .ok_or("Declare-extern expression does not have a symbol as its name.")?;
+    if lst[2].as_str().is_none() || lst[3].as_str().is_none() {
+        return Err(MacroExpandError::from(
+            "Declare-extern expression must have strings as its module and field.",
+        ));
+    }
+    let decl = ExternDecl {
+        module: lst[2].clone(),
+        name: lst[3].clone(),
+        typ: lst[4].clone(),
+    };
+    Ok((String::from(name), Macro::Extern(decl)))
+}
+
+/// Creates a lambda which calls an extern function, for uses of the function
+/// as a value, e.g. to pass it as a callback to another function.
+fn extern_lambda(name: &str, decl: &ExternDecl) -> Result<Value, MacroExpandError> {
+    let not_function = || {
+        MacroExpandError(format!(
+            "Extern function {} does not have a function type: {}",
+            name, decl.typ
+        ))
+    };
+    let typ = decl.typ.to_vec().ok_or_else(not_function)?;
+    match typ.first().and_then(|head| head.as_symbol()) {
+        Some("->") | Some("pure->") if typ.len() >= 2 => (),
+        _ => return Err(not_function()),
+    }
+    let (ret_type, param_types) = typ[1..].split_last().unwrap();
+    let params: Vec<Value> = param_types
+        .iter()
+        .map(|_| Value::symbol(format!("{}%{}", name, generate_id())))
+        .collect();
+    let mut call = vec![
+        Value::symbol("call-extern"),
+        decl.module.clone(),
+        decl.name.clone(),
+        decl.typ.clone(),
+    ];
+    call.extend(params.iter().cloned());
+    let param_list = params
+        .into_iter()
+        .zip(param_types)
+        .map(|(param, typ)| Value::list(vec![param, Value::symbol(":"), typ.clone()]));
+    Ok(Value::list(vec![
+        Value::symbol("lambda"),
+        Value::list(param_list.collect::<Vec<Value>>()),
+        Value::symbol(":"),
+        ret_type.clone(),
+        Value::list(call),
+    ]))
+}
+
+/// Evaluates the value of a constant, which is either a string or may only
+/// use numbers, booleans, previously defined constants, binary operators and
+/// if expressions.
+fn eval_constant(exp: &Value, macros: &MacroEnv) -> Result<Value, MacroExpandError> {
+    let not_constant = || {
+        MacroExpandError(format!(
+            "Expression {} is not a compile-time constant.",
+            exp
+        ))
+    };
+    match exp {
+        Value::Number(_) | Value::Bool(_) | Value::String(_) => return Ok(exp.clone()),
+        Value::Symbol(sym) => {
+            return match (&**sym, find_macro(sym, macros)) {
+                ("true", _) => Ok(Value::Bool(true)),
+                ("false", _) => Ok(Value::Bool(false)),
+                (_, Some(Macro::Constant(val))) => Ok(val.clone()),
+                _ => Err(not_constant()),
+            }
+        }
+        _ => (),
+    }
+    let lst = exp.to_vec().ok_or_else(not_constant)?;
+    let op = match lst.first().and_then(|first| first.as_symbol()) {
+        Some(op) if lst.len() == 3 || (op == "if" && lst.len() == 4) => op,
+        _ => return Err(not_constant()),
+    };
+    if op == "if" {
+        return match eval_constant(&lst[1], macros)? {
+            Value::Bool(true) => eval_constant(&lst[2], macros),
+            Value::Bool(false) => eval_constant(&lst[3], macros),
+            _ => Err(not_constant()),
+        };
+    }
+    let arg1 = eval_constant(&lst[1], macros)?;
+    let arg2 = eval_constant(&lst[2], macros)?;
+    let overflow = || MacroExpandError(format!("Overflow in constant expression {}.", exp));
+    match (op, &arg1, &arg2) {
+        ("and", Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(*a && *b)),
+        ("or", Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(*a || *b)),
+        (_, Value::Number(_), Value::Number(_)) => {
+            let a = arg1.as_i64().ok_or_else(not_constant)?;
+            let b = arg2.as_i64().ok_or_else(not_constant)?;
+            let result = match op {
+                "+" => Value::from(a.checked_add(b).ok_or_else(overflow)?),
+                "-" => Value::from(a.checked_sub(b).ok_or_else(overflow)?),
+                "*" => Value::from(a.checked_mul(b).ok_or_else(overflow)?),
+                "/" if b == 0 => {
+                    return Err(MacroExpandError::from(
+                        "Division by zero in constant expression.",
+                    ))
+                }
+                "/" => Value::from(a.checked_div(b).ok_or_else(overflow)?),
+                "<" => Value::Bool(a < b),
+                ">" => Value::Bool(a > b),
+                "<=" => Value::Bool(a <= b),
+                ">=" => Value::Bool(a >= b),
+                "=" => Value::Bool(a == b),
+                _ => return Err(not_constant()),
+            };
+            match result.as_i64() {
+                Some(val) if val < i64::from(i32::MIN) || val > i64::from(i32::MAX) => {
+                    Err(overflow())
+                }
+                _ => Ok(result),
+            }
+        }
+        _ => Err(not_constant()),
+    }
+}
+
+fn parse_define_syntax(value: &Value) -> Result<(String, Macro), MacroExpandError> {
+    let lst = value.to_vec().unwrap();
+    if lst.len() != 3 {
+        return Err(MacroExpandError::from(
+            "Define-syntax expression has incorrect number of arguments.",
+        ));
+    }
+    let name = lst[1]
+        .as_symbol()
+        .ok_or("Define-syntax expression does not have a symbol as its name.")?;
+    let rules_lst = lst[2]
+        .to_vec()
+        .ok_or("Define-syntax expression does not contain syntax-rules.")?;
+    if rules_lst.len() < 2 || rules_lst[0].as_symbol() != Some("syntax-rules") {
+        return Err(MacroExpandError(format!(
+            "Macro {} is not defined with syntax-rules.",
+            name
+        )));
+    }
+    let literals = rules_lst[1]
+        .to_vec()
+        .ok_or("Syntax-rules does not have a list of literals.")?
+        .iter()
+        .map(|literal| {
+            literal
+                .as_symbol()
+                .map(String::from)
+                .ok_or_else(|| MacroExpandError::from("Syntax-rules literal is not a symbol."))
+        })
+        .collect::<Result<Vec<String>, MacroExpandError>>()?;
+    let rules = rules_lst[2..]
+        .iter()
+        .map(|rule| match rule.to_vec() {
+            Some(pair) if pair.len() == 2 && pair[0].is_cons() => {
+                Ok((pair[0].clone(), pair[1].clone()))
+            }
+            _ => Err(MacroExpandError(format!(
+                "Rule in macro {} is not of the form (pattern template).",
+                name
+            ))),
+        })
+        .collect::<Result<Vec<(Value, Value)>, MacroExpandError>>()?;
+    Ok((
+        String::from(name),
+        Macro::Rules(SyntaxRules { literals, rules }),
+    ))
+}
+
+fn parse_define_macro(value: &Value) -> Result<(String, Macro), MacroExpandError> {
+    let lst = value.to_vec().unwrap();
+    if lst.len() != 3 {
+        return Err(MacroExpandError::from(
+            "Define-macro expression has incorrect number of arguments.",
+        ));
+    }
+    // (name param ...) or (name param ... . rest)
+    let (signature, rest) = lst[1]
+        .as_cons()
+        .map(|cons| cons.to_ref_vec())
+        .ok_or("Define-macro expression does not have a list as its signature.")?;
+    let names = signature
+        .iter()
+        .map(|val| {
+            val.as_symbol().map(String::from).ok_or_else(|| {
+                MacroExpandError::from("Define-macro signature contains a non-symbol.")
+            })
+        })
+        .collect::<Result<Vec<String>, MacroExpandError>>()?;
+    let rest_param = match rest {
+        Value::Null => None,
+        Value::Symbol(sym) => Some(sym.to_string()),
+        _ => {
+            return Err(MacroExpandError::from(
+                "Define-macro signature contains a non-symbol.",
+            ))
+        }
+    };
+    let proc_macro = ProcMacro {
+        params: names[1..].to_vec(),
+        rest_param,
+        body: lst[2].clone(),
+    };
+    Ok((names[0].clone(), Macro::Proc(proc_macro)))
+}
+
+fn expand_macro_use(
+    name: &str,
+    syntax_rules: &SyntaxRules,
+    value: &Value,
+) -> Result<Value, MacroExpandError> {
+    // The first element of a pattern is always the macro's name, so it is
+    // skipped when matching
+    let args = value.to_vec().unwrap().split_off(1);
+    for (pattern, template) in syntax_rules.rules.iter() {
+        let pattern_args = pattern.to_vec().unwrap().split_off(1);
+        let mut bindings = Bindings::new();
+        if match_list(&pattern_args, &args, &syntax_rules.literals, &mut bindings)? {
+            let renames: HashMap<String, String> = template_binders(template, &bindings)
+                .into_iter()
+                .map(|binder| {
+                    let fresh = format!("{}%{}", binder, generate_id());
+                    (binder, fresh)
+                })
+                .collect();
+            return instantiate(template, &bindings, &renames);
+        }
+    }
+    Err(MacroExpandError(format!(
+        "No rule of macro {} matches {}.",
+        name, value
+    )))
+}
+
+fn expand_proc_macro_use(
+    name: &str,
+    proc_macro: &ProcMacro,
+    args: &[Value],
+) -> Result<Value, MacroExpandError> {
+    let params_len = proc_macro.params.len();
+    if args.len() < params_len || (proc_macro.rest_param.is_none() && args.len() > params_len) {
+        return Err(MacroExpandError(format!(
+            "Macro {} was given the wrong number of arguments.",
+            name
+        )));
+    }
+    let mut env: EvalEnv = proc_macro
+        .params
+        .iter()
+        .cloned()
+        .zip(args.iter().cloned())
+        .collect();
+    if let Some(rest_param) = &proc_macro.rest_param {
+        env.push_back((rest_param.clone(), Value::list(args[params_len..].to_vec())));
+    }
+    eval_macro_body(&proc_macro.body, &env)
+}
+
+fn is_truthy(value: &Value) -> bool {
+    !matches!(value, Value::Bool(false)) && value.as_symbol() != Some("false")
+}
+
+fn eval_int(exp: &Value, env: &EvalEnv) -> Result<i64, MacroExpandError> {
+    let val = eval_macro_body(exp, env)?;
+    val.as_i64()
+        .ok_or_else(|| MacroExpandError(format!("Expected a number in macro, found {}.", val)))
+}
+
+/// Evaluates the body of a `define-macro` macro, where values are
+/// s-expressions.
+///
+/// The body can use numbers, booleans, strings, variables, quote and
+/// quasiquote (with unquote and unquote-splicing), if, let and begin, and
+/// calls to the following functions: cons, car, cdr, list, append, length,
+/// null?, pair?, symbol?, number?, eq?, not, +, -, *, <, >, = and gensym.
+fn eval_macro_body(exp: &Value, env: &EvalEnv) -> Result<Value, MacroExpandError> {
+    if let Value::Symbol(sym) = exp {
+        return env
+            .iter()
+            .rev()
+            .find(|pair| *pair.0 == **sym)
+            .map(|pair| pair.1.clone())
+            .ok_or_else(|| MacroExpandError(format!("Unbound variable in macro: {}.", sym)));
+    }
+    let lst = match exp {
+        Value::Cons(_) => exp.to_vec().ok_or("Improper list found in macro body.")?,
+        Value::Null => return Err(MacroExpandError::from("Empty list found in macro body.")),
+        _ => return Ok(exp.clone()),
+    };
+    let op = lst[0]
+        .as_symbol()
+        .ok_or_else(|| MacroExpandError(format!("Not a function in macro: {}.", lst[0])))?;
+    let args = &lst[1..];
+    match op {
+        "quote" if args.len() == 1 => return Ok(args[0].clone()),
+        "quasiquote" if args.len() == 1 => return eval_quasiquote(&args[0], env),
+        "if" if args.len() == 3 => {
+            return if is_truthy(&eval_macro_body(&args[0], env)?) {
+                eval_macro_body(&args[1], env)
+            } else {
+                eval_macro_body(&args[2], env)
+            }
+        }
+        "let" if args.len() == 2 => {
+            let mut body_env = env.clone();
+            for binding in args[0]
+                .to_vec()
+                .ok_or("Let expression in macro has invalid bindings.")?
+            {
+                match binding.to_vec().as_deref() {
+                    Some([Value::Symbol(var), val]) => {
+                        body_env.push_back((var.to_string(), eval_macro_body(val, env)?))
+                    }
+                    _ => {
+                        return Err(MacroExpandError::from(
+                            "Let expression in macro has invalid bindings.",
+                        ))
+                    }
+                }
+            }
+            return eval_macro_body(&args[1], &body_env);
+        }
+        "begin" if !args.is_empty() => {
+            let vals = args
+                .iter()
+                .map(|arg| eval_macro_body(arg, env))
+                .collect::<Result<Vec<Value>, MacroExpandError>>()?;
+            return Ok(vals.last().unwrap().clone());
+        }
+        "+" | "-" | "*" | "<" | ">" | "=" if args.len() == 2 => {
+            let a = eval_int(&args[0], env)?;
+            let b = eval_int(&args[1], env)?;
+            return Ok(match op {
+                "+" => Value::from(a + b),
+                "-" => Value::from(a - b),
+                "*" => Value::from(a * b),
+                "<" => Value::from(a < b),
+                ">" => Value::from(a > b),
+                _ => Value::from(a == b),
+            });
+        }
+        _ => (),
+    }
+
+    let vals = args
+        .iter()
+        .map(|arg| eval_macro_body(arg, env))
+        .collect::<Result<Vec<Value>, MacroExpandError>>()?;
+    let not_a_list =
+        |val: &Value| MacroExpandError(format!("Expected a list in macro, found {}.", val));
+    match (op, vals.as_slice()) {
+        ("cons", [first, rest]) => Ok(Value::cons(first.clone(), rest.clone())),
+        ("car", [Value::Cons(pair)]) => Ok(pair.car().clone()),
+        ("cdr", [Value::Cons(pair)]) => Ok(pair.cdr().clone()),
+        ("list", _) => Ok(Value::list(vals.clone())),
+        ("append", _) => {
+            let mut appended: Vec<Value> = vec![];
+            for val in vals.iter() {
+                appended.append(&mut val.to_vec().ok_or_else(|| not_a_list(val))?);
+            }
+            Ok(Value::list(appended))
+        }
+        ("length", [val]) => Ok(Value::from(
+            val.to_vec().ok_or_else(|| not_a_list(val))?.len() as u64,
+        )),
+        ("null?", [val]) => Ok(Value::from(val.is_null())),
+        ("pair?", [val]) => Ok(Value::from(val.is_cons())),
+        ("symbol?", [val]) => Ok(Value::from(val.is_symbol())),
+        ("number?", [val]) => Ok(Value::from(val.is_number())),
+        ("eq?", [a, b]) => Ok(Value::from(a == b)),
+        ("not", [val]) => Ok(Value::from(!is_truthy(val))),
+        ("gensym", []) => Ok(Value::symbol(format!("g%{}", generate_id()))),
+        _ => Err(MacroExpandError(format!(
+            "Unrecognized function or wrong arguments in macro: {}.",
+            exp
+        ))),
+    }
+}
+
+/// Evaluates a quasiquoted template, where only unquoted parts are
+/// evaluated.
+fn eval_quasiquote(template: &Value, env: &EvalEnv) -> Result<Value, MacroExpandError> {
+    let lst = match template.to_vec() {
+        Some(lst) if !lst.is_empty() => lst,
+        _ => return Ok(template.clone()),
+    };
+    if lst.len() == 2 && lst[0].as_symbol() == Some("unquote") {
+        return eval_macro_body(&lst[1], env);
+    }
+    let mut vals: Vec<Value> = vec![];
+    for val in lst.iter() {
+        match val.to_vec().as_deref() {
+            Some([Value::Symbol(sym), spliced]) if &**sym == "unquote-splicing" => {
+                let spliced_val = eval_macro_body(spliced, env)?;
+                vals.append(&mut spliced_val.to_vec().ok_or_else(|| {
+                    MacroExpandError(format!(
+                        "Expected a list in unquote-splicing, found {}.",
+                        spliced_val
+                    ))
+                })?);
+            }
+            _ => vals.push(eval_quasiquote(val, env)?),
+        }
+    }
+    Ok(Value::list(vals))
+}
+
+fn is_ellipsis(value: &Value) -> bool {
+    value.as_symbol() == Some("...")
+}
+
+/// Matches a list of patterns against a list of values, adding the values
+/// matched by each pattern variable to `bindings`.
+///
+/// At most one of the patterns may be followed by an ellipsis, in which case
+/// it matches as many values as are left over by the other patterns.
+fn match_list(
+    patterns: &[Value],
+    values: &[Value],
+    literals: &[String],
+    bindings: &mut Bindings,
+) -> Result<bool, MacroExpandError> {
+    let ellipsis_pos = patterns.iter().position(is_ellipsis);
+    let ellipsis_pos = match ellipsis_pos {
+        Some(0) => {
+            return Err(MacroExpandError::from(
+                "Ellipsis found at start of pattern.",
+            ))
+        }
+        Some(pos) => pos,
+        None => {
+            if patterns.len() != values.len() {
+                return Ok(false);
+            }
+            for (pattern, value) in patterns.iter().zip(values.iter()) {
+                if !match_pattern(pattern, value, literals, bindings)? {
+                    return Ok(false);
+                }
+            }
+            return Ok(true);
+        }
+    };
+    if patterns[ellipsis_pos + 1..].iter().any(is_ellipsis) {
+        return Err(MacroExpandError::from(
+            "Pattern contains more than one ellipsis in the same list.",
+        ));
+    }
+
+    let before = &patterns[..ellipsis_pos - 1];
+    let repeated = &patterns[ellipsis_pos - 1];
+    let after = &patterns[ellipsis_pos + 1..];
+    if values.len() < before.len() + after.len() {
+        return Ok(false);
+    }
+    let repeated_end = values.len() - after.len();
+    if !match_list(before, &values[..before.len()], literals, bindings)?
+        || !match_list(after, &values[repeated_end..], literals, bindings)?
+    {
+        return Ok(false);
+    }
+
+    let mut repetitions: Vec<Bindings> = vec![];
+    for value in values[before.len()..repeated_end].iter() {
+        let mut repetition = Bindings::new();
+        if !match_pattern(repeated, value, literals, &mut repetition)? {
+            return Ok(false);
+        }
+        repetitions.push(repetition);
+    }
+    for var in pattern_vars(repeated, literals) {
+        let matches = repetitions
+            .iter()
+            .map(|repetition| repetition.get(&var).unwrap().clone())
+            .collect();
+        bindings.insert(var, Match::Many(matches));
+    }
+    Ok(true)
+}
+
+fn match_pattern(
+    pattern: &Value,
+    value: &Value,
+    literals: &[String],
+    bindings: &mut Bindings,
+) -> Result<bool, MacroExpandError> {
+    match pattern {
+        Value::Symbol(sym) if &**sym == "_" => Ok(true),
+        Value::Symbol(sym) if literals.contains(&sym.to_string()) => {
+            Ok(value.as_symbol() == Some(&**sym))
+        }
+        Value::Symbol(sym) => {
+            bindings.insert(sym.to_string(), Match::One(value.clone()));
+            Ok(true)
+        }
+        Value::Cons(_) | Value::Null => match (pattern.to_vec(), value.to_vec()) {
+            (Some(patterns), Some(values)) => match_list(&patterns, &values, literals, bindings),
+            _ => Ok(false),
+        },
+        _ => Ok(pattern == value),
+    }
+}
+
+/// Returns the variables bound by a pattern.
+fn pattern_vars(pattern: &Value, literals: &[String]) -> Vec<String> {
+    match pattern {
+        Value::Symbol(sym) => {
+            if &**sym == "_" || &**sym == "..." || literals.contains(&sym.to_string()) {
+                vec![]
+            } else {
+                vec![sym.to_string()]
+            }
+        }
+        _ => match pattern.to_vec() {
+            Some(patterns) => patterns
+                .iter()
+                .flat_map(|pattern| pattern_vars(pattern, literals))
+                .collect(),
+            None => vec![],
+        },
+    }
+}
+
+/// Returns the variables which a template binds itself (as opposed to
+/// variables passed in through pattern variables).
+fn template_binders(template: &Value, bindings: &Bindings) -> Vec<String> {
+    let lst = match template.to_vec() {
+        Some(lst) if !lst.is_empty() => lst,
+        _ => return vec![],
+    };
+    let mut names: Vec<String> = form_binders(&lst)
+        .into_iter()
+        .filter(|name| name != "..." && !bindings.contains_key(name))
+        .collect();
+    for inner in lst.iter() {
+        names.append(&mut template_binders(inner, bindings));
+    }
+    names
+}
+
+/// Returns the variables bound by a let, lambda, unpack or typecase
+/// expression, given as a list of values.
+fn form_binders(lst: &[Value]) -> Vec<String> {
+    if lst.is_empty() {
+        return vec![];
+    }
+    // the first symbol of each list, e.g. `x` in `(x 3)` or `(x : int)`
+    let list_heads = |value: &Value| -> Vec<Value> {
+        value
+            .to_vec()
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|inner| inner.to_vec().and_then(|inner| inner.first().cloned()))
+            .collect()
+    };
+    let binders: Vec<Value> = match (lst[0].as_symbol(), lst.len()) {
+        (Some("let"), 3) => list_heads(&lst[1]),
+        (Some("lambda"), 5) => list_heads(&lst[1]),
+        (Some("unpack"), 3) => lst[1]
+            .to_vec()
+            .unwrap_or_default()
+            .into_iter()
+            .take(1)
+            .collect(),
+        (Some("typecase"), _) => lst[2..]
+            .iter()
+            .filter_map(|clause| clause.to_vec().and_then(|clause| clause.first().cloned()))
+            .filter_map(|head| head.to_vec().and_then(|head| head.get(1).cloned()))
+            .collect(),
+        _ => vec![],
+    };
+    binders
+        .iter()
+        .filter_map(|binder| binder.as_symbol())
+        .map(String::from)
+        .collect()
+}
+
+fn instantiate(
+    template: &Value,
+    bindings: &Bindings,
+    renames: &HashMap<String, String>,
+) -> Result<Value, MacroExpandError> {
+    match template {
+        Value::Symbol(sym) => match bindings.get(&sym.to_string()) {
+            Some(Match::One(value)) => Ok(value.clone()),
+            Some(Match::Many(_)) => Err(MacroExpandError(format!(
+                "Pattern variable {} is used without an ellipsis.",
+                sym
+            ))),
+            None => match renames.get(&sym.to_string()) {
+                Some(fresh) => Ok(Value::symbol(fresh.as_str())),
+                None => Ok(template.clone()),
+            },
+        },
+        Value::Cons(_) => match template.to_vec() {
+            Some(templates) => instantiate_list(&templates, bindings, renames),
+            None => Ok(template.clone()),
+        },
+        _ => Ok(template.clone()),
+    }
+}
+
+fn instantiate_list(
+    templates: &[Value],
+    bindings: &Bindings,
+    renames: &HashMap<String, String>,
+) -> Result<Value, MacroExpandError> {
+    let mut values: Vec<Value> = vec![];
+    let mut i = 0;
+    while i < templates.len() {
+        let template = &templates[i];
+        if i + 1 < templates.len() && is_ellipsis(&templates[i + 1]) {
+            values.append(&mut instantiate_repeated(template, bindings, renames)?);
+            i += 2;
+        } else {
+            values.push(instantiate(template, bindings, renames)?);
+            i += 1;
+        }
+    }
+    Ok(Value::list(values))
+}
+
+/// Instantiates a template followed by an ellipsis, once for each value
+/// matched by the sequence pattern variables it contains.
+fn instantiate_repeated(
+    template: &Value,
+    bindings: &Bindings,
+    renames: &HashMap<String, String>,
+) -> Result<Vec<Value>, MacroExpandError> {
+    let sequences: Vec<(String, Vec<Match>)> = pattern_vars(template, &[])
+        .into_iter()
+        .filter_map(|var| match bindings.get(&var) {
+            Some(Match::Many(matches)) => Some((var, matches.clone())),
+            _ => None,
+        })
+        .collect();
+    if sequences.is_empty() {
+        return Err(MacroExpandError(format!(
+            "Template {} is followed by an ellipsis, but contains no pattern variables which \
+             match a sequence.",
+            template
+        )));
+    }
+    let len = sequences[0].1.len();
+    if sequences.iter().any(|(_, matches)| matches.len() != len) {
+        return Err(MacroExpandError::from(
+            "Pattern variables under the same ellipsis matched sequences of different lengths.",
+        ));
+    }
+    (0..len)
+        .map(|i| {
+            let mut inner_bindings = bindings.clone();
+            for (var, matches) in sequences.iter() {
+                inner_bindings.insert(var.clone(), matches[i].clone());
+            }
+            instantiate(template, &inner_bindings, renames)
+        })
+        .collect()
+}
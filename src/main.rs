@@ -0,0 +1,344 @@
+/// The command-line interface to the compiler, which reads programs from
+/// files and runs one of the library's entry points on them:
+///
+/// - `compile <file.scm | manifest.toml> [-o <output.wasm>] [-O0|-O1|-O2]`
+///   compiles a program (or a project, see `project::Manifest`) to a wasm
+///   module, by default next to the source with a .wasm extension.
+/// - `eval <file.scm> [--trace] [--seed <n>] [--arg <arg>]...` runs a program
+///   in the interpreter, which gives the reference semantics for compiled
+///   programs, and prints its value (see `interpret::eval_source`).
+/// - `fmt [--check] [--width <n>] <file.scm>...` formats programs in place,
+///   or with `--check`, only lists the files which aren't formatted.
+/// - `ast <file.scm> [--stage expanded|desugared|parsed|typed] [--json]`
+///   prints a program as it was understood (see `dump::dump_ast`).
+/// - `explain <code>` prints the explanation of an error code, like `T001`.
+/// - `repl` starts an interactive session (see `repl::Repl`).
+/// - `bench <file.scm> [--runs <n>] [-O0|-O1|-O2]` times repeated runs of a
+///   compiled program, when built with the `host` feature.
+///
+/// Errors are printed with their codes (see `explain::error_code`), and the
+/// exit status is 1 if a command fails.
+use scheme_to_wasm::common::{Prog, TypedExpr};
+use scheme_to_wasm::compile::{compile_exp_with_options, CompileOptions};
+use scheme_to_wasm::desugar::desugar;
+use scheme_to_wasm::dump::{dump_ast, DumpFormat, DumpStage};
+use scheme_to_wasm::explain::{error_code, explain};
+use scheme_to_wasm::generate_code::construct_module_from_prog;
+use scheme_to_wasm::interpret::{eval_source, EvalOptions};
+use scheme_to_wasm::limits::CompilerLimits;
+use scheme_to_wasm::macro_expand::macro_expand_with_limits;
+use scheme_to_wasm::optimize::OptLevel;
+use scheme_to_wasm::parse::parse_with_limits;
+use scheme_to_wasm::pretty::{format_source, DEFAULT_WIDTH};
+use scheme_to_wasm::project::write_project;
+use scheme_to_wasm::read::read;
+use scheme_to_wasm::repl::{run_repl, Repl};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+const USAGE: &str = "Usage:
+    scheme-to-wasm compile <file.scm | manifest.toml> [-o <output.wasm>] [-O0|-O1|-O2]
+    scheme-to-wasm eval <file.scm> [--trace] [--seed <n>] [--arg <arg>]...
+    scheme-to-wasm fmt [--check] [--width <n>] <file.scm>...
+    scheme-to-wasm ast <file.scm> [--stage expanded|desugared|parsed|typed] [--json]
+    scheme-to-wasm explain <code>
+    scheme-to-wasm repl
+    scheme-to-wasm bench <file.scm> [--runs <n>] [-O0|-O1|-O2]";
+
+#[derive(Clone, Debug)]
+struct UsageError(String);
+
+// Allows other errors to wrap this one
+impl Error for UsageError {}
+
+impl From<&str> for UsageError {
+    fn from(message: &str) -> Self {
+        UsageError(String::from(message))
+    }
+}
+
+impl std::fmt::Display for UsageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}\n\n{}", self.0, USAGE)
+    }
+}
+
+/// The arguments of a command, split into flags (with their values, if they
+/// take one) and the remaining positional arguments.
+struct Args {
+    flags: Vec<(String, Option<String>)>,
+    positional: Vec<String>,
+}
+
+impl Args {
+    /// Splits `args`, where the flags in `with_values` take the argument
+    /// after them as their value, and any other argument starting with `-`
+    /// is a flag without a value.
+    fn parse(args: &[String], with_values: &[&str]) -> Result<Self, UsageError> {
+        let mut flags = vec![];
+        let mut positional = vec![];
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if with_values.contains(&arg.as_str()) {
+                let value = args
+                    .next()
+                    .ok_or_else(|| UsageError(format!("Expected a value after {}.", arg)))?;
+                flags.push((arg.clone(), Some(value.clone())));
+            } else if arg.starts_with('-') {
+                flags.push((arg.clone(), None));
+            } else {
+                positional.push(arg.clone());
+            }
+        }
+        Ok(Args { flags, positional })
+    }
+
+    /// Returns an error if any flag isn't one of `allowed`.
+    fn check_flags(&self, allowed: &[&str]) -> Result<(), UsageError> {
+        match self
+            .flags
+            .iter()
+            .find(|(flag, _value)| !allowed.contains(&flag.as_str()))
+        {
+            Some((flag, _value)) => Err(UsageError(format!("Unknown option {}.", flag))),
+            None => Ok(()),
+        }
+    }
+
+    fn has(&self, flag: &str) -> bool {
+        self.flags.iter().any(|(name, _value)| name == flag)
+    }
+
+    /// The value of the last use of a flag.
+    fn value(&self, flag: &str) -> Option<&str> {
+        self.flags
+            .iter()
+            .rev()
+            .find(|(name, _value)| name == flag)
+            .and_then(|(_name, value)| value.as_deref())
+    }
+
+    /// The values of every use of a flag, in order.
+    fn values(&self, flag: &str) -> Vec<String> {
+        self.flags
+            .iter()
+            .filter(|(name, _value)| name == flag)
+            .filter_map(|(_name, value)| value.clone())
+            .collect()
+    }
+
+    fn number(&self, flag: &str) -> Result<Option<u64>, UsageError> {
+        match self.value(flag) {
+            Some(value) => value
+                .parse()
+                .map(Some)
+                .map_err(|_| UsageError(format!("Expected a number after {}.", flag))),
+            None => Ok(None),
+        }
+    }
+
+    /// The optimization level from the last of `-O0`, `-O1` or `-O2`.
+    fn opt_level(&self) -> OptLevel {
+        let level = self
+            .flags
+            .iter()
+            .rev()
+            .find_map(|(flag, _value)| match flag.as_str() {
+                "-O0" => Some(OptLevel::O0),
+                "-O1" => Some(OptLevel::O1),
+                "-O2" => Some(OptLevel::O2),
+                _ => None,
+            });
+        level.unwrap_or_default()
+    }
+
+    /// The only positional argument, which is required.
+    fn file(&self) -> Result<&str, UsageError> {
+        match self.positional.as_slice() {
+            [file] => Ok(file),
+            [] => Err(UsageError::from("Expected a file.")),
+            _ => Err(UsageError::from("Expected only one file.")),
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(success) => std::process::exit(if success { 0 } else { 1 }),
+        Err(err) => {
+            match error_code(err.as_ref()) {
+                Some(code) => eprintln!(
+                    "error[{}]: {}\n(run `scheme-to-wasm explain {}` for more information)",
+                    code, err, code
+                ),
+                None => eprintln!("error: {}", err),
+            }
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Runs a command, returning whether it succeeded (a command can fail
+/// without an error, like `fmt --check` on a file which isn't formatted).
+fn run(args: &[String]) -> Result<bool, Box<dyn Error>> {
+    let (command, rest) = match args.split_first() {
+        Some((command, rest)) => (command.as_str(), rest),
+        None => return Err(UsageError::from("Expected a command.").into()),
+    };
+    match command {
+        "compile" => run_compile(&Args::parse(rest, &["-o"])?),
+        "eval" => run_eval(&Args::parse(rest, &["--seed", "--arg"])?),
+        "fmt" => run_fmt(&Args::parse(rest, &["--width"])?),
+        "ast" => run_ast(&Args::parse(rest, &["--stage"])?),
+        "explain" => run_explain(&Args::parse(rest, &[])?),
+        "repl" => run_repl_command(&Args::parse(rest, &[])?),
+        "bench" => run_bench(&Args::parse(rest, &["--runs"])?),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(true)
+        }
+        _ => Err(UsageError(format!("Unknown command {}.", command)).into()),
+    }
+}
+
+fn read_file(path: &str) -> Result<String, Box<dyn Error>> {
+    std::fs::read_to_string(path).map_err(|err| format!("Could not read {}: {}", path, err).into())
+}
+
+/// Reads a program from a file and compiles it with the given optimization
+/// level.
+fn compile_file(path: &str, opt_level: OptLevel) -> Result<Prog<TypedExpr>, Box<dyn Error>> {
+    let limits = CompilerLimits::default();
+    let exp = parse_with_limits(
+        &desugar(&macro_expand_with_limits(
+            &read(&read_file(path)?)?,
+            &limits,
+        )?)?,
+        &limits,
+    )?;
+    let options = CompileOptions {
+        opt_level,
+        ..CompileOptions::default()
+    };
+    compile_exp_with_options(&exp, &options)
+}
+
+fn run_compile(args: &Args) -> Result<bool, Box<dyn Error>> {
+    args.check_flags(&["-o", "-O0", "-O1", "-O2"])?;
+    let file = args.file()?;
+    let output = args.value("-o").map(PathBuf::from);
+    let output = if Path::new(file).extension() == Some("toml".as_ref()) {
+        // projects set their optimization level in the manifest
+        write_project(Path::new(file), output.as_deref())?
+    } else {
+        let output = output.unwrap_or_else(|| Path::new(file).with_extension("wasm"));
+        let module = construct_module_from_prog(&compile_file(file, args.opt_level())?)?;
+        parity_wasm::serialize_to_file(&output, module)
+            .map_err(|err| format!("Could not write {}: {}", output.display(), err))?;
+        output
+    };
+    println!("Wrote {}", output.display());
+    Ok(true)
+}
+
+fn run_eval(args: &Args) -> Result<bool, Box<dyn Error>> {
+    args.check_flags(&["--trace", "--seed", "--arg"])?;
+    let options = EvalOptions {
+        trace: args.has("--trace"),
+        args: args.values("--arg"),
+        seed: args.number("--seed")?,
+        ..EvalOptions::default()
+    };
+    println!("{}", eval_source(&read_file(args.file()?)?, &options)?);
+    Ok(true)
+}
+
+fn run_fmt(args: &Args) -> Result<bool, Box<dyn Error>> {
+    args.check_flags(&["--check", "--width"])?;
+    if args.positional.is_empty() {
+        return Err(UsageError::from("Expected at least one file.").into());
+    }
+    let width = args
+        .number("--width")?
+        .map_or(DEFAULT_WIDTH, |width| width as usize);
+    let mut formatted = true;
+    for file in &args.positional {
+        let source = read_file(file)?;
+        let output = format_source(&source, width)?;
+        if output == source {
+            continue;
+        }
+        if args.has("--check") {
+            println!("{} is not formatted", file);
+            formatted = false;
+        } else {
+            std::fs::write(file, output)
+                .map_err(|err| format!("Could not write {}: {}", file, err))?;
+        }
+    }
+    Ok(formatted)
+}
+
+fn run_ast(args: &Args) -> Result<bool, Box<dyn Error>> {
+    args.check_flags(&["--stage", "--json"])?;
+    let stage = match args.value("--stage") {
+        Some("expanded") => DumpStage::Expanded,
+        Some("desugared") | None => DumpStage::Desugared,
+        Some("parsed") => DumpStage::Parsed,
+        Some("typed") => DumpStage::Typed,
+        Some(stage) => return Err(UsageError(format!("Unknown stage {}.", stage)).into()),
+    };
+    let format = if args.has("--json") {
+        DumpFormat::Json
+    } else {
+        DumpFormat::SExpr
+    };
+    print!("{}", dump_ast(&read_file(args.file()?)?, stage, format)?);
+    Ok(true)
+}
+
+fn run_explain(args: &Args) -> Result<bool, Box<dyn Error>> {
+    args.check_flags(&[])?;
+    let code = match args.positional.as_slice() {
+        [code] => code,
+        _ => return Err(UsageError::from("Expected an error code.").into()),
+    };
+    match explain(code) {
+        Some(explanation) => {
+            println!("{}", explanation);
+            Ok(true)
+        }
+        None => Err(format!("There is no error with the code {}.", code).into()),
+    }
+}
+
+fn run_repl_command(args: &Args) -> Result<bool, Box<dyn Error>> {
+    args.check_flags(&[])?;
+    if !args.positional.is_empty() {
+        return Err(UsageError::from("The repl command doesn't take any files.").into());
+    }
+    let mut repl = Repl::new(EvalOptions::default());
+    run_repl(&mut repl, std::io::stdin().lock(), std::io::stdout())?;
+    Ok(true)
+}
+
+#[cfg(feature = "host")]
+fn run_bench(args: &Args) -> Result<bool, Box<dyn Error>> {
+    use scheme_to_wasm::host::{runtime_imports, Instance};
+
+    args.check_flags(&["--runs", "-O0", "-O1", "-O2"])?;
+    let runs = args.number("--runs")?.unwrap_or(100) as usize;
+    let prog = compile_file(args.file()?, args.opt_level())?;
+    let report = Instance::new(&prog, &runtime_imports())?.bench(runs)?;
+    println!(
+        "{} runs: mean {:?}, median {:?}, p95 {:?}",
+        report.runs, report.mean, report.median, report.p95
+    );
+    Ok(true)
+}
+
+#[cfg(not(feature = "host"))]
+fn run_bench(_args: &Args) -> Result<bool, Box<dyn Error>> {
+    Err("Benchmarks need the compiler to be built with the host feature.".into())
+}
@@ -0,0 +1,352 @@
+use crate::ast_transform::{transform_typed_exp_recursive, transform_typed_prog_recursive};
+use crate::common::{BinOp, ExprKind, Prog, TypedExpr};
+use crate::effects::is_pure;
+use crate::types::Type;
+use im_rc::Vector;
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Clone, Debug)]
+pub struct OptimizeError(String);
+
+// Allows other errors to wrap this one
+impl std::error::Error for OptimizeError {}
+
+impl From<&str> for OptimizeError {
+    fn from(message: &str) -> Self {
+        OptimizeError(String::from(message))
+    }
+}
+
+impl std::fmt::Display for OptimizeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "OptimizeError: {}", self.0)
+    }
+}
+
+/// Which optimizer passes are run on a program (like -O0, -O1, -O2).
+///
+/// Each level performs all of the optimizations of the levels below it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OptLevel {
+    #[default]
+    O0, // no optimizations
+    O1, // fold constant arithmetic, and if and case expressions with constant predicates or keys
+    O2, // also remove unused pure let bindings and pure begin expressions
+}
+
+/// Optimizes a program after all other compiler passes have been performed.
+///
+/// The program must be type checked (annotated with types), and the output
+/// program will have valid type annotations.
+pub fn optimize_prog(
+    prog: &Prog<TypedExpr>,
+    level: OptLevel,
+) -> Result<Prog<TypedExpr>, OptimizeError> {
+    let _span = tracing::info_span!("optimize", ?level).entered();
+    transform_typed_prog_recursive(prog, |exp| opt_helper(exp, level), opt_type_helper)
+}
+
+/// Optimizes a single expression. See `optimize_prog` for details.
+pub fn optimize_exp(exp: &TypedExpr, level: OptLevel) -> Result<TypedExpr, OptimizeError> {
+    transform_typed_exp_recursive(exp, |exp| opt_helper(exp, level), opt_type_helper)
+}
+
+fn opt_type_helper(_typ: &Type) -> Option<Result<Type, OptimizeError>> {
+    None
+}
+
+fn opt_helper(exp: &TypedExpr, level: OptLevel) -> Option<Result<TypedExpr, OptimizeError>> {
+    if level == OptLevel::O0 {
+        return Some(Ok(exp.clone()));
+    }
+    match &*exp.kind {
+        ExprKind::Binop(op, arg1, arg2) => Some(opt_binop(*op, arg1, arg2, &exp.typ, level)),
+        ExprKind::If(pred, cons, alt) => Some(opt_if(pred, cons, alt, &exp.typ, level)),
+        ExprKind::Case(key, clauses, else_body) => {
+            Some(opt_case(key, clauses, else_body, &exp.typ, level))
+        }
+        ExprKind::Begin(exps) if level >= OptLevel::O2 => Some(opt_begin(exps, level)),
+        ExprKind::Let(bindings, body) if level >= OptLevel::O2 => {
+            Some(opt_let(bindings, body, level))
+        }
+        _ => None,
+    }
+}
+
+fn opt_binop(
+    op: BinOp,
+    arg1: &TypedExpr,
+    arg2: &TypedExpr,
+    typ: &Type,
+    level: OptLevel,
+) -> Result<TypedExpr, OptimizeError> {
+    let oarg1 = optimize_exp(arg1, level)?;
+    let oarg2 = optimize_exp(arg2, level)?;
+    match fold_binop(op, &oarg1.kind, &oarg2.kind) {
+        Some(folded) => Ok(folded),
+        None => Ok(TypedExpr::new(
+            typ.clone(),
+            ExprKind::Binop(op, oarg1, oarg2),
+        )),
+    }
+}
+
+/// Evaluates a binary operation on two literals, using the same (wrapping)
+/// semantics as the generated wasm code.
+///
+/// Operations which would trap at runtime (like division by zero) are left
+/// alone.
+fn fold_binop(
+    op: BinOp,
+    arg1: &ExprKind<TypedExpr>,
+    arg2: &ExprKind<TypedExpr>,
+) -> Option<TypedExpr> {
+    let num = |x: i32| TypedExpr::new(Type::Int, ExprKind::Num(x));
+    let boolean = |x: bool| TypedExpr::new(Type::Bool, ExprKind::Bool(x));
+    match (op, arg1, arg2) {
+        (BinOp::Add, ExprKind::Num(a), ExprKind::Num(b)) => Some(num(a.wrapping_add(*b))),
+        (BinOp::Subtract, ExprKind::Num(a), ExprKind::Num(b)) => Some(num(a.wrapping_sub(*b))),
+        (BinOp::Multiply, ExprKind::Num(a), ExprKind::Num(b)) => Some(num(a.wrapping_mul(*b))),
+        (BinOp::Divide, ExprKind::Num(a), ExprKind::Num(b)) => a.checked_div(*b).map(num),
+        (BinOp::Expt, ExprKind::Num(a), ExprKind::Num(b)) if *b >= 0 => {
+            Some(num(a.wrapping_pow(*b as u32)))
+        }
+        (BinOp::LessThan, ExprKind::Num(a), ExprKind::Num(b)) => Some(boolean(a < b)),
+        (BinOp::GreaterThan, ExprKind::Num(a), ExprKind::Num(b)) => Some(boolean(a > b)),
+        (BinOp::LessOrEqual, ExprKind::Num(a), ExprKind::Num(b)) => Some(boolean(a <= b)),
+        (BinOp::GreaterOrEqual, ExprKind::Num(a), ExprKind::Num(b)) => Some(boolean(a >= b)),
+        (BinOp::EqualTo, ExprKind::Num(a), ExprKind::Num(b)) => Some(boolean(a == b)),
+        (BinOp::UnsignedLessThan, ExprKind::Num(a), ExprKind::Num(b)) => {
+            Some(boolean((*a as u32) < (*b as u32)))
+        }
+        (BinOp::UnsignedGreaterThan, ExprKind::Num(a), ExprKind::Num(b)) => {
+            Some(boolean((*a as u32) > (*b as u32)))
+        }
+        (BinOp::UnsignedDivide, ExprKind::Num(a), ExprKind::Num(b)) => {
+            (*a as u32).checked_div(*b as u32).map(|x| num(x as i32))
+        }
+        (BinOp::UnsignedRemainder, ExprKind::Num(a), ExprKind::Num(b)) => {
+            (*a as u32).checked_rem(*b as u32).map(|x| num(x as i32))
+        }
+        (BinOp::EqualTo, ExprKind::Bool(a), ExprKind::Bool(b)) => Some(boolean(a == b)),
+        (BinOp::And, ExprKind::Bool(a), ExprKind::Bool(b)) => Some(boolean(*a && *b)),
+        (BinOp::Or, ExprKind::Bool(a), ExprKind::Bool(b)) => Some(boolean(*a || *b)),
+        _ => None,
+    }
+}
+
+fn opt_if(
+    pred: &TypedExpr,
+    cons: &TypedExpr,
+    alt: &TypedExpr,
+    typ: &Type,
+    level: OptLevel,
+) -> Result<TypedExpr, OptimizeError> {
+    let opred = optimize_exp(pred, level)?;
+    match &*opred.kind {
+        ExprKind::Bool(true) => optimize_exp(cons, level),
+        ExprKind::Bool(false) => optimize_exp(alt, level),
+        _ => {
+            let ocons = optimize_exp(cons, level)?;
+            let oalt = optimize_exp(alt, level)?;
+            Ok(TypedExpr::new(
+                typ.clone(),
+                ExprKind::If(opred, ocons, oalt),
+            ))
+        }
+    }
+}
+
+fn opt_case(
+    key: &TypedExpr,
+    clauses: &Vector<(Vector<i32>, TypedExpr)>,
+    else_body: &TypedExpr,
+    typ: &Type,
+    level: OptLevel,
+) -> Result<TypedExpr, OptimizeError> {
+    let okey = optimize_exp(key, level)?;
+    if let ExprKind::Num(x) = &*okey.kind {
+        return match clauses.iter().find(|(datums, _body)| datums.contains(x)) {
+            Some((_datums, body)) => optimize_exp(body, level),
+            None => optimize_exp(else_body, level),
+        };
+    }
+    let oclauses = clauses
+        .iter()
+        .map(|(datums, body)| Ok((datums.clone(), optimize_exp(body, level)?)))
+        .collect::<Result<Vector<(Vector<i32>, TypedExpr)>, OptimizeError>>()?;
+    let oelse = optimize_exp(else_body, level)?;
+    Ok(TypedExpr::new(
+        typ.clone(),
+        ExprKind::Case(okey, oclauses, oelse),
+    ))
+}
+
+fn opt_begin(exps: &Vector<TypedExpr>, level: OptLevel) -> Result<TypedExpr, OptimizeError> {
+    let oexps = exps
+        .iter()
+        .map(|exp| optimize_exp(exp, level))
+        .collect::<Result<Vector<TypedExpr>, OptimizeError>>()?;
+    let last = match oexps.last() {
+        Some(exp) => exp.clone(),
+        None => {
+            return Err(OptimizeError::from(
+                "Begin expression has no subexpressions.",
+            ))
+        }
+    };
+
+    // only the value of the last expression is used, so any of the others
+    // can be dropped if they have no side effects
+    let mut kept: Vector<TypedExpr> = oexps
+        .take(oexps.len() - 1)
+        .into_iter()
+        .filter(|exp| !is_removable(exp))
+        .collect();
+    if kept.is_empty() {
+        return Ok(last);
+    }
+    kept.push_back(last.clone());
+    Ok(TypedExpr::new(last.typ, ExprKind::Begin(kept)))
+}
+
+fn opt_let(
+    bindings: &Vector<(String, TypedExpr)>,
+    body: &TypedExpr,
+    level: OptLevel,
+) -> Result<TypedExpr, OptimizeError> {
+    let obindings = bindings
+        .iter()
+        .map(|(var, val)| Ok((var.clone(), optimize_exp(val, level)?)))
+        .collect::<Result<Vector<(String, TypedExpr)>, OptimizeError>>()?;
+    let obody = optimize_exp(body, level)?;
+
+    // a binding can be dropped if evaluating it has no side effects, and its
+    // variable is not referred to anywhere else
+    let is_used = |var: &str| {
+        mentions_var(&obody, var)
+            || obindings
+                .iter()
+                .any(|(other, val)| other != var && mentions_var(val, var))
+    };
+    let kept: Vector<(String, TypedExpr)> = obindings
+        .iter()
+        .filter(|(var, val)| !is_removable(val) || is_used(var))
+        .cloned()
+        .collect();
+    if kept.is_empty() {
+        return Ok(obody);
+    }
+    Ok(TypedExpr::new(
+        obody.typ.clone(),
+        ExprKind::Let(kept, obody),
+    ))
+}
+
+/// Returns whether an expression can be dropped without changing the
+/// behavior of the program. Assertions are always kept, even though failing
+/// ones are not considered to be side effects, and so are while loops, which
+/// may never terminate.
+fn is_removable(exp: &TypedExpr) -> bool {
+    is_pure(exp)
+        && !any_subexp(exp, |exp| {
+            matches!(&*exp.kind, ExprKind::Assert(_, _) | ExprKind::While(_, _))
+        })
+}
+
+/// Returns whether the variable is referenced or set anywhere inside of the
+/// expression.
+///
+/// Shadowing is not taken into account, so this may return true for variables
+/// which are not actually referenced, but never the other way around.
+fn mentions_var(exp: &TypedExpr, var: &str) -> bool {
+    any_subexp(exp, |exp| match &*exp.kind {
+        ExprKind::Id(x) | ExprKind::Set(x, _) => x == var,
+        _ => false,
+    })
+}
+
+fn any_subexp<P>(exp: &TypedExpr, pred: P) -> bool
+where
+    P: Fn(&TypedExpr) -> bool + Copy,
+{
+    let found = RefCell::new(false);
+    let check = |exp: &TypedExpr| -> Option<Result<TypedExpr, OptimizeError>> {
+        if pred(exp) {
+            *found.borrow_mut() = true;
+        }
+        None
+    };
+    // the walk itself cannot fail, since check never returns an error
+    let _ = transform_typed_exp_recursive(exp, check, opt_type_helper);
+    found.into_inner()
+}
+
+/// Presets for post-processing a binary with binaryen's wasm-opt.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WasmOptPreset {
+    Size,  // optimize aggressively for code size (-Oz)
+    Speed, // optimize aggressively for speed (-O3)
+}
+
+impl WasmOptPreset {
+    fn flag(self) -> &'static str {
+        match self {
+            WasmOptPreset::Size => "-Oz",
+            WasmOptPreset::Speed => "-O3",
+        }
+    }
+}
+
+static WASM_OPT_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Runs binaryen's `wasm-opt` on a serialized wasm module, returning the
+/// optimized module.
+///
+/// `wasm_opt_path` is the wasm-opt executable to run, e.g. just "wasm-opt" if
+/// it can be found on the PATH. An error is returned if it cannot be run or it
+/// fails on the input.
+pub fn run_wasm_opt(
+    binary: &[u8],
+    preset: WasmOptPreset,
+    wasm_opt_path: &str,
+) -> Result<Vec<u8>, OptimizeError> {
+    let id = WASM_OPT_COUNTER.fetch_add(1, Ordering::SeqCst);
+    let dir = std::env::temp_dir();
+    let in_path = dir.join(format!("scheme-to-wasm-{}-{}.wasm", std::process::id(), id));
+    let out_path = dir.join(format!(
+        "scheme-to-wasm-{}-{}.opt.wasm",
+        std::process::id(),
+        id
+    ));
+
+    let result = std::fs::write(&in_path, binary)
+        .map_err(|err| OptimizeError(format!("Could not write wasm-opt input: {}", err)))
+        .and_then(|_| {
+            std::process::Command::new(wasm_opt_path)
+                .arg(&in_path)
+                .arg(preset.flag())
+                .arg("-o")
+                .arg(&out_path)
+                .output()
+                .map_err(|err| OptimizeError(format!("Could not run {}: {}", wasm_opt_path, err)))
+        })
+        .and_then(|output| {
+            if output.status.success() {
+                std::fs::read(&out_path).map_err(|err| {
+                    OptimizeError(format!("Could not read wasm-opt output: {}", err))
+                })
+            } else {
+                Err(OptimizeError(format!(
+                    "{} failed: {}",
+                    wasm_opt_path,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                )))
+            }
+        });
+
+    let _ = std::fs::remove_file(&in_path);
+    let _ = std::fs::remove_file(&out_path);
+    result
+}
@@ -1,23 +1,48 @@
-use crate::common::{BinOp, Expr, ExprKind};
-use crate::types::Type;
+use crate::common::{BinOp, Builtin, Expr, ExprKind, UnOp};
+use crate::limits::{check_value, CompilerLimits};
+use crate::types::{option_type, unit_type, Effect, Type};
+use crate::util::format_directives;
 use im_rc::Vector;
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
 use std::num::ParseIntError;
 
 #[derive(Clone, Debug)]
-pub struct ParseError(String);
+pub struct ParseError {
+    message: String,
+    code: &'static str,
+}
+
+impl ParseError {
+    fn new(message: String) -> Self {
+        ParseError {
+            message,
+            code: "P001",
+        }
+    }
+
+    fn with_code(self, code: &'static str) -> Self {
+        ParseError { code, ..self }
+    }
+
+    /// The code for the kind of error this is (see `explain::explain`).
+    pub fn code(&self) -> &'static str {
+        self.code
+    }
+}
 
 // Allows other errors to wrap this one
 impl std::error::Error for ParseError {}
 
 impl From<&str> for ParseError {
     fn from(message: &str) -> Self {
-        ParseError(String::from(message))
+        ParseError::new(String::from(message))
     }
 }
 
 impl std::fmt::Display for ParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "ParseError: {}", self.0)
+        write!(f, "ParseError: {}", self.message)
     }
 }
 
@@ -26,10 +51,113 @@ impl std::fmt::Display for ParseError {
 // needs to be converted into a `ParseError`.
 impl From<ParseIntError> for ParseError {
     fn from(err: ParseIntError) -> ParseError {
-        ParseError(format!("ParseIntError: {}", err))
+        ParseError::new(format!("ParseIntError: {}", err))
     }
 }
 
+/// Names which are always parsed as special forms (or literals) rather than
+/// as variables, so binding them would have no effect.
+const RESERVED_WORDS: [&str; 86] = [
+    "and",
+    "or",
+    "+",
+    "*",
+    "-",
+    "/",
+    ">",
+    "<",
+    ">=",
+    "<=",
+    "=",
+    "u<",
+    "u>",
+    "u/",
+    "u%",
+    "concat",
+    "if",
+    "let",
+    "lambda",
+    "make-record",
+    "record-ref",
+    "begin",
+    "set!",
+    "cons",
+    "car",
+    "cdr",
+    "null?",
+    "null",
+    "make-tuple",
+    "tuple-ref",
+    "tuple-set!",
+    "pack",
+    "unpack",
+    "inject",
+    "typecase",
+    "case",
+    "assert",
+    "while",
+    "curry",
+    "compose",
+    "delay",
+    "force",
+    "format",
+    "call-extern",
+    "wasm-asm",
+    "true",
+    "false",
+    "random",
+    "random-float",
+    "current-millis",
+    "read-file",
+    "write-file",
+    "command-line",
+    "getenv",
+    "string-match",
+    "string-replace",
+    "string->utf8",
+    "utf8->string",
+    "hex-encode",
+    "hex-decode",
+    "base64-encode",
+    "base64-decode",
+    "string-length",
+    "string-ref",
+    "substring",
+    "string->list",
+    "string-graphemes",
+    "exact->inexact",
+    "inexact->exact",
+    "int->float",
+    "float->int",
+    "round",
+    "floor",
+    "ceiling",
+    "truncate",
+    "expt",
+    "sqrt",
+    "exp",
+    "log",
+    "sin",
+    "cos",
+    "tan",
+    "atan2",
+    "nan?",
+    "infinite?",
+    "float->string",
+];
+
+/// Returns an error if `name` cannot be bound as a variable.
+fn check_binder(name: &str) -> Result<(), ParseError> {
+    if RESERVED_WORDS.contains(&name) {
+        return Err(ParseError::new(format!(
+            "{} is a reserved word and cannot be used as a variable name.",
+            name
+        ))
+        .with_code("P003"));
+    }
+    Ok(())
+}
+
 fn check_separator(value: &lexpr::Value, expected: char) -> bool {
     match value.as_symbol() {
         Some(sep) => sep.len() == 1 && sep.chars().nth(0).unwrap() == expected,
@@ -38,12 +166,18 @@ fn check_separator(value: &lexpr::Value, expected: char) -> bool {
 }
 
 pub fn parse_type(annotation: &lexpr::Value) -> Result<Type, ParseError> {
+    parse_type_annotation(annotation).map_err(|err| err.with_code("P004"))
+}
+
+fn parse_type_annotation(annotation: &lexpr::Value) -> Result<Type, ParseError> {
     match annotation {
         lexpr::Value::Symbol(val) => match val.as_ref() {
             "int" => Ok(Type::Int),
+            "float" => Ok(Type::Float),
             "bool" => Ok(Type::Bool),
             "string" => Ok(Type::Str),
             "unknown" => Ok(Type::Unknown),
+            "unit" => Ok(unit_type()),
             val => match val.chars().next() {
                 Some('T') => Ok(Type::TypeVar(
                     val[1..val.len()]
@@ -65,11 +199,15 @@ pub fn parse_type(annotation: &lexpr::Value) -> Result<Type, ParseError> {
                 return Err(ParseError::from("Type annotation is missing values."));
             }
             match lst_vec[0].as_symbol() {
-                Some("->") => parse_func_annotation(lst_vec),
+                Some("->") => parse_func_annotation(lst_vec, Effect::Mutates),
+                Some("pure->") => parse_func_annotation(lst_vec, Effect::Pure),
                 Some("list") => parse_list_annotation(lst_vec),
+                Some("promise") => parse_promise_annotation(lst_vec),
+                Some("option") => parse_option_annotation(lst_vec),
                 Some("tuple") => parse_tuple_annotation(lst_vec),
                 Some("record") => parse_record_annotation(lst_vec),
                 Some("exists") => parse_exists_annotation(lst_vec),
+                Some("union") => parse_union_annotation(lst_vec),
                 _ => Err(ParseError::from(
                     r#"Type annotation does not have "->", "tuple", or "list" as first symbol."#,
                 )),
@@ -81,7 +219,11 @@ pub fn parse_type(annotation: &lexpr::Value) -> Result<Type, ParseError> {
     }
 }
 
-fn parse_func_annotation(lst_vec: Vec<lexpr::Value>) -> Result<Type, ParseError> {
+/// Parses a function type annotation, e.g. `(-> int bool)`.
+///
+/// Functions annotated with `->` may have any effect, while functions
+/// annotated with `pure->` must not have any side effects.
+fn parse_func_annotation(lst_vec: Vec<lexpr::Value>, effect: Effect) -> Result<Type, ParseError> {
     if lst_vec.len() < 2 {
         return Err(ParseError::from(
             "Type annotation for function is missing values.",
@@ -92,7 +234,11 @@ fn parse_func_annotation(lst_vec: Vec<lexpr::Value>) -> Result<Type, ParseError>
         .map(|val| parse_type(val))
         .collect::<Result<Vec<Type>, ParseError>>()?;
     let return_type = parse_type(&lst_vec[lst_vec.len() - 1])?;
-    Ok(Type::Func(Vector::from(input_types), Box::new(return_type)))
+    Ok(Type::Func(
+        Vector::from(input_types),
+        Box::new(return_type),
+        effect,
+    ))
 }
 
 fn parse_list_annotation(lst_vec: Vec<lexpr::Value>) -> Result<Type, ParseError> {
@@ -105,6 +251,26 @@ fn parse_list_annotation(lst_vec: Vec<lexpr::Value>) -> Result<Type, ParseError>
     Ok(Type::List(Box::new(lst_type)))
 }
 
+fn parse_promise_annotation(lst_vec: Vec<lexpr::Value>) -> Result<Type, ParseError> {
+    if lst_vec.len() != 2 {
+        return Err(ParseError::from(
+            "Type annotation for promise has incorrect number of values.",
+        ));
+    }
+    let base_type = parse_type(&lst_vec[1])?;
+    Ok(Type::Promise(Box::new(base_type)))
+}
+
+fn parse_option_annotation(lst_vec: Vec<lexpr::Value>) -> Result<Type, ParseError> {
+    if lst_vec.len() != 2 {
+        return Err(ParseError::from(
+            "Type annotation for option has incorrect number of values.",
+        ));
+    }
+    let base_type = parse_type(&lst_vec[1])?;
+    Ok(option_type(base_type))
+}
+
 fn parse_tuple_annotation(lst_vec: Vec<lexpr::Value>) -> Result<Type, ParseError> {
     let tuple_types: Vec<Type> = lst_vec[1..(lst_vec.len())]
         .iter()
@@ -113,8 +279,57 @@ fn parse_tuple_annotation(lst_vec: Vec<lexpr::Value>) -> Result<Type, ParseError
     Ok(Type::Tuple(Vector::from(tuple_types)))
 }
 
+fn parse_union_annotation(lst_vec: Vec<lexpr::Value>) -> Result<Type, ParseError> {
+    if lst_vec.len() < 2 {
+        return Err(ParseError::from(
+            "Type annotation for union is missing values.",
+        ));
+    }
+    let union_types: Vec<Type> = lst_vec[1..(lst_vec.len())]
+        .iter()
+        .map(parse_type)
+        .collect::<Result<Vec<Type>, ParseError>>()?;
+    // each type in a union is identified by its position, so listing a type
+    // twice would make it ambiguous
+    for (i, typ) in union_types.iter().enumerate() {
+        if union_types[..i].contains(typ) {
+            return Err(ParseError::from(
+                "Type annotation for union contains duplicate types.",
+            ));
+        }
+    }
+    Ok(Type::Union(Vector::from(union_types)))
+}
+
+fn parse_type_var(value: &lexpr::Value) -> Result<u64, ParseError> {
+    let type_var_str = value
+        .as_symbol()
+        .ok_or("Type variable is not a valid symbol.")?;
+    match type_var_str.chars().next() {
+        Some('T') => Ok(type_var_str[1..type_var_str.len()]
+            .chars()
+            .collect::<String>()
+            .parse::<u64>()?),
+        _ => Err(ParseError::from(
+            "Type variable does not start with the letter T.",
+        )),
+    }
+}
+
+/// Parses a record type annotation, e.g. `(record (x : int) (y : bool))`.
+///
+/// If the last two values of the annotation are `...` followed by a type
+/// variable, e.g. `(record (x : int) ... T1)`, then the annotation describes
+/// any record with at least the listed fields, and the type variable (a "row
+/// variable") stands for the rest of the fields.
 fn parse_record_annotation(lst_vec: Vec<lexpr::Value>) -> Result<Type, ParseError> {
-    let record_types: Vec<(String, Type)> = lst_vec[1..(lst_vec.len())]
+    let is_open = lst_vec.len() >= 3 && lst_vec[lst_vec.len() - 2].as_symbol() == Some("...");
+    let fields_end = if is_open {
+        lst_vec.len() - 2
+    } else {
+        lst_vec.len()
+    };
+    let record_types: Vec<(String, Type)> = lst_vec[1..fields_end]
         .iter()
         .map(|exp| match exp.to_vec() {
             Some(binding) => {
@@ -142,7 +357,12 @@ fn parse_record_annotation(lst_vec: Vec<lexpr::Value>) -> Result<Type, ParseErro
             )),
         })
         .collect::<Result<Vec<(String, Type)>, ParseError>>()?;
-    Ok(Type::Record(Vector::from(record_types)))
+    if is_open {
+        let row_var = parse_type_var(&lst_vec[lst_vec.len() - 1])?;
+        Ok(Type::OpenRecord(Vector::from(record_types), row_var))
+    } else {
+        Ok(Type::Record(Vector::from(record_types)))
+    }
 }
 
 fn parse_exists_annotation(lst_vec: Vec<lexpr::Value>) -> Result<Type, ParseError> {
@@ -165,7 +385,7 @@ fn parse_exists_annotation(lst_vec: Vec<lexpr::Value>) -> Result<Type, ParseErro
 }
 
 fn parse_array(exps: &[lexpr::Value]) -> Result<Vector<Expr>, ParseError> {
-    exps.iter().map(|exp| parse(exp)).collect()
+    exps.iter().map(parse_exp).collect()
 }
 
 fn parse_binop(op: &str, rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
@@ -174,8 +394,8 @@ fn parse_binop(op: &str, rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
             "Binary operator has incorrect number of sub-expressions.",
         ));
     }
-    let exp1 = parse(&rest[0])?;
-    let exp2 = parse(&rest[1])?;
+    let exp1 = parse_exp(&rest[0])?;
+    let exp2 = parse_exp(&rest[1])?;
     let operator = match op {
         "and" => BinOp::And,
         "or" => BinOp::Or,
@@ -188,21 +408,37 @@ fn parse_binop(op: &str, rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
         "<=" => BinOp::LessOrEqual,
         ">=" => BinOp::GreaterOrEqual,
         "=" => BinOp::EqualTo,
+        "u<" => BinOp::UnsignedLessThan,
+        "u>" => BinOp::UnsignedGreaterThan,
+        "u/" => BinOp::UnsignedDivide,
+        "u%" => BinOp::UnsignedRemainder,
         "concat" => BinOp::Concat,
+        "expt" => BinOp::Expt,
         _ => return Err(ParseError::from("Unrecognized binary operator.")),
     };
     Ok(Expr::new(ExprKind::Binop(operator, exp1, exp2)))
 }
 
+fn parse_unop(op: UnOp, rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
+    if rest.len() != 1 {
+        return Err(ParseError::new(format!(
+            "{} expression has incorrect number of arguments.",
+            op
+        )));
+    }
+    let exp = parse_exp(&rest[0])?;
+    Ok(Expr::new(ExprKind::Unop(op, exp)))
+}
+
 fn parse_if(rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
     if rest.len() != 3 {
         return Err(ParseError::from(
             "If expression has incorrect number of arguments.",
         ));
     }
-    let predicate = parse(&rest[0])?;
-    let consequent = parse(&rest[1])?;
-    let alternate = parse(&rest[2])?;
+    let predicate = parse_exp(&rest[0])?;
+    let consequent = parse_exp(&rest[1])?;
+    let alternate = parse_exp(&rest[2])?;
     Ok(Expr::new(ExprKind::If(predicate, consequent, alternate)))
 }
 
@@ -229,11 +465,12 @@ fn parse_let(rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
             let binding_name = binding_vec[0]
                 .as_symbol()
                 .ok_or_else(|| "Let binding does not have a valid name.")?;
-            let binding_val = parse(&binding_vec[1])?;
+            check_binder(binding_name)?;
+            let binding_val = parse_exp(&binding_vec[1])?;
             Ok((String::from(binding_name), binding_val))
         })
         .collect::<Result<Vector<(String, Expr)>, ParseError>>()?;
-    let body = parse(&rest[1])?;
+    let body = parse_exp(&rest[1])?;
     Ok(Expr::new(ExprKind::Let(bindings_vec, body)))
 }
 
@@ -248,7 +485,7 @@ fn parse_lambda(rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
         return Err(ParseError::from("Lambda expression does not have the correct separator : between the arguments list and return type."));
     }
     let ret_type = parse_type(&rest[2])?;
-    let body = parse(&rest[3])?;
+    let body = parse_exp(&rest[3])?;
     Ok(Expr::new(ExprKind::Lambda(args, ret_type, body)))
 }
 
@@ -271,6 +508,7 @@ fn unwrap_lambda_args(args: &lexpr::Value) -> Result<Vector<(String, Type)>, Par
             let arg_name = arg_vec[0]
                 .as_symbol()
                 .ok_or_else(|| "Lambda argument does not have a valid name.")?;
+            check_binder(arg_name)?;
             if !check_separator(&arg_vec[1], ':') {
                 return Err(ParseError::from(
                     "Lambda argument does not contain the correct : separator.",
@@ -297,7 +535,7 @@ fn parse_make_record(rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
             let binding_name = binding_vec[0]
                 .as_symbol()
                 .ok_or_else(|| "Make-record binding does not have a valid name.")?;
-            let binding_val = parse(&binding_vec[1])?;
+            let binding_val = parse_exp(&binding_vec[1])?;
             Ok((String::from(binding_name), binding_val))
         })
         .collect::<Result<Vector<(String, Expr)>, ParseError>>()?;
@@ -307,13 +545,13 @@ fn parse_make_record(rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
 fn parse_get_record(rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
     if rest.len() != 2 {
         return Err(ParseError::from(
-            "Env-ref expression has incorrect number of arguments.",
+            "Record-ref expression has incorrect number of arguments.",
         ));
     }
-    let bindings = parse(&rest[0])?;
+    let bindings = parse_exp(&rest[0])?;
     let key = rest[1]
         .as_symbol()
-        .ok_or_else(|| "Env-ref key is not a valid identifier.")?;
+        .ok_or_else(|| "Record-ref key is not a valid identifier.")?;
     Ok(Expr::new(ExprKind::RecordGet(bindings, String::from(key))))
 }
 
@@ -334,7 +572,7 @@ fn parse_set_bang(rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
     let var = rest[0]
         .as_symbol()
         .ok_or_else(|| "Set expression does not have a symbol as its first argument.")?;
-    let new_val = parse(&rest[1])?;
+    let new_val = parse_exp(&rest[1])?;
     Ok(Expr::new(ExprKind::Set(String::from(var), new_val)))
 }
 
@@ -344,8 +582,8 @@ fn parse_cons(rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
             "Cons expression has incorrect number of arguments.",
         ));
     }
-    let first = parse(&rest[0])?;
-    let second = parse(&rest[1])?;
+    let first = parse_exp(&rest[0])?;
+    let second = parse_exp(&rest[1])?;
     Ok(Expr::new(ExprKind::Cons(first, second)))
 }
 
@@ -355,7 +593,7 @@ fn parse_car(rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
             "Car expression has incorrect number of arguments.",
         ));
     }
-    let pair = parse(&rest[0])?;
+    let pair = parse_exp(&rest[0])?;
     Ok(Expr::new(ExprKind::Car(pair)))
 }
 
@@ -365,7 +603,7 @@ fn parse_cdr(rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
             "Cdr expression has incorrect number of arguments.",
         ));
     }
-    let pair = parse(&rest[0])?;
+    let pair = parse_exp(&rest[0])?;
     Ok(Expr::new(ExprKind::Cdr(pair)))
 }
 
@@ -375,7 +613,7 @@ fn parse_is_null(rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
             "Null? expression has incorrect number of arguments.",
         ));
     }
-    let val = parse(&rest[0])?;
+    let val = parse_exp(&rest[0])?;
     Ok(Expr::new(ExprKind::IsNull(val)))
 }
 
@@ -390,7 +628,7 @@ fn parse_null(rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
 }
 
 fn parse_func(first: &lexpr::Value, rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
-    let func = parse(first)?;
+    let func = parse_exp(first)?;
     let args = parse_array(rest)?;
     Ok(Expr::new(ExprKind::FnApp(func, args)))
 }
@@ -406,11 +644,72 @@ fn parse_get_tuple(rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
             "Tuple-ref expression has incorrect number of arguments.",
         ));
     }
-    let tuple = parse(&rest[0])?;
-    let key = rest[1]
-        .as_u64()
-        .ok_or_else(|| "Second argument in tuple-ref is not an integer.")?;
-    Ok(Expr::new(ExprKind::TupleGet(tuple, key as u32)))
+    let tuple = parse_exp(&rest[0])?;
+    let key = parse_tuple_index(&rest[1], "tuple-ref")?;
+    Ok(Expr::new(ExprKind::TupleGet(tuple, key)))
+}
+
+fn parse_set_tuple(rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
+    if rest.len() != 3 {
+        return Err(ParseError::from(
+            "Tuple-set! expression has incorrect number of arguments.",
+        ));
+    }
+    let tuple = parse_exp(&rest[0])?;
+    let key = parse_tuple_index(&rest[1], "tuple-set!")?;
+    let val = parse_exp(&rest[2])?;
+    Ok(Expr::new(ExprKind::TupleSet(tuple, key, val)))
+}
+
+fn parse_tuple_index(value: &lexpr::Value, form: &str) -> Result<u32, ParseError> {
+    // The index may be any expression which can be evaluated to a constant,
+    // so that it can still be checked against the size of the tuple during
+    // type checking.
+    let key = eval_const_int(&parse_exp(value)?).map_err(|err| err.with_code("P005"))?;
+    if key < 0 || key > i64::from(u32::MAX) {
+        return Err(
+            ParseError::new(format!("Index {} in {} is out of bounds.", key, form))
+                .with_code("P005"),
+        );
+    }
+    Ok(key as u32)
+}
+
+/// Evaluates an expression built from integer literals and the arithmetic
+/// operators +, -, *, and /.
+///
+/// Values are computed with 64-bit integers so that reasonable intermediate
+/// results don't overflow.
+fn eval_const_int(exp: &Expr) -> Result<i64, ParseError> {
+    match &*exp.kind {
+        ExprKind::Num(x) => Ok(i64::from(*x)),
+        ExprKind::Binop(op, arg1, arg2) => {
+            let val1 = eval_const_int(arg1)?;
+            let val2 = eval_const_int(arg2)?;
+            let result = match op {
+                BinOp::Add => val1.checked_add(val2),
+                BinOp::Subtract => val1.checked_sub(val2),
+                BinOp::Multiply => val1.checked_mul(val2),
+                BinOp::Divide => {
+                    if val2 == 0 {
+                        return Err(ParseError::from("Division by zero in constant expression."));
+                    }
+                    val1.checked_div(val2)
+                }
+                _ => {
+                    return Err(ParseError::new(format!(
+                        "Operator {} is not allowed in a constant integer expression.",
+                        op
+                    )))
+                }
+            };
+            result.ok_or_else(|| ParseError::from("Overflow in constant expression."))
+        }
+        _ => Err(ParseError::new(format!(
+            "Expression {} is not a constant integer expression.",
+            exp
+        ))),
+    }
 }
 
 fn parse_pack(rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
@@ -419,7 +718,7 @@ fn parse_pack(rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
             "Pack expression has incorrect number of arguments.",
         ));
     }
-    let package = parse(&rest[0])?;
+    let package = parse_exp(&rest[0])?;
     let type_sub = parse_type(&rest[1])?;
     let exist_typ = parse_type(&rest[2])?;
     Ok(Expr::new(ExprKind::Pack(package, type_sub, exist_typ)))
@@ -442,7 +741,8 @@ fn parse_unpack(rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
     let var_name = String::from(inner_lst[0].as_symbol().ok_or_else(|| {
         "Unpack expression does not contain an identifier to bind the packed expression to."
     })?);
-    let package: Expr = parse(&inner_lst[1])?;
+    check_binder(&var_name)?;
+    let package: Expr = parse_exp(&inner_lst[1])?;
     let typ_var_symbol = inner_lst[2]
         .as_symbol()
         .ok_or_else(|| "Third argument in unpack is not a type variable.")?;
@@ -457,27 +757,325 @@ fn parse_unpack(rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
             ))
         }
     };
-    let body: Expr = parse(&rest[1])?;
+    let body: Expr = parse_exp(&rest[1])?;
     Ok(Expr::new(ExprKind::Unpack(
         var_name, package, typ_var, body,
     )))
 }
 
+fn parse_inject(rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
+    if rest.len() != 2 {
+        return Err(ParseError::from(
+            "Inject expression has incorrect number of arguments.",
+        ));
+    }
+    let exp = parse_exp(&rest[0])?;
+    let union_typ = parse_type(&rest[1])?;
+    Ok(Expr::new(ExprKind::Inject(exp, union_typ)))
+}
+
+fn parse_assert(rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
+    if rest.len() != 2 {
+        return Err(ParseError::from(
+            "Assert expression has incorrect number of arguments.",
+        ));
+    }
+    let exp = parse_exp(&rest[0])?;
+    let message = rest[1]
+        .as_str()
+        .ok_or("Assert expression does not have a string as its message.")?;
+    Ok(Expr::new(ExprKind::Assert(exp, String::from(message))))
+}
+
+fn parse_while(rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
+    if rest.len() != 2 {
+        return Err(ParseError::from(
+            "While expression has incorrect number of arguments.",
+        ));
+    }
+    let pred = parse_exp(&rest[0])?;
+    let body = parse_exp(&rest[1])?;
+    Ok(Expr::new(ExprKind::While(pred, body)))
+}
+
+fn parse_curry(rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
+    if rest.is_empty() {
+        return Err(ParseError::from("Curry expression is missing a function."));
+    }
+    let func = parse_exp(&rest[0])?;
+    let args = parse_array(&rest[1..])?;
+    Ok(Expr::new(ExprKind::Curry(func, args)))
+}
+
+fn parse_compose(rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
+    if rest.len() != 2 {
+        return Err(ParseError::from(
+            "Compose expression has incorrect number of arguments.",
+        ));
+    }
+    let outer = parse_exp(&rest[0])?;
+    let inner = parse_exp(&rest[1])?;
+    Ok(Expr::new(ExprKind::Compose(outer, inner)))
+}
+
+fn parse_delay(rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
+    if rest.len() != 1 {
+        return Err(ParseError::from(
+            "Delay expression has incorrect number of arguments.",
+        ));
+    }
+    let exp = parse_exp(&rest[0])?;
+    Ok(Expr::new(ExprKind::Delay(exp)))
+}
+
+fn parse_force(rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
+    if rest.len() != 1 {
+        return Err(ParseError::from(
+            "Force expression has incorrect number of arguments.",
+        ));
+    }
+    let promise = parse_exp(&rest[0])?;
+    Ok(Expr::new(ExprKind::Force(promise)))
+}
+
+fn parse_format(rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
+    if rest.is_empty() {
+        return Err(ParseError::from(
+            "Format expression is missing a control string.",
+        ));
+    }
+    let control = rest[0]
+        .as_str()
+        .ok_or("Format expression does not have a string as its control string.")?;
+    let directives = format_directives(control).map_err(ParseError::new)?;
+    if directives.len() != rest.len() - 1 {
+        return Err(ParseError::new(format!(
+            "Format string has {} directives, but {} arguments were given.",
+            directives.len(),
+            rest.len() - 1
+        )));
+    }
+    let args = rest[1..]
+        .iter()
+        .map(parse_exp)
+        .collect::<Result<Vector<Expr>, ParseError>>()?;
+    Ok(Expr::new(ExprKind::Format(String::from(control), args)))
+}
+
+fn parse_builtin(builtin: Builtin, rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
+    let (param_types, _ret_type) = builtin.signature();
+    if rest.len() != param_types.len() {
+        return Err(ParseError::new(format!(
+            "{} expression has incorrect number of arguments.",
+            builtin
+        )));
+    }
+    let args = rest
+        .iter()
+        .map(parse_exp)
+        .collect::<Result<Vector<Expr>, ParseError>>()?;
+    Ok(Expr::new(ExprKind::Builtin(builtin, args)))
+}
+
+fn parse_call_extern(rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
+    if rest.len() < 3 {
+        return Err(ParseError::from(
+            "Call-extern expression is missing values.",
+        ));
+    }
+    let module = rest[0]
+        .as_str()
+        .ok_or("Call-extern expression does not have a string as its module.")?;
+    let name = rest[1]
+        .as_str()
+        .ok_or("Call-extern expression does not have a string as its name.")?;
+    let typ = parse_type(&rest[2])?;
+    match &typ {
+        Type::Func(param_types, _ret_type, _effect) if param_types.len() == rest.len() - 3 => (),
+        Type::Func(_, _, _) => {
+            return Err(ParseError::new(format!(
+                "Call to extern function {}.{} has incorrect number of arguments.",
+                module, name
+            )))
+        }
+        _ => {
+            return Err(ParseError::from(
+                "Call-extern expression does not have a function type.",
+            ))
+        }
+    }
+    let args = rest[3..]
+        .iter()
+        .map(parse_exp)
+        .collect::<Result<Vector<Expr>, ParseError>>()?;
+    Ok(Expr::new(ExprKind::Extern(
+        String::from(module),
+        String::from(name),
+        typ,
+        args,
+    )))
+}
+
+fn parse_wasm_asm(rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
+    if rest.len() < 3 || rest[1].as_symbol() != Some(":") {
+        return Err(ParseError::from(
+            "Wasm-asm expression must have instructions, a colon, and a result type.",
+        ));
+    }
+    let code = rest[0]
+        .as_str()
+        .ok_or("Wasm-asm expression does not have a string of instructions.")?;
+    let typ = parse_type(&rest[2])?;
+    let args = rest[3..]
+        .iter()
+        .map(parse_exp)
+        .collect::<Result<Vector<Expr>, ParseError>>()?;
+    Ok(Expr::new(ExprKind::WasmAsm(String::from(code), typ, args)))
+}
+
+fn parse_typecase(rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
+    if rest.len() < 2 {
+        return Err(ParseError::from(
+            "Typecase expression must have an expression and at least one clause.",
+        ));
+    }
+    let exp = parse_exp(&rest[0])?;
+    let clauses = rest[1..]
+        .iter()
+        .map(|clause| {
+            // ((int x) body) as a vec
+            let clause_vec = clause
+                .to_vec()
+                .ok_or("Typecase clause is not a valid list.")?;
+            if clause_vec.len() != 2 {
+                return Err(ParseError::from(
+                    "Typecase clause is missing values or contains extra values.",
+                ));
+            }
+            let pattern_vec = clause_vec[0]
+                .to_vec()
+                .ok_or("Typecase clause pattern is not a valid list.")?;
+            if pattern_vec.len() != 2 {
+                return Err(ParseError::from(
+                    "Typecase clause pattern must contain a type and a variable name.",
+                ));
+            }
+            let typ = parse_type(&pattern_vec[0])?;
+            let var = pattern_vec[1]
+                .as_symbol()
+                .ok_or("Typecase clause pattern does not have a valid variable name.")?;
+            check_binder(var)?;
+            let body = parse_exp(&clause_vec[1])?;
+            Ok((typ, String::from(var), body))
+        })
+        .collect::<Result<Vector<(Type, String, Expr)>, ParseError>>()?;
+    Ok(Expr::new(ExprKind::TypeCase(exp, clauses)))
+}
+
+/// Parses `(case key ((datum ...) body) ... (else body))`, where each datum
+/// is a constant integer expression (see `eval_const_int`), and no datum
+/// appears in more than one clause.
+fn parse_case(rest: &[lexpr::Value]) -> Result<Expr, ParseError> {
+    if rest.len() < 2 {
+        return Err(ParseError::from(
+            "Case expression must have a key and an else clause.",
+        ));
+    }
+    let key = parse_exp(&rest[0])?;
+    let (else_clause, clauses) = rest[1..].split_last().unwrap();
+    let else_body = match else_clause.to_vec() {
+        Some(lst) if lst.len() == 2 && lst[0].as_symbol() == Some("else") => parse_exp(&lst[1])?,
+        _ => {
+            return Err(ParseError::from(
+                "Case expression does not end with an else clause.",
+            ))
+        }
+    };
+    let mut seen = BTreeSet::new();
+    let clauses = clauses
+        .iter()
+        .map(|clause| {
+            // ((1 2) body) as a vec
+            let clause_vec = clause.to_vec().ok_or("Case clause is not a valid list.")?;
+            if clause_vec.len() != 2 {
+                return Err(ParseError::from(
+                    "Case clause is missing values or contains extra values.",
+                ));
+            }
+            let datums = clause_vec[0]
+                .to_vec()
+                .ok_or("Case clause datums are not a valid list.")?
+                .iter()
+                .map(|datum| {
+                    let val =
+                        eval_const_int(&parse_exp(datum)?).map_err(|err| err.with_code("P005"))?;
+                    let val = i32::try_from(val).map_err(|_| {
+                        ParseError::new(format!("Case datum {} is not a 32-bit integer.", val))
+                            .with_code("P005")
+                    })?;
+                    if !seen.insert(val) {
+                        return Err(ParseError::new(format!(
+                            "Case datum {} appears in more than one clause.",
+                            val
+                        )));
+                    }
+                    Ok(val)
+                })
+                .collect::<Result<Vector<i32>, ParseError>>()?;
+            if datums.is_empty() {
+                return Err(ParseError::from("Case clause has no datums."));
+            }
+            let body = parse_exp(&clause_vec[1])?;
+            Ok((datums, body))
+        })
+        .collect::<Result<Vector<(Vector<i32>, Expr)>, ParseError>>()?;
+    Ok(Expr::new(ExprKind::Case(key, clauses, else_body)))
+}
+
+/// Parses an s-expression into an expression. Macros should be expanded and
+/// syntactic sugar removed first (see `macro_expand::macro_expand` and
+/// `desugar::desugar`).
 pub fn parse(value: &lexpr::Value) -> Result<Expr, ParseError> {
-    match value {
+    let _span = tracing::info_span!("parse").entered();
+    parse_exp(value)
+}
+
+/// Like `parse`, but first checks that the s-expression is within the depth
+/// and node limits, returning a `LimitError` if it isn't.
+pub fn parse_with_limits(
+    value: &lexpr::Value,
+    limits: &CompilerLimits,
+) -> Result<Expr, Box<dyn std::error::Error>> {
+    check_value(value, limits)?;
+    Ok(parse(value)?)
+}
+
+fn parse_exp(value: &lexpr::Value) -> Result<Expr, ParseError> {
+    let exp = match value {
         lexpr::Value::Number(x) => match x.as_i64() {
             Some(val) => {
                 if val >= i32::min_value() as i64 && val <= i32::max_value() as i64 {
                     Ok(Expr::new(ExprKind::Num(val as i32)))
                 } else {
-                    Err(ParseError::from(
-                        "Invalid number found (must be a 32-bit integer).",
+                    Err(ParseError::new(format!(
+                        "Number {} is out of range (must be a 32-bit integer, from {} to {}).",
+                        val,
+                        i32::MIN,
+                        i32::MAX
                     ))
+                    .with_code("P002"))
                 }
             }
-            None => Err(ParseError::from(
-                "Invalid number found (must be a 32-bit integer).",
-            )),
+            None => match x.as_f64() {
+                // floats are 32-bit, so literals are rounded to the nearest
+                // 32-bit float
+                Some(val) if x.is_f64() => Ok(Expr::new(ExprKind::Float(val as f32))),
+                _ => Err(ParseError::new(format!(
+                    "Invalid number {} found (must be a 32-bit integer or a float).",
+                    x
+                ))
+                .with_code("P002")),
+            },
         },
         lexpr::Value::Bool(x) => Ok(Expr::new(ExprKind::Bool(*x))),
         lexpr::Value::String(x) => Ok(Expr::new(ExprKind::Str((*x).to_string()))),
@@ -499,8 +1097,19 @@ pub fn parse(value: &lexpr::Value) -> Result<Expr, ParseError> {
 
             match first.as_symbol() {
                 Some(val) => match val {
-                    "and" | "or" | "+" | "*" | "-" | "/" | ">" | "<" | ">=" | "<=" | "="
-                    | "concat" => parse_binop(val, &rest),
+                    "and" | "or" | "+" | "*" | "-" | "/" | ">" | "<" | ">=" | "<=" | "=" | "u<"
+                    | "u>" | "u/" | "u%" | "concat" | "expt" => parse_binop(val, rest),
+                    "exact->inexact" => parse_unop(UnOp::ExactToInexact, rest),
+                    "inexact->exact" => parse_unop(UnOp::InexactToExact, rest),
+                    "int->float" => parse_unop(UnOp::IntToFloat, rest),
+                    "float->int" => parse_unop(UnOp::FloatToInt, rest),
+                    "round" => parse_unop(UnOp::Round, rest),
+                    "floor" => parse_unop(UnOp::Floor, rest),
+                    "ceiling" => parse_unop(UnOp::Ceiling, rest),
+                    "truncate" => parse_unop(UnOp::Truncate, rest),
+                    "sqrt" => parse_unop(UnOp::Sqrt, rest),
+                    "nan?" => parse_unop(UnOp::IsNan, rest),
+                    "infinite?" => parse_unop(UnOp::IsInfinite, rest),
                     "if" => parse_if(&rest),
                     "let" => parse_let(&rest),
                     "lambda" => parse_lambda(&rest),
@@ -515,8 +1124,48 @@ pub fn parse(value: &lexpr::Value) -> Result<Expr, ParseError> {
                     "null" => parse_null(&rest),
                     "make-tuple" => parse_make_tuple(&rest),
                     "tuple-ref" => parse_get_tuple(&rest),
+                    "tuple-set!" => parse_set_tuple(rest),
                     "pack" => parse_pack(&rest),
                     "unpack" => parse_unpack(&rest),
+                    "inject" => parse_inject(rest),
+                    "typecase" => parse_typecase(rest),
+                    "case" => parse_case(rest),
+                    "assert" => parse_assert(rest),
+                    "while" => parse_while(rest),
+                    "curry" => parse_curry(rest),
+                    "compose" => parse_compose(rest),
+                    "delay" => parse_delay(rest),
+                    "force" => parse_force(rest),
+                    "format" => parse_format(rest),
+                    "random" => parse_builtin(Builtin::Random, rest),
+                    "random-float" => parse_builtin(Builtin::RandomFloat, rest),
+                    "current-millis" => parse_builtin(Builtin::CurrentMillis, rest),
+                    "read-file" => parse_builtin(Builtin::ReadFile, rest),
+                    "write-file" => parse_builtin(Builtin::WriteFile, rest),
+                    "command-line" => parse_builtin(Builtin::CommandLine, rest),
+                    "getenv" => parse_builtin(Builtin::GetEnv, rest),
+                    "string-match" => parse_builtin(Builtin::StringMatch, rest),
+                    "string-replace" => parse_builtin(Builtin::StringReplace, rest),
+                    "string->utf8" => parse_builtin(Builtin::StringToUtf8, rest),
+                    "utf8->string" => parse_builtin(Builtin::Utf8ToString, rest),
+                    "hex-encode" => parse_builtin(Builtin::HexEncode, rest),
+                    "hex-decode" => parse_builtin(Builtin::HexDecode, rest),
+                    "base64-encode" => parse_builtin(Builtin::Base64Encode, rest),
+                    "base64-decode" => parse_builtin(Builtin::Base64Decode, rest),
+                    "string-length" => parse_builtin(Builtin::StringLength, rest),
+                    "string-ref" => parse_builtin(Builtin::StringRef, rest),
+                    "substring" => parse_builtin(Builtin::Substring, rest),
+                    "string->list" => parse_builtin(Builtin::StringToList, rest),
+                    "string-graphemes" => parse_builtin(Builtin::Graphemes, rest),
+                    "exp" => parse_builtin(Builtin::Exp, rest),
+                    "log" => parse_builtin(Builtin::Log, rest),
+                    "sin" => parse_builtin(Builtin::Sin, rest),
+                    "cos" => parse_builtin(Builtin::Cos, rest),
+                    "tan" => parse_builtin(Builtin::Tan, rest),
+                    "atan2" => parse_builtin(Builtin::Atan2, rest),
+                    "float->string" => parse_builtin(Builtin::FloatToString, rest),
+                    "call-extern" => parse_call_extern(rest),
+                    "wasm-asm" => parse_wasm_asm(rest),
                     _ => parse_func(&first, &rest),
                 },
                 None => parse_func(&first, &rest),
@@ -528,5 +1177,8 @@ pub fn parse(value: &lexpr::Value) -> Result<Expr, ParseError> {
             symbol => Ok(Expr::new(ExprKind::Id(symbol.to_string()))),
         },
         _ => Err(ParseError::from("Unrecognized form of expression found.")),
-    }
+    }?;
+    #[cfg(feature = "trace-exprs")]
+    tracing::debug!(%exp, "parsed");
+    Ok(exp)
 }
@@ -0,0 +1,144 @@
+/// This module formats source code into a canonical layout, with consistent
+/// indentation and line breaks only where a line would otherwise be too wide.
+use crate::read::{contains_comments, read};
+use crate::util::escape_string;
+use lexpr::Value;
+
+#[derive(Clone, Debug)]
+pub struct PrettyError(String);
+
+// Allows other errors to wrap this one
+impl std::error::Error for PrettyError {}
+
+impl From<&str> for PrettyError {
+    fn from(message: &str) -> Self {
+        PrettyError(String::from(message))
+    }
+}
+
+impl std::fmt::Display for PrettyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "PrettyError: {}", self.0)
+    }
+}
+
+/// The maximum line width which source is formatted to by default.
+pub const DEFAULT_WIDTH: usize = 80;
+
+/// Formats source code, returning the formatted source (ending in a newline).
+///
+/// Lists which fit within `width` columns are kept on one line. Otherwise,
+/// the body of a binding form (like let or lambda) is put on separate lines
+/// indented by two spaces, and the arguments of any other list are put on
+/// separate lines aligned with its first argument.
+///
+/// TODO: comments are not kept by the reader, so source containing comments
+/// is rejected rather than having them removed.
+pub fn format_source(source: &str, width: usize) -> Result<String, PrettyError> {
+    if contains_comments(source).map_err(|err| PrettyError(format!("{}", err)))? {
+        return Err(PrettyError::from(
+            "Source containing comments can't be formatted yet.",
+        ));
+    }
+    let value = read(source).map_err(|err| PrettyError(format!("{}", err)))?;
+    let mut output = String::new();
+    pretty_print(&value, width, &mut output);
+    output.push('\n');
+    Ok(output)
+}
+
+/// Returns whether source code is already formatted (see `format_source`),
+/// e.g. to check formatting in CI.
+pub fn is_formatted(source: &str, width: usize) -> Result<bool, PrettyError> {
+    Ok(format_source(source, width)? == source)
+}
+
+/// Appends the formatted s-expression to `output`, starting at the current
+/// column of the output.
+pub fn pretty_print(value: &Value, width: usize, output: &mut String) {
+    let indent = current_column(output);
+    let flat = print_flat(value);
+    let items = match value.to_vec() {
+        Some(items) if !items.is_empty() && indent + flat.len() > width => items,
+        _ => {
+            output.push_str(&flat);
+            return;
+        }
+    };
+
+    output.push('(');
+    match items[0].as_symbol() {
+        Some(head) if num_header_args(head, &items).is_some() => {
+            let num_header = num_header_args(head, &items).unwrap();
+            output.push_str(head);
+            for item in &items[1..=num_header] {
+                output.push(' ');
+                pretty_print(item, width, output);
+            }
+            for item in &items[(num_header + 1)..] {
+                new_line(indent + 2, output);
+                pretty_print(item, width, output);
+            }
+        }
+        Some(head) if items.len() > 1 => {
+            output.push_str(head);
+            output.push(' ');
+            let arg_indent = current_column(output);
+            pretty_print(&items[1], width, output);
+            for item in &items[2..] {
+                new_line(arg_indent, output);
+                pretty_print(item, width, output);
+            }
+        }
+        _ => {
+            pretty_print(&items[0], width, output);
+            for item in &items[1..] {
+                new_line(indent + 1, output);
+                pretty_print(item, width, output);
+            }
+        }
+    }
+    output.push(')');
+}
+
+/// For forms which have a body, returns how many of the arguments come before
+/// the body, and so are kept on the first line.
+fn num_header_args(head: &str, items: &[Value]) -> Option<usize> {
+    let num_header = match head {
+        "begin" => 0,
+        "let" | "unpack" | "typecase" | "while" | "define-syntax" | "define-macro"
+        | "define-constant" | "syntax-rules" => 1,
+        // (lambda (params) : ret-type body)
+        "lambda" if items.get(2).and_then(|item| item.as_symbol()) == Some(":") => 3,
+        "lambda" => 1,
+        "declare-extern" => 4,
+        _ => return None,
+    };
+    Some(num_header.min(items.len() - 1))
+}
+
+/// Prints an s-expression on a single line.
+fn print_flat(value: &Value) -> String {
+    match value {
+        Value::String(val) => escape_string(val),
+        _ => match value.to_vec() {
+            Some(items) => {
+                let items: Vec<String> = items.iter().map(print_flat).collect();
+                format!("({})", items.join(" "))
+            }
+            None => format!("{}", value),
+        },
+    }
+}
+
+fn current_column(output: &str) -> usize {
+    match output.rfind('\n') {
+        Some(index) => output[(index + 1)..].chars().count(),
+        None => output.chars().count(),
+    }
+}
+
+fn new_line(indent: usize, output: &mut String) {
+    output.push('\n');
+    output.push_str(&" ".repeat(indent));
+}
@@ -0,0 +1,343 @@
+/// This module builds projects made of several source files, described by a
+/// manifest (conventionally named `scheme.toml`), e.g.
+///
+/// ```toml
+/// entry = "main.scm"
+/// files = ["macros.scm", "constants.scm"]
+/// target = "wasm"
+/// opt-level = "O2"
+/// features = ["wasi", "log-level=2"]
+/// ```
+///
+/// The entry file contains the program's expression. Each of the other files
+/// contains definitions (`define-syntax`, `define-macro`, `define-constant`
+/// and `declare-extern`) which can be used by the files after it and by the
+/// entry file. Paths are relative to the directory containing the manifest.
+/// Only `entry` is required; `target` defaults to "wasm" (the only backend),
+/// `opt-level` to "O0", and `features` (user-defined features, in the form
+/// `name[=value]` described in `features::FeatureFlags::define`) to none.
+///
+/// Files can also import other files, with `(import name ...)` forms before
+/// their definitions: the name `util/strings` refers to the file
+/// `util/strings.scm` relative to the project's root. Imported files are
+/// included before the files which import them (see `ModuleResolver`), so
+/// they don't need to be listed in the manifest.
+///
+/// Since the files are combined into a single program before compiling, the
+/// definitions from each file are inlined where they're used, and unused
+/// ones add nothing to the module. Likewise, functions which the program
+/// can't reach aren't included in the module (see `analysis::reachable_fns`).
+use crate::compile::{compile_exp_with_options, CompileOptions};
+use crate::desugar::desugar_with_features;
+use crate::features::FeatureFlags;
+use crate::generate_code::construct_module_from_prog;
+use crate::limits::CompilerLimits;
+use crate::macro_expand::macro_expand_with_limits;
+use crate::optimize::OptLevel;
+use crate::parse::parse_with_limits;
+use crate::read::read;
+use lexpr::Value;
+use parity_wasm::elements::Module;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug)]
+pub struct ProjectError(String);
+
+// Allows other errors to wrap this one
+impl std::error::Error for ProjectError {}
+
+impl From<&str> for ProjectError {
+    fn from(message: &str) -> Self {
+        ProjectError(String::from(message))
+    }
+}
+
+impl std::fmt::Display for ProjectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "ProjectError: {}", self.0)
+    }
+}
+
+/// The backends which a project can be compiled with.
+///
+/// TODO: a backend which emits Rust source would write a cargo project rather
+/// than a module: a `Cargo.toml` with `crate-type = ["cdylib"]` and the
+/// runtime as a dependency, `src/lib.rs` with the generated code, and a
+/// README saying how to build it for wasm32. The wasm backend's modules are
+/// already the final output, so there's nothing to build.
+///
+/// Building such projects would be slow without caching the compiled runtime
+/// between builds, e.g. in the user's cache directory, keyed by the compiler
+/// version and the flags it was built with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Target {
+    Wasm,
+}
+
+/// A parsed project manifest. Paths are relative to `root`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Manifest {
+    pub root: PathBuf,
+    pub entry: PathBuf,
+    pub files: Vec<PathBuf>,
+    pub target: Target,
+    pub opt_level: OptLevel,
+    pub features: FeatureFlags,
+}
+
+impl Manifest {
+    /// Parses the contents of a manifest whose paths are relative to `root`.
+    pub fn parse(source: &str, root: &Path) -> Result<Manifest, ProjectError> {
+        let table = match source.parse::<toml::Value>() {
+            Ok(toml::Value::Table(table)) => table,
+            Ok(_) => return Err(ProjectError::from("Manifest is not a table.")),
+            Err(err) => return Err(ProjectError(format!("Invalid manifest: {}", err))),
+        };
+        if let Some(key) = table.keys().find(|key| {
+            !["entry", "files", "target", "opt-level", "features"].contains(&key.as_str())
+        }) {
+            return Err(ProjectError(format!("Unknown manifest key: {}.", key)));
+        }
+        let entry = match table.get("entry") {
+            Some(toml::Value::String(entry)) => PathBuf::from(entry),
+            Some(_) => return Err(ProjectError::from("Manifest entry is not a string.")),
+            None => return Err(ProjectError::from("Manifest has no entry.")),
+        };
+        let files = match table.get("files") {
+            Some(toml::Value::Array(files)) => files
+                .iter()
+                .map(|file| match file {
+                    toml::Value::String(file) => Ok(PathBuf::from(file)),
+                    _ => Err(ProjectError::from("Manifest files are not all strings.")),
+                })
+                .collect::<Result<Vec<PathBuf>, ProjectError>>()?,
+            Some(_) => return Err(ProjectError::from("Manifest files is not an array.")),
+            None => vec![],
+        };
+        let target = match table.get("target").map(|target| target.as_str()) {
+            None | Some(Some("wasm")) => Target::Wasm,
+            Some(_) => return Err(ProjectError::from("Manifest target is not \"wasm\".")),
+        };
+        let opt_level = match table.get("opt-level").map(|level| level.as_str()) {
+            None | Some(Some("O0")) => OptLevel::O0,
+            Some(Some("O1")) => OptLevel::O1,
+            Some(Some("O2")) => OptLevel::O2,
+            Some(_) => {
+                return Err(ProjectError::from(
+                    "Manifest opt-level is not \"O0\", \"O1\" or \"O2\".",
+                ))
+            }
+        };
+        let mut features = FeatureFlags::new();
+        match table.get("features") {
+            Some(toml::Value::Array(definitions)) => {
+                for definition in definitions {
+                    let definition = definition.as_str().ok_or_else(|| {
+                        ProjectError::from("Manifest features are not all strings.")
+                    })?;
+                    features.define(definition).map_err(|err| {
+                        ProjectError(format!("Invalid manifest feature: {}", err))
+                    })?;
+                }
+            }
+            Some(_) => return Err(ProjectError::from("Manifest features is not an array.")),
+            None => (),
+        }
+        Ok(Manifest {
+            root: root.to_path_buf(),
+            entry,
+            files,
+            target,
+            opt_level,
+            features,
+        })
+    }
+
+    /// Where `write_project` writes the project's module unless told
+    /// otherwise: the entry file's path with the extension ".wasm".
+    pub fn output_path(&self) -> PathBuf {
+        self.root.join(self.entry.with_extension("wasm"))
+    }
+
+    /// Reads the manifest at `path`.
+    pub fn read(path: &Path) -> Result<Manifest, ProjectError> {
+        let source = read_file(path)?;
+        let root = path.parent().unwrap_or_else(|| Path::new(""));
+        Manifest::parse(&source, root)
+    }
+
+    /// Combines the project's files into a single program: a begin
+    /// expression containing the definitions from each file, with every file
+    /// after the files it imports, followed by the entry file's expression.
+    pub fn program(&self) -> Result<Value, ProjectError> {
+        let mut resolver = ModuleResolver::new(&self.root);
+        let mut order = vec![];
+        for file in self.files.iter().chain(std::iter::once(&self.entry)) {
+            resolver.resolve(file, &mut order)?;
+        }
+        let mut program = vec![Value::symbol("begin")];
+        for file in order {
+            program.extend(resolver.modules[&file].forms.iter().cloned());
+        }
+        Ok(Value::list(program))
+    }
+}
+
+/// A file of a project, read into the forms it contains (not including its
+/// import forms).
+#[derive(Clone, Debug)]
+struct SourceModule {
+    imports: Vec<PathBuf>,
+    forms: Vec<Value>,
+}
+
+/// Finds the files which a project's files import, relative to the root of
+/// the project. Each file is only read once, however many files import it.
+pub struct ModuleResolver {
+    root: PathBuf,
+    modules: BTreeMap<PathBuf, SourceModule>,
+}
+
+impl ModuleResolver {
+    pub fn new(root: &Path) -> Self {
+        ModuleResolver {
+            root: root.to_path_buf(),
+            modules: BTreeMap::new(),
+        }
+    }
+
+    /// Adds `file` to `order`, after all of the files it imports (directly
+    /// or indirectly), unless it is already there. Returns an error if a file
+    /// imports itself, directly or indirectly.
+    pub fn resolve(&mut self, file: &Path, order: &mut Vec<PathBuf>) -> Result<(), ProjectError> {
+        self.resolve_with_stack(file, order, &mut vec![])
+    }
+
+    fn resolve_with_stack(
+        &mut self,
+        file: &Path,
+        order: &mut Vec<PathBuf>,
+        stack: &mut Vec<PathBuf>,
+    ) -> Result<(), ProjectError> {
+        if let Some(start) = stack.iter().position(|path| path == file) {
+            let cycle: Vec<String> = stack[start..]
+                .iter()
+                .chain(std::iter::once(&file.to_path_buf()))
+                .map(|path| path.display().to_string())
+                .collect();
+            return Err(ProjectError(format!(
+                "Import cycle: {}.",
+                cycle.join(" -> ")
+            )));
+        }
+        if order.iter().any(|path| path == file) {
+            return Ok(());
+        }
+        let imports = self.load(file)?.imports.clone();
+        stack.push(file.to_path_buf());
+        for import in imports.iter() {
+            self.resolve_with_stack(import, order, stack)?;
+        }
+        stack.pop();
+        order.push(file.to_path_buf());
+        Ok(())
+    }
+
+    /// Returns the forms of a file, reading it if it hasn't been read yet.
+    fn load(&mut self, file: &Path) -> Result<&SourceModule, ProjectError> {
+        if !self.modules.contains_key(file) {
+            let module = read_module(&self.root, file)?;
+            self.modules.insert(file.to_path_buf(), module);
+        }
+        Ok(&self.modules[file])
+    }
+}
+
+/// Reads the forms in a file, separating the imports at its start from the
+/// rest of its forms.
+fn read_module(root: &Path, file: &Path) -> Result<SourceModule, ProjectError> {
+    // the forms are read as a begin expression, on the first line so that
+    // line numbers in errors are unchanged
+    let source = format!("(begin {}\n)", read_file(&root.join(file))?);
+    let value = read(&source)
+        .map_err(|err| ProjectError(format!("Could not read {}: {}", file.display(), err)))?;
+    let mut forms = value.to_vec().unwrap().split_off(1);
+    let num_imports = forms.iter().take_while(|form| is_import(form)).count();
+    let mut imports = vec![];
+    for form in forms.drain(..num_imports) {
+        for name in form.to_vec().unwrap().iter().skip(1) {
+            let name = name.as_symbol().ok_or_else(|| {
+                ProjectError(format!(
+                    "Import in {} is not a module name: {}",
+                    file.display(),
+                    name
+                ))
+            })?;
+            imports.push(PathBuf::from(format!("{}.scm", name)));
+        }
+    }
+    if let Some(form) = forms.iter().find(|form| is_import(form)) {
+        return Err(ProjectError(format!(
+            "Import in {} is not at the start of the file: {}",
+            file.display(),
+            form
+        )));
+    }
+    Ok(SourceModule { imports, forms })
+}
+
+fn is_import(form: &Value) -> bool {
+    match form.to_vec() {
+        Some(lst) => lst.first().and_then(|head| head.as_symbol()) == Some("import"),
+        None => false,
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, ProjectError> {
+    std::fs::read_to_string(path)
+        .map_err(|err| ProjectError(format!("Could not read {}: {}", path.display(), err)))
+}
+
+/// Compiles the project described by the manifest at `path` into a module.
+pub fn build_project(path: &Path) -> Result<Module, Box<dyn std::error::Error>> {
+    build_manifest(&Manifest::read(path)?)
+}
+
+/// Compiles the project described by the manifest at `path`, and writes the
+/// module to `output` (by default, the manifest's `output_path`). Returns the
+/// path of the written module, which is ready to run; there's no further
+/// build step, since modules don't link against a runtime.
+pub fn write_project(
+    path: &Path,
+    output: Option<&Path>,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let manifest = Manifest::read(path)?;
+    let module = build_manifest(&manifest)?;
+    let output = output
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| manifest.output_path());
+    parity_wasm::serialize_to_file(&output, module)
+        .map_err(|err| ProjectError(format!("Could not write {}: {}", output.display(), err)))?;
+    Ok(output)
+}
+
+fn build_manifest(manifest: &Manifest) -> Result<Module, Box<dyn std::error::Error>> {
+    let program = manifest.features.bind(&manifest.program()?);
+    let limits = CompilerLimits::default();
+    let exp = parse_with_limits(
+        &desugar_with_features(
+            &macro_expand_with_limits(&program, &limits)?,
+            &manifest.features.features(),
+        )?,
+        &limits,
+    )?;
+    let options = CompileOptions {
+        opt_level: manifest.opt_level,
+        ..CompileOptions::default()
+    };
+    let prog = compile_exp_with_options(&exp, &options)?;
+    match manifest.target {
+        Target::Wasm => Ok(construct_module_from_prog(&prog)?),
+    }
+}
@@ -0,0 +1,239 @@
+/// This module reads source code into s-expressions, which can then be passed
+/// to `macro_expand::macro_expand` and `parse::parse`.
+///
+/// Reading is mostly handled by lexpr, but comment syntax which lexpr doesn't
+/// support is removed first.
+use lexpr::Value;
+
+#[derive(Clone, Debug)]
+pub struct ReadError(String);
+
+// Allows other errors to wrap this one
+impl std::error::Error for ReadError {}
+
+impl From<&str> for ReadError {
+    fn from(message: &str) -> Self {
+        ReadError(String::from(message))
+    }
+}
+
+impl std::fmt::Display for ReadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "ReadError: {}", self.0)
+    }
+}
+
+/// Reads a single s-expression from source code.
+///
+/// Besides line comments (`; ...`), the source may contain block comments
+/// (`#| ... |#`, which can be nested) and datum comments (`#;` followed by
+/// any s-expression, which is ignored).
+///
+/// Strings may contain the escapes `\n`, `\t`, `\r`, `\"`, `\\`, and
+/// `\xNN;` or `\u{NN}` for any unicode code point (in hexadecimal).
+///
+/// Integer literals can also be written in binary, octal, or hexadecimal
+/// (`#b1010`, `#o755`, `#x1F`), and are parsed like any other number.
+pub fn read(source: &str) -> Result<Value, ReadError> {
+    let stripped = strip_comments(source)?;
+    lexpr::from_str(&stripped).map_err(|err| ReadError(format!("{}", err)))
+}
+
+/// Returns whether the source contains any comments (of any kind).
+pub fn contains_comments(source: &str) -> Result<bool, ReadError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        match (chars[i], chars.get(i + 1)) {
+            (';', _) | ('#', Some('|')) | ('#', Some(';')) => return Ok(true),
+            _ => i = skip_atom(&chars, i)?,
+        }
+    }
+    Ok(false)
+}
+
+/// Replaces block comments and datum comments with whitespace, and rewrites
+/// escapes which lexpr doesn't understand. Newlines are kept, so that line
+/// numbers in lexpr's error messages are still accurate.
+fn strip_comments(source: &str) -> Result<String, ReadError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut output = String::with_capacity(source.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let end = match (chars[i], chars.get(i + 1)) {
+            ('#', Some('|')) => {
+                let end = skip_block_comment(&chars, i)?;
+                blank_out(&chars[i..end], &mut output);
+                end
+            }
+            ('#', Some(';')) => {
+                let end = skip_datum(&chars, i + 2)?;
+                blank_out(&chars[i..end], &mut output);
+                end
+            }
+            ('"', _) => {
+                let end = skip_atom(&chars, i)?;
+                output.push_str(&convert_unicode_escapes(&chars[i..end])?);
+                end
+            }
+            _ => {
+                let end = skip_atom(&chars, i)?;
+                output.extend(&chars[i..end]);
+                end
+            }
+        };
+        i = end;
+    }
+    Ok(output)
+}
+
+/// Rewrites `\u{NN}` escapes in a string literal to the equivalent `\xNN;`
+/// escapes, which lexpr understands.
+fn convert_unicode_escapes(literal: &[char]) -> Result<String, ReadError> {
+    let mut output = String::with_capacity(literal.len());
+    let mut i = 0;
+    while i < literal.len() {
+        match (literal[i], literal.get(i + 1), literal.get(i + 2)) {
+            ('\\', Some('u'), Some('{')) => {
+                let close = match literal[i..].iter().position(|c| *c == '}') {
+                    Some(offset) => i + offset,
+                    None => return Err(ReadError::from("Unicode escape is not terminated.")),
+                };
+                let digits: String = literal[(i + 3)..close].iter().collect();
+                if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Err(ReadError(format!(
+                        "Invalid unicode escape: \\u{{{}}}.",
+                        digits
+                    )));
+                }
+                output.push_str(&format!("\\x{};", digits));
+                i = close + 1;
+            }
+            ('\\', Some(c), _) => {
+                output.push('\\');
+                output.push(*c);
+                i += 2;
+            }
+            (c, _, _) => {
+                output.push(c);
+                i += 1;
+            }
+        }
+    }
+    Ok(output)
+}
+
+fn blank_out(chars: &[char], output: &mut String) {
+    output.extend(chars.iter().map(|c| if *c == '\n' { '\n' } else { ' ' }));
+}
+
+/// Skips over the smallest piece of source starting at `start` which can't
+/// contain a comment: a string literal, a line comment, a character literal,
+/// or else a single character. Returns the index just after it.
+fn skip_atom(chars: &[char], start: usize) -> Result<usize, ReadError> {
+    match (chars[start], chars.get(start + 1)) {
+        ('"', _) => {
+            let mut i = start + 1;
+            while i < chars.len() {
+                match chars[i] {
+                    '\\' => i += 2,
+                    '"' => return Ok(i + 1),
+                    _ => i += 1,
+                }
+            }
+            Err(ReadError::from("String literal is not terminated."))
+        }
+        (';', _) => {
+            let mut i = start;
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            Ok(i)
+        }
+        ('#', Some('\\')) => Ok((start + 3).min(chars.len())),
+        _ => Ok(start + 1),
+    }
+}
+
+/// Returns the index just after the block comment starting at `start`.
+/// String literals in the comment are skipped, so they may contain `|#`.
+fn skip_block_comment(chars: &[char], start: usize) -> Result<usize, ReadError> {
+    let mut depth = 0;
+    let mut i = start;
+    while i < chars.len() {
+        match (chars[i], chars.get(i + 1)) {
+            ('#', Some('|')) => {
+                depth += 1;
+                i += 2;
+            }
+            ('|', Some('#')) => {
+                depth -= 1;
+                i += 2;
+                if depth == 0 {
+                    return Ok(i);
+                }
+            }
+            ('"', _) => i = skip_atom(chars, i)?,
+            _ => i += 1,
+        }
+    }
+    Err(ReadError::from("Block comment is not terminated."))
+}
+
+/// Returns the index just after the s-expression which follows `start`
+/// (skipping any whitespace and comments before it).
+fn skip_datum(chars: &[char], start: usize) -> Result<usize, ReadError> {
+    let mut i = start;
+    // skip to the start of the datum
+    loop {
+        match (chars.get(i), chars.get(i + 1)) {
+            (None, _) | (Some(')'), _) | (Some(']'), _) => {
+                return Err(ReadError::from("Datum comment is not followed by a datum."))
+            }
+            (Some(c), _) if c.is_whitespace() => i += 1,
+            (Some(';'), _) => i = skip_atom(chars, i)?,
+            (Some('#'), Some('|')) => i = skip_block_comment(chars, i)?,
+            // the datum after a datum comment is ignored as well
+            (Some('#'), Some(';')) => i = skip_datum(chars, i + 2)?,
+            _ => break,
+        }
+    }
+    match chars[i] {
+        '\'' | '`' => skip_datum(chars, i + 1),
+        ',' if chars.get(i + 1) == Some(&'@') => skip_datum(chars, i + 2),
+        ',' => skip_datum(chars, i + 1),
+        '(' | '[' => {
+            let mut depth = 0;
+            while i < chars.len() {
+                match (chars[i], chars.get(i + 1)) {
+                    ('(', _) | ('[', _) => {
+                        depth += 1;
+                        i += 1;
+                    }
+                    (')', _) | (']', _) => {
+                        depth -= 1;
+                        i += 1;
+                        if depth == 0 {
+                            return Ok(i);
+                        }
+                    }
+                    ('#', Some('|')) => i = skip_block_comment(chars, i)?,
+                    ('#', Some(';')) => i = skip_datum(chars, i + 2)?,
+                    _ => i = skip_atom(chars, i)?,
+                }
+            }
+            Err(ReadError::from("List in datum comment is not terminated."))
+        }
+        '"' => skip_atom(chars, i),
+        _ => {
+            while i < chars.len() && !is_delimiter(chars[i]) {
+                i = skip_atom(chars, i)?;
+            }
+            Ok(i)
+        }
+    }
+}
+
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || matches!(c, '(' | ')' | '[' | ']' | '"' | ';')
+}
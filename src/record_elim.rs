@@ -7,7 +7,7 @@ use crate::ast_transform::{
     transform_type_recursive, transform_typed_exp_recursive, transform_typed_prog_recursive,
 };
 use crate::common::{ExprKind, Prog, TypedExpr};
-use crate::types::Type;
+use crate::types::{type_contains_var, Type};
 use im_rc::Vector;
 
 #[derive(Clone, Debug)]
@@ -53,7 +53,9 @@ fn re_type(typ: &Type) -> Result<Type, RecordElimError> {
 
 fn re_type_helper(typ: &Type) -> Option<Result<Type, RecordElimError>> {
     match typ {
-        Type::Record(bindings) => {
+        // Open records are converted to tuples of just their known fields;
+        // arguments are projected down to these fields at call sites.
+        Type::Record(bindings) | Type::OpenRecord(bindings, _) => {
             let re_bindings = bindings
                 .iter()
                 .map(|(name, inner_type)| Ok((name.clone(), re_type(inner_type)?)))
@@ -80,6 +82,21 @@ fn re_type_helper(typ: &Type) -> Option<Result<Type, RecordElimError>> {
 
 fn re_helper(exp: &TypedExpr) -> Option<Result<TypedExpr, RecordElimError>> {
     match &*exp.kind {
+        ExprKind::Lambda(params, ret_type, _) => {
+            // Only the known fields of an open record parameter are passed in,
+            // so the rest of the record cannot be returned.
+            let escapes = params.iter().any(|(_, typ)| match typ {
+                Type::OpenRecord(_, row_var) => type_contains_var(ret_type, *row_var),
+                _ => false,
+            });
+            if escapes {
+                Some(Err(RecordElimError::from(
+                    "Row variable of an open record parameter appears in the function's return type.",
+                )))
+            } else {
+                None
+            }
+        }
         ExprKind::Record(bindings) => {
             let rbindings = bindings
                 .iter()
@@ -141,7 +158,7 @@ fn re_helper(exp: &TypedExpr) -> Option<Result<TypedExpr, RecordElimError>> {
 
 fn get_field_index(record: &TypedExpr, field: &str) -> Result<u32, RecordElimError> {
     let mut fields_vec: Vec<(String, Type)> = match &record.typ {
-        Type::Record(fields) => Ok(fields.iter().cloned().collect()),
+        Type::Record(fields) | Type::OpenRecord(fields, _) => Ok(fields.iter().cloned().collect()),
         _ => Err(RecordElimError::from(
             "Type annotation for record has incorrect type.",
         )),
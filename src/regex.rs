@@ -0,0 +1,428 @@
+/// This module is a small backtracking regular expression engine, which
+/// implements the `string-match` and `string-replace` builtins in the
+/// interpreter (compiled modules import them from the host instead).
+///
+/// Patterns support literal characters, `.` (any character but a newline),
+/// classes like `[a-z_]` and `[^0-9]`, the escapes `\d`, `\w`, `\s` (and
+/// their negations `\D`, `\W`, `\S`), `\n` and `\t`, the anchors `^` and `$`,
+/// capturing groups `(...)`, non-capturing groups `(?:...)`, alternation `|`,
+/// and the greedy quantifiers `*`, `+`, `?`, `{n}`, `{n,}` and `{n,m}`.
+///
+/// Matching backtracks, so it can take exponential time on patterns like
+/// `(a*)*b`.
+#[derive(Clone, Debug)]
+pub struct RegexError(String);
+
+// Allows other errors to wrap this one
+impl std::error::Error for RegexError {}
+
+impl From<&str> for RegexError {
+    fn from(message: &str) -> Self {
+        RegexError(String::from(message))
+    }
+}
+
+impl std::fmt::Display for RegexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "RegexError: {}", self.0)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Char(char),
+    Any,
+    Class(Vec<(char, char)>, bool), // ranges of characters, negated
+    Start,
+    End,
+    Group(Box<Node>, Option<usize>), // pattern, index if capturing
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat(Box<Node>, usize, Option<usize>), // pattern, min, max
+}
+
+/// The start and end (in characters) of each group in a match, where group 0
+/// is the whole match. Groups which didn't participate in the match are None.
+type Captures = Vec<Option<(usize, usize)>>;
+
+/// A compiled regular expression.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Regex {
+    node: Node,
+    groups: usize,
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, RegexError> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+            groups: 1,
+        };
+        let node = parser.parse_alt()?;
+        if parser.pos < parser.chars.len() {
+            return Err(RegexError::from("Unmatched ) in pattern."));
+        }
+        Ok(Regex {
+            node,
+            groups: parser.groups,
+        })
+    }
+
+    /// Returns the text of the first match in `text` followed by the text of
+    /// each of the pattern's groups, or None if the pattern doesn't match.
+    /// Groups which didn't participate in the match are empty strings.
+    pub fn captures(&self, text: &str) -> Option<Vec<String>> {
+        let chars: Vec<char> = text.chars().collect();
+        let caps = self.find_at(&chars, 0)?;
+        Some(
+            caps.iter()
+                .map(|span| match span {
+                    Some((start, end)) => chars[*start..*end].iter().collect(),
+                    None => String::new(),
+                })
+                .collect(),
+        )
+    }
+
+    /// Replaces every (non-overlapping) match in `text` with `replacement`,
+    /// in which `\0` to `\9` stand for the text of the match and its groups,
+    /// and `\\` for a backslash.
+    pub fn replace_all(&self, text: &str, replacement: &str) -> String {
+        let chars: Vec<char> = text.chars().collect();
+        let mut result = String::new();
+        let mut pos = 0;
+        while pos <= chars.len() {
+            let caps = match self.find_at(&chars, pos) {
+                Some(caps) => caps,
+                None => break,
+            };
+            let (start, end) = caps[0].unwrap();
+            result.extend(&chars[pos..start]);
+            expand_replacement(replacement, &chars, &caps, &mut result);
+            if end == start {
+                // step past empty matches so that they aren't found again
+                result.extend(chars.get(end));
+                pos = end + 1;
+            } else {
+                pos = end;
+            }
+        }
+        if pos < chars.len() {
+            result.extend(&chars[pos..]);
+        }
+        result
+    }
+
+    /// Finds the leftmost match which starts at or after `start`.
+    fn find_at(&self, text: &[char], start: usize) -> Option<Captures> {
+        for pos in start..=text.len() {
+            let mut caps = vec![None; self.groups];
+            let mut end = None;
+            let matched = match_node(&self.node, text, pos, &mut caps, &mut |next, _caps| {
+                end = Some(next);
+                true
+            });
+            if matched {
+                caps[0] = Some((pos, end.unwrap()));
+                return Some(caps);
+            }
+        }
+        None
+    }
+}
+
+fn expand_replacement(replacement: &str, text: &[char], caps: &Captures, result: &mut String) {
+    let mut chars = replacement.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some('\\')) => {
+                chars.next();
+                result.push('\\');
+            }
+            ('\\', Some(digit)) if digit.is_ascii_digit() => {
+                let index = digit.to_digit(10).unwrap() as usize;
+                chars.next();
+                if let Some(Some((start, end))) = caps.get(index) {
+                    result.extend(&text[*start..*end]);
+                }
+            }
+            _ => result.push(c),
+        }
+    }
+}
+
+/// Matches `node` at `pos`, then calls `k` with the position after the match.
+/// Backtracks into other ways of matching `node` until `k` returns true.
+fn match_node(
+    node: &Node,
+    text: &[char],
+    pos: usize,
+    caps: &mut Captures,
+    k: &mut dyn FnMut(usize, &mut Captures) -> bool,
+) -> bool {
+    match node {
+        Node::Char(c) => text.get(pos) == Some(c) && k(pos + 1, caps),
+        Node::Any => pos < text.len() && text[pos] != '\n' && k(pos + 1, caps),
+        Node::Class(ranges, negated) => match text.get(pos) {
+            Some(c) => {
+                ranges.iter().any(|(lo, hi)| lo <= c && c <= hi) != *negated && k(pos + 1, caps)
+            }
+            None => false,
+        },
+        Node::Start => pos == 0 && k(pos, caps),
+        Node::End => pos == text.len() && k(pos, caps),
+        Node::Concat(nodes) => match_concat(nodes, text, pos, caps, k),
+        Node::Alt(alts) => {
+            for alt in alts {
+                if match_node(alt, text, pos, caps, k) {
+                    return true;
+                }
+            }
+            false
+        }
+        Node::Group(inner, None) => match_node(inner, text, pos, caps, k),
+        Node::Group(inner, Some(index)) => {
+            let index = *index;
+            match_node(inner, text, pos, caps, &mut |next, caps| {
+                let saved = caps[index];
+                caps[index] = Some((pos, next));
+                if k(next, caps) {
+                    return true;
+                }
+                caps[index] = saved;
+                false
+            })
+        }
+        Node::Repeat(inner, min, max) => match_repeat(inner, *min, *max, 0, text, pos, caps, k),
+    }
+}
+
+fn match_concat(
+    nodes: &[Node],
+    text: &[char],
+    pos: usize,
+    caps: &mut Captures,
+    k: &mut dyn FnMut(usize, &mut Captures) -> bool,
+) -> bool {
+    match nodes.split_first() {
+        Some((first, rest)) => match_node(first, text, pos, caps, &mut |next, caps| {
+            match_concat(rest, text, next, caps, k)
+        }),
+        None => k(pos, caps),
+    }
+}
+
+/// Matches as many repetitions of `node` as possible (after `count` have
+/// already been matched), backtracking to fewer.
+#[allow(clippy::too_many_arguments)]
+fn match_repeat(
+    node: &Node,
+    min: usize,
+    max: Option<usize>,
+    count: usize,
+    text: &[char],
+    pos: usize,
+    caps: &mut Captures,
+    k: &mut dyn FnMut(usize, &mut Captures) -> bool,
+) -> bool {
+    if max.is_none_or(|max| count < max) {
+        // an empty repetition can't lead anywhere new once min is reached,
+        // and would repeat forever
+        let matched = match_node(node, text, pos, caps, &mut |next, caps| {
+            (next != pos || count < min)
+                && match_repeat(node, min, max, count + 1, text, next, caps, k)
+        });
+        if matched {
+            return true;
+        }
+    }
+    count >= min && k(pos, caps)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    groups: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn parse_alt(&mut self) -> Result<Node, RegexError> {
+        let mut alts = vec![self.parse_concat()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            alts.push(self.parse_concat()?);
+        }
+        Ok(if alts.len() == 1 {
+            alts.pop().unwrap()
+        } else {
+            Node::Alt(alts)
+        })
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, RegexError> {
+        let mut nodes = vec![];
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.parse_atom()?;
+            nodes.push(self.parse_quantifiers(atom)?);
+        }
+        Ok(Node::Concat(nodes))
+    }
+
+    fn parse_quantifiers(&mut self, mut node: Node) -> Result<Node, RegexError> {
+        loop {
+            let (min, max) = match self.peek() {
+                Some('*') => (0, None),
+                Some('+') => (1, None),
+                Some('?') => (0, Some(1)),
+                Some('{') => {
+                    self.pos += 1;
+                    self.parse_counts()?
+                }
+                _ => return Ok(node),
+            };
+            if let Some('*') | Some('+') | Some('?') = self.peek() {
+                self.pos += 1;
+            }
+            if let Node::Start | Node::End = node {
+                return Err(RegexError::from("Anchors cannot be repeated."));
+            }
+            node = Node::Repeat(Box::new(node), min, max);
+        }
+    }
+
+    /// Parses the rest of `{n}`, `{n,}` or `{n,m}`, after the `{`.
+    fn parse_counts(&mut self) -> Result<(usize, Option<usize>), RegexError> {
+        let end = match self.chars[self.pos..].iter().position(|c| *c == '}') {
+            Some(offset) => self.pos + offset,
+            None => return Err(RegexError::from("Unclosed { in pattern.")),
+        };
+        let counts: String = self.chars[self.pos..end].iter().collect();
+        self.pos = end + 1;
+        let invalid = || RegexError(format!("Invalid repetition count {{{}}}.", counts));
+        let parse_count = |count: &str| count.parse::<usize>().map_err(|_| invalid());
+        let (min, max) = match counts.find(',') {
+            None => {
+                let count = parse_count(&counts)?;
+                (count, Some(count))
+            }
+            Some(index) if index == counts.len() - 1 => (parse_count(&counts[..index])?, None),
+            Some(index) => (
+                parse_count(&counts[..index])?,
+                Some(parse_count(&counts[index + 1..])?),
+            ),
+        };
+        match max {
+            Some(max) if max < min => Err(invalid()),
+            _ => Ok((min, max)),
+        }
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, RegexError> {
+        match self.next().unwrap() {
+            '(' => {
+                let index = if self.chars[self.pos..].starts_with(&['?', ':']) {
+                    self.pos += 2;
+                    None
+                } else {
+                    self.groups += 1;
+                    Some(self.groups - 1)
+                };
+                let inner = self.parse_alt()?;
+                if self.next() != Some(')') {
+                    return Err(RegexError::from("Unclosed ( in pattern."));
+                }
+                Ok(Node::Group(Box::new(inner), index))
+            }
+            '[' => self.parse_class(),
+            '.' => Ok(Node::Any),
+            '^' => Ok(Node::Start),
+            '$' => Ok(Node::End),
+            '\\' => self.parse_escape(),
+            c @ '*' | c @ '+' | c @ '?' | c @ '{' => Err(RegexError(format!(
+                "Nothing to repeat before {} in pattern.",
+                c
+            ))),
+            c => Ok(Node::Char(c)),
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<Node, RegexError> {
+        let c = self
+            .next()
+            .ok_or_else(|| RegexError::from("Pattern ends with a backslash."))?;
+        Ok(match c {
+            'd' | 'w' | 's' => Node::Class(class_ranges(c), false),
+            'D' | 'W' | 'S' => Node::Class(class_ranges(c.to_ascii_lowercase()), true),
+            'n' => Node::Char('\n'),
+            't' => Node::Char('\t'),
+            c => Node::Char(c),
+        })
+    }
+
+    /// Parses the rest of a class, after the `[`. A `]` at the start of the
+    /// class is a literal character.
+    fn parse_class(&mut self) -> Result<Node, RegexError> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut ranges = vec![];
+        let mut first = true;
+        loop {
+            let c = match self.next() {
+                Some(']') if !first => return Ok(Node::Class(ranges, negated)),
+                Some(c) => c,
+                None => return Err(RegexError::from("Unclosed [ in pattern.")),
+            };
+            first = false;
+            let lo = match c {
+                '\\' => match self.next() {
+                    Some(c @ 'd') | Some(c @ 'w') | Some(c @ 's') => {
+                        ranges.extend(class_ranges(c));
+                        continue;
+                    }
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some(c) => c,
+                    None => return Err(RegexError::from("Unclosed [ in pattern.")),
+                },
+                c => c,
+            };
+            let is_range =
+                self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|c| *c != ']');
+            if is_range {
+                let hi = self.chars[self.pos + 1];
+                self.pos += 2;
+                if hi < lo {
+                    return Err(RegexError(format!("Invalid class range {}-{}.", lo, hi)));
+                }
+                ranges.push((lo, hi));
+            } else {
+                ranges.push((lo, lo));
+            }
+        }
+    }
+}
+
+/// The characters matched by `\d`, `\w` or `\s`.
+fn class_ranges(c: char) -> Vec<(char, char)> {
+    match c {
+        'd' => vec![('0', '9')],
+        'w' => vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')],
+        _ => vec![(' ', ' '), ('\t', '\r')],
+    }
+}
@@ -0,0 +1,225 @@
+/// This module provides an interactive read-eval-print loop, which evaluates
+/// each line of input with the interpreter and prints its value.
+///
+/// Lines starting with a colon are commands rather than expressions:
+///
+/// - `:type <exp>` prints the type of the expression, without evaluating it
+/// - `:expand <exp>` prints the expression after macro expansion and
+///   desugaring, i.e. what the parser sees
+/// - `:load <file>` adds the definitions in a file to the session
+/// - `:save <file>` writes the session's definitions to a file, so that it
+///   can be loaded again, or imported by a project (see `project`)
+///
+/// (`:load` and `:save` need the "fs" feature.)
+///
+/// Definitions (`define-syntax`, `define-macro`, `define-constant` and
+/// `declare-extern`) can also be entered directly. They are kept for the rest
+/// of the session, and each expression is evaluated within a begin
+/// expression following them. Defining a name again replaces its previous
+/// definition.
+///
+/// The loop doesn't depend on a terminal, so any input and output can be
+/// used (see `run_repl`).
+use crate::desugar::desugar;
+use crate::interpret::{eval_program, EvalOptions};
+use crate::macro_expand::macro_expand_with_limits;
+use crate::parse::parse_with_limits;
+use crate::pretty::{pretty_print, DEFAULT_WIDTH};
+use crate::read::read;
+use crate::type_check::type_check;
+use lexpr::Value;
+use std::error::Error;
+use std::io::{BufRead, Write};
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+#[derive(Clone, Debug)]
+pub struct ReplError(String);
+
+// Allows other errors to wrap this one
+impl std::error::Error for ReplError {}
+
+impl From<&str> for ReplError {
+    fn from(message: &str) -> Self {
+        ReplError(String::from(message))
+    }
+}
+
+impl std::fmt::Display for ReplError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "ReplError: {}", self.0)
+    }
+}
+
+/// The prompt printed before reading each line.
+pub const PROMPT: &str = "> ";
+
+/// The state of an interactive session.
+#[derive(Clone, Debug, Default)]
+pub struct Repl {
+    options: EvalOptions,
+    definitions: Vec<Value>,
+}
+
+impl Repl {
+    pub fn new(options: EvalOptions) -> Self {
+        Repl {
+            options,
+            definitions: vec![],
+        }
+    }
+
+    /// The definitions made so far, in order.
+    pub fn definitions(&self) -> &[Value] {
+        &self.definitions
+    }
+
+    /// Handles one line of input, returning the output to print. Blank lines
+    /// have no output.
+    pub fn handle(&mut self, input: &str) -> Result<String, Box<dyn Error>> {
+        let input = input.trim();
+        if input.is_empty() {
+            return Ok(String::new());
+        }
+        if !input.starts_with(':') {
+            let value = read(input)?;
+            if definition_name(&value).is_some() {
+                self.define(vec![value])?;
+                return Ok(String::new());
+            }
+            return eval_program(&self.program(value), &self.options);
+        }
+        let (command, arg) = match input.find(char::is_whitespace) {
+            Some(index) => (&input[..index], input[index..].trim()),
+            None => (input, ""),
+        };
+        match command {
+            ":type" => {
+                let program = self.program(read(arg)?);
+                let limits = &self.options.limits;
+                let typed_exp = type_check(&parse_with_limits(
+                    &desugar(&macro_expand_with_limits(&program, limits)?)?,
+                    limits,
+                )?)?;
+                Ok(typed_exp.typ.to_string())
+            }
+            ":expand" => {
+                let program = self.program(read(arg)?);
+                let mut output = String::new();
+                pretty_print(
+                    &desugar(&macro_expand_with_limits(&program, &self.options.limits)?)?,
+                    DEFAULT_WIDTH,
+                    &mut output,
+                );
+                Ok(output)
+            }
+            #[cfg(feature = "fs")]
+            ":load" => {
+                let source = std::fs::read_to_string(file_path(arg)?)
+                    .map_err(|err| ReplError(format!("Could not read {}: {}", arg, err)))?;
+                // the forms are read as a begin expression, on the first
+                // line so that line numbers in errors are unchanged
+                let forms = read(&format!("(begin {}\n)", source))?
+                    .to_vec()
+                    .unwrap()
+                    .split_off(1);
+                let count = forms.len();
+                self.define(forms)?;
+                Ok(format!("Loaded {} definitions from {}.", count, arg))
+            }
+            #[cfg(feature = "fs")]
+            ":save" => {
+                let mut output = String::new();
+                for definition in &self.definitions {
+                    pretty_print(definition, DEFAULT_WIDTH, &mut output);
+                    output.push('\n');
+                }
+                std::fs::write(file_path(arg)?, output)
+                    .map_err(|err| ReplError(format!("Could not write {}: {}", arg, err)))?;
+                Ok(format!(
+                    "Saved {} definitions to {}.",
+                    self.definitions.len(),
+                    arg
+                ))
+            }
+            _ => Err(Box::new(ReplError(format!(
+                "Unknown command {}; the commands are :type, :expand, :load and :save.",
+                command
+            )))),
+        }
+    }
+
+    /// Adds definitions to the session, if they are all valid, replacing any
+    /// earlier definitions of the same names.
+    fn define(&mut self, forms: Vec<Value>) -> Result<(), Box<dyn Error>> {
+        let mut definitions = self.definitions.clone();
+        for form in forms {
+            let name = definition_name(&form)
+                .ok_or_else(|| ReplError(format!("{} is not a definition.", form)))?;
+            definitions.retain(|definition| definition_name(definition) != Some(name));
+            definitions.push(form);
+        }
+        // expanding the definitions checks them, e.g. for malformed rules
+        let mut program = vec![Value::symbol("begin")];
+        program.extend(definitions.iter().cloned());
+        program.push(Value::from(0));
+        macro_expand_with_limits(&Value::list(program), &self.options.limits)?;
+        self.definitions = definitions;
+        Ok(())
+    }
+
+    /// Wraps an expression in a begin expression following the session's
+    /// definitions, if there are any.
+    fn program(&self, exp: Value) -> Value {
+        if self.definitions.is_empty() {
+            return exp;
+        }
+        let mut program = vec![Value::symbol("begin")];
+        program.extend(self.definitions.iter().cloned());
+        program.push(exp);
+        Value::list(program)
+    }
+}
+
+/// Returns the name defined by a form, if it's a definition.
+fn definition_name(form: &Value) -> Option<&str> {
+    let lst = form.as_cons()?;
+    let mut items = lst.iter().map(|pair| pair.car());
+    let head = items.next()?.as_symbol()?;
+    let name = items.next()?;
+    match head {
+        "define-syntax" | "define-constant" | "declare-extern" => name.as_symbol(),
+        // (define-macro (name param ...) body)
+        "define-macro" => name.as_cons()?.car().as_symbol(),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "fs")]
+fn file_path(arg: &str) -> Result<&Path, ReplError> {
+    if arg.is_empty() {
+        return Err(ReplError::from("Expected a file name."));
+    }
+    Ok(Path::new(arg))
+}
+
+/// Runs a session, reading lines from `input` until it ends, and writing
+/// prompts and results (or errors) to `output`.
+pub fn run_repl(
+    repl: &mut Repl,
+    input: impl BufRead,
+    mut output: impl Write,
+) -> std::io::Result<()> {
+    write!(output, "{}", PROMPT)?;
+    output.flush()?;
+    for line in input.lines() {
+        match repl.handle(&line?) {
+            Ok(result) if result.is_empty() => (),
+            Ok(result) => writeln!(output, "{}", result)?,
+            Err(err) => writeln!(output, "{}", err)?,
+        }
+        write!(output, "{}", PROMPT)?;
+        output.flush()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,240 @@
+/// This module resolves each identifier to the binder it refers to, renaming
+/// every variable so that no two binders in a program share a name. After
+/// this pass, later passes (and the locals created in code generation) never
+/// have to deal with shadowing.
+use crate::common::{generate_id, Expr, ExprKind};
+use crate::types::Type;
+use im_rc::{HashMap, Vector};
+
+#[derive(Clone, Debug)]
+pub struct ResolveError(String);
+
+// Allows other errors to wrap this one
+impl std::error::Error for ResolveError {}
+
+impl From<&str> for ResolveError {
+    fn from(message: &str) -> Self {
+        ResolveError(String::from(message))
+    }
+}
+
+impl std::fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "ResolveError: {}", self.0)
+    }
+}
+
+/// Maps each variable in scope to its unique name.
+type ScopeMap = HashMap<String, String>;
+
+/// Renames every binder in an expression (let bindings, lambda parameters,
+/// and the variables bound by unpack and typecase) to a unique name of the
+/// form `name.N`, and rewrites each use of a variable to refer to it.
+///
+/// Since the suffix N is unique to each binder, renamed variables can't
+/// collide with each other, even if the original names already contained a
+/// dot. Variables which aren't bound anywhere are an error.
+pub fn resolve(exp: &Expr) -> Result<Expr, ResolveError> {
+    resolve_with_scope(exp, &ScopeMap::new())
+}
+
+/// Returns the name a variable had before it was renamed by `resolve`, e.g.
+/// for showing it to users. Other names are returned unchanged.
+pub fn original_name(name: &str) -> &str {
+    match name.rfind('.') {
+        Some(index)
+            if index + 1 < name.len()
+                && name[(index + 1)..].chars().all(|c| c.is_ascii_digit()) =>
+        {
+            &name[..index]
+        }
+        _ => name,
+    }
+}
+
+fn unique_name(name: &str) -> String {
+    format!("{}.{}", name, generate_id())
+}
+
+fn lookup(name: &str, scope: &ScopeMap) -> Result<String, ResolveError> {
+    match scope.get(name) {
+        Some(unique) => Ok(unique.clone()),
+        None => Err(ResolveError(format!("Identifier {} is not bound.", name))),
+    }
+}
+
+fn resolve_array(exps: &Vector<Expr>, scope: &ScopeMap) -> Result<Vector<Expr>, ResolveError> {
+    exps.iter()
+        .map(|exp| resolve_with_scope(exp, scope))
+        .collect()
+}
+
+fn resolve_let(
+    bindings: &Vector<(String, Expr)>,
+    body: &Expr,
+    scope: &ScopeMap,
+) -> Result<Expr, ResolveError> {
+    // bindings are evaluated in the outer scope, like in type checking
+    let mut body_scope = scope.clone();
+    let rbindings = bindings
+        .iter()
+        .map(|(name, val)| {
+            let rval = resolve_with_scope(val, scope)?;
+            let unique = unique_name(name);
+            body_scope.insert(name.clone(), unique.clone());
+            Ok((unique, rval))
+        })
+        .collect::<Result<Vector<(String, Expr)>, ResolveError>>()?;
+    let rbody = resolve_with_scope(body, &body_scope)?;
+    Ok(Expr::new(ExprKind::Let(rbindings, rbody)))
+}
+
+fn resolve_lambda(
+    params: &Vector<(String, Type)>,
+    ret_type: &Type,
+    body: &Expr,
+    scope: &ScopeMap,
+) -> Result<Expr, ResolveError> {
+    let mut body_scope = scope.clone();
+    let rparams = params
+        .iter()
+        .map(|(name, typ)| {
+            let unique = unique_name(name);
+            body_scope.insert(name.clone(), unique.clone());
+            (unique, typ.clone())
+        })
+        .collect();
+    let rbody = resolve_with_scope(body, &body_scope)?;
+    Ok(Expr::new(ExprKind::Lambda(
+        rparams,
+        ret_type.clone(),
+        rbody,
+    )))
+}
+
+fn resolve_with_scope(exp: &Expr, scope: &ScopeMap) -> Result<Expr, ResolveError> {
+    let kind = match &*exp.kind {
+        ExprKind::Num(_)
+        | ExprKind::Float(_)
+        | ExprKind::Bool(_)
+        | ExprKind::Str(_)
+        | ExprKind::Null(_) => return Ok(exp.clone()),
+        ExprKind::Id(name) => ExprKind::Id(lookup(name, scope)?),
+        ExprKind::Binop(op, arg1, arg2) => ExprKind::Binop(
+            *op,
+            resolve_with_scope(arg1, scope)?,
+            resolve_with_scope(arg2, scope)?,
+        ),
+        ExprKind::Unop(op, arg) => ExprKind::Unop(*op, resolve_with_scope(arg, scope)?),
+        ExprKind::If(pred, cons, alt) => ExprKind::If(
+            resolve_with_scope(pred, scope)?,
+            resolve_with_scope(cons, scope)?,
+            resolve_with_scope(alt, scope)?,
+        ),
+        ExprKind::Let(bindings, body) => return resolve_let(bindings, body, scope),
+        ExprKind::Lambda(params, ret_type, body) => {
+            return resolve_lambda(params, ret_type, body, scope)
+        }
+        ExprKind::Begin(exps) => ExprKind::Begin(resolve_array(exps, scope)?),
+        ExprKind::Set(name, val) => {
+            ExprKind::Set(lookup(name, scope)?, resolve_with_scope(val, scope)?)
+        }
+        ExprKind::Cons(first, rest) => ExprKind::Cons(
+            resolve_with_scope(first, scope)?,
+            resolve_with_scope(rest, scope)?,
+        ),
+        ExprKind::Car(val) => ExprKind::Car(resolve_with_scope(val, scope)?),
+        ExprKind::Cdr(val) => ExprKind::Cdr(resolve_with_scope(val, scope)?),
+        ExprKind::IsNull(val) => ExprKind::IsNull(resolve_with_scope(val, scope)?),
+        ExprKind::FnApp(func, args) => ExprKind::FnApp(
+            resolve_with_scope(func, scope)?,
+            resolve_array(args, scope)?,
+        ),
+        ExprKind::Tuple(exps) => ExprKind::Tuple(resolve_array(exps, scope)?),
+        ExprKind::TupleGet(tuple, key) => {
+            ExprKind::TupleGet(resolve_with_scope(tuple, scope)?, *key)
+        }
+        ExprKind::TupleSet(tuple, key, val) => ExprKind::TupleSet(
+            resolve_with_scope(tuple, scope)?,
+            *key,
+            resolve_with_scope(val, scope)?,
+        ),
+        ExprKind::Pack(val, sub, exist) => {
+            ExprKind::Pack(resolve_with_scope(val, scope)?, sub.clone(), exist.clone())
+        }
+        ExprKind::Unpack(var, package, type_var, body) => {
+            let unique = unique_name(var);
+            let rpackage = resolve_with_scope(package, scope)?;
+            let rbody = resolve_with_scope(body, &scope.update(var.clone(), unique.clone()))?;
+            ExprKind::Unpack(unique, rpackage, *type_var, rbody)
+        }
+        ExprKind::Record(bindings) => ExprKind::Record(
+            bindings
+                .iter()
+                .map(|(label, val)| Ok((label.clone(), resolve_with_scope(val, scope)?)))
+                .collect::<Result<Vector<(String, Expr)>, ResolveError>>()?,
+        ),
+        ExprKind::RecordGet(record, label) => {
+            ExprKind::RecordGet(resolve_with_scope(record, scope)?, label.clone())
+        }
+        ExprKind::Inject(val, union_type) => {
+            ExprKind::Inject(resolve_with_scope(val, scope)?, union_type.clone())
+        }
+        ExprKind::TypeCase(val, clauses) => ExprKind::TypeCase(
+            resolve_with_scope(val, scope)?,
+            clauses
+                .iter()
+                .map(|(typ, var, body)| {
+                    let unique = unique_name(var);
+                    let clause_scope = scope.update(var.clone(), unique.clone());
+                    Ok((
+                        typ.clone(),
+                        unique,
+                        resolve_with_scope(body, &clause_scope)?,
+                    ))
+                })
+                .collect::<Result<Vector<_>, ResolveError>>()?,
+        ),
+        ExprKind::Assert(val, message) => {
+            ExprKind::Assert(resolve_with_scope(val, scope)?, message.clone())
+        }
+        ExprKind::While(pred, body) => ExprKind::While(
+            resolve_with_scope(pred, scope)?,
+            resolve_with_scope(body, scope)?,
+        ),
+        ExprKind::Case(key, clauses, else_body) => ExprKind::Case(
+            resolve_with_scope(key, scope)?,
+            clauses
+                .iter()
+                .map(|(datums, body)| Ok((datums.clone(), resolve_with_scope(body, scope)?)))
+                .collect::<Result<Vector<(Vector<i32>, Expr)>, ResolveError>>()?,
+            resolve_with_scope(else_body, scope)?,
+        ),
+        ExprKind::Curry(func, args) => ExprKind::Curry(
+            resolve_with_scope(func, scope)?,
+            resolve_array(args, scope)?,
+        ),
+        ExprKind::Compose(outer, inner) => ExprKind::Compose(
+            resolve_with_scope(outer, scope)?,
+            resolve_with_scope(inner, scope)?,
+        ),
+        ExprKind::Delay(val) => ExprKind::Delay(resolve_with_scope(val, scope)?),
+        ExprKind::Force(promise) => ExprKind::Force(resolve_with_scope(promise, scope)?),
+        ExprKind::Format(control, args) => {
+            ExprKind::Format(control.clone(), resolve_array(args, scope)?)
+        }
+        ExprKind::Builtin(builtin, args) => {
+            ExprKind::Builtin(*builtin, resolve_array(args, scope)?)
+        }
+        ExprKind::Extern(module, name, typ, args) => ExprKind::Extern(
+            module.clone(),
+            name.clone(),
+            typ.clone(),
+            resolve_array(args, scope)?,
+        ),
+        ExprKind::WasmAsm(code, typ, args) => {
+            ExprKind::WasmAsm(code.clone(), typ.clone(), resolve_array(args, scope)?)
+        }
+    };
+    Ok(Expr::new(kind))
+}
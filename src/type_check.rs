@@ -1,22 +1,182 @@
-use crate::common::{BinOp, Expr, ExprKind, Prog, TypeEnv, TypedExpr};
-use crate::types::{type_contains_var, type_var_substitute, Type};
+use crate::common::{
+    generate_var_name, BinOp, Builtin, Expr, ExprKind, Prog, TypeEnv, TypedExpr, UnOp,
+};
+use crate::effects::lambda_effect;
+use crate::types::{forced_type, type_contains_var, type_var_substitute, unit_type, Type};
+use crate::util::format_directives;
 use im_rc::{vector, Vector};
+use std::fmt::Display;
 
+/// An error found while type checking.
+///
+/// When the error is an expression with the wrong type, the error also
+/// records the type which was expected, the type which was found, and the
+/// expression itself (see `TypeMismatch`).
 #[derive(Clone, Debug)]
-pub struct TypeCheckError(String);
+pub struct TypeCheckError {
+    message: String,
+    code: &'static str,
+    mismatch: Option<Box<TypeMismatch>>,
+}
+
+/// An expression which was found to have a different type from the one
+/// required where it is used.
+///
+/// The expression is printed as source code, since expressions don't record
+/// where they were read from.
+///
+/// TODO: include the span of the expression in the source once the reader
+/// keeps track of positions
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypeMismatch {
+    pub expected: Type,
+    pub found: Type,
+    pub exp: String,
+}
+
+impl TypeCheckError {
+    fn new(message: String) -> Self {
+        TypeCheckError {
+            message,
+            code: "T007",
+            mismatch: None,
+        }
+    }
+
+    fn with_code(self, code: &'static str) -> Self {
+        TypeCheckError { code, ..self }
+    }
+
+    /// An error for `exp` having the type `found` where a value of type
+    /// `expected` is required. `context` describes where the expression is
+    /// used, e.g. "Predicate of if expression".
+    fn mismatch(context: &str, expected: &Type, found: &Type, exp: &dyn Display) -> Self {
+        let exp = exp.to_string();
+        TypeCheckError {
+            message: format!(
+                "{} must have type {}, but {} has type {}.",
+                context, expected, exp, found
+            ),
+            code: "T001",
+            mismatch: Some(Box::new(TypeMismatch {
+                expected: expected.clone(),
+                found: found.clone(),
+                exp,
+            })),
+        }
+    }
+
+    /// The code for the kind of error this is (see `explain::explain`).
+    pub fn code(&self) -> &'static str {
+        self.code
+    }
+
+    /// The mismatched types which caused the error, if that is what it is.
+    pub fn mismatch_info(&self) -> Option<&TypeMismatch> {
+        self.mismatch.as_deref()
+    }
+}
 
 // Allows other errors to wrap this one
 impl std::error::Error for TypeCheckError {}
 
 impl From<&str> for TypeCheckError {
     fn from(message: &str) -> Self {
-        TypeCheckError(String::from(message))
+        TypeCheckError::new(String::from(message))
     }
 }
 
 impl std::fmt::Display for TypeCheckError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "TypeCheckError: {}", self.0)
+        write!(f, "TypeCheckError: {}", self.message)
+    }
+}
+
+/// The ways in which a type can fail to unify with the type it is expected
+/// to match (see `unify`).
+#[derive(Clone, Debug, PartialEq)]
+pub enum UnifyError {
+    /// The found type cannot be used where the expected type is required.
+    Mismatch { expected: Type, found: Type },
+    /// A record is missing a field which the expected record type requires.
+    MissingField { label: String, found: Type },
+    /// A field of a record does not have the type the expected record type
+    /// requires.
+    FieldMismatch {
+        label: String,
+        expected: Type,
+        found: Type,
+    },
+    /// A function which may mutate was found where a pure function is
+    /// required.
+    ImpureFunction { expected: Type, found: Type },
+    /// Binding a row variable would make it part of its own binding.
+    InfiniteType { var: u64, typ: Type },
+    /// A row variable would be bound to two different types.
+    RowConflict { var: u64, first: Type, second: Type },
+}
+
+impl std::fmt::Display for UnifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            UnifyError::Mismatch { expected, found } => {
+                write!(
+                    f,
+                    "Expected a value of type {}, but found {}.",
+                    expected, found
+                )
+            }
+            UnifyError::MissingField { label, found } => write!(
+                f,
+                "Record argument of type {} is missing the field {}.",
+                found, label
+            ),
+            UnifyError::FieldMismatch {
+                label,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Field {} of record argument does not have the expected type {}, found {}.",
+                label, expected, found
+            ),
+            UnifyError::ImpureFunction { expected, found } => write!(
+                f,
+                "Expected a pure function of type {}, but found a function which may mutate: {}.",
+                expected, found
+            ),
+            UnifyError::InfiniteType { var, typ } => write!(
+                f,
+                "Row variable T{} cannot be bound to {}, which contains it.",
+                var, typ
+            ),
+            UnifyError::RowConflict { var, first, second } => write!(
+                f,
+                "Row variable T{} is bound to both {} and {}.",
+                var, first, second
+            ),
+        }
+    }
+}
+
+impl std::error::Error for UnifyError {}
+
+impl UnifyError {
+    /// The code for the kind of error this is (see `explain::explain`).
+    pub fn code(&self) -> &'static str {
+        match self {
+            UnifyError::Mismatch { .. } | UnifyError::FieldMismatch { .. } => "T001",
+            UnifyError::MissingField { .. } => "T009",
+            UnifyError::ImpureFunction { .. } => "T010",
+            UnifyError::InfiniteType { .. } => "T011",
+            UnifyError::RowConflict { .. } => "T012",
+        }
+    }
+}
+
+impl From<UnifyError> for TypeCheckError {
+    fn from(err: UnifyError) -> Self {
+        TypeCheckError::new(err.to_string()).with_code(err.code())
     }
 }
 
@@ -27,25 +187,295 @@ impl std::fmt::Display for TypeCheckError {
 /// Given a function type and a list of parameter types, check that provided
 /// list of parameter types matches the types for the function, and return the
 /// function's return type.
+///
+/// Parameters with an open record type (see `Type::OpenRecord`) accept any
+/// record containing at least the listed fields. The row variables of these
+/// parameters are bound to the remaining fields of the corresponding
+/// arguments, and these bindings are substituted into the return type.
+///
+/// Likewise, a parameter with a record type accepts any record containing at
+/// least its fields. Tuples must match exactly; see `validate_application`
+/// for a version which optionally allows tuples with extra elements.
 pub fn validate_lambda_type(
     fn_type: &Type,
     param_types: &Vector<Type>,
+) -> Result<Type, TypeCheckError> {
+    validate_application(fn_type, param_types, false)
+}
+
+/// Like `validate_lambda_type`, but if `tuple_width_subtyping` is true, then
+/// a tuple argument may have more elements than its parameter type, as long
+/// as its first elements match.
+pub fn validate_application(
+    fn_type: &Type,
+    param_types: &Vector<Type>,
+    tuple_width_subtyping: bool,
 ) -> Result<Type, TypeCheckError> {
     match fn_type {
-        Type::Func(arg_types, ret_type_boxed) => {
-            let ret_type = ret_type_boxed.as_ref();
-            if *arg_types == *param_types {
-                Ok((*ret_type).clone())
-            } else {
-                Err(TypeCheckError::from(
+        Type::Func(arg_types, ret_type_boxed, _) => {
+            if arg_types.len() != param_types.len() {
+                return Err(TypeCheckError::from(
                     "Argument types and parameter types of function application do not match.",
-                ))
+                )
+                .with_code("T003"));
+            }
+            let mut subst: Vec<(u64, Type)> = vec![];
+            for (i, (expected, found)) in arg_types.iter().zip(param_types.iter()).enumerate() {
+                unify(expected, found, tuple_width_subtyping, &mut subst).map_err(|err| {
+                    TypeCheckError::new(format!(
+                        "In argument {} of function application: {}",
+                        i, err
+                    ))
+                    .with_code(err.code())
+                })?;
             }
+            let ret_type = subst
+                .iter()
+                .fold(ret_type_boxed.as_ref().clone(), |typ, (row_var, rest)| {
+                    type_var_substitute(&typ, *row_var, rest)
+                });
+            Ok(ret_type)
         }
-        _ => Err(TypeCheckError(format!(
+        _ => Err(TypeCheckError::new(format!(
             "Expected a function type, instead found {}",
             fn_type
-        ))),
+        ))
+        .with_code("T005")),
+    }
+}
+
+/// Checks that a value of type `found` can be used where a value of type
+/// `expected` is required, recording any row variables bound along the way in
+/// `subst`.
+///
+/// Only records and tuples at the top level of `expected` are matched
+/// structurally; every other type must be equal to the type it is matched
+/// against. A row variable is never bound to a type which contains it, since
+/// that would describe an infinitely large record.
+#[allow(clippy::result_large_err)]
+pub fn unify(
+    expected: &Type,
+    found: &Type,
+    tuple_width_subtyping: bool,
+    subst: &mut Vec<(u64, Type)>,
+) -> Result<(), UnifyError> {
+    match (expected, found) {
+        (Type::OpenRecord(fields, row_var), Type::Record(found_fields))
+        | (Type::OpenRecord(fields, row_var), Type::OpenRecord(found_fields, _)) => {
+            check_record_fields(fields, found_fields, found)?;
+            let rest_fields: Vector<(String, Type)> = found_fields
+                .iter()
+                .filter(|pair| !fields.iter().any(|field| field.0 == pair.0))
+                .cloned()
+                .collect();
+            let rest = match found {
+                Type::OpenRecord(_, found_row_var) => Type::OpenRecord(rest_fields, *found_row_var),
+                _ => Type::Record(rest_fields),
+            };
+            if type_contains_var(&rest, *row_var) {
+                return Err(UnifyError::InfiniteType {
+                    var: *row_var,
+                    typ: rest,
+                });
+            }
+            match subst.iter().find(|pair| pair.0 == *row_var) {
+                Some((_, bound)) if *bound != rest => Err(UnifyError::RowConflict {
+                    var: *row_var,
+                    first: bound.clone(),
+                    second: rest,
+                }),
+                Some(_) => Ok(()),
+                None => {
+                    subst.push((*row_var, rest));
+                    Ok(())
+                }
+            }
+        }
+        (Type::Record(fields), Type::Record(found_fields))
+        | (Type::Record(fields), Type::OpenRecord(found_fields, _)) => {
+            check_record_fields(fields, found_fields, found)
+        }
+        (Type::Union(types), _) if types.contains(found) => Ok(()),
+        (Type::Func(_, _, effect), Type::Func(_, _, found_effect)) if found_effect > effect => {
+            Err(UnifyError::ImpureFunction {
+                expected: expected.clone(),
+                found: found.clone(),
+            })
+        }
+        (Type::Tuple(types), Type::Tuple(found_types))
+            if tuple_width_subtyping && found_types.len() > types.len() =>
+        {
+            if found_types.clone().slice(..types.len()) == *types {
+                Ok(())
+            } else {
+                Err(UnifyError::Mismatch {
+                    expected: expected.clone(),
+                    found: found.clone(),
+                })
+            }
+        }
+        _ => {
+            if is_subtype(expected, found) {
+                Ok(())
+            } else {
+                Err(UnifyError::Mismatch {
+                    expected: expected.clone(),
+                    found: found.clone(),
+                })
+            }
+        }
+    }
+}
+
+/// Returns whether a value of type `found` can be used where a value of type
+/// `expected` is required. The types must be equal, except that a function
+/// may have a less permissive effect than expected, so a pure function can be
+/// used where one which may mutate is expected (but not the other way
+/// around). This extends to the return types of functions and the element
+/// types of lists and promises, and the other way around to the parameter
+/// types of functions. Tuples can be assigned to with tuple-set!, so their
+/// element types must match exactly, as must those of records and unions.
+pub fn is_subtype(expected: &Type, found: &Type) -> bool {
+    match (expected, found) {
+        (
+            Type::Func(params, ret_type, effect),
+            Type::Func(found_params, found_ret_type, found_effect),
+        ) => {
+            found_effect <= effect
+                && params.len() == found_params.len()
+                && params
+                    .iter()
+                    .zip(found_params.iter())
+                    .all(|(param, found_param)| is_subtype(found_param, param))
+                && is_subtype(ret_type, found_ret_type)
+        }
+        (Type::List(elem), Type::List(found_elem))
+        | (Type::Promise(elem), Type::Promise(found_elem)) => is_subtype(elem, found_elem),
+        _ => expected == found,
+    }
+}
+
+/// The type of a value which may come from an expression of either type `a`
+/// or `b`, e.g. from either arm of an if expression: whichever of the two
+/// types the other can be used as (see `is_subtype`), if either.
+fn join_types(a: &Type, b: &Type) -> Option<Type> {
+    if is_subtype(a, b) {
+        Some(a.clone())
+    } else if is_subtype(b, a) {
+        Some(b.clone())
+    } else {
+        None
+    }
+}
+
+/// Checks that every field in `fields` is present in `found_fields` with the
+/// same type. `found` is the record type which `found_fields` belong to.
+#[allow(clippy::result_large_err)]
+fn check_record_fields(
+    fields: &Vector<(String, Type)>,
+    found_fields: &Vector<(String, Type)>,
+    found: &Type,
+) -> Result<(), UnifyError> {
+    for (label, typ) in fields.iter() {
+        match found_fields.iter().find(|pair| pair.0 == *label) {
+            Some((_, found_typ)) if *found_typ == *typ => (),
+            Some((_, found_typ)) => {
+                return Err(UnifyError::FieldMismatch {
+                    label: label.clone(),
+                    expected: typ.clone(),
+                    found: found_typ.clone(),
+                })
+            }
+            None => {
+                return Err(UnifyError::MissingField {
+                    label: label.clone(),
+                    found: found.clone(),
+                })
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Given a typed argument passed to a parameter of type `param_type`, returns
+/// an expression which projects the argument down to the shape of the
+/// parameter type, if the argument is a record with extra fields or a tuple
+/// with extra elements.
+///
+/// This lets the body of the function find each field or element at the same
+/// tuple index, no matter which record or tuple was passed in.
+fn coerce_arg(param_type: &Type, arg: TypedExpr) -> TypedExpr {
+    if let Type::Union(_) = param_type {
+        return inject_into_union(param_type, arg);
+    }
+    if !needs_projection(param_type, &arg.typ) {
+        return arg;
+    }
+    let temp = generate_var_name();
+    let temp_id = TypedExpr::new(arg.typ.clone(), ExprKind::Id(temp.clone()));
+    let projected = match param_type {
+        Type::Record(fields) | Type::OpenRecord(fields, _) => {
+            let projected_bindings = fields
+                .iter()
+                .map(|(label, typ)| {
+                    (
+                        label.clone(),
+                        TypedExpr::new(
+                            typ.clone(),
+                            ExprKind::RecordGet(temp_id.clone(), label.clone()),
+                        ),
+                    )
+                })
+                .collect::<Vector<(String, TypedExpr)>>();
+            TypedExpr::new(
+                Type::Record(fields.clone()),
+                ExprKind::Record(projected_bindings),
+            )
+        }
+        Type::Tuple(types) => {
+            let projected_exps = types
+                .iter()
+                .enumerate()
+                .map(|(index, typ)| {
+                    TypedExpr::new(
+                        typ.clone(),
+                        ExprKind::TupleGet(temp_id.clone(), index as u32),
+                    )
+                })
+                .collect::<Vector<TypedExpr>>();
+            TypedExpr::new(Type::Tuple(types.clone()), ExprKind::Tuple(projected_exps))
+        }
+        _ => return arg,
+    };
+    TypedExpr::new(
+        projected.typ.clone(),
+        ExprKind::Let(vector![(temp, arg)], projected),
+    )
+}
+
+/// If `typ` is a union type and `exp` has the type of one of its members,
+/// returns `exp` wrapped in an inject expression. Otherwise returns `exp`.
+fn inject_into_union(typ: &Type, exp: TypedExpr) -> TypedExpr {
+    match typ {
+        Type::Union(types) if types.contains(&exp.typ) => {
+            TypedExpr::new(typ.clone(), ExprKind::Inject(exp, typ.clone()))
+        }
+        _ => exp,
+    }
+}
+
+/// Returns whether an argument of type `arg_type` must be projected down to
+/// `param_type`, i.e. if it has extra fields or elements.
+fn needs_projection(param_type: &Type, arg_type: &Type) -> bool {
+    match (param_type, arg_type) {
+        (Type::Record(fields), Type::Record(arg_fields))
+        | (Type::Record(fields), Type::OpenRecord(arg_fields, _))
+        | (Type::OpenRecord(fields, _), Type::Record(arg_fields))
+        | (Type::OpenRecord(fields, _), Type::OpenRecord(arg_fields, _)) => {
+            fields.len() != arg_fields.len()
+        }
+        (Type::Tuple(types), Type::Tuple(arg_types)) => types.len() != arg_types.len(),
+        _ => false,
     }
 }
 
@@ -59,46 +489,101 @@ fn tc_binop_with_env(
     arg2: &Expr,
     env: &TypeEnv,
 ) -> Result<TypedExpr, TypeCheckError> {
-    let arg1_expect_typ: Type;
-    let arg2_expect_typ: Type;
+    let arg1 = tc_with_env(arg1, env)?;
+    let arg2 = tc_with_env(arg2, env)?;
+    // Arithmetic and comparisons work on either ints or floats, decided by
+    // the first argument. Ints are never implicitly converted to floats (or
+    // floats to ints), since the conversion can lose precision, so they
+    // can't be mixed; exact->inexact and inexact->exact convert explicitly.
+    let num_typ = if arg1.typ == Type::Float {
+        Type::Float
+    } else {
+        Type::Int
+    };
+    let arg_expect_typ: Type;
     let ret_typ: Type;
     match op {
-        BinOp::Add | BinOp::Subtract | BinOp::Multiply | BinOp::Divide => {
-            arg1_expect_typ = Type::Int;
-            arg2_expect_typ = Type::Int;
-            ret_typ = Type::Int;
+        BinOp::Add | BinOp::Subtract | BinOp::Multiply | BinOp::Divide | BinOp::Expt => {
+            arg_expect_typ = num_typ.clone();
+            ret_typ = num_typ;
         }
         BinOp::LessThan
         | BinOp::GreaterThan
         | BinOp::LessOrEqual
         | BinOp::GreaterOrEqual
         | BinOp::EqualTo => {
-            arg1_expect_typ = Type::Int;
-            arg2_expect_typ = Type::Int;
+            arg_expect_typ = num_typ;
+            ret_typ = Type::Bool;
+        }
+        // there are no unsigned floats
+        BinOp::UnsignedDivide | BinOp::UnsignedRemainder => {
+            arg_expect_typ = Type::Int;
+            ret_typ = Type::Int;
+        }
+        BinOp::UnsignedLessThan | BinOp::UnsignedGreaterThan => {
+            arg_expect_typ = Type::Int;
             ret_typ = Type::Bool;
         }
         BinOp::And | BinOp::Or => {
-            arg1_expect_typ = Type::Bool;
-            arg2_expect_typ = Type::Bool;
+            arg_expect_typ = Type::Bool;
             ret_typ = Type::Bool;
         }
         BinOp::Concat => {
-            arg1_expect_typ = Type::Str;
-            arg2_expect_typ = Type::Str;
+            arg_expect_typ = Type::Str;
             ret_typ = Type::Str;
         }
     }
-    let arg1 = tc_with_env(arg1, env)?;
-    let arg2 = tc_with_env(arg2, env)?;
-    if arg1_expect_typ != arg1.typ || arg2_expect_typ != arg2.typ {
-        Err(TypeCheckError::from(
-            "Binary operation parameters do not match expected types.",
+    let is_num = |typ: &Type| *typ == Type::Int || *typ == Type::Float;
+    if arg_expect_typ != arg1.typ || arg_expect_typ != arg2.typ {
+        // (u/ 1.0 2.0) doesn't mix ints and floats; it just needs ints
+        if is_num(&arg_expect_typ) && is_num(&arg1.typ) && is_num(&arg2.typ) && arg1.typ != arg2.typ
+        {
+            return Err(TypeCheckError::new(format!(
+                "Cannot mix ints and floats in ({} {} {}); convert with exact->inexact or inexact->exact.",
+                op, arg1, arg2
+            ))
+            .with_code("T002"));
+        }
+        let (found, arg) = if arg_expect_typ != arg1.typ {
+            (&arg1.typ, &arg1)
+        } else {
+            (&arg2.typ, &arg2)
+        };
+        Err(TypeCheckError::mismatch(
+            &format!("Argument of {}", op),
+            &arg_expect_typ,
+            found,
+            arg,
         ))
+    } else if matches!(
+        op,
+        BinOp::Divide | BinOp::UnsignedDivide | BinOp::UnsignedRemainder
+    ) && *arg2.kind == ExprKind::Num(0)
+    {
+        // dividing by zero otherwise traps at runtime
+        Err(
+            TypeCheckError::new(format!("Division by zero in ({} {} {}).", op, arg1, arg2))
+                .with_code("T006"),
+        )
     } else {
         Ok(TypedExpr::new(ret_typ, ExprKind::Binop(op, arg1, arg2)))
     }
 }
 
+fn tc_unop_with_env(op: UnOp, arg: &Expr, env: &TypeEnv) -> Result<TypedExpr, TypeCheckError> {
+    let (arg_typ, ret_typ) = op.signature();
+    let arg = tc_with_env(arg, env)?;
+    if arg.typ != arg_typ {
+        return Err(TypeCheckError::mismatch(
+            &format!("Argument of {}", op),
+            &arg_typ,
+            &arg.typ,
+            &arg,
+        ));
+    }
+    Ok(TypedExpr::new(ret_typ, ExprKind::Unop(op, arg)))
+}
+
 fn tc_if_with_env(
     predicate: &Expr,
     consequent: &Expr,
@@ -109,18 +594,22 @@ fn tc_if_with_env(
     let cons = tc_with_env(consequent, env)?;
     let alt = tc_with_env(alternate, env)?;
     if pred.typ != Type::Bool {
-        Err(TypeCheckError::from(
-            "Predicate in if expression does not evaluate to a boolean value.",
-        ))
-    } else if cons.typ != alt.typ {
-        Err(TypeCheckError::from(
-            "Consequent and alternate values in if expression do not match types.",
+        Err(TypeCheckError::mismatch(
+            "Predicate of if expression",
+            &Type::Bool,
+            &pred.typ,
+            &pred,
         ))
     } else {
-        Ok(TypedExpr::new(
-            cons.typ.clone(),
-            ExprKind::If(pred, cons, alt),
-        ))
+        match join_types(&cons.typ, &alt.typ) {
+            Some(typ) => Ok(TypedExpr::new(typ, ExprKind::If(pred, cons, alt))),
+            None => Err(TypeCheckError::mismatch(
+                "Alternate of if expression (like its consequent)",
+                &cons.typ,
+                &alt.typ,
+                &alt,
+            )),
+        }
     }
 }
 
@@ -154,18 +643,23 @@ fn tc_lambda_with_env(
     // Add arg types to the type environment for use in the body
     let new_env = env.add_bindings(params.clone());
 
-    // Type check lambda body
-    let body = tc_with_env(body, &new_env)?;
-    if *ret_type == body.typ {
+    // Type check lambda body, injecting it into the return type if the
+    // function returns a union
+    let body = inject_into_union(ret_type, tc_with_env(body, &new_env)?);
+    if is_subtype(ret_type, &body.typ) {
         let param_types: Vector<Type> = params.iter().map(|pair| pair.1.clone()).collect();
-        let lambda_typ = Type::Func(param_types, Box::new(ret_type.clone()));
+        let effect = lambda_effect(params, &body);
+        let lambda_typ = Type::Func(param_types, Box::new(ret_type.clone()), effect);
         Ok(TypedExpr::new(
             lambda_typ,
             ExprKind::Lambda(params.clone(), ret_type.clone(), body),
         ))
     } else {
-        Err(TypeCheckError::from(
-            "Lambda expression body type does not match the expected return type.",
+        Err(TypeCheckError::mismatch(
+            "Body of lambda expression (its return type)",
+            ret_type,
+            &body.typ,
+            &body,
         ))
     }
 }
@@ -198,17 +692,23 @@ fn tc_set_bang_with_env(
 ) -> Result<TypedExpr, TypeCheckError> {
     let expected_typ = env
         .find(var)
-        .ok_or_else(|| "Variable in set! cannot be found within the local scope - the variable must already be defined by a function parameter or a let expression.")?
+        .ok_or_else(|| {
+            TypeCheckError::from("Variable in set! cannot be found within the local scope - the variable must already be defined by a function parameter or a let expression.")
+                .with_code("T004")
+        })?
         .clone();
     let new_val = tc_with_env(new_val, env)?;
-    if new_val.typ == expected_typ {
+    if is_subtype(&expected_typ, &new_val.typ) {
         Ok(TypedExpr::new(
-            new_val.typ.clone(),
+            expected_typ,
             ExprKind::Set(String::from(var), new_val),
         ))
     } else {
-        Err(TypeCheckError::from(
-            "Type of set! body does not match variable's initialized type.",
+        Err(TypeCheckError::mismatch(
+            &format!("Value assigned to {} by set!", var),
+            &expected_typ,
+            &new_val.typ,
+            &new_val,
         ))
     }
 }
@@ -218,23 +718,207 @@ fn tc_cons_with_env(first: &Expr, rest: &Expr, env: &TypeEnv) -> Result<TypedExp
     let cdr = tc_with_env(rest, env)?;
     match cdr.typ.clone() {
         Type::List(boxed_type) => {
-            if *boxed_type == car.typ {
+            if let Some(elem_type) = join_types(&boxed_type, &car.typ) {
                 Ok(TypedExpr::new(
-                    Type::List(boxed_type),
+                    Type::List(Box::new(elem_type)),
                     ExprKind::Cons(car, cdr),
                 ))
             } else {
-                Err(TypeCheckError::from(
-                    "Car of cons does not match type of cdr.",
+                Err(TypeCheckError::mismatch(
+                    "Car of cons (the element type of its cdr)",
+                    &boxed_type,
+                    &car.typ,
+                    &car,
                 ))
             }
         }
-        _ => Err(TypeCheckError::from(
-            "Cdr of cons expression is not a list type.",
+        _ => Err(TypeCheckError::mismatch(
+            "Cdr of cons",
+            &Type::List(Box::new(car.typ.clone())),
+            &cdr.typ,
+            &cdr,
         )),
     }
 }
 
+fn tc_force_with_env(promise: &Expr, env: &TypeEnv) -> Result<TypedExpr, TypeCheckError> {
+    let promise = tc_with_env(promise, env)?;
+    match forced_type(&promise.typ) {
+        Some(typ) => Ok(TypedExpr::new(typ, ExprKind::Force(promise))),
+        None => Err(TypeCheckError::new(format!(
+            "Expression in force is not a promise, found {}.",
+            promise.typ
+        ))),
+    }
+}
+
+fn tc_format_with_env(
+    control: &str,
+    args: &Vector<Expr>,
+    env: &TypeEnv,
+) -> Result<TypedExpr, TypeCheckError> {
+    let directives = format_directives(control).map_err(TypeCheckError::new)?;
+    if directives.len() != args.len() {
+        return Err(TypeCheckError::new(format!(
+            "Format string has {} directives, but {} arguments were given.",
+            directives.len(),
+            args.len()
+        ))
+        .with_code("T003"));
+    }
+    let typed_args = tc_array_with_env(args, env)?;
+    for (i, (directive, arg)) in directives.iter().zip(typed_args.iter()).enumerate() {
+        let expected = match directive {
+            'd' => Type::Int,
+            's' => Type::Str,
+            _ => continue,
+        };
+        if arg.typ != expected {
+            return Err(TypeCheckError::mismatch(
+                &format!("Argument {} of format (used by ~{})", i, directive),
+                &expected,
+                &arg.typ,
+                arg,
+            ));
+        }
+    }
+    Ok(TypedExpr::new(
+        Type::Str,
+        ExprKind::Format(String::from(control), typed_args),
+    ))
+}
+
+fn tc_builtin_with_env(
+    builtin: Builtin,
+    args: &Vector<Expr>,
+    env: &TypeEnv,
+) -> Result<TypedExpr, TypeCheckError> {
+    if env.wasm_target() && !builtin.supported_by_wasm() {
+        return Err(TypeCheckError::new(format!(
+            "{} is not supported by the wasm target, only by the interpreter.",
+            builtin
+        ))
+        .with_code("T008"));
+    }
+    let (param_types, ret_type) = builtin.signature();
+    if args.len() != param_types.len() {
+        return Err(TypeCheckError::new(format!(
+            "{} expects {} arguments, but {} were given.",
+            builtin,
+            param_types.len(),
+            args.len()
+        ))
+        .with_code("T003"));
+    }
+    let typed_args = tc_array_with_env(args, env)?;
+    for (i, (param_type, arg)) in param_types.iter().zip(typed_args.iter()).enumerate() {
+        if *param_type != arg.typ {
+            return Err(TypeCheckError::mismatch(
+                &format!("Argument {} of {}", i, builtin),
+                param_type,
+                &arg.typ,
+                arg,
+            ));
+        }
+    }
+    Ok(TypedExpr::new(
+        ret_type,
+        ExprKind::Builtin(builtin, typed_args),
+    ))
+}
+
+fn tc_extern_with_env(
+    module: &str,
+    name: &str,
+    typ: &Type,
+    args: &Vector<Expr>,
+    env: &TypeEnv,
+) -> Result<TypedExpr, TypeCheckError> {
+    let (param_types, ret_type) = match typ {
+        Type::Func(param_types, ret_type, _effect) => (param_types, ret_type),
+        _ => {
+            return Err(TypeCheckError::new(format!(
+                "Extern function {}.{} does not have a function type.",
+                module, name
+            )))
+        }
+    };
+    // values are passed to the host as they are represented in memory, so
+    // only simple values can be exchanged with it
+    if param_types
+        .iter()
+        .chain(std::iter::once(&**ret_type))
+        .any(|typ| !matches!(typ, Type::Int | Type::Bool | Type::Str))
+    {
+        return Err(TypeCheckError::new(format!(
+            "Extern function {}.{} can only take and return ints, bools, and strings, but has type {}.",
+            module, name, typ
+        )));
+    }
+    if args.len() != param_types.len() {
+        return Err(TypeCheckError::new(format!(
+            "{}.{} expects {} arguments, but {} were given.",
+            module,
+            name,
+            param_types.len(),
+            args.len()
+        ))
+        .with_code("T003"));
+    }
+    let typed_args = tc_array_with_env(args, env)?;
+    for (i, (param_type, arg)) in param_types.iter().zip(typed_args.iter()).enumerate() {
+        if *param_type != arg.typ {
+            return Err(TypeCheckError::mismatch(
+                &format!("Argument {} of {}.{}", i, module, name),
+                param_type,
+                &arg.typ,
+                arg,
+            ));
+        }
+    }
+    Ok(TypedExpr::new(
+        (**ret_type).clone(),
+        ExprKind::Extern(
+            String::from(module),
+            String::from(name),
+            typ.clone(),
+            typed_args,
+        ),
+    ))
+}
+
+fn tc_wasm_asm_with_env(
+    code: &str,
+    typ: &Type,
+    args: &Vector<Expr>,
+    env: &TypeEnv,
+) -> Result<TypedExpr, TypeCheckError> {
+    // the instructions take and return values as they are represented in
+    // memory, so like with extern functions, only simple values can be used
+    let is_simple = |typ: &Type| matches!(typ, Type::Int | Type::Bool | Type::Str);
+    if !is_simple(typ) {
+        return Err(TypeCheckError::new(format!(
+            "Wasm-asm expression can only return an int, bool, or string, but has type {}.",
+            typ
+        )));
+    }
+    let typed_args = tc_array_with_env(args, env)?;
+    if let Some((i, arg)) = typed_args
+        .iter()
+        .enumerate()
+        .find(|(_i, arg)| !is_simple(&arg.typ))
+    {
+        return Err(TypeCheckError::new(format!(
+            "In argument {} of wasm-asm expression: expected an int, bool, or string, found {}.",
+            i, arg.typ
+        )));
+    }
+    Ok(TypedExpr::new(
+        typ.clone(),
+        ExprKind::WasmAsm(String::from(code), typ.clone(), typed_args),
+    ))
+}
+
 fn tc_car_with_env(pair: &Expr, env: &TypeEnv) -> Result<TypedExpr, TypeCheckError> {
     let pair = tc_with_env(pair, env)?;
     match pair.typ.clone() {
@@ -270,6 +954,42 @@ fn tc_tuple_with_env(exps: &Vector<Expr>, env: &TypeEnv) -> Result<TypedExpr, Ty
     ))
 }
 
+// like set!, tuple-set! returns the value that is being assigned
+fn tc_tuple_set_with_env(
+    tup: &Expr,
+    key: u32,
+    val: &Expr,
+    env: &TypeEnv,
+) -> Result<TypedExpr, TypeCheckError> {
+    let tup = tc_with_env(tup, env)?;
+    let val = tc_with_env(val, env)?;
+    match tup.typ.clone() {
+        Type::Tuple(vec) => {
+            if (key as usize) >= vec.len() {
+                return Err(TypeCheckError::new(format!(
+                    "Index {} in tuple-set! is out of bounds for tuple of type {}.",
+                    key, tup.typ
+                )));
+            }
+            if vec[key as usize] != val.typ {
+                return Err(TypeCheckError::mismatch(
+                    &format!("Value of tuple-set! (element {} of {})", key, tup.typ),
+                    &vec[key as usize],
+                    &val.typ,
+                    &val,
+                ));
+            }
+            Ok(TypedExpr::new(
+                val.typ.clone(),
+                ExprKind::TupleSet(tup, key, val),
+            ))
+        }
+        _ => Err(TypeCheckError::from(
+            "First expression in tuple-set! is not a tuple.",
+        )),
+    }
+}
+
 fn tc_tuple_get_with_env(tup: &Expr, key: u32, env: &TypeEnv) -> Result<TypedExpr, TypeCheckError> {
     let tup = tc_with_env(tup, env)?;
     match tup.typ.clone() {
@@ -278,9 +998,10 @@ fn tc_tuple_get_with_env(tup: &Expr, key: u32, env: &TypeEnv) -> Result<TypedExp
                 let elem_type = vec[key as usize].clone();
                 Ok(TypedExpr::new(elem_type, ExprKind::TupleGet(tup, key)))
             } else {
-                Err(TypeCheckError::from(
-                    "Key in tuple-ref is too large for the provided tuple.",
-                ))
+                Err(TypeCheckError::new(format!(
+                    "Index {} in tuple-ref is out of bounds for tuple of type {}.",
+                    key, tup.typ
+                )))
             }
         }
         _ => Err(TypeCheckError::from(
@@ -314,7 +1035,7 @@ fn tc_record_get_with_env(
 ) -> Result<TypedExpr, TypeCheckError> {
     let typed_record = tc_with_env(record, env)?;
     match typed_record.typ.clone() {
-        Type::Record(fields) => {
+        Type::Record(fields) | Type::OpenRecord(fields, _) => {
             let matches: Vector<(String, Type)> = fields
                 .iter()
                 .cloned()
@@ -350,13 +1071,64 @@ fn tc_apply_with_env(
         .collect::<Vector<Type>>();
 
     // TODO: is this variable (and the function call) appropriately named?
-    let lambda_type = validate_lambda_type(&func.typ, &arg_types)?;
+    let lambda_type = validate_application(&func.typ, &arg_types, env.tuple_width_subtyping())
+        .map_err(|err| argument_mismatch(&func.typ, &typed_args, env, err))?;
+    let typed_args = match &func.typ {
+        Type::Func(param_types, _, _) => param_types
+            .iter()
+            .zip(typed_args)
+            .map(|(param_type, arg)| coerce_arg(param_type, arg))
+            .collect(),
+        _ => typed_args,
+    };
     Ok(TypedExpr::new(
         lambda_type,
         ExprKind::FnApp(func, typed_args),
     ))
 }
 
+/// Replaces an error from applying a function of type `fn_type` to `args`
+/// with one for the first argument which doesn't match its parameter, so
+/// that the error includes the argument. Other errors are left alone.
+fn argument_mismatch(
+    fn_type: &Type,
+    args: &Vector<TypedExpr>,
+    env: &TypeEnv,
+    err: TypeCheckError,
+) -> TypeCheckError {
+    let param_types = match fn_type {
+        Type::Func(param_types, _, _) if param_types.len() == args.len() => param_types,
+        _ => return err,
+    };
+    for (i, (param_type, arg)) in param_types.iter().zip(args.iter()).enumerate() {
+        let mut subst = vec![];
+        if let Err(unify_err) = unify(
+            param_type,
+            &arg.typ,
+            env.tuple_width_subtyping(),
+            &mut subst,
+        ) {
+            let mut mismatch = TypeCheckError::mismatch(
+                &format!("Argument {} of function application", i),
+                param_type,
+                &arg.typ,
+                arg,
+            );
+            // explain mismatches within the argument's type, e.g. of fields
+            if unify_err
+                != (UnifyError::Mismatch {
+                    expected: param_type.clone(),
+                    found: arg.typ.clone(),
+                })
+            {
+                mismatch.message = format!("{} {}", mismatch.message, unify_err);
+            }
+            return mismatch.with_code(unify_err.code());
+        }
+    }
+    err
+}
+
 fn tc_is_null_with_env(exp: &Expr, env: &TypeEnv) -> Result<TypedExpr, TypeCheckError> {
     let typed_exp = tc_with_env(exp, env)?;
     Ok(TypedExpr::new(Type::Bool, ExprKind::IsNull(typed_exp)))
@@ -373,14 +1145,38 @@ fn tc_pack_with_env(
         let substituted_typ = type_var_substitute(base_typ, *type_var, sub);
         // now check if the type of "substituted" matches the type of the packed expression
         let packed_exp = tc_with_env(packed_exp, env)?;
-        if packed_exp.typ == substituted_typ {
+        let packable = match (&substituted_typ, &packed_exp.typ, &*packed_exp.kind) {
+            // a new tuple or record can't be assigned to through any other
+            // type, so its elements only need to be usable as the expected
+            // ones (e.g. pure functions in closures, see `cc_lambda`)
+            (Type::Tuple(types), Type::Tuple(found_types), ExprKind::Tuple(_)) => {
+                types.len() == found_types.len()
+                    && types
+                        .iter()
+                        .zip(found_types.iter())
+                        .all(|(typ, found_typ)| is_subtype(typ, found_typ))
+            }
+            (Type::Record(types), Type::Record(found_types), ExprKind::Record(_)) => {
+                types.len() == found_types.len()
+                    && types.iter().zip(found_types.iter()).all(
+                        |((label, typ), (found_label, found_typ))| {
+                            label == found_label && is_subtype(typ, found_typ)
+                        },
+                    )
+            }
+            _ => is_subtype(&substituted_typ, &packed_exp.typ),
+        };
+        if packable {
             Ok(TypedExpr::new(
                 exist.clone(),
                 ExprKind::Pack(packed_exp, sub.clone(), exist.clone()),
             ))
         } else {
-            Err(TypeCheckError::from(
-                "Packed expression does not match existential type.",
+            Err(TypeCheckError::mismatch(
+                &format!("Packed expression (of {} with {})", exist, sub),
+                &substituted_typ,
+                &packed_exp.typ,
+                &packed_exp,
             ))
         }
     } else {
@@ -390,6 +1186,263 @@ fn tc_pack_with_env(
     }
 }
 
+fn tc_assert_with_env(
+    exp: &Expr,
+    message: &str,
+    env: &TypeEnv,
+) -> Result<TypedExpr, TypeCheckError> {
+    let typed_exp = tc_with_env(exp, env)?;
+    match typed_exp.typ {
+        Type::Bool => Ok(TypedExpr::new(
+            Type::Bool,
+            ExprKind::Assert(typed_exp, String::from(message)),
+        )),
+        _ => Err(TypeCheckError::mismatch(
+            "Condition of assert expression",
+            &Type::Bool,
+            &typed_exp.typ,
+            &typed_exp,
+        )),
+    }
+}
+
+fn tc_while_with_env(pred: &Expr, body: &Expr, env: &TypeEnv) -> Result<TypedExpr, TypeCheckError> {
+    let typed_pred = tc_with_env(pred, env)?;
+    if typed_pred.typ != Type::Bool {
+        return Err(TypeCheckError::mismatch(
+            "Condition of while expression",
+            &Type::Bool,
+            &typed_pred.typ,
+            &typed_pred,
+        ));
+    }
+    let typed_body = tc_with_env(body, env)?;
+    Ok(TypedExpr::new(
+        unit_type(),
+        ExprKind::While(typed_pred, typed_body),
+    ))
+}
+
+fn tc_case_with_env(
+    key: &Expr,
+    clauses: &Vector<(Vector<i32>, Expr)>,
+    else_body: &Expr,
+    env: &TypeEnv,
+) -> Result<TypedExpr, TypeCheckError> {
+    let typed_key = tc_with_env(key, env)?;
+    if typed_key.typ != Type::Int {
+        return Err(TypeCheckError::mismatch(
+            "Key of case expression",
+            &Type::Int,
+            &typed_key.typ,
+            &typed_key,
+        ));
+    }
+    let typed_else = tc_with_env(else_body, env)?;
+    let mut typ = typed_else.typ.clone();
+    let typed_clauses = clauses
+        .iter()
+        .map(|(datums, body)| {
+            let typed_body = tc_with_env(body, env)?;
+            typ = join_types(&typ, &typed_body.typ).ok_or_else(|| {
+                TypeCheckError::mismatch(
+                    "Clause of case expression (like its else clause)",
+                    &typed_else.typ,
+                    &typed_body.typ,
+                    &typed_body,
+                )
+            })?;
+            Ok((datums.clone(), typed_body))
+        })
+        .collect::<Result<Vector<(Vector<i32>, TypedExpr)>, TypeCheckError>>()?;
+    Ok(TypedExpr::new(
+        typ,
+        ExprKind::Case(typed_key, typed_clauses, typed_else),
+    ))
+}
+
+/// Checks that the first arguments of a curried function match its first
+/// parameters, returning a function type which takes the remaining
+/// parameters, e.g. `(curry f 1)` where `f` is `(-> int bool int)` has type
+/// `(-> bool int)`.
+fn tc_curry_with_env(
+    func: &Expr,
+    args: &Vector<Expr>,
+    env: &TypeEnv,
+) -> Result<TypedExpr, TypeCheckError> {
+    let typed_func = tc_with_env(func, env)?;
+    let typed_args = tc_array_with_env(&args, env)?;
+    let residual_type = match &typed_func.typ {
+        Type::Func(param_types, ret_type, effect) => {
+            if args.len() > param_types.len() {
+                return Err(TypeCheckError::new(format!(
+                    "Curry expression has {} arguments, but the function only takes {}.",
+                    args.len(),
+                    param_types.len()
+                ))
+                .with_code("T003"));
+            }
+            // check the given arguments against a function which returns
+            // the residual function, so that any type variables bound by
+            // the arguments are substituted in its type
+            let residual = Type::Func(
+                param_types.clone().slice(args.len()..),
+                ret_type.clone(),
+                *effect,
+            );
+            let partial = Type::Func(param_types.take(args.len()), Box::new(residual), *effect);
+            let arg_types = typed_args
+                .iter()
+                .map(|typed_arg| typed_arg.typ.clone())
+                .collect::<Vector<Type>>();
+            validate_application(&partial, &arg_types, env.tuple_width_subtyping())?
+        }
+        _ => {
+            return Err(TypeCheckError::new(format!(
+                "Curry expression expected a function, instead found {}.",
+                typed_func.typ
+            ))
+            .with_code("T005"))
+        }
+    };
+    Ok(TypedExpr::new(
+        residual_type,
+        ExprKind::Curry(typed_func, typed_args),
+    ))
+}
+
+/// Checks that the result of the inner function can be passed to the outer
+/// function, returning a function type which takes the parameters of the
+/// inner function and returns the result of the outer function.
+fn tc_compose_with_env(
+    outer: &Expr,
+    inner: &Expr,
+    env: &TypeEnv,
+) -> Result<TypedExpr, TypeCheckError> {
+    let typed_outer = tc_with_env(outer, env)?;
+    let typed_inner = tc_with_env(inner, env)?;
+    let composed_type = match (&typed_outer.typ, &typed_inner.typ) {
+        (Type::Func(_, _, outer_effect), Type::Func(inner_params, inner_ret, inner_effect)) => {
+            let ret_type = validate_application(
+                &typed_outer.typ,
+                &vector![inner_ret.as_ref().clone()],
+                env.tuple_width_subtyping(),
+            )
+            .map_err(|err| {
+                TypeCheckError::new(format!(
+                    "Result of inner function in compose expression cannot be passed to outer function: {}",
+                    err
+                ))
+            })?;
+            Type::Func(
+                inner_params.clone(),
+                Box::new(ret_type),
+                (*outer_effect).max(*inner_effect),
+            )
+        }
+        _ => {
+            return Err(TypeCheckError::new(format!(
+                "Compose expression expected two functions, instead found {} and {}.",
+                typed_outer.typ, typed_inner.typ
+            ))
+            .with_code("T005"))
+        }
+    };
+    Ok(TypedExpr::new(
+        composed_type,
+        ExprKind::Compose(typed_outer, typed_inner),
+    ))
+}
+
+fn tc_inject_with_env(
+    exp: &Expr,
+    union_typ: &Type,
+    env: &TypeEnv,
+) -> Result<TypedExpr, TypeCheckError> {
+    let typed_exp = tc_with_env(exp, env)?;
+    match union_typ {
+        Type::Union(types) => {
+            if types.contains(&typed_exp.typ) {
+                Ok(TypedExpr::new(
+                    union_typ.clone(),
+                    ExprKind::Inject(typed_exp, union_typ.clone()),
+                ))
+            } else {
+                Err(TypeCheckError::new(format!(
+                    "Type {} in inject expression is not one of the types in {}.",
+                    typed_exp.typ, union_typ
+                )))
+            }
+        }
+        _ => Err(TypeCheckError::from(
+            "Type annotation in inject expression is not a union type.",
+        )),
+    }
+}
+
+fn tc_typecase_with_env(
+    exp: &Expr,
+    clauses: &Vector<(Type, String, Expr)>,
+    env: &TypeEnv,
+) -> Result<TypedExpr, TypeCheckError> {
+    let typed_exp = tc_with_env(exp, env)?;
+    let union_types = match &typed_exp.typ {
+        Type::Union(types) => types.clone(),
+        _ => {
+            return Err(TypeCheckError::new(format!(
+                "Expression in typecase has type {}, which is not a union type.",
+                typed_exp.typ
+            )))
+        }
+    };
+    let mut typed_clauses: Vector<(Type, String, TypedExpr)> = vector![];
+    let mut typecase_typ: Option<Type> = None;
+    for (typ, var, body) in clauses.iter() {
+        if !union_types.contains(typ) {
+            return Err(TypeCheckError::new(format!(
+                "Typecase clause type {} is not one of the types in {}.",
+                typ, typed_exp.typ
+            )));
+        }
+        if typed_clauses.iter().any(|clause| clause.0 == *typ) {
+            return Err(TypeCheckError::new(format!(
+                "Typecase contains more than one clause for type {}.",
+                typ
+            )));
+        }
+        let typed_body = tc_with_env(body, &env.add_binding((var.clone(), typ.clone())))?;
+        typecase_typ = match (typecase_typ, typed_clauses.front()) {
+            (Some(clauses_typ), Some(first_clause)) => {
+                match join_types(&clauses_typ, &typed_body.typ) {
+                    Some(joined) => Some(joined),
+                    None => {
+                        return Err(TypeCheckError::mismatch(
+                            &format!("Typecase clause for {} (like the first clause)", typ),
+                            &first_clause.2.typ,
+                            &typed_body.typ,
+                            &typed_body,
+                        ))
+                    }
+                }
+            }
+            _ => Some(typed_body.typ.clone()),
+        };
+        typed_clauses.push_back((typ.clone(), var.clone(), typed_body));
+    }
+    // since there is no runtime error handling, every type in the union must
+    // be handled by some clause
+    if typed_clauses.len() != union_types.len() {
+        return Err(TypeCheckError::new(format!(
+            "Typecase does not have a clause for every type in {}.",
+            typed_exp.typ
+        )));
+    }
+    Ok(TypedExpr::new(
+        typecase_typ.unwrap_or(Type::Unknown),
+        ExprKind::TypeCase(typed_exp, typed_clauses),
+    ))
+}
+
 fn tc_unpack_with_env(
     var: &str,
     package: &Expr,
@@ -441,21 +1494,24 @@ fn tc_array_with_env(
 }
 
 pub fn tc_with_env(value: &Expr, env: &TypeEnv) -> Result<TypedExpr, TypeCheckError> {
-    match &*value.kind {
+    let typed_exp = match &*value.kind {
         ExprKind::Num(x) => Ok(TypedExpr::new(Type::Int, ExprKind::Num(*x))),
+        ExprKind::Float(x) => Ok(TypedExpr::new(Type::Float, ExprKind::Float(*x))),
         ExprKind::Bool(x) => Ok(TypedExpr::new(Type::Bool, ExprKind::Bool(*x))),
         ExprKind::Str(x) => Ok(TypedExpr::new(Type::Str, ExprKind::Str(x.clone()))),
         ExprKind::Id(sym) => {
             let typ = match env.find(sym.as_str()) {
                 Some(val) => Ok(val.clone()),
-                None => Err(TypeCheckError(format!(
+                None => Err(TypeCheckError::new(format!(
                     "Not a recognized function name: {}.",
                     sym
-                ))),
+                ))
+                .with_code("T004")),
             }?;
             Ok(TypedExpr::new(typ, ExprKind::Id(sym.clone())))
         }
         ExprKind::Binop(op, arg1, arg2) => tc_binop_with_env(*op, &arg1, &arg2, env),
+        ExprKind::Unop(op, arg) => tc_unop_with_env(*op, &arg, env),
         ExprKind::If(pred, cons, alt) => tc_if_with_env(&pred, &cons, &alt, env),
         ExprKind::Let(bindings, body) => tc_let_with_env(&bindings, &body, env),
         ExprKind::Lambda(params, ret_typ, body) => {
@@ -475,20 +1531,55 @@ pub fn tc_with_env(value: &Expr, env: &TypeEnv) -> Result<TypedExpr, TypeCheckEr
         }),
         ExprKind::Tuple(exps) => tc_tuple_with_env(&exps, env),
         ExprKind::TupleGet(tup, key) => tc_tuple_get_with_env(&tup, *key, env),
+        ExprKind::TupleSet(tup, key, val) => tc_tuple_set_with_env(&tup, *key, &val, env),
         ExprKind::Pack(val, sub, exist) => tc_pack_with_env(&val, &sub, &exist, env),
         ExprKind::Unpack(var, package, type_sub, body) => {
             tc_unpack_with_env(&var, &package, *type_sub, &body, env)
         }
+        ExprKind::Inject(exp, union_typ) => tc_inject_with_env(&exp, &union_typ, env),
+        ExprKind::TypeCase(exp, clauses) => tc_typecase_with_env(&exp, &clauses, env),
+        ExprKind::Assert(exp, message) => tc_assert_with_env(&exp, &message, env),
+        ExprKind::While(pred, body) => tc_while_with_env(&pred, &body, env),
+        ExprKind::Case(key, clauses, else_body) => tc_case_with_env(key, clauses, else_body, env),
+        ExprKind::Curry(func, args) => tc_curry_with_env(&func, &args, env),
+        ExprKind::Compose(outer, inner) => tc_compose_with_env(&outer, &inner, env),
+        ExprKind::Delay(exp) => {
+            let typed_exp = tc_with_env(exp, env)?;
+            Ok(TypedExpr::new(
+                Type::Promise(Box::new(typed_exp.typ.clone())),
+                ExprKind::Delay(typed_exp),
+            ))
+        }
+        ExprKind::Force(promise) => tc_force_with_env(&promise, env),
+        ExprKind::Format(control, args) => tc_format_with_env(&control, &args, env),
+        ExprKind::Builtin(builtin, args) => tc_builtin_with_env(*builtin, &args, env),
+        ExprKind::Extern(module, name, typ, args) => {
+            tc_extern_with_env(module, name, typ, &args, env)
+        }
+        ExprKind::WasmAsm(code, typ, args) => tc_wasm_asm_with_env(code, typ, &args, env),
         ExprKind::FnApp(func, args) => tc_apply_with_env(&func, &args, env),
-    }
+    }?;
+    #[cfg(feature = "trace-exprs")]
+    tracing::debug!(exp = %value, typ = %typed_exp.typ, "type checked");
+    Ok(typed_exp)
 }
 
 pub fn type_check(value: &Expr) -> Result<TypedExpr, TypeCheckError> {
+    let _span = tracing::info_span!("type_check").entered();
     tc_with_env(value, &TypeEnv::new())
 }
 
 pub fn type_check_prog(prog: &Prog<Expr>) -> Result<Prog<TypedExpr>, TypeCheckError> {
-    let mut env = TypeEnv::new();
+    type_check_prog_with_env(prog, &TypeEnv::new())
+}
+
+/// Type checks a program, starting from the bindings and flags in `env`.
+pub fn type_check_prog_with_env(
+    prog: &Prog<Expr>,
+    env: &TypeEnv,
+) -> Result<Prog<TypedExpr>, TypeCheckError> {
+    let _span = tracing::info_span!("type_check_prog").entered();
+    let mut env = env.clone();
     let mut typed_fns: Vector<(String, TypedExpr)> = vector![];
     for def in prog.fns.iter() {
         let typed_fn = tc_with_env(&def.1, &env)?;
@@ -1,32 +1,64 @@
 use crate::ast_transform::transform_typed_exp_recursive;
 use crate::common::TypedExpr;
 use crate::util::format_vector;
-use im_rc::Vector;
+use im_rc::{vector, Vector};
 
+// TODO: there is no let-polymorphism yet (no type for a generic function).
+// Once there is, add a monomorphization pass after lambda lifting, which
+// clones each generic function for every combination of type arguments it's
+// instantiated with (caching the clones by type arguments), since wasm can't
+// express the polymorphism directly. Functions taking open records don't need
+// this, because arguments are projected to the fields the function expects
+// at each call (see `type_check::coerce_arg`).
 #[derive(Clone, Debug)]
 pub enum Type {
     Int,
+    Float, // 32-bit floating point, so that all values are 4 bytes (see generate_code)
     Bool,
     Str,
-    List(Box<Type>),                // homogenous list
-    Func(Vector<Type>, Box<Type>),  // array of input types, and a return type
-    Tuple(Vector<Type>),            // array of types
-    Record(Vector<(String, Type)>), // array of bindings
-    Exists(u64, Box<Type>),         // abstract type T, and base type in terms of T
-    TypeVar(u64),                   // abstract type T
-    Unknown,                        // placeholder, for debugging etc.
+    List(Box<Type>),                         // homogenous list
+    Promise(Box<Type>),                      // delayed computation of a value
+    Func(Vector<Type>, Box<Type>, Effect),   // array of input types, a return type, and an effect
+    Tuple(Vector<Type>),                     // array of types
+    Record(Vector<(String, Type)>),          // array of bindings
+    OpenRecord(Vector<(String, Type)>, u64), // known bindings, and row variable for the rest
+    Union(Vector<Type>),                     // array of alternative types
+    Exists(u64, Box<Type>),                  // abstract type T, and base type in terms of T
+    TypeVar(u64),                            // abstract type T
+    Unknown,                                 // placeholder, for debugging etc.
+}
+
+/// The side effects which calling a function may have.
+///
+/// Effects are ordered from least to most permissive, so the effect of
+/// evaluating several expressions is the maximum of their effects.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Effect {
+    Pure,    // no observable side effects
+    Mutates, // may modify variables outside of the function
 }
 
 // PartialEq is implemented manually to handle the specific case where two
 // types are both existential types, and they should be equal with respect to
-// substitution of one type variable for the other
+// substitution of one type variable for the other.
+//
+// Function types are only equal if their effects are. A pure function can
+// still be used where one which may mutate is expected (see
+// `type_check::is_subtype`).
 impl PartialEq for Type {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (Type::List(base_a), Type::List(base_b)) => base_a == base_b,
-            (Type::Func(in_a, ret_a), Type::Func(in_b, ret_b)) => in_a == in_b && ret_a == ret_b,
+            (Type::Promise(base_a), Type::Promise(base_b)) => base_a == base_b,
+            (Type::Func(in_a, ret_a, effect_a), Type::Func(in_b, ret_b, effect_b)) => {
+                in_a == in_b && ret_a == ret_b && effect_a == effect_b
+            }
             (Type::Tuple(vec_a), Type::Tuple(vec_b)) => vec_a == vec_b,
             (Type::Record(vec_a), Type::Record(vec_b)) => vec_a == vec_b,
+            (Type::OpenRecord(vec_a, row_a), Type::OpenRecord(vec_b, row_b)) => {
+                vec_a == vec_b && row_a == row_b
+            }
+            (Type::Union(vec_a), Type::Union(vec_b)) => vec_a == vec_b,
             (Type::Exists(typ_var_a, base_typ_a), Type::Exists(typ_var_b, base_typ_b)) => {
                 let other_sub =
                     type_var_substitute(base_typ_b, *typ_var_b, &Type::TypeVar(*typ_var_a));
@@ -34,6 +66,7 @@ impl PartialEq for Type {
             }
             (Type::TypeVar(a), Type::TypeVar(b)) => a == b,
             (Type::Int, Type::Int) => true,
+            (Type::Float, Type::Float) => true,
             (Type::Bool, Type::Bool) => true,
             (Type::Str, Type::Str) => true,
             (Type::Unknown, Type::Unknown) => true,
@@ -85,19 +118,24 @@ pub fn type_var_substitute_recursive(
 pub fn type_var_substitute(typ: &Type, type_var: u64, replace_with: &Type) -> Type {
     match typ {
         Type::Int => Type::Int,
+        Type::Float => Type::Float,
         Type::Bool => Type::Bool,
         Type::Str => Type::Str,
         Type::List(base_typ) => {
             let sbase_typ = type_var_substitute(base_typ, type_var, replace_with);
             Type::List(Box::new(sbase_typ))
         }
-        Type::Func(in_typs, ret_typ) => {
+        Type::Promise(base_typ) => {
+            let sbase_typ = type_var_substitute(base_typ, type_var, replace_with);
+            Type::Promise(Box::new(sbase_typ))
+        }
+        Type::Func(in_typs, ret_typ, effect) => {
             let sin_typs: Vector<Type> = in_typs
                 .iter()
                 .map(|inner_typ| type_var_substitute(inner_typ, type_var, replace_with))
                 .collect();
             let sret_typ = type_var_substitute(ret_typ, type_var, replace_with);
-            Type::Func(sin_typs, Box::new(sret_typ))
+            Type::Func(sin_typs, Box::new(sret_typ), *effect)
         }
         Type::Tuple(typs) => {
             let styps: Vector<Type> = typs
@@ -106,6 +144,13 @@ pub fn type_var_substitute(typ: &Type, type_var: u64, replace_with: &Type) -> Ty
                 .collect();
             Type::Tuple(styps)
         }
+        Type::Union(typs) => {
+            let styps: Vector<Type> = typs
+                .iter()
+                .map(|inner_typ| type_var_substitute(inner_typ, type_var, replace_with))
+                .collect();
+            Type::Union(styps)
+        }
         Type::Record(bindings) => {
             let sbindings: Vector<(String, Type)> = bindings
                 .iter()
@@ -116,6 +161,28 @@ pub fn type_var_substitute(typ: &Type, type_var: u64, replace_with: &Type) -> Ty
                 .collect();
             Type::Record(sbindings)
         }
+        Type::OpenRecord(bindings, row_var) => {
+            let sbindings: Vector<(String, Type)> = bindings
+                .iter()
+                .map(|pair| {
+                    let styp = type_var_substitute(&pair.1, type_var, replace_with);
+                    (pair.0.clone(), styp)
+                })
+                .collect();
+            if *row_var != type_var {
+                return Type::OpenRecord(sbindings, *row_var);
+            }
+            // A row variable can only be replaced by the rest of a record, so
+            // the fields it stands for get merged into the known fields.
+            match replace_with {
+                Type::Record(rest) => Type::Record(sbindings + rest.clone()),
+                Type::OpenRecord(rest, rest_row_var) => {
+                    Type::OpenRecord(sbindings + rest.clone(), *rest_row_var)
+                }
+                Type::TypeVar(new_row_var) => Type::OpenRecord(sbindings, *new_row_var),
+                _ => Type::OpenRecord(sbindings, *row_var),
+            }
+        }
         Type::Exists(base_typ_var, base_typ) => {
             if *base_typ_var == type_var {
                 let new_base_typ_var = type_var + 1;
@@ -139,17 +206,64 @@ pub fn type_var_substitute(typ: &Type, type_var: u64, replace_with: &Type) -> Ty
     }
 }
 
+/// Returns the type of an optional value, which is either a value of the given
+/// type or an empty tuple if there is no value. Written as `(option T)`.
+pub fn option_type(typ: Type) -> Type {
+    Type::Union(vector![typ, Type::Tuple(Vector::new())])
+}
+
+/// Returns the type of a sequence of bytes, which is a list of ints from 0 to
+/// 255 (see `common::Builtin`).
+pub fn bytes_type() -> Type {
+    Type::List(Box::new(Type::Int))
+}
+
+/// Returns the unit type, the type of expressions which are only evaluated for
+/// their effects (like while loops). It is an empty tuple, written `unit`.
+pub fn unit_type() -> Type {
+    Type::Tuple(Vector::new())
+}
+
+/// Returns the type of the value which forcing a promise produces, given the
+/// type of the promise.
+///
+/// After closure conversion, promises are represented as a tuple of a bool
+/// (whether the value has been computed yet) and the closure which computes
+/// it, so this type is also recognized.
+pub fn forced_type(typ: &Type) -> Option<Type> {
+    match typ {
+        Type::Promise(base_typ) => Some(*base_typ.clone()),
+        Type::Tuple(typs) if typs.len() == 2 && typs[0] == Type::Bool => match &typs[1] {
+            Type::Exists(_, closure_typ) => match &**closure_typ {
+                Type::Tuple(parts) if parts.len() == 2 => match &parts[0] {
+                    Type::Func(in_typs, ret_typ, _) if in_typs.len() == 1 => Some(*ret_typ.clone()),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 pub fn type_contains_var(typ: &Type, var: u64) -> bool {
     match typ {
         Type::Int => false,
+        Type::Float => false,
         Type::Bool => false,
         Type::Str => false,
         Type::List(x) => type_contains_var(x, var),
-        Type::Func(typs, ret_typ) => {
+        Type::Promise(x) => type_contains_var(x, var),
+        Type::Func(typs, ret_typ, _) => {
             typs.iter().any(|typ| type_contains_var(typ, var)) || type_contains_var(ret_typ, var)
         }
         Type::Tuple(typs) => typs.iter().any(|typ| type_contains_var(typ, var)),
+        Type::Union(typs) => typs.iter().any(|typ| type_contains_var(typ, var)),
         Type::Record(fields) => fields.iter().any(|field| type_contains_var(&field.1, var)),
+        Type::OpenRecord(fields, row_var) => {
+            *row_var == var || fields.iter().any(|field| type_contains_var(&field.1, var))
+        }
         Type::Exists(bound_var, inner_typ) => {
             *bound_var != var && type_contains_var(inner_typ, var)
         }
@@ -162,20 +276,33 @@ impl std::fmt::Display for Type {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
             Type::Bool => write!(f, "bool"),
             Type::Str => write!(f, "string"),
             Type::List(typ) => write!(f, "(list {})", typ),
-            Type::Func(in_typs, ret_typ) => {
+            Type::Promise(typ) => write!(f, "(promise {})", typ),
+            Type::Func(in_typs, ret_typ, effect) => {
+                let arrow = match effect {
+                    Effect::Pure => "pure->",
+                    Effect::Mutates => "->",
+                };
                 if in_typs.is_empty() {
-                    write!(f, "(-> {})", ret_typ)
+                    write!(f, "({} {})", arrow, ret_typ)
                 } else {
-                    write!(f, "(-> {} {})", format_vector(in_typs.clone()), ret_typ)
+                    write!(
+                        f,
+                        "({} {} {})",
+                        arrow,
+                        format_vector(in_typs.clone()),
+                        ret_typ
+                    )
                 }
             }
             Type::Tuple(typs) => match typs.len() {
                 0 => write!(f, "(tuple)"),
                 _ => write!(f, "(tuple {})", format_vector(typs.clone())),
             },
+            Type::Union(typs) => write!(f, "(union {})", format_vector(typs.clone())),
             Type::Record(bindings) => {
                 if bindings.is_empty() {
                     write!(f, "(record)")
@@ -187,6 +314,22 @@ impl std::fmt::Display for Type {
                     write!(f, "(record {})", format_vector(bindings_str_vec))
                 }
             }
+            Type::OpenRecord(bindings, row_var) => {
+                if bindings.is_empty() {
+                    write!(f, "(record ... T{})", row_var)
+                } else {
+                    let bindings_str_vec = bindings
+                        .iter()
+                        .map(|pair| format!("({} : {})", pair.0, pair.1))
+                        .collect();
+                    write!(
+                        f,
+                        "(record {} ... T{})",
+                        format_vector(bindings_str_vec),
+                        row_var
+                    )
+                }
+            }
             Type::Exists(typ_var, base) => write!(f, "(exists T{} {})", typ_var, base),
             Type::TypeVar(id) => write!(f, "T{}", id),
             Type::Unknown => write!(f, "unknown"),
@@ -14,3 +14,176 @@ pub fn format_vector<T: Clone + std::fmt::Display>(arr: Vector<T>) -> String {
         result
     }
 }
+
+/// Prints a string as a string literal, escaping characters so that reading
+/// the literal back (see `read::read`) produces the same string.
+pub fn escape_string(val: &str) -> String {
+    let mut result = String::from("\"");
+    for c in val.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\t' => result.push_str("\\t"),
+            '\r' => result.push_str("\\r"),
+            c if c.is_control() => result.push_str(&format!("\\x{:x};", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+/// Prints a float as the shortest decimal number which reads back as the same
+/// float, so that floats are printed the same way everywhere (and don't
+/// depend on how the host formats floats). Numbers are always printed
+/// positionally, with at least one digit after the decimal point (e.g. "3.0",
+/// "0.001" and "1500000000000000000000.0"), since the reader can't read
+/// scientific notation back. NaN and the infinities are printed as
+/// "+nan.0", "+inf.0" and "-inf.0", like in R7RS.
+pub fn format_float(x: f32) -> String {
+    if x.is_nan() {
+        return String::from("+nan.0");
+    }
+    if x.is_infinite() {
+        return String::from(if x > 0.0 { "+inf.0" } else { "-inf.0" });
+    }
+    // Rust prints the shortest digits which round trip in scientific notation
+    let sci = format!("{:e}", x.abs());
+    let (mantissa, exponent) = sci.split_at(sci.find('e').unwrap());
+    let digits = mantissa.replace('.', "");
+    let exponent = exponent[1..].parse::<i32>().unwrap();
+    let sign = if x.is_sign_negative() { "-" } else { "" };
+    let point = exponent + 1;
+    let positional = if point <= 0 {
+        format!("0.{}{}", "0".repeat(-point as usize), digits)
+    } else if point as usize >= digits.len() {
+        format!("{}{}.0", digits, "0".repeat(point as usize - digits.len()))
+    } else {
+        let (whole, fraction) = digits.split_at(point as usize);
+        format!("{}.{}", whole, fraction)
+    };
+    format!("{}{}", sign, positional)
+}
+
+/// Advances an xorshift64 random number generator, returning its next state.
+/// The state must be nonzero. The interpreter and the host's `random` use the
+/// same generator, so that a seed gives the same numbers in both.
+pub fn xorshift(mut state: u64) -> u64 {
+    state ^= state << 13;
+    state ^= state >> 7;
+    state ^= state << 17;
+    state
+}
+
+/// The state of a random number generator seeded with `seed`. xorshift needs
+/// a nonzero state, so a seed of zero is replaced with another constant.
+pub fn random_state(seed: u64) -> u64 {
+    if seed == 0 {
+        0x2545_f491_4f6c_dd1d
+    } else {
+        seed
+    }
+}
+
+/// A float from 0 up to (but not including) 1, taken from the top 24 bits of
+/// a random number generator's state (as many as a float can represent
+/// exactly).
+pub fn random_float(state: u64) -> f32 {
+    (state >> 40) as f32 / (1 << 24) as f32
+}
+
+/// Finds the directives in a `format` control string which consume an
+/// argument (`~a` for any value, `~s` for strings, and `~d` for numbers), in
+/// order. `~%` (newline) and `~~` (a tilde) don't consume arguments, and any
+/// other directive is an error.
+pub fn format_directives(control: &str) -> Result<Vector<char>, String> {
+    let mut directives = Vector::new();
+    let mut chars = control.chars();
+    while let Some(c) = chars.next() {
+        if c != '~' {
+            continue;
+        }
+        match chars.next() {
+            Some(directive @ 'a') | Some(directive @ 's') | Some(directive @ 'd') => {
+                directives.push_back(directive)
+            }
+            Some('%') | Some('~') => (),
+            Some(other) => return Err(format!("Unrecognized format directive: ~{}.", other)),
+            None => {
+                return Err(String::from(
+                    "Format string ends with an incomplete directive.",
+                ))
+            }
+        }
+    }
+    Ok(directives)
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes bytes as lowercase hexadecimal digits, two per byte.
+pub fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Decodes hexadecimal digits (in either case) into bytes, or returns None if
+/// the string has an odd length or other characters.
+pub fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let high = (pair[0] as char).to_digit(16)?;
+            let low = (pair[1] as char).to_digit(16)?;
+            Some((high * 16 + low) as u8)
+        })
+        .collect()
+}
+
+/// Encodes bytes as base64 (RFC 4648, with padding).
+pub fn encode_base64(bytes: &[u8]) -> String {
+    let mut result = String::new();
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, byte)| {
+            group | u32::from(*byte) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = (group >> (18 - 6 * i)) & 0x3f;
+                result.push(BASE64_ALPHABET[index as usize] as char);
+            } else {
+                result.push('=');
+            }
+        }
+    }
+    result
+}
+
+/// Decodes base64 (RFC 4648, with padding) into bytes, or returns None if the
+/// string isn't valid base64.
+pub fn decode_base64(encoded: &str) -> Option<Vec<u8>> {
+    if !encoded.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut bytes = vec![];
+    let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(4).collect();
+    for (chunk_index, chunk) in chunks.iter().enumerate() {
+        let padding = chunk.iter().rev().take_while(|c| **c == b'=').count();
+        if padding > 2 || (padding > 0 && chunk_index != chunks.len() - 1) {
+            return None;
+        }
+        let mut group = 0u32;
+        for (i, c) in chunk[..4 - padding].iter().enumerate() {
+            let index = BASE64_ALPHABET.iter().position(|letter| letter == c)?;
+            group |= (index as u32) << (18 - 6 * i);
+        }
+        for i in 0..3 - padding {
+            bytes.push((group >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(bytes)
+}
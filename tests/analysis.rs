@@ -0,0 +1,225 @@
+use scheme_to_wasm::analysis::{
+    assigned_vars, binding_occurrences, captured_vars, children, free_vars, node_count,
+    reachable_fns, Binder,
+};
+use scheme_to_wasm::common::ExprKind;
+use scheme_to_wasm::compile::{compile_exp_with_options, CompileOptions};
+use scheme_to_wasm::optimize::OptLevel;
+use scheme_to_wasm::parse::parse;
+use scheme_to_wasm::type_check::type_check;
+
+use std::collections::BTreeSet;
+
+fn free_vars_str(source: &str) -> Vec<String> {
+    let exp = parse(&lexpr::from_str(source).unwrap()).unwrap();
+    free_vars(&exp).into_iter().collect()
+}
+
+#[test]
+fn test_free_vars_all_kinds() {
+    let cases: Vec<(&str, Vec<&str>)> = vec![
+        ("(+ a b)", vec!["a", "b"]),
+        ("(if a b c)", vec!["a", "b", "c"]),
+        ("(let ((x a) (y x)) (+ x b))", vec!["a", "b", "x"]),
+        ("(lambda ((x : int) (y : int)) : int (+ x z))", vec!["z"]),
+        ("(begin a b)", vec!["a", "b"]),
+        ("(set! a b)", vec!["a", "b"]),
+        ("(cons a b)", vec!["a", "b"]),
+        ("(car a)", vec!["a"]),
+        ("(cdr a)", vec!["a"]),
+        ("(null? a)", vec!["a"]),
+        ("(null int)", vec![]),
+        ("(f a b)", vec!["a", "b", "f"]),
+        ("(make-tuple a b)", vec!["a", "b"]),
+        ("(tuple-ref a 0)", vec!["a"]),
+        ("(tuple-set! a 0 b)", vec!["a", "b"]),
+        ("(pack a int (exists T0 T0))", vec!["a"]),
+        ("(unpack (x a T0) (make-tuple x b))", vec!["a", "b"]),
+        ("(unpack (x x T0) x)", vec!["x"]),
+        ("(make-record (x a) (y b))", vec!["a", "b"]),
+        ("(record-ref a x)", vec!["a"]),
+        ("(inject a (union int bool))", vec!["a"]),
+        (
+            "(typecase a ((int x) (+ x b)) ((bool y) x))",
+            vec!["a", "b", "x"],
+        ),
+        (r#"(assert a "message")"#, vec!["a"]),
+        ("(curry f a b)", vec!["a", "b", "f"]),
+        ("(compose f g)", vec!["f", "g"]),
+        ("(delay a)", vec!["a"]),
+        ("(force a)", vec!["a"]),
+        (r#"(format "~a ~a" a b)"#, vec!["a", "b"]),
+        ("(current-millis)", vec![]),
+        ("(read-file a)", vec!["a"]),
+        (
+            r#"(call-extern "m" "f" (-> int int int) a b)"#,
+            vec!["a", "b"],
+        ),
+        (r#"(wasm-asm "i32.add" : int a b)"#, vec!["a", "b"]),
+        ("a", vec!["a"]),
+        ("1", vec![]),
+        ("true", vec![]),
+        (r#""a""#, vec![]),
+    ];
+    for (source, expected) in cases {
+        let expected: Vec<String> = expected.into_iter().map(String::from).collect();
+        assert_eq!(free_vars_str(source), expected, "free vars of {}", source);
+    }
+}
+
+#[test]
+fn test_free_vars_typed() {
+    // free variables can be found after type checking too
+    let exp = parse(&lexpr::from_str("(let ((x 1)) (lambda ((y : int)) : int (+ x y)))").unwrap())
+        .unwrap();
+    let typed_exp = type_check(&exp).unwrap();
+    assert_eq!(free_vars(&typed_exp), BTreeSet::new());
+    match &*typed_exp.kind {
+        ExprKind::Let(_bindings, body) => {
+            let expected: BTreeSet<String> = vec![String::from("x")].into_iter().collect();
+            assert_eq!(free_vars(body), expected);
+        }
+        _ => panic!("Expected a let expression."),
+    }
+}
+
+#[test]
+fn test_assigned_and_captured_vars() {
+    let exp = parse(
+        &lexpr::from_str(
+            r#"
+(let ((x 1) (y 2) (z 3))
+  (begin
+    (set! x 5)
+    (let ((f (lambda ((a : int)) : int (begin (set! a y) (+ a z)))))
+      (force (delay x)))))
+"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let to_vec = |vars: std::collections::BTreeSet<String>| vars.into_iter().collect::<Vec<_>>();
+    assert_eq!(to_vec(assigned_vars(&exp)), vec!["a", "x"]);
+    assert_eq!(to_vec(captured_vars(&exp)), vec!["x", "y", "z"]);
+}
+
+#[test]
+fn test_children() {
+    let exp = parse(&lexpr::from_str("(let ((x 1) (y 2)) (f x y))").unwrap()).unwrap();
+    let kids = children(&*exp.kind);
+    assert_eq!(kids.len(), 3);
+    assert_eq!(kids[2].to_string(), "(f x y)");
+    assert_eq!(children(&*kids[2].kind).len(), 3);
+    assert_eq!(children(&*kids[0].kind).len(), 0);
+}
+
+#[test]
+fn test_reachable_fns() {
+    let reachable = |source: &str| {
+        let exp = parse(&lexpr::from_str(source).unwrap()).unwrap();
+        // the optimizer removes unused bindings, but not their functions
+        let options = CompileOptions {
+            opt_level: OptLevel::O2,
+            ..CompileOptions::default()
+        };
+        let prog = compile_exp_with_options(&exp, &options).unwrap();
+        (reachable_fns(&prog).len(), prog.fns.len())
+    };
+    assert_eq!(
+        reachable(
+            "(let ((f (lambda ((x : int)) : int (+ x 1)))
+                   (g (lambda ((x : int)) : int (* x 2))))
+               (f 2))"
+        ),
+        (1, 2)
+    );
+    // functions reached through other functions are reachable
+    assert_eq!(
+        reachable(
+            "(let ((f (lambda ((x : int)) : int (+ x 1))))
+               (let ((g (lambda ((x : int)) : int (f x))))
+                 (g 2)))"
+        ),
+        (2, 2)
+    );
+    assert_eq!(
+        reachable("(let ((f (lambda ((x : int)) : int (+ x 1)))) 3)"),
+        (0, 1)
+    );
+}
+
+#[test]
+fn test_node_count() {
+    let exp = parse(&lexpr::from_str("(let ((x 1) (y 2)) (f x y))").unwrap()).unwrap();
+    assert_eq!(node_count(&exp), 7);
+    assert_eq!(
+        node_count(&type_check(&parse(&lexpr::from_str("(+ 1 2)").unwrap()).unwrap()).unwrap()),
+        3
+    );
+}
+
+#[test]
+fn test_binding_occurrences() {
+    let exp = parse(
+        &lexpr::from_str(
+            r#"
+(let ((x 1) (y 2))
+  (begin
+    (set! x (+ x y))
+    (let ((x 10)) (+ x z))))
+"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let occurrences = binding_occurrences(&exp);
+    let outer_x = Binder {
+        node: vec![],
+        index: 0,
+        name: String::from("x"),
+    };
+    let outer_y = Binder {
+        node: vec![],
+        index: 1,
+        name: String::from("y"),
+    };
+    let inner_x = Binder {
+        node: vec![2, 1],
+        index: 0,
+        name: String::from("x"),
+    };
+    assert_eq!(occurrences.len(), 3);
+    // the set! expression and the x in its value
+    assert_eq!(occurrences[&outer_x], vec![vec![2, 0], vec![2, 0, 0, 0]]);
+    assert_eq!(occurrences[&outer_y], vec![vec![2, 0, 0, 1]]);
+    // z is free, so it isn't included
+    assert_eq!(occurrences[&inner_x], vec![vec![2, 1, 1, 0]]);
+
+    // unused bindings are included with no uses, and binders in lambdas and
+    // typecase clauses are found
+    let exp = parse(
+        &lexpr::from_str(
+            r#"
+(typecase (inject 1 (union int bool))
+  ((int n) ((lambda ((a : int) (b : int)) : int a) n 2))
+  ((bool b) 0))
+"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let occurrences = binding_occurrences(&exp);
+    let uses: Vec<(String, usize)> = occurrences
+        .iter()
+        .map(|(binder, uses)| (binder.name.clone(), uses.len()))
+        .collect();
+    assert_eq!(
+        uses,
+        vec![
+            (String::from("n"), 1),
+            (String::from("b"), 0),
+            (String::from("a"), 1),
+            (String::from("b"), 0),
+        ]
+    );
+}
@@ -0,0 +1,62 @@
+use scheme_to_wasm::assignment_convert::assignment_convert;
+use scheme_to_wasm::common::dangerously_reset_gensym_count;
+use scheme_to_wasm::parse::parse;
+use scheme_to_wasm::type_check::type_check;
+use serial_test_derive::serial;
+
+fn parse_str(source: &str) -> scheme_to_wasm::common::Expr {
+    parse(&lexpr::from_str(source).unwrap()).unwrap()
+}
+
+#[test]
+fn test_assignment_convert_let() {
+    let exp = parse_str(
+        "(let ((x 0) (y 1)) (let ((f (lambda () : int (set! x (+ x y))))) (begin (f) x)))",
+    );
+    let expected_exp = parse_str(
+        r#"
+(let ((x (make-tuple 0)) (y 1))
+  (let ((f (lambda () : int (tuple-set! x 0 (+ (tuple-ref x 0) y)))))
+    (begin (f) (tuple-ref x 0))))
+"#,
+    );
+    let ac_exp = assignment_convert(&exp);
+    type_check(&ac_exp).unwrap();
+    assert_eq!(ac_exp, expected_exp);
+}
+
+#[test]
+#[serial]
+fn test_assignment_convert_params() {
+    dangerously_reset_gensym_count();
+
+    // a parameter which is assigned inside a nested lambda is boxed, but one
+    // which is only assigned in its own lambda is not
+    let exp = parse_str(
+        r#"
+(lambda ((a : int) (b : int)) : (-> int)
+  (begin
+    (set! b 2)
+    (lambda () : int (begin (set! a (+ a 1)) a))))
+"#,
+    );
+    let expected_exp = parse_str(
+        r#"
+(lambda ((temp0 : int) (b : int)) : (-> int)
+  (let ((a (make-tuple temp0)))
+    (begin
+      (set! b 2)
+      (lambda () : int (begin (tuple-set! a 0 (+ (tuple-ref a 0) 1)) (tuple-ref a 0))))))
+"#,
+    );
+    let ac_exp = assignment_convert(&exp);
+    type_check(&ac_exp).unwrap();
+    assert_eq!(ac_exp, expected_exp);
+}
+
+#[test]
+fn test_assignment_convert_unchanged() {
+    // variables which are captured but never assigned are left alone
+    let exp = parse_str("(let ((x 0)) (let ((f (lambda () : int x))) (begin (set! f f) (f))))");
+    assert_eq!(assignment_convert(&exp), exp);
+}
@@ -0,0 +1,139 @@
+#![cfg(feature = "fs")]
+
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_scheme-to-wasm"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+/// Writes a program to a file which is unique to this test process.
+fn write_program(name: &str, source: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("cli-{}-{}.scm", name, std::process::id()));
+    std::fs::write(&path, source).unwrap();
+    path
+}
+
+#[test]
+fn test_cli_eval() {
+    let path = write_program(
+        "eval",
+        "(let ((args (command-line)))
+           (if (null? args) (* 2 3) (string-length (car (cdr args)))))",
+    );
+    let path = path.to_str().unwrap();
+    let output = run(&["eval", path, "--arg", "a", "--arg", "bcd"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "3\n");
+
+    // the trace comes before the value
+    let output = run(&["eval", "--trace", path]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let printed = stdout(&output);
+    assert!(printed.lines().count() > 1, "{}", printed);
+    assert_eq!(printed.lines().last(), Some("6"));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_cli_compile() {
+    let path = write_program("compile", "(let ((x 5)) (* x x))");
+    let output_path = path.with_extension("wasm");
+    let output = run(&["compile", "-O2", path.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let binary = std::fs::read(&output_path).unwrap();
+    assert_eq!(&binary[0..4], b"\0asm");
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&output_path).unwrap();
+}
+
+#[test]
+fn test_cli_errors() {
+    // errors are printed with their codes
+    let path = write_program("errors", "(+ 1 true)");
+    let output = run(&["compile", path.to_str().unwrap()]);
+    assert_eq!(output.status.success(), false);
+    assert!(
+        stderr(&output).starts_with("error[T001]"),
+        "{}",
+        stderr(&output)
+    );
+    std::fs::remove_file(&path).unwrap();
+
+    let output = run(&["frobnicate"]);
+    assert_eq!(output.status.success(), false);
+    assert!(stderr(&output).contains("Usage:"), "{}", stderr(&output));
+    let output = run(&["eval", "--verbose", "main.scm"]);
+    assert_eq!(output.status.success(), false);
+}
+
+#[test]
+fn test_cli_fmt() {
+    let path = write_program("fmt", "(let ((x 1))\n      x)");
+    let path = path.to_str().unwrap();
+    let output = run(&["fmt", "--check", path]);
+    assert_eq!(output.status.success(), false);
+    assert!(stdout(&output).contains("is not formatted"));
+
+    let output = run(&["fmt", path]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "(let ((x 1)) x)\n");
+    let output = run(&["fmt", "--check", path]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_cli_ast() {
+    let path = write_program("ast", "(let* ((x 1)) (+ x 2 3))");
+    let path = path.to_str().unwrap();
+    let output = run(&["ast", path]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "(let ((x 1)) (+ (+ x 2) 3))\n");
+
+    let output = run(&["ast", path, "--stage", "typed", "--json"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stdout(&output).ends_with("\"type\": \"int\"}\n"),
+        "{}",
+        stdout(&output)
+    );
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_cli_explain() {
+    let output = run(&["explain", "T001"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).starts_with("T001: "));
+    let output = run(&["explain", "X999"]);
+    assert_eq!(output.status.success(), false);
+}
+
+#[test]
+fn test_cli_repl() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_scheme-to-wasm"))
+        .arg("repl")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"(+ 1 2)\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert!(stdout(&output).contains('3'), "{}", stdout(&output));
+}
@@ -235,13 +235,13 @@ fn test_closure_convert_lambda_with_func_param() {
       : (exists T7 (tuple (-> T7 int) T7))
       (pack
        (make-tuple
-        (lambda ((env2 : (record (f : (exists T3 (tuple (-> T3 int int) T3)))
-                                 (a : int))))
+        (lambda ((env2 : (record (a : int)
+                                 (f : (exists T3 (tuple (-> T3 int int) T3))))))
           : int
           (unpack (temp0 (record-ref env2 f) T1)
                   ((tuple-ref temp0 0) (tuple-ref temp0 1) (record-ref env2 a))))
-        (make-record (f f) (a (record-ref env5 a))))
-       (record (f : (exists T3 (tuple (-> T3 int int) T3))) (a : int))
+        (make-record (a (record-ref env5 a)) (f f)))
+       (record (a : int) (f : (exists T3 (tuple (-> T3 int int) T3))))
        (exists T4 (tuple (-> T4 int) T4))))
     (make-record (a a)))
    (record (a : int))
@@ -0,0 +1,171 @@
+use scheme_to_wasm::compile::compile_exp;
+use scheme_to_wasm::desugar::desugar;
+use scheme_to_wasm::explain::error_code;
+use scheme_to_wasm::generate_code::construct_module_from_prog;
+use scheme_to_wasm::interpret::{eval_source, EvalOptions};
+use scheme_to_wasm::macro_expand::macro_expand;
+use scheme_to_wasm::parse::parse;
+use scheme_to_wasm::read::read;
+use scheme_to_wasm::types::Type;
+use scheme_to_wasm::util::format_float;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use wasmer_runtime::{imports, instantiate, Value};
+
+// The conformance tests are a specification of the language: each program
+// under tests/conformance states the behavior required of it with a comment,
+// either
+//
+//     ;; expect: <value>
+//
+// for a program which evaluates to the printed value, both when interpreted
+// and when compiled (unless compiling it is unsupported, see C001), or
+//
+//     ;; expect-error: <code>
+//
+// for a program which is rejected with the error code (see `explain`). Parse
+// and type errors are also checked with the interpreter, but code generation
+// errors only apply to compiled programs.
+
+#[derive(Debug, PartialEq)]
+enum Expectation {
+    Value(String),
+    Error(String),
+}
+
+/// Finds the single expectation in a conformance test's source.
+fn expectation(source: &str) -> Result<Expectation, String> {
+    let mut expectations = vec![];
+    for line in source.lines() {
+        let line = line.trim();
+        if let Some(value) = line.strip_prefix(";; expect:") {
+            expectations.push(Expectation::Value(String::from(value.trim())));
+        } else if let Some(code) = line.strip_prefix(";; expect-error:") {
+            expectations.push(Expectation::Error(String::from(code.trim())));
+        }
+    }
+    match expectations.len() {
+        1 => Ok(expectations.remove(0)),
+        0 => Err(String::from("no expect or expect-error comment")),
+        _ => Err(String::from("more than one expect or expect-error comment")),
+    }
+}
+
+/// Describes an error by its code, or its message if it has no code.
+fn describe_error(err: &(dyn Error + 'static)) -> String {
+    match error_code(err) {
+        Some(code) => String::from(code),
+        None => err.to_string(),
+    }
+}
+
+/// Runs a program through the whole pipeline, from source code to wasm, and
+/// returns its result printed the same way as the interpreter prints values.
+fn run_compiled(source: &str) -> Result<String, Box<dyn Error>> {
+    let exp = parse(&desugar(&macro_expand(&read(source)?)?)?)?;
+    let prog = compile_exp(&exp)?;
+    let binary = parity_wasm::serialize(construct_module_from_prog(&prog)?)?;
+    let instance = instantiate(&binary, &imports! {}).map_err(|err| err.to_string())?;
+    let values = instance
+        .dyn_func("$$MAIN$$")
+        .map_err(|err| err.to_string())?
+        .call(&[])
+        .map_err(|err| err.to_string())?;
+    match (&prog.exp.typ, values.as_slice()) {
+        (Type::Int, [Value::I32(x)]) => Ok(x.to_string()),
+        (Type::Bool, [Value::I32(x)]) => Ok((*x != 0).to_string()),
+        (Type::Float, [Value::I32(x)]) => Ok(format_float(f32::from_bits(*x as u32))),
+        (typ, values) => Err(format!("Cannot print {:?} of type {}", values, typ).into()),
+    }
+}
+
+/// Checks a conformance test, returning a description of each way in which
+/// the program doesn't behave as expected.
+fn check(source: &str) -> Vec<String> {
+    let expected = match expectation(source) {
+        Ok(expected) => expected,
+        Err(err) => return vec![err],
+    };
+    let mut failures = vec![];
+    let interpreted = eval_source(source, &EvalOptions::default());
+    let compiled = run_compiled(source);
+    match &expected {
+        Expectation::Value(value) => {
+            match interpreted {
+                Ok(result) if result == *value => (),
+                Ok(result) => failures.push(format!("interpreted to {}", result)),
+                Err(err) => failures.push(format!("interpreter failed: {}", err)),
+            }
+            match compiled {
+                Ok(result) if result == *value => (),
+                Ok(result) => failures.push(format!("compiled to {}", result)),
+                Err(err) if error_code(err.as_ref()) == Some("C001") => (),
+                Err(err) => failures.push(format!("compiled program failed: {}", err)),
+            }
+        }
+        Expectation::Error(code) => {
+            if !code.starts_with('C') {
+                match interpreted {
+                    Ok(result) => failures.push(format!("interpreted to {}", result)),
+                    Err(err) if describe_error(err.as_ref()) == *code => (),
+                    Err(err) => failures.push(format!("interpreter failed with {}", err)),
+                }
+            }
+            match compiled {
+                Ok(result) => failures.push(format!("compiled to {}", result)),
+                Err(err) if describe_error(err.as_ref()) == *code => (),
+                Err(err) => failures.push(format!("compiler failed with {}", err)),
+            }
+        }
+    }
+    failures
+}
+
+fn conformance_tests() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("conformance");
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension() == Some("scm".as_ref()))
+        .collect();
+    paths.sort();
+    paths
+}
+
+#[test]
+fn test_expectations() {
+    assert_eq!(
+        expectation(";; a comment\n;; expect:  (1 2)\n(cons 1 (cons 2 (null int)))"),
+        Ok(Expectation::Value(String::from("(1 2)")))
+    );
+    assert_eq!(
+        expectation(";; expect-error: T001\n(if 0 1 2)"),
+        Ok(Expectation::Error(String::from("T001")))
+    );
+    assert_eq!(expectation("(+ 1 2)").is_err(), true);
+    assert_eq!(
+        expectation(";; expect: 1\n;; expect: 2\n(+ 1 2)").is_err(),
+        true
+    );
+
+    // the checker itself notices programs which don't behave as expected
+    assert_eq!(check(";; expect: 3\n(+ 1 2)"), Vec::<String>::new());
+    assert_eq!(check(";; expect: 4\n(+ 1 2)").len(), 2);
+    assert_eq!(check(";; expect-error: T002\n(if 0 1 2)").len(), 2);
+}
+
+#[test]
+fn test_conformance() {
+    let paths = conformance_tests();
+    assert_eq!(paths.is_empty(), false);
+    let mut failures = vec![];
+    for path in paths {
+        let source = std::fs::read_to_string(&path).unwrap();
+        for failure in check(&source) {
+            failures.push(format!("{}: {}", path.display(), failure));
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
@@ -0,0 +1,3 @@
+;; Int arithmetic wraps around on overflow, like wasm's i32 instructions.
+;; expect: 2147483647
+(- -2147483648 1)
@@ -0,0 +1,10 @@
+;; A case expression runs the clause listing the key's value, or the else
+;; clause if no clause does.
+;; expect: 321
+(let ((digit (lambda ((n : int)) : int
+               (case n
+                 ((1 2 3) 1)
+                 ((4 5) 2)
+                 ((6) 3)
+                 (else 0)))))
+  (+ (* 100 (digit 6)) (+ (* 10 (digit 4)) (digit 2))))
@@ -0,0 +1,7 @@
+;; Closures capture variables by reference, so they see later assignments.
+;; expect: 11
+(let ((x 1))
+  (let ((f (lambda () : int x)))
+    (begin
+      (set! x 10)
+      (+ (f) 1))))
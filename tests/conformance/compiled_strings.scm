@@ -0,0 +1,3 @@
+;; Strings can be evaluated, but not yet compiled.
+;; expect-error: C001
+(concat "a" "b")
@@ -0,0 +1,3 @@
+;; Dividing a float by zero gives an infinity rather than trapping.
+;; expect: true
+(infinite? (/ 1.0 0.0))
@@ -0,0 +1,3 @@
+;; round rounds halfway cases to even.
+;; expect: -2.0
+(round -2.5)
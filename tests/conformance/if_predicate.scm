@@ -0,0 +1,3 @@
+;; The predicate of an if expression must be a bool; there is no truthiness.
+;; expect-error: T001
+(if 0 1 2)
@@ -0,0 +1,3 @@
+;; Integer literals must fit in 32 bits.
+;; expect-error: P002
+(+ 2147483648 1)
@@ -0,0 +1,5 @@
+;; Bindings in a let can't see each other, but its body can see them all.
+;; expect: 4
+(let ((x 1) (y 2))
+  (let ((x y))
+    (+ x y)))
@@ -0,0 +1,2 @@
+;; expect-error: T006
+(/ 10 0)
@@ -0,0 +1,3 @@
+;; Ints and floats are never converted implicitly.
+;; expect-error: T002
+(* 2 0.5)
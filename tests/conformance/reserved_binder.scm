@@ -0,0 +1,3 @@
+;; The names of forms can't be bound as variables.
+;; expect-error: P003
+(let ((if 1)) if)
@@ -0,0 +1,3 @@
+;; Strings are indexed by Unicode scalar values, not bytes.
+;; expect: 5
+(string-length "héllo")
@@ -0,0 +1,2 @@
+;; expect-error: T004
+(let ((x 1)) (+ x y))
@@ -0,0 +1,7 @@
+;; u<, u>, u/ and u% treat ints as 32-bit unsigned integers, e.g. for
+;; reducing a hash (which may be negative as a signed int) to a bucket index.
+;; expect: 5
+(let ((hash (* 65535 65537)))
+  (if (and (u> hash 0) (u< 0 hash))
+      (u% hash (u/ 20 2))
+      -1))
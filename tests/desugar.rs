@@ -0,0 +1,230 @@
+use scheme_to_wasm::desugar::{desugar, desugar_with_features};
+use scheme_to_wasm::interpret::{eval_source, EvalOptions};
+use scheme_to_wasm::parse::parse;
+use scheme_to_wasm::type_check::type_check;
+use scheme_to_wasm::types::{unit_type, Type};
+use std::collections::BTreeSet;
+
+fn desugar_str(source: &str) -> Result<lexpr::Value, scheme_to_wasm::desugar::DesugarError> {
+    desugar(&lexpr::from_str(source).unwrap())
+}
+
+#[test]
+fn test_desugar_let_star() {
+    let exp = desugar_str("(let* ((x 1) (y (+ x 1))) (* x y))").unwrap();
+    assert_eq!(
+        exp,
+        lexpr::from_str("(let ((x 1)) (let ((y (+ x 1))) (* x y)))").unwrap()
+    );
+    let exp = desugar_str("(let* () 5)").unwrap();
+    assert_eq!(exp, lexpr::from_str("(let () 5)").unwrap());
+    assert_eq!(desugar_str("(let* ((x 1)))").is_err(), true);
+    assert_eq!(desugar_str("(let* x 1)").is_err(), true);
+}
+
+#[test]
+fn test_desugar_cond() {
+    let exp = desugar_str("(cond ((< x 0) -1) ((= x 0) 0) (else 1))").unwrap();
+    assert_eq!(
+        exp,
+        lexpr::from_str("(if (< x 0) -1 (if (= x 0) 0 1))").unwrap()
+    );
+    let exp = desugar_str("(cond (else 1))").unwrap();
+    assert_eq!(exp, lexpr::from_str("1").unwrap());
+    assert_eq!(desugar_str("(cond)").is_err(), true);
+    assert_eq!(desugar_str("(cond ((< x 0) -1))").is_err(), true);
+    assert_eq!(desugar_str("(cond (else 1) ((< x 0) -1))").is_err(), true);
+    assert_eq!(desugar_str("(cond ((< x 0) -1 2) (else 1))").is_err(), true);
+}
+
+#[test]
+fn test_desugar_cond_expand() {
+    let exp = desugar_str("(cond-expand (wasm (let* ((x 1)) x)) (else 0))").unwrap();
+    assert_eq!(exp, lexpr::from_str("(let ((x 1)) x)").unwrap());
+    let exp = desugar_str("(cond-expand (wasi 1) (else 2))").unwrap();
+    assert_eq!(exp, lexpr::from_str("2").unwrap());
+
+    let features: BTreeSet<String> = vec![String::from("wasi")].into_iter().collect();
+    let desugar_features =
+        |source: &str| desugar_with_features(&lexpr::from_str(source).unwrap(), &features);
+    let source = "(cond-expand (wasi 1) (browser 2) (else 3))";
+    assert_eq!(
+        desugar_features(source).unwrap(),
+        lexpr::from_str("1").unwrap()
+    );
+    let source = "(cond-expand ((and wasm (not wasi)) 1) ((or browser wasi) 2))";
+    assert_eq!(
+        desugar_features(source).unwrap(),
+        lexpr::from_str("2").unwrap()
+    );
+    // clauses which aren't selected aren't desugared
+    let source = "(+ 1 (cond-expand (browser (let* x 1)) (wasi (+ 2 3 4))))";
+    assert_eq!(
+        desugar_features(source).unwrap(),
+        lexpr::from_str("(+ 1 (+ (+ 2 3) 4))").unwrap()
+    );
+
+    assert_eq!(desugar_str("(cond-expand (browser 1))").is_err(), true);
+    assert_eq!(
+        desugar_str("(cond-expand (else 1) (wasm 2))").is_err(),
+        true
+    );
+    assert_eq!(desugar_str("(cond-expand (wasm 1 2))").is_err(), true);
+    assert_eq!(desugar_str("(cond-expand ((xor wasm) 1))").is_err(), true);
+}
+
+#[test]
+fn test_desugar_begin0() {
+    let exp = desugar_str("(begin0 x (set! x 5) (set! y 6))").unwrap();
+    assert_eq!(
+        exp,
+        lexpr::from_str("(let ((begin0-value x)) (begin (set! x 5) (set! y 6) begin0-value))")
+            .unwrap()
+    );
+    // the variable holding the result doesn't capture any other variable
+    let exp = desugar_str("(begin0 1 (+ begin0-value begin0-value1))").unwrap();
+    assert_eq!(
+        exp,
+        lexpr::from_str(
+            "(let ((begin0-value2 1)) (begin (+ begin0-value begin0-value1) begin0-value2))"
+        )
+        .unwrap()
+    );
+    let exp = desugar_str("(begin0 5)").unwrap();
+    assert_eq!(exp, lexpr::from_str("5").unwrap());
+    assert_eq!(desugar_str("(begin0)").is_err(), true);
+}
+
+#[test]
+fn test_desugar_for_each() {
+    let exp = desugar_str("(for-each f (cons 1 (null int)))").unwrap();
+    assert_eq!(
+        exp,
+        lexpr::from_str(
+            r#"
+(let ((for-each-func f) (for-each-list (cons 1 (null int))))
+  (while (if (null? for-each-list) false true)
+    (begin (for-each-func (car for-each-list))
+           (set! for-each-list (cdr for-each-list)))))
+"#
+        )
+        .unwrap()
+    );
+    let exp = desugar_str(
+        "(let ((lst (cons 1 (null int)))) (for-each (lambda ((x : int)) : unit (make-tuple)) lst))",
+    )
+    .unwrap();
+    assert_eq!(type_check(&parse(&exp).unwrap()).unwrap().typ, unit_type());
+
+    // the function must accept the elements of the list
+    let exp =
+        desugar_str("(for-each (lambda ((x : bool)) : unit (make-tuple)) (cons 1 (null int)))")
+            .unwrap();
+    assert_eq!(type_check(&parse(&exp).unwrap()).is_err(), true);
+    assert_eq!(desugar_str("(for-each f)").is_err(), true);
+}
+
+#[test]
+fn test_desugar_when() {
+    let exp = desugar_str("(when (< x 0) (set! x 0) (set! y 1))").unwrap();
+    assert_eq!(
+        exp,
+        lexpr::from_str("(if (< x 0) (begin (set! x 0) (set! y 1) (make-tuple)) (make-tuple))")
+            .unwrap()
+    );
+    let exp = desugar_str("(unless (< x 0) (set! x 0))").unwrap();
+    assert_eq!(
+        exp,
+        lexpr::from_str("(if (< x 0) (make-tuple) (begin (set! x 0) (make-tuple)))").unwrap()
+    );
+    let exp = desugar_str("(let ((x 1)) (when (> x 0) (set! x 5)))").unwrap();
+    assert_eq!(type_check(&parse(&exp).unwrap()).unwrap().typ, unit_type());
+    assert_eq!(desugar_str("(when (< x 0))").is_err(), true);
+    assert_eq!(desugar_str("(unless)").is_err(), true);
+}
+
+#[test]
+fn test_desugar_named_let() {
+    let source = "
+(let loop ((i : int 0) (acc : int 1)) : int
+  (if (< i 5) (loop (+ i 1) (* acc 2)) acc))";
+    let exp = desugar_str(source).unwrap();
+    assert_eq!(
+        exp,
+        lexpr::from_str(
+            r#"
+(let ((i 0) (acc 1))
+  (let ((loop (if true
+                  (lambda ((i : int) (acc : int)) : int (car (null int)))
+                  (car (null (-> int int int))))))
+    (begin (set! loop (lambda ((i : int) (acc : int)) : int
+                        (if (< i 5) (loop (+ i 1) (* acc 2)) acc)))
+           (loop i acc))))
+"#
+        )
+        .unwrap()
+    );
+    assert_eq!(type_check(&parse(&exp).unwrap()).unwrap().typ, Type::Int);
+    assert_eq!(eval_source(source, &EvalOptions::default()).unwrap(), "32");
+
+    // the body can mutate, and the initial values can't see the loop
+    let source = "
+(let ((total 0) (loop 10))
+  (begin
+    (let loop ((n : int loop)) : unit
+      (when (> n 0) (set! total (+ total n)) (loop (- n 1))))
+    total))";
+    assert_eq!(eval_source(source, &EvalOptions::default()).unwrap(), "55");
+
+    assert_eq!(
+        desugar_str("(let loop ((i : int 0)) (loop i))").is_err(),
+        true
+    );
+    assert_eq!(
+        desugar_str("(let loop ((i 0)) : int (loop i))").is_err(),
+        true
+    );
+    assert_eq!(
+        desugar_str("(let loop ((loop : int 0)) : int 0)").is_err(),
+        true
+    );
+}
+
+#[test]
+fn test_desugar_variadic() {
+    let exp = desugar_str("(+ 1 2 3 4)").unwrap();
+    assert_eq!(exp, lexpr::from_str("(+ (+ (+ 1 2) 3) 4)").unwrap());
+    let exp = desugar_str("(- 10 2 3)").unwrap();
+    assert_eq!(exp, lexpr::from_str("(- (- 10 2) 3)").unwrap());
+    let exp = desugar_str("(- x)").unwrap();
+    assert_eq!(exp, lexpr::from_str("(- 0 x)").unwrap());
+    let exp = desugar_str("(* x)").unwrap();
+    assert_eq!(exp, lexpr::from_str("x").unwrap());
+    let exp = desugar_str("(and)").unwrap();
+    assert_eq!(exp, lexpr::from_str("true").unwrap());
+    let exp = desugar_str("(or a b c)").unwrap();
+    assert_eq!(exp, lexpr::from_str("(or (or a b) c)").unwrap());
+    assert_eq!(desugar_str("(-)").is_err(), true);
+    assert_eq!(desugar_str("(concat)").is_err(), true);
+
+    // binary uses and other forms are left alone
+    let exp = desugar_str("(let ((x (+ 1 2))) (< x 3 4))").unwrap();
+    assert_eq!(
+        exp,
+        lexpr::from_str("(let ((x (+ 1 2))) (< x 3 4))").unwrap()
+    );
+}
+
+#[test]
+fn test_desugar_nested() {
+    let exp = desugar_str(
+        r#"
+(let* ((x (+ 1 2 3))
+       (y (cond ((< x 5) (* x 2 2)) (else (- x)))))
+  (+ x y 1))
+"#,
+    )
+    .unwrap();
+    let exp = parse(&exp).unwrap();
+    assert_eq!(type_check(&exp).unwrap().typ, Type::Int);
+}
@@ -0,0 +1,55 @@
+use scheme_to_wasm::dump::{dump_ast, DumpFormat, DumpStage};
+
+#[test]
+fn test_dump_stages() {
+    let source = r#"
+(begin
+  (define-constant limit 10)
+  (let ((x #x10)) (< x limit)))
+"#;
+    let output = dump_ast(source, DumpStage::Expanded, DumpFormat::SExpr).unwrap();
+    assert_eq!(output, "(begin (let ((x 16)) (< x 10)))\n");
+
+    let output = dump_ast(source, DumpStage::Parsed, DumpFormat::SExpr).unwrap();
+    assert_eq!(output, "(begin (let ((x 16)) (< x 10)))\n");
+
+    let output = dump_ast(source, DumpStage::Typed, DumpFormat::SExpr).unwrap();
+    assert_eq!(output, "(: (begin (let ((x 16)) (< x 10))) bool)\n");
+
+    // errors from any stage are reported
+    let output = dump_ast("(+ 1 #t)", DumpStage::Typed, DumpFormat::SExpr);
+    assert_eq!(output.is_err(), true);
+    let output = dump_ast("(+ 1 #t)", DumpStage::Parsed, DumpFormat::SExpr);
+    assert_eq!(output.is_ok(), true);
+
+    // sugar is only rewritten from the desugared stage on
+    let output = dump_ast("(+ 1 2 3)", DumpStage::Expanded, DumpFormat::SExpr).unwrap();
+    assert_eq!(output, "(+ 1 2 3)\n");
+    let output = dump_ast("(+ 1 2 3)", DumpStage::Desugared, DumpFormat::SExpr).unwrap();
+    assert_eq!(output, "(+ (+ 1 2) 3)\n");
+}
+
+#[test]
+fn test_dump_json() {
+    let output = dump_ast(
+        r#"(let ((s "hi\n")) (+ 1 2))"#,
+        DumpStage::Parsed,
+        DumpFormat::Json,
+    )
+    .unwrap();
+    assert_eq!(
+        output,
+        "[\"let\", [[\"s\", {\"string\": \"hi\\n\"}]], [\"+\", 1, 2]]\n"
+    );
+
+    let output = dump_ast(
+        "(lambda ((x : int)) : int x)",
+        DumpStage::Typed,
+        DumpFormat::Json,
+    )
+    .unwrap();
+    assert_eq!(
+        output,
+        "{\"exp\": [\"lambda\", [[\"x\", \":\", \"int\"]], \":\", \"int\", \"x\"], \"type\": [\"pure->\", \"int\", \"int\"]}\n"
+    );
+}
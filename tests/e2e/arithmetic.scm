@@ -0,0 +1,4 @@
+; Arithmetic folds from the left, and overflow wraps around
+(let* ((x (+ 1 2 3))
+       (y (* x x)))
+  (- y (- 4 2) 2147483647 2147483647))
@@ -0,0 +1,4 @@
+(let ((x 7))
+  (cond ((< x 5) false)
+        ((= x 7) true)
+        (else false)))
@@ -0,0 +1,4 @@
+; The count is updated through a box, since the closure assigns to it
+(let ((count 0))
+  (let ((next (lambda () : int (begin (set! count (+ count 1)) count))))
+    (begin (next) (next) (* (next) 10))))
@@ -0,0 +1,3 @@
+(let ((sub (lambda ((x : int) (y : int)) : int (- x y)))
+      (double (lambda ((x : int)) : int (* x 2))))
+  ((compose double (curry sub 10)) 4))
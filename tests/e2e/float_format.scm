@@ -0,0 +1,4 @@
+; Floats print as the shortest decimal which reads back as the same float,
+; without scientific notation even for very small numbers
+(let ((third (/ 1.0 3.0)))
+  (* (* third third) (/ third 1000000.0)))
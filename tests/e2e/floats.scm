@@ -0,0 +1,6 @@
+; Float arithmetic, with explicit conversions from and to ints
+(let* ((area (lambda ((r : float)) : float (* 3.25 (* r r))))
+       (total (+ (area 2.0) (exact->inexact 7))))
+  (if (and (> total 19.5) (= (inexact->exact total) 20))
+      (/ total -4.0)
+      0.0))
@@ -0,0 +1,9 @@
+; Sums a list with a while loop
+(let ((lst (cons 1 (cons 2 (cons 3 (cons 4 (null int))))))
+      (sum 0))
+  (begin
+    (while (if (null? lst) false true)
+      (begin
+        (set! sum (+ sum (car lst)))
+        (set! lst (cdr lst))))
+    sum))
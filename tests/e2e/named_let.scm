@@ -0,0 +1,7 @@
+; The loop is a recursive function, which is assigned to its variable
+(let ((total 0))
+  (begin
+    (let loop ((n : int 10)) : unit
+      (when (> n 0) (set! total (+ total n)) (loop (- n 1))))
+    (let fact ((n : int 5) (acc : int 1)) : int
+      (if (= n 0) (+ acc total) (fact (- n 1) (* acc n))))))
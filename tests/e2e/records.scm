@@ -0,0 +1,3 @@
+(let ((point (make-record (x 3) (y 4))))
+  (+ (* (record-ref point x) (record-ref point x))
+     (* (record-ref point y) (record-ref point y))))
@@ -0,0 +1,6 @@
+(let ((describe (lambda ((val : (union int bool))) : int
+                  (typecase val
+                    ((int x) (+ x 1))
+                    ((bool b) (if b 100 200))))))
+  (+ (describe (inject 41 (union int bool)))
+     (describe (inject false (union int bool)))))
@@ -0,0 +1,64 @@
+use scheme_to_wasm::effects::{infer_effect, is_pure};
+use scheme_to_wasm::parse::parse;
+use scheme_to_wasm::type_check::type_check;
+use scheme_to_wasm::types::{Effect, Type};
+
+#[test]
+fn test_effects_pure() {
+    let exp = lexpr::from_str("(+ 1 (* 2 3))").unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(is_pure(&typed_exp), true);
+
+    // dividing by a constant which can't trap
+    let exp = lexpr::from_str("(let ((x 7)) (+ (/ x 2) (u% x -1)))").unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(is_pure(&typed_exp), true);
+
+    // creating a lambda doesn't evaluate its body
+    let exp = lexpr::from_str("(let ((x 3)) (lambda () : int (set! x 4)))").unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(is_pure(&typed_exp), true);
+
+    // assigning to a lambda's own parameters or locals is not observable
+    let exp = lexpr::from_str(
+        "(lambda ((x : int)) : int (let ((y 1)) (begin (set! x 2) (set! y 3) (+ x y))))",
+    )
+    .unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    match typed_exp.typ {
+        Type::Func(_, _, effect) => assert_eq!(effect, Effect::Pure),
+        _ => panic!("Expected a function type."),
+    }
+}
+
+#[test]
+fn test_effects_mutates() {
+    let exp = lexpr::from_str("(let ((x 3)) (begin (set! x 4) x))").unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(infer_effect(&typed_exp), Effect::Pure);
+
+    // dividing by zero, or -2147483648 by -1, traps
+    let exp = lexpr::from_str("(let ((x 7)) (/ 7 x))").unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(infer_effect(&typed_exp), Effect::Mutates);
+    let exp = lexpr::from_str("(let ((x 7)) (/ x -1))").unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(infer_effect(&typed_exp), Effect::Mutates);
+    // but dividing floats doesn't
+    let exp = lexpr::from_str("(let ((x 7.0)) (/ 7.0 x))").unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(infer_effect(&typed_exp), Effect::Pure);
+
+    let exp =
+        lexpr::from_str("(let ((x 3)) (let ((f (lambda () : int (set! x 4)))) (f)))").unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(infer_effect(&typed_exp), Effect::Mutates);
+
+    // calling a function which may have any effect
+    let exp = lexpr::from_str("(lambda ((f : (-> int))) : int (f))").unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    match typed_exp.typ {
+        Type::Func(_, _, effect) => assert_eq!(effect, Effect::Mutates),
+        _ => panic!("Expected a function type."),
+    }
+}
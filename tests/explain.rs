@@ -0,0 +1,102 @@
+use scheme_to_wasm::compile::compile_exp;
+use scheme_to_wasm::desugar::desugar;
+use scheme_to_wasm::explain::{explain, EXPLANATIONS};
+use scheme_to_wasm::generate_code::construct_module_from_prog;
+use scheme_to_wasm::macro_expand::macro_expand;
+use scheme_to_wasm::parse::parse;
+use scheme_to_wasm::read::read;
+use scheme_to_wasm::type_check::type_check;
+use std::collections::BTreeSet;
+use std::error::Error;
+
+fn compile_source(source: &str) -> Result<(), Box<dyn Error>> {
+    let exp = parse(&desugar(&macro_expand(&read(source)?)?)?)?;
+    type_check(&exp)?;
+    // compiling checks types again, for the wasm target
+    construct_module_from_prog(&compile_exp(&exp)?)?;
+    Ok(())
+}
+
+/// The code of the first error found when compiling the source.
+fn error_code(source: &str) -> Option<&'static str> {
+    compile_source(source).err().map(|err| {
+        scheme_to_wasm::explain::error_code(err.as_ref())
+            .unwrap_or_else(|| panic!("Error has no code: {}", err))
+    })
+}
+
+#[test]
+fn test_explain() {
+    let explanation = explain("T001").unwrap();
+    assert_eq!(explanation.title, "Type mismatch");
+    assert_eq!(explain("t001"), Some(explanation));
+    assert_eq!(
+        explanation
+            .to_string()
+            .starts_with("T001: Type mismatch\n\n"),
+        true
+    );
+    assert_eq!(explain("T999"), None);
+
+    let codes = EXPLANATIONS
+        .iter()
+        .map(|explanation| explanation.code)
+        .collect::<BTreeSet<&str>>();
+    assert_eq!(codes.len(), EXPLANATIONS.len());
+}
+
+#[test]
+fn test_explanation_examples() {
+    // every example which is a program causes an error with its code, and
+    // its fix doesn't
+    for explanation in EXPLANATIONS.iter() {
+        if !explanation.example.starts_with('(') || explanation.example.starts_with("(generated") {
+            continue;
+        }
+        assert_eq!(
+            error_code(explanation.example),
+            Some(explanation.code),
+            "{}",
+            explanation.example
+        );
+        let fixed = &explanation.fix[explanation.fix.find(": (").map_or(0, |i| i + 2)..];
+        if fixed.starts_with('(') {
+            assert_eq!(error_code(fixed), None, "{}", fixed);
+        }
+    }
+}
+
+#[test]
+fn test_error_codes() {
+    let cases = vec![
+        ("(let ((x 1)))", Some("P001")),
+        ("(let ((if 1)) if)", Some("P003")),
+        ("(null (list))", Some("P004")),
+        ("(tuple-ref (make-tuple 1) (/ 1 0))", Some("P005")),
+        (
+            "(let ((f (lambda ((x : int)) : int x))) (f true))",
+            Some("T001"),
+        ),
+        ("(curry (lambda ((x : int)) : int x) 1 2)", Some("T003")),
+        ("(let ((x 1)) (set! y 2))", Some("T004")),
+        ("(curry 1 2)", Some("T005")),
+        ("(tuple-ref (make-tuple 1) 3)", Some("T007")),
+        (r#"(write-file "out.txt" "hello")"#, Some("T008")),
+        (
+            "(let ((f (lambda ((r : (record (x : int)))) : int 0)))
+               (f (make-record (x true))))",
+            Some("T001"),
+        ),
+        ("(concat \"a\" \"b", Some("R001")),
+        ("(+ 1 #| 2)", Some("R001")),
+        (
+            "(begin (define-syntax f (syntax-rules ())) (f))",
+            Some("M001"),
+        ),
+        ("(cond ((< 1 2) 3))", Some("P001")),
+        ("(+ 1 2)", None),
+    ];
+    for (source, code) in cases {
+        assert_eq!(error_code(source), code, "{}", source);
+    }
+}
@@ -0,0 +1,86 @@
+use scheme_to_wasm::compile::{compile_exp_with_options, CompileOptions};
+use scheme_to_wasm::desugar::desugar_with_features;
+use scheme_to_wasm::features::FeatureFlags;
+use scheme_to_wasm::macro_expand::macro_expand;
+use scheme_to_wasm::optimize::OptLevel;
+use scheme_to_wasm::parse::parse;
+
+#[test]
+fn test_define_features() {
+    let mut flags = FeatureFlags::new();
+    flags.define("debug").unwrap();
+    flags.define("log-level=3").unwrap();
+    flags.define("name=app=1").unwrap();
+    flags.define("empty=").unwrap();
+    assert_eq!(
+        flags.features().into_iter().collect::<Vec<String>>(),
+        vec!["debug", "empty", "log-level", "name"]
+    );
+    assert_eq!(flags.value("debug"), None);
+    assert_eq!(flags.value("log-level"), Some(&lexpr::Value::from(3)));
+    assert_eq!(flags.value("name"), Some(&lexpr::Value::string("app=1")));
+    assert_eq!(flags.value("empty"), Some(&lexpr::Value::string("")));
+    assert_eq!(flags.value("missing"), None);
+
+    // redefining a feature replaces its value
+    flags.define("log-level=1").unwrap();
+    assert_eq!(flags.value("log-level"), Some(&lexpr::Value::from(1)));
+
+    for definition in &["", "=1", "two words", "(x)=1", "5=5"] {
+        assert_eq!(flags.define(definition).is_err(), true, "{}", definition);
+    }
+}
+
+#[test]
+fn test_bind_features() {
+    let program = lexpr::from_str("(if (> log-level 2) (string-length name) 0)").unwrap();
+    let mut flags = FeatureFlags::new();
+    assert_eq!(flags.bind(&program), program);
+    flags.define("debug").unwrap();
+    assert_eq!(flags.bind(&program), program);
+
+    flags.define("log-level=3").unwrap();
+    flags.define("name=app").unwrap();
+    assert_eq!(
+        flags.bind(&program),
+        lexpr::from_str(
+            r#"(begin
+  (define-constant log-level 3)
+  (define-constant name "app")
+  (if (> log-level 2) (string-length name) 0))"#
+        )
+        .unwrap()
+    );
+    assert_eq!(
+        macro_expand(&flags.bind(&program)).unwrap(),
+        lexpr::from_str(r#"(begin (if (> 3 2) (string-length "app") 0))"#).unwrap()
+    );
+}
+
+#[test]
+fn test_compile_with_features() {
+    let program =
+        lexpr::from_str("(cond-expand (debug (if (> log-level 2) 10 20)) (else (+ log-level 1)))")
+            .unwrap();
+    let compile = |definitions: &[&str]| {
+        let mut flags = FeatureFlags::new();
+        for definition in definitions {
+            flags.define(definition).unwrap();
+        }
+        let value = desugar_with_features(
+            &macro_expand(&flags.bind(&program)).unwrap(),
+            &flags.features(),
+        )
+        .unwrap();
+        let options = CompileOptions {
+            opt_level: OptLevel::O1,
+            ..CompileOptions::default()
+        };
+        let prog = compile_exp_with_options(&parse(&value).unwrap(), &options).unwrap();
+        format!("{}", prog.exp)
+    };
+    // the constants are folded by the optimizer
+    assert_eq!(compile(&["debug", "log-level=3"]), "(begin 10)");
+    assert_eq!(compile(&["debug", "log-level=1"]), "(begin 20)");
+    assert_eq!(compile(&["log-level=1"]), "(begin 2)");
+}
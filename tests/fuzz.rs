@@ -0,0 +1,165 @@
+use scheme_to_wasm::compile::compile_exp;
+use scheme_to_wasm::generate_code::construct_module_from_prog;
+use scheme_to_wasm::parse::parse;
+use scheme_to_wasm::type_check::type_check;
+use wasmer_runtime::{imports, instantiate};
+
+/// A small xorshift generator, so that the generated programs are the same on
+/// every run (and failures can be reproduced from the seed).
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+/// Generates random programs which are well-scoped (every variable is bound)
+/// but which may be ill-typed.
+///
+/// Division is never generated, since dividing by zero traps at runtime. The
+/// bodies of lambdas can only refer to their own parameter, since closures
+/// which capture variables aren't fully supported by code generation.
+struct ProgramGenerator {
+    rng: Rng,
+    next_var: u32,
+}
+
+impl ProgramGenerator {
+    fn gen_exp(&mut self, depth: u32, scope: &[String]) -> String {
+        if depth == 0 {
+            return self.gen_atom(scope);
+        }
+        match self.rng.below(9) {
+            0 => {
+                let ops = ["+", "-", "*", "<", ">", "<=", ">=", "=", "and", "or"];
+                let op = ops[self.rng.below(ops.len())];
+                let arg1 = self.gen_exp(depth - 1, scope);
+                let arg2 = self.gen_exp(depth - 1, scope);
+                format!("({} {} {})", op, arg1, arg2)
+            }
+            1 => {
+                let pred = self.gen_exp(depth - 1, scope);
+                let cons = self.gen_exp(depth - 1, scope);
+                let alt = self.gen_exp(depth - 1, scope);
+                format!("(if {} {} {})", pred, cons, alt)
+            }
+            2 => {
+                let val = self.gen_exp(depth - 1, scope);
+                let var = self.fresh_var();
+                let mut body_scope = scope.to_vec();
+                body_scope.push(var.clone());
+                let body = self.gen_exp(depth - 1, &body_scope);
+                format!("(let (({} {})) {})", var, val, body)
+            }
+            3 => {
+                let first = self.gen_exp(depth - 1, scope);
+                let second = self.gen_exp(depth - 1, scope);
+                format!("(begin {} {})", first, second)
+            }
+            4 if !scope.is_empty() => {
+                let var = &scope[self.rng.below(scope.len())];
+                let val = self.gen_exp(depth - 1, scope);
+                format!("(set! {} {})", var, val)
+            }
+            5 => {
+                let var = self.fresh_var();
+                let param_type = self.gen_type();
+                let ret_type = self.gen_type();
+                let body = self.gen_exp(depth - 1, std::slice::from_ref(&var));
+                let arg = self.gen_exp(depth - 1, scope);
+                format!(
+                    "((lambda (({} : {})) : {} {}) {})",
+                    var, param_type, ret_type, body, arg
+                )
+            }
+            6 => {
+                let first = self.gen_exp(depth - 1, scope);
+                let second = self.gen_exp(depth - 1, scope);
+                format!("(make-tuple {} {})", first, second)
+            }
+            7 => {
+                let tuple = self.gen_exp(depth - 1, scope);
+                format!("(tuple-ref {} {})", tuple, self.rng.below(3))
+            }
+            _ => self.gen_atom(scope),
+        }
+    }
+
+    fn gen_atom(&mut self, scope: &[String]) -> String {
+        match self.rng.below(3) {
+            0 => format!("{}", self.rng.below(100) as i32 - 50),
+            1 => String::from(if self.rng.below(2) == 0 {
+                "true"
+            } else {
+                "false"
+            }),
+            _ if !scope.is_empty() => scope[self.rng.below(scope.len())].clone(),
+            _ => String::from("0"),
+        }
+    }
+
+    fn gen_type(&mut self) -> &'static str {
+        if self.rng.below(2) == 0 {
+            "int"
+        } else {
+            "bool"
+        }
+    }
+
+    fn fresh_var(&mut self) -> String {
+        self.next_var += 1;
+        format!("v{}", self.next_var)
+    }
+}
+
+#[test]
+fn test_fuzz_type_check() {
+    let mut generator = ProgramGenerator {
+        rng: Rng(0x2545_f491_4f6c_dd1d),
+        next_var: 0,
+    };
+    let mut num_accepted = 0;
+    for _ in 0..300 {
+        let source = generator.gen_exp(4, &[]);
+        let exp = parse(&lexpr::from_str(&source).unwrap()).unwrap();
+
+        // the type checker must never panic, even on ill-typed programs
+        let result = std::panic::catch_unwind(|| type_check(&exp).is_ok());
+        let accepted = match result {
+            Ok(accepted) => accepted,
+            Err(_) => panic!("Type checker panicked on {}", source),
+        };
+        if !accepted {
+            continue;
+        }
+        num_accepted += 1;
+
+        // accepted programs must make it through the rest of the compiler
+        // (which type checks the program again after transforming it) and run
+        // without trapping
+        let prog = match compile_exp(&exp) {
+            Ok(prog) => prog,
+            Err(err) => panic!("Compiling {} failed: {}", source, err),
+        };
+        let module = construct_module_from_prog(&prog).unwrap();
+        let binary = parity_wasm::serialize(module).unwrap();
+        let instance = instantiate(&binary, &imports! {}).unwrap();
+        if let Err(err) = instance.dyn_func("$$MAIN$$").unwrap().call(&[]) {
+            panic!("Running {} failed: {}", source, err);
+        }
+    }
+    // make sure the generator isn't only producing ill-typed programs
+    assert!(
+        num_accepted > 30,
+        "only {} programs were accepted",
+        num_accepted
+    );
+}
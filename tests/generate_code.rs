@@ -1,16 +1,25 @@
 use scheme_to_wasm::common::{Expr, ExprKind, Prog, TypedExpr};
-use scheme_to_wasm::compile::compile_exp;
+use scheme_to_wasm::compile::{
+    compile_exp, compile_exp_with_options, compile_exp_with_stats, CompileOptions,
+};
+use scheme_to_wasm::desugar::desugar;
 use scheme_to_wasm::generate_code::{
-    construct_module, construct_module_from_prog, gen_instr, CodeGenerateState,
+    construct_module, construct_module_from_prog, construct_module_from_prog_with_options,
+    coverage_report, gen_instr, size_report, CodeGenerateError, CodeGenerateOptions,
+    CodeGenerateState, Instrumentation,
 };
+use scheme_to_wasm::macro_expand::macro_expand_with_constants;
+use scheme_to_wasm::optimize::OptLevel;
 use scheme_to_wasm::parse::parse;
 use scheme_to_wasm::type_check::type_check;
-use scheme_to_wasm::types::Type;
+use scheme_to_wasm::types::{Effect, Type};
 
 use im_rc::vector;
 use parity_wasm::builder;
 use parity_wasm::elements::{Instruction, Instructions, Module, ValueType};
-use wasmer_runtime::{imports, instantiate, Value};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicI32, Ordering};
+use wasmer_runtime::{func, imports, instantiate, Export, Value};
 
 fn output_wasm_to_file(module: Module, test_name: &str) {
     let output_dir = std::env::current_dir().unwrap().join("wasm-output");
@@ -55,6 +64,77 @@ fn test_compile_math() {
     assert_eq!(output, Value::I32(16));
 }
 
+#[test]
+fn test_compile_floats() {
+    let exp = parse(&lexpr::from_str("(/ (* 1.5 (exact->inexact 3)) 2.0)").unwrap()).unwrap();
+    let output = test_runner_exp(exp, "floats.wasm");
+    assert_eq!(output, Value::I32(2.25f32.to_bits() as i32));
+
+    let exp = parse(&lexpr::from_str("(inexact->exact (- 10.0 2.0))").unwrap()).unwrap();
+    let output = test_runner_exp(exp, "floats2.wasm");
+    assert_eq!(output, Value::I32(8));
+
+    let exp = parse(&lexpr::from_str("(float->int (round -2.5))").unwrap()).unwrap();
+    let output = test_runner_exp(exp, "floats3.wasm");
+    assert_eq!(output, Value::I32(-2));
+
+    let exp = parse(&lexpr::from_str("(sqrt (exact->inexact (expt 3 4)))").unwrap()).unwrap();
+    let output = test_runner_exp(exp, "floats4.wasm");
+    assert_eq!(output, Value::I32(9.0f32.to_bits() as i32));
+
+    // converting a float with a fractional part to an exact int traps, as
+    // does truncating NaN or an out of range float
+    for source in &[
+        "(inexact->exact 2.5)",
+        "(float->int (/ 0.0 0.0))",
+        "(float->int 3000000000.0)",
+        "(let ((x -1)) (expt 2 x))",
+    ] {
+        let exp = parse(&lexpr::from_str(source).unwrap()).unwrap();
+        let prog = compile_exp(&exp).unwrap();
+        let binary = parity_wasm::serialize(construct_module_from_prog(&prog).unwrap()).unwrap();
+        let instance = instantiate(&binary, &imports! {}).unwrap();
+        assert_eq!(
+            instance.dyn_func("$$MAIN$$").unwrap().call(&[]).is_err(),
+            true,
+            "{}",
+            source
+        );
+    }
+}
+
+#[test]
+fn test_compile_float_math_imports() {
+    // exp, log, and expt on floats are imported from the host, and take and
+    // return floats as their bits
+    let exp =
+        parse(&lexpr::from_str("(+ (expt 2.0 (exp 1.0)) (expt (log 4.0) 2.0))").unwrap()).unwrap();
+    let module = construct_module_from_prog(&compile_exp(&exp).unwrap()).unwrap();
+    let binary = parity_wasm::serialize(module).unwrap();
+    fn host_exp(x: i32) -> i32 {
+        f32::from_bits(x as u32).exp().to_bits() as i32
+    }
+    fn host_log(x: i32) -> i32 {
+        f32::from_bits(x as u32).ln().to_bits() as i32
+    }
+    fn host_expt(x: i32, y: i32) -> i32 {
+        f32::from_bits(x as u32)
+            .powf(f32::from_bits(y as u32))
+            .to_bits() as i32
+    }
+    let import_object = imports! {
+        "env" => {
+            "exp" => func!(host_exp),
+            "log" => func!(host_log),
+            "expt" => func!(host_expt),
+        },
+    };
+    let instance = instantiate(&binary, &import_object).unwrap();
+    let values = instance.dyn_func("$$MAIN$$").unwrap().call(&[]).unwrap();
+    let expected = 2.0f32.powf(1.0f32.exp()) + 4.0f32.ln().powf(2.0);
+    assert_eq!(values[0], Value::I32(expected.to_bits() as i32));
+}
+
 #[test]
 fn test_compile_control() {
     let exp = parse(&lexpr::from_str("(if (< 5 3) 10 20)").unwrap()).unwrap();
@@ -80,6 +160,57 @@ fn test_compile_let_multiple_bindings() {
     assert_eq!(output, Value::I32(56));
 }
 
+#[test]
+fn test_compile_unusual_identifiers() {
+    // names are only used as keys and in the name section, so they can
+    // contain any characters (and can be keywords in other languages)
+    let exp = parse(
+        &lexpr::from_str(
+            r#"
+(let ((list->vec 1) (even? 2) (fn 3) (match 4))
+  (let ((f (lambda ((set-x! : int)) : int (* set-x! 10))))
+    (+ (f list->vec) (+ even? (+ fn match)))))
+"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let output = test_runner_prog(prog, "unusual_identifiers.wasm");
+    assert_eq!(output, Value::I32(19));
+}
+
+#[test]
+fn test_compile_shadowing() {
+    // inner bindings must not overwrite the locals of outer bindings with the
+    // same name
+    let exp = parse(
+        &lexpr::from_str(
+            r#"
+(let ((x 1))
+  (let ((y (let ((x 10)) (+ x 5))))
+    (let ((f (lambda ((x : int)) : int (* x 2))))
+      (+ (f y) (+ x (let ((x 100)) x))))))
+"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let output = test_runner_prog(prog, "shadowing.wasm");
+    assert_eq!(output, Value::I32(131));
+}
+
+#[test]
+fn test_compile_begin0() {
+    let exp =
+        desugar(&lexpr::from_str("(let ((x 1)) (+ (begin0 x (set! x 10)) (* x 100)))").unwrap())
+            .unwrap();
+    let prog = compile_exp(&parse(&exp).unwrap()).unwrap();
+    let output = test_runner_prog(prog, "begin0.wasm");
+    assert_eq!(output, Value::I32(1001));
+}
+
 #[test]
 fn test_compile_tuple() {
     let exp = parse(&lexpr::from_str("(tuple-ref (make-tuple 3 4) 1)").unwrap()).unwrap();
@@ -253,6 +384,331 @@ fn test_compile_set() {
     assert_eq!(output, Value::I32(7));
 }
 
+#[test]
+fn test_compile_while() {
+    let exp = parse(
+        &lexpr::from_str(
+            r#"
+(let ((i 0) (sum 0))
+  (begin
+    (while (< i 10)
+      (begin (set! i (+ i 1)) (set! sum (+ sum i))))
+    sum))
+"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let output = test_runner_prog(prog, "while1.wasm");
+    assert_eq!(output, Value::I32(55));
+
+    // a loop whose condition is false from the start never runs its body
+    let exp =
+        parse(&lexpr::from_str("(let ((x 7)) (begin (while (> x 10) (set! x 0)) x))").unwrap())
+            .unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let output = test_runner_prog(prog, "while2.wasm");
+    assert_eq!(output, Value::I32(7));
+}
+
+#[test]
+fn test_compile_case() {
+    // dense datums are compiled to a jump table, and sparse datums to a chain
+    // of comparisons; both must agree with the equivalent cond expression
+    let tests = [
+        (
+            "(case k ((1 2) 10) ((3) 20) ((5 6) 30) (else 40))",
+            "(cond ((or (= k 1) (= k 2)) 10) ((= k 3) 20) ((or (= k 5) (= k 6)) 30) (else 40))",
+            true,
+        ),
+        (
+            "(case k ((-100) 10) ((0 7) 20) ((100000) 30) (else 40))",
+            "(cond ((= k -100) 10) ((or (= k 0) (= k 7)) 20) ((= k 100000) 30) (else 40))",
+            false,
+        ),
+    ];
+    let keys = [
+        -2147483648,
+        -101,
+        -100,
+        -1,
+        0,
+        1,
+        2,
+        3,
+        4,
+        5,
+        6,
+        7,
+        8,
+        100000,
+        2147483647,
+    ];
+    for (i, (case_source, cond_source, uses_table)) in tests.iter().enumerate() {
+        let parse_with_key = |source: &str, key: i32| {
+            let value = lexpr::from_str(&format!("(let ((k {})) {})", key, source)).unwrap();
+            parse(&desugar(&value).unwrap()).unwrap()
+        };
+        let typed_exp = type_check(&parse_with_key(case_source, 0)).unwrap();
+        let instructions = gen_instr(&typed_exp, &mut CodeGenerateState::default()).unwrap();
+        assert_eq!(
+            instructions
+                .iter()
+                .any(|instr| matches!(instr, Instruction::BrTable(_))),
+            *uses_table,
+            "{}",
+            case_source
+        );
+        for key in &keys {
+            let output = test_runner_exp(parse_with_key(case_source, *key), "case.wasm");
+            let expected = test_runner_exp(parse_with_key(cond_source, *key), "case_cond.wasm");
+            assert_eq!(output, expected, "case {} with key {}", i, key);
+        }
+    }
+
+    // case expressions nested in a clause branch to their own blocks
+    let exp = parse(
+        &lexpr::from_str(
+            "(let ((k 2)) (+ (case k ((1 2 3) (case (+ k 1) ((1) 1) ((2) 2) ((3) 3) (else 4))) (else 5)) 10))",
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let output = test_runner_prog(prog, "case_nested.wasm");
+    assert_eq!(output, Value::I32(13));
+}
+
+#[test]
+fn test_compile_tuple_set() {
+    let exp = parse(
+        &lexpr::from_str(
+            "(let ((t (make-tuple 1 2))) (+ (tuple-set! t 1 10) (+ (tuple-ref t 0) (tuple-ref t 1))))",
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let output = test_runner_prog(prog, "tuple_set.wasm");
+    assert_eq!(output, Value::I32(21));
+}
+
+#[test]
+fn test_compile_mutated_captures() {
+    // assignments inside a closure are seen outside of it
+    let exp = parse(
+        &lexpr::from_str(
+            "(let ((x 0)) (let ((f (lambda () : int (set! x (+ x 1))))) (begin (f) (f) x)))",
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let output = test_runner_prog(prog, "captures1.wasm");
+    assert_eq!(output, Value::I32(2));
+
+    // and assignments outside are seen by the closure, including by delayed
+    // expressions
+    let exp = parse(
+        &lexpr::from_str(
+            r#"
+(let ((x 1))
+  (let ((f (lambda () : int x)) (p (delay x)))
+    (begin (set! x 5) (+ (f) (* 10 (force p))))))
+"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let output = test_runner_prog(prog, "captures2.wasm");
+    assert_eq!(output, Value::I32(55));
+
+    // parameters can be captured and assigned too
+    let exp = parse(
+        &lexpr::from_str(
+            r#"
+(let ((make-counter (lambda ((start : int)) : (-> int)
+                      (lambda () : int (begin (set! start (+ start 1)) start)))))
+  (let ((c (make-counter 5)))
+    (begin (c) (c))))
+"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let output = test_runner_prog(prog, "captures3.wasm");
+    assert_eq!(output, Value::I32(7));
+}
+
+#[test]
+fn test_compile_closure_calling_convention() {
+    // each nested closure captures variables from every enclosing lambda
+    let exp = parse(
+        &lexpr::from_str(
+            r#"
+(let ((a 1))
+  (let ((f (lambda ((b : int)) : (-> int (-> int int))
+             (lambda ((c : int)) : (-> int int)
+               (lambda ((d : int)) : int (+ a (+ b (+ c d))))))))
+    (((f 10) 100) 1000)))
+"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let output = test_runner_prog(prog, "closures_nested.wasm");
+    assert_eq!(output, Value::I32(1111));
+
+    // a closure can call itself through a variable it captures, once the
+    // variable is assigned the closure
+    let exp = parse(
+        &lexpr::from_str(
+            r#"
+(let ((fib (lambda ((n : int)) : int 0)))
+  (begin
+    (set! fib (lambda ((n : int)) : int
+                (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2))))))
+    (fib 10)))
+"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let output = test_runner_prog(prog, "closures_recursive.wasm");
+    assert_eq!(output, Value::I32(55));
+}
+
+#[test]
+fn test_compile_curry_and_compose() {
+    let exp = parse(
+        &lexpr::from_str(
+            "(let ((sub (lambda ((x : int) (y : int)) : int (- x y)))) ((curry sub 10) 4))",
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let output = test_runner_prog(prog, "curry1.wasm");
+    assert_eq!(output, Value::I32(6));
+
+    let exp = parse(
+        &lexpr::from_str(
+            r#"
+(let ((double (lambda ((x : int)) : int (* x 2)))
+      (inc (lambda ((x : int)) : int (+ x 1))))
+  ((compose double inc) 4))
+"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let output = test_runner_prog(prog, "compose1.wasm");
+    assert_eq!(output, Value::I32(10));
+
+    // point-free style, with functions that capture variables
+    let exp = parse(
+        &lexpr::from_str(
+            r#"
+(let ((a 100))
+  (let ((add3 (lambda ((x : int) (y : int) (z : int)) : int (+ x (+ y (* z a)))))
+        (sub (lambda ((x : int) (y : int)) : int (- x y))))
+    (+ ((compose (curry add3 1 2) (curry sub 10)) 4)
+       ((curry add3 1 2 3)))))
+"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let output = test_runner_prog(prog, "curry2.wasm");
+    assert_eq!(output, Value::I32(603 + 303));
+
+    // arguments are evaluated once, when the curried function is created
+    let exp = parse(
+        &lexpr::from_str(
+            r#"
+(let ((n 0) (sub (lambda ((x : int) (y : int)) : int (- x y))))
+  (let ((f (curry sub (begin (set! n (+ n 1)) n))))
+    (begin (f 0) (f 0) n)))
+"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let output = test_runner_prog(prog, "curry3.wasm");
+    assert_eq!(output, Value::I32(1));
+}
+
+#[test]
+fn test_compile_for_each() {
+    let exp = lexpr::from_str(
+        r#"
+(begin
+  (declare-extern record "host" "record" (-> int int))
+  (let ((lst (cons 1 (cons 20 (cons 300 (null int))))))
+    (begin
+      (for-each (lambda ((x : int)) : unit (begin (record x) (make-tuple))) lst)
+      (car lst))))
+"#,
+    )
+    .unwrap();
+    let (expanded, _constants) = macro_expand_with_constants(&exp).unwrap();
+    let prog = compile_exp(&parse(&desugar(&expanded).unwrap()).unwrap()).unwrap();
+    let binary = parity_wasm::serialize(construct_module_from_prog(&prog).unwrap()).unwrap();
+
+    static TOTAL: AtomicI32 = AtomicI32::new(0);
+    fn record(x: i32) -> i32 {
+        // each element is recorded in a different digit, in order
+        let total = TOTAL.load(Ordering::SeqCst);
+        TOTAL.store(total * 10 + x, Ordering::SeqCst);
+        0
+    }
+    let import_object = imports! {
+        "host" => {
+            "record" => func!(record),
+        },
+    };
+    let instance = instantiate(&binary, &import_object).unwrap();
+    let values = instance.dyn_func("$$MAIN$$").unwrap().call(&[]).unwrap();
+    assert_eq!(values[0], Value::I32(1));
+    assert_eq!(TOTAL.load(Ordering::SeqCst), (10 + 20) * 10 + 300);
+}
+
+#[test]
+fn test_compile_extern_callback() {
+    let exp = lexpr::from_str(
+        r#"
+(begin
+  (declare-extern host-double "host" "double" (-> int int))
+  (let ((twice (lambda ((f : (-> int int)) (x : int)) : int (f (f x)))))
+    (twice host-double 5)))
+"#,
+    )
+    .unwrap();
+    let (expanded, _constants) = macro_expand_with_constants(&exp).unwrap();
+    let prog = compile_exp(&parse(&expanded).unwrap()).unwrap();
+    let binary = parity_wasm::serialize(construct_module_from_prog(&prog).unwrap()).unwrap();
+
+    fn double(x: i32) -> i32 {
+        x * 2
+    }
+    let import_object = imports! {
+        "host" => {
+            "double" => func!(double),
+        },
+    };
+    let instance = instantiate(&binary, &import_object).unwrap();
+    let values = instance.dyn_func("$$MAIN$$").unwrap().call(&[]).unwrap();
+    assert_eq!(values[0], Value::I32(20));
+}
+
 #[test]
 fn test_compile_func_without_closure_conversion() {
     let func = parse(&lexpr::from_str("(lambda ((x : int)) : int (+ x 1))").unwrap()).unwrap();
@@ -262,7 +718,7 @@ fn test_compile_func_without_closure_conversion() {
         Type::Int,
         ExprKind::FnApp(
             TypedExpr::new(
-                Type::Func(vector![Type::Int], Box::from(Type::Int)),
+                Type::Func(vector![Type::Int], Box::from(Type::Int), Effect::Pure),
                 ExprKind::Id(String::from("func0")),
             ),
             vector![TypedExpr::new(Type::Int, ExprKind::Num(5))],
@@ -421,6 +877,569 @@ fn test_compile_higher_order_func() {
     assert_eq!(output, Value::I32(6));
 }
 
+#[test]
+fn test_compile_open_records() {
+    let exp = parse(
+        &lexpr::from_str(
+            r#"
+(let ((get-x (lambda ((r : (record (x : int) ... T1))) : int (record-ref r x))))
+  (+ (get-x (make-record (x 3)))
+     (get-x (make-record (a 10) (x 4) (z 20)))))
+"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let output = test_runner_prog(prog, "open_records.wasm");
+    assert_eq!(output, Value::I32(7));
+
+    // the rest of an open record cannot be returned, since only its known
+    // fields are passed to the function
+    let exp = parse(
+        &lexpr::from_str(
+            r#"
+(let ((id (lambda ((r : (record (x : int) ... T1))) : (record (x : int) ... T1) r)))
+  (record-ref (id (make-record (x 3) (y 4))) y))
+"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(compile_exp(&exp).is_err(), true);
+}
+
+#[test]
+fn test_compile_subtyping() {
+    let exp = parse(
+        &lexpr::from_str(
+            r#"
+(let ((get-y (lambda ((r : (record (y : int)))) : int (record-ref r y))))
+  (get-y (make-record (x 3) (y 4) (z 5))))
+"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let output = test_runner_prog(prog, "record_subtyping.wasm");
+    assert_eq!(output, Value::I32(4));
+
+    let exp = parse(
+        &lexpr::from_str(
+            r#"
+(let ((second (lambda ((t : (tuple int int))) : int (tuple-ref t 1))))
+  (second (make-tuple 3 4 5)))
+"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(compile_exp(&exp).is_err(), true);
+    let options = CompileOptions {
+        tuple_width_subtyping: true,
+        ..CompileOptions::default()
+    };
+    let prog = compile_exp_with_options(&exp, &options).unwrap();
+    let output = test_runner_prog(prog, "tuple_subtyping.wasm");
+    assert_eq!(output, Value::I32(4));
+}
+
+#[test]
+fn test_compile_optimized() {
+    let exp = parse(
+        &lexpr::from_str(
+            r#"
+(let ((unused (* 6 7))
+      (add (lambda ((x : int) (y : int)) : int
+             (begin (+ 1 1) (if (< 1 2) (+ x y) (- x y))))))
+  (add 3 (* 2 4)))
+"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    for (level, name) in &[
+        (OptLevel::O0, "optimized_o0.wasm"),
+        (OptLevel::O1, "optimized_o1.wasm"),
+        (OptLevel::O2, "optimized_o2.wasm"),
+    ] {
+        let options = CompileOptions {
+            opt_level: *level,
+            ..CompileOptions::default()
+        };
+        let prog = compile_exp_with_options(&exp, &options).unwrap();
+        let output = test_runner_prog(prog, name);
+        assert_eq!(output, Value::I32(11));
+    }
+}
+
+#[test]
+fn test_compile_stats() {
+    let exp =
+        parse(&lexpr::from_str("(let ((f (lambda ((x : int)) : int (* x 2)))) (f 21))").unwrap())
+            .unwrap();
+    let (prog, stats) = compile_exp_with_stats(&exp, &CompileOptions::default()).unwrap();
+    let names: Vec<&str> = stats.passes.iter().map(|pass| pass.name).collect();
+    assert_eq!(
+        names,
+        vec![
+            "type check",
+            "resolve",
+            "assignment convert",
+            "closure convert",
+            "lambda lift",
+            "type check prog",
+            "record elim",
+            "optimize"
+        ]
+    );
+    // closure conversion makes the program larger
+    assert_eq!(stats.passes[0].nodes, 8);
+    assert_eq!(stats.passes[3].nodes > stats.passes[2].nodes, true);
+    assert_eq!(
+        stats
+            .to_string()
+            .lines()
+            .last()
+            .unwrap()
+            .starts_with("total"),
+        true
+    );
+    let output = test_runner_prog(prog, "compile_stats.wasm");
+    assert_eq!(output, Value::I32(42));
+}
+
+#[test]
+fn test_compile_deterministic() {
+    let source = r#"
+(let ((count 0)
+      (val (inject 5 (union int bool))))
+  (let ((next (lambda () : int (begin (set! count (+ count 1)) count)))
+        (p (delay (* 6 7))))
+    (+ (next)
+       (+ (force p)
+          (typecase val ((int x) x) ((bool b) 0))))))
+"#;
+    let compile_to_binary = |source: &str| -> Vec<u8> {
+        let exp = parse(&lexpr::from_str(source).unwrap()).unwrap();
+        let module = construct_module_from_prog(&compile_exp(&exp).unwrap()).unwrap();
+        parity_wasm::serialize(module).unwrap()
+    };
+    let binary = compile_to_binary(source);
+    // compiling something else in between doesn't change the output
+    compile_to_binary("(let ((f (lambda ((x : int)) : int x))) (f 1))");
+    assert_eq!(compile_to_binary(source), binary);
+
+    let instance = instantiate(&binary, &imports! {}).unwrap();
+    let values = instance.dyn_func("$$MAIN$$").unwrap().call(&[]).unwrap();
+    assert_eq!(values[0], Value::I32(48));
+}
+
+/// Records the names of the spans which are created, in order.
+#[derive(Default)]
+struct SpanRecorder {
+    names: std::sync::Mutex<Vec<&'static str>>,
+}
+
+impl tracing::Subscriber for SpanRecorder {
+    fn enabled(&self, _metadata: &tracing::Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, attrs: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+        let mut names = self.names.lock().unwrap();
+        names.push(attrs.metadata().name());
+        tracing::span::Id::from_u64(names.len() as u64)
+    }
+
+    fn record(&self, _span: &tracing::span::Id, _values: &tracing::span::Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &tracing::span::Id, _follows: &tracing::span::Id) {}
+
+    fn event(&self, _event: &tracing::Event<'_>) {}
+
+    fn enter(&self, _span: &tracing::span::Id) {}
+
+    fn exit(&self, _span: &tracing::span::Id) {}
+}
+
+#[test]
+fn test_compile_spans() {
+    let recorder = std::sync::Arc::new(SpanRecorder::default());
+    tracing::subscriber::with_default(recorder.clone(), || {
+        let exp = parse(&lexpr::from_str("(+ 1 2)").unwrap()).unwrap();
+        let prog = compile_exp(&exp).unwrap();
+        construct_module_from_prog(&prog).unwrap();
+    });
+    let names = recorder.names.lock().unwrap();
+    assert_eq!(names.first(), Some(&"parse"));
+    assert_eq!(names.iter().filter(|name| **name == "pass").count(), 8);
+    for name in &["type_check_prog", "optimize", "generate_code"] {
+        assert_eq!(names.contains(name), true, "missing span {}", name);
+    }
+}
+
+#[test]
+fn test_compile_promises() {
+    let exp =
+        parse(&lexpr::from_str("(let ((p (delay (+ 1 2)))) (+ (force p) (force p)))").unwrap())
+            .unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let output = test_runner_prog(prog, "promises.wasm");
+    assert_eq!(output, Value::I32(6));
+
+    // promises can capture variables, and be passed to functions
+    let exp = parse(
+        &lexpr::from_str(
+            r#"
+(let ((y 20))
+  (let ((add-one (lambda ((p : (promise int))) : int (+ (force p) 1))))
+    (add-one (delay (* y 2)))))
+"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let output = test_runner_prog(prog, "promises_capture.wasm");
+    assert_eq!(output, Value::I32(41));
+}
+
+#[test]
+fn test_compile_unions() {
+    let exp = parse(
+        &lexpr::from_str(
+            r#"
+(let ((total (lambda ((val : (union int (tuple int int) bool))) : int
+                (typecase val
+                  ((int x) x)
+                  (((tuple int int) x) (+ (tuple-ref x 0) (tuple-ref x 1)))
+                  ((bool x) (if x 1 0))))))
+  (+ (total 3) (+ (total (make-tuple 10 20)) (total true))))
+"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let output = test_runner_prog(prog, "unions.wasm");
+    assert_eq!(output, Value::I32(34));
+}
+
+#[test]
+fn test_compile_asserts() {
+    let exp = parse(
+        &lexpr::from_str(
+            r#"
+(let ((safe-div (lambda ((x : int) (y : int)) : int
+                  (begin (assert (> y 0) "division by zero") (/ x y)))))
+  (safe-div 10 2))
+"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let output = test_runner_prog(prog, "asserts.wasm");
+    assert_eq!(output, Value::I32(5));
+
+    // failing asserts trap
+    let exp = parse(&lexpr::from_str(r#"(begin (assert (< 2 1) "oops") 3)"#).unwrap()).unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let module = construct_module_from_prog(&prog).unwrap();
+    let binary = parity_wasm::serialize(module).unwrap();
+    let instance = instantiate(&binary, &imports! {}).unwrap();
+    assert_eq!(
+        instance.dyn_func("$$MAIN$$").unwrap().call(&[]).is_err(),
+        true
+    );
+
+    // asserts can be stripped
+    let options = CodeGenerateOptions {
+        asserts: false,
+        ..CodeGenerateOptions::default()
+    };
+    let module = construct_module_from_prog_with_options(&prog, &options).unwrap();
+    let binary = parity_wasm::serialize(module).unwrap();
+    let instance = instantiate(&binary, &imports! {}).unwrap();
+    let values = instance.dyn_func("$$MAIN$$").unwrap().call(&[]).unwrap();
+    assert_eq!(values[0], Value::I32(3));
+}
+
+#[test]
+fn test_compile_exported_constants() {
+    let exp = lexpr::from_str(
+        r#"
+(begin
+  (define-constant width 8)
+  (define-constant area (* width 4))
+  (+ area 1))
+"#,
+    )
+    .unwrap();
+    let (expanded, constants) = macro_expand_with_constants(&exp).unwrap();
+    let prog = compile_exp(&parse(&expanded).unwrap()).unwrap();
+    let options = CodeGenerateOptions {
+        exported_constants: constants,
+        ..CodeGenerateOptions::default()
+    };
+    let module = construct_module_from_prog_with_options(&prog, &options).unwrap();
+
+    let exports = module.export_section().unwrap().entries();
+    assert_eq!(
+        exports
+            .iter()
+            .map(|entry| entry.field())
+            .collect::<Vec<&str>>(),
+        vec!["width", "area", "$$MAIN$$"]
+    );
+    let global_values = module
+        .global_section()
+        .unwrap()
+        .entries()
+        .iter()
+        .map(|global| global.init_expr().code()[0].clone())
+        .collect::<Vec<Instruction>>();
+    assert_eq!(
+        global_values,
+        vec![Instruction::I32Const(8), Instruction::I32Const(32)]
+    );
+
+    let binary = parity_wasm::serialize(module).unwrap();
+    let instance = instantiate(&binary, &imports! {}).unwrap();
+    let values = instance.dyn_func("$$MAIN$$").unwrap().call(&[]).unwrap();
+    assert_eq!(values[0], Value::I32(33));
+}
+
+#[test]
+fn test_compile_host_memory() {
+    let exp = parse(&lexpr::from_str("(tuple-ref (make-tuple 1 2) 1)").unwrap()).unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let options = CodeGenerateOptions {
+        host_memory: true,
+        ..CodeGenerateOptions::default()
+    };
+    let module = construct_module_from_prog_with_options(&prog, &options).unwrap();
+    let binary = parity_wasm::serialize(module).unwrap();
+    let instance = instantiate(&binary, &imports! {}).unwrap();
+    let values = instance.dyn_func("$$MAIN$$").unwrap().call(&[]).unwrap();
+    assert_eq!(values[0], Value::I32(2));
+
+    let alloc = |len: i32| match instance.dyn_func("alloc").unwrap().call(&[Value::I32(len)]) {
+        Ok(values) => match values[0] {
+            Value::I32(ptr) => ptr,
+            _ => panic!("alloc returned {:?}", values),
+        },
+        Err(err) => panic!("alloc failed: {}", err),
+    };
+    let free = |ptr: i32, len: i32| {
+        let values = instance
+            .dyn_func("free")
+            .unwrap()
+            .call(&[Value::I32(ptr), Value::I32(len)])
+            .unwrap();
+        assert_eq!(values, vec![]);
+    };
+    let memory = match instance.exports().find(|(name, _)| name == "memory") {
+        Some((_, Export::Memory(memory))) => memory,
+        _ => panic!("memory is not exported"),
+    };
+
+    // allocations start after the tuple, and are aligned
+    let hello = alloc(5);
+    assert_eq!(hello, 8);
+    for (cell, byte) in memory.view::<u8>()[hello as usize..].iter().zip(b"hello") {
+        cell.set(*byte);
+    }
+    let bytes: Vec<u8> = memory.view::<u8>()[hello as usize..hello as usize + 5]
+        .iter()
+        .map(|cell| cell.get())
+        .collect();
+    assert_eq!(bytes, b"hello");
+    let other = alloc(3);
+    assert_eq!(other, 16);
+
+    // only the last allocation is reclaimed
+    free(hello, 5);
+    assert_eq!(alloc(1), 24);
+    free(24, 1);
+    assert_eq!(alloc(1), 24);
+    free(24, 1);
+
+    // memory grows as needed
+    let pages = memory.size().0;
+    let big = alloc(pages as i32 * 65536);
+    assert_eq!(big, 24);
+    assert_eq!(memory.size().0 > pages, true);
+    memory.view::<u8>()[(big + pages as i32 * 65536 - 1) as usize].set(1);
+
+    // the heap pointer is kept apart from instrumentation counters
+    let exp =
+        parse(&lexpr::from_str("(if (< 1 2) (tuple-ref (make-tuple 1 2) 1) 3)").unwrap()).unwrap();
+    let options = CodeGenerateOptions {
+        host_memory: true,
+        instrument: Instrumentation::Coverage,
+        ..CodeGenerateOptions::default()
+    };
+    let module =
+        construct_module_from_prog_with_options(&compile_exp(&exp).unwrap(), &options).unwrap();
+    let binary = parity_wasm::serialize(module).unwrap();
+    let instance = instantiate(&binary, &imports! {}).unwrap();
+    let alloc = instance.dyn_func("alloc").unwrap();
+    assert_eq!(alloc.call(&[Value::I32(4)]).unwrap(), vec![Value::I32(8)]);
+    assert_eq!(alloc.call(&[Value::I32(4)]).unwrap(), vec![Value::I32(16)]);
+    instance.dyn_func("$$MAIN$$").unwrap().call(&[]).unwrap();
+    let mut coverage = instance
+        .exports()
+        .filter_map(|(name, export)| match export {
+            Export::Global(global) if name.starts_with("$$COVERAGE$$") => {
+                Some((name, global.get()))
+            }
+            _ => None,
+        })
+        .collect::<Vec<(String, Value)>>();
+    coverage.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        coverage,
+        vec![
+            (String::from("$$COVERAGE$$.0"), Value::I32(1)),
+            (String::from("$$COVERAGE$$.1"), Value::I32(0))
+        ]
+    );
+
+    // modules don't export memory unless asked to
+    let module = construct_module_from_prog(&prog).unwrap();
+    let exports = module.export_section().unwrap().entries();
+    assert_eq!(exports.len(), 1);
+    assert_eq!(exports[0].field(), "$$MAIN$$");
+}
+
+#[test]
+fn test_compile_unreachable_fns() {
+    let exp = parse(
+        &lexpr::from_str(
+            r#"
+(let ((unused (lambda ((x : int)) : int (* x 3))))
+  (let ((add-one (lambda ((x : int)) : int (+ x 1))))
+    (let ((add-two (lambda ((y : int)) : int (add-one (add-one y)))))
+      (add-two 5))))
+"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let options = CompileOptions {
+        opt_level: OptLevel::O2,
+        ..CompileOptions::default()
+    };
+    let prog = compile_exp_with_options(&exp, &options).unwrap();
+    assert_eq!(prog.fns.len(), 3);
+    let module = construct_module_from_prog(&prog).unwrap();
+    let names = module.names_section().unwrap();
+    let function_names = names.functions().unwrap().names();
+    assert_eq!(function_names.get(0).unwrap(), "add-one");
+    assert_eq!(function_names.get(1).unwrap(), "add-two");
+    assert_eq!(function_names.get(2).unwrap(), "$$MAIN$$");
+    assert_eq!(function_names.get(3), None);
+    let output = test_runner_prog(prog, "unreachable_fns.wasm");
+    assert_eq!(output, Value::I32(7));
+}
+
+#[test]
+fn test_compile_name_section() {
+    let exp = parse(
+        &lexpr::from_str(
+            r#"
+(let ((add-one (lambda ((x : int)) : int (+ x 1))))
+  (let ((twice (lambda ((y : int)) : int (* y 2))))
+    (twice (add-one 5))))
+"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let module = construct_module_from_prog(&prog).unwrap();
+
+    let names = module.names_section().unwrap();
+    let function_names = names.functions().unwrap().names();
+    assert_eq!(function_names.get(0).unwrap(), "add-one");
+    assert_eq!(function_names.get(1).unwrap(), "twice");
+    assert_eq!(function_names.get(2).unwrap(), "$$MAIN$$");
+    let local_names = names.locals().unwrap().local_names();
+    assert_eq!(
+        local_names
+            .get(0)
+            .unwrap()
+            .iter()
+            .any(|(_, name)| name == "x"),
+        true
+    );
+    assert_eq!(
+        local_names
+            .get(1)
+            .unwrap()
+            .iter()
+            .any(|(_, name)| name == "y"),
+        true
+    );
+    assert_eq!(
+        local_names
+            .get(2)
+            .unwrap()
+            .iter()
+            .any(|(_, name)| name == "twice"),
+        true
+    );
+
+    let binary = parity_wasm::serialize(module).unwrap();
+    let instance = instantiate(&binary, &imports! {}).unwrap();
+    let values = instance.dyn_func("$$MAIN$$").unwrap().call(&[]).unwrap();
+    assert_eq!(values[0], Value::I32(12));
+}
+
+#[test]
+fn test_size_report() {
+    let exp = parse(
+        &lexpr::from_str(
+            r#"
+(let ((small (lambda ((x : int)) : int x)))
+  (let ((large (lambda ((y : int)) : int (* (+ y 1) (- (* y y) (+ y 2))))))
+    (+ (small 1) (large 2))))
+"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let module = construct_module_from_prog(&compile_exp(&exp).unwrap()).unwrap();
+    let total = parity_wasm::serialize(module.clone()).unwrap().len();
+    let report = size_report(&module).unwrap();
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines[0], format!("{:>8}  total", total));
+    assert_eq!(
+        lines.iter().any(|line| line.ends_with("  code section")),
+        true
+    );
+    assert_eq!(
+        lines.iter().any(|line| line.ends_with("  name section")),
+        true
+    );
+
+    // functions are listed by name, largest first
+    let functions: Vec<&str> = lines[1 + module.sections().len()..]
+        .iter()
+        .filter_map(|line| line.split("  function ").nth(1))
+        .collect();
+    assert_eq!(functions.len(), 3);
+    assert_eq!(
+        functions.iter().position(|name| *name == "large")
+            < functions.iter().position(|name| *name == "small"),
+        true
+    );
+    assert_eq!(functions.contains(&"$$MAIN$$"), true);
+}
+
 #[test]
 fn test_handwritten_lambda() {
     let module = builder::module()
@@ -520,3 +1539,308 @@ fn test_handwritten_tuple() {
 
     assert_eq!(values[0], Value::I32(10));
 }
+
+#[test]
+fn test_compile_instrument_calls() {
+    let exp = parse(
+        &lexpr::from_str(
+            r#"
+(let ((add-one (lambda ((x : int)) : int (+ x 1))))
+  (let ((twice (lambda ((y : int)) : int (* y 2))))
+    (add-one (add-one (twice 5)))))
+"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let options = CodeGenerateOptions {
+        instrument: Instrumentation::Calls,
+        ..CodeGenerateOptions::default()
+    };
+    let module = construct_module_from_prog_with_options(&prog, &options).unwrap();
+    let binary = parity_wasm::serialize(module).unwrap();
+    let instance = instantiate(&binary, &imports! {}).unwrap();
+    let values = instance.dyn_func("$$MAIN$$").unwrap().call(&[]).unwrap();
+    assert_eq!(values[0], Value::I32(12));
+
+    let mut counts = instance
+        .exports()
+        .filter_map(|(name, export)| match export {
+            Export::Global(global) => Some((name, global.get())),
+            _ => None,
+        })
+        .collect::<Vec<(String, Value)>>();
+    counts.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        counts,
+        vec![
+            (String::from("$$CALLS$$.add-one"), Value::I32(2)),
+            (String::from("$$CALLS$$.twice"), Value::I32(1)),
+        ]
+    );
+}
+
+#[test]
+fn test_compile_instrument_coverage() {
+    let exp = parse(
+        &lexpr::from_str(
+            r#"
+(let ((abs (lambda ((x : int)) : int (if (< x 0) (- 0 x) x))))
+  (+ (abs 3) (abs 4)))
+"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let options = CodeGenerateOptions {
+        instrument: Instrumentation::Coverage,
+        ..CodeGenerateOptions::default()
+    };
+    let module = construct_module_from_prog_with_options(&prog, &options).unwrap();
+    let binary = parity_wasm::serialize(module.clone()).unwrap();
+    let instance = instantiate(&binary, &imports! {}).unwrap();
+    let values = instance.dyn_func("$$MAIN$$").unwrap().call(&[]).unwrap();
+    assert_eq!(values[0], Value::I32(7));
+
+    let counts = instance
+        .exports()
+        .filter_map(|(name, export)| match export {
+            Export::Global(global) => match global.get() {
+                Value::I32(count) => Some((
+                    name.trim_start_matches("$$COVERAGE$$.").parse().unwrap(),
+                    count,
+                )),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect::<BTreeMap<u32, i32>>();
+    assert_eq!(counts.len(), 2);
+    let report = coverage_report(&module, &counts).unwrap();
+    assert_eq!(
+        report,
+        "       0  then branch of (if (< x 0) ...)\n       2  else branch of (if (< x 0) ...)\n"
+    );
+
+    // modules without coverage can't be reported on
+    let module = construct_module_from_prog(&prog).unwrap();
+    assert_eq!(coverage_report(&module, &counts).is_err(), true);
+}
+
+#[test]
+fn test_compile_builtin_random() {
+    let exp = parse(
+        &lexpr::from_str(
+            r#"
+(let ((roll (lambda ((sides : int)) : int (+ (random sides) 1))))
+  (+ (roll 6) (random 100)))
+"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let module = construct_module_from_prog(&prog).unwrap();
+    let names = module.names_section().unwrap();
+    let function_names = names.functions().unwrap().names();
+    assert_eq!(function_names.get(0).unwrap(), "random");
+    assert_eq!(function_names.get(1).unwrap(), "roll");
+    let binary = parity_wasm::serialize(module).unwrap();
+
+    // the host decides how random numbers are generated, so results can be
+    // made reproducible in tests
+    fn random(n: i32) -> i32 {
+        n - 1
+    }
+    let import_object = imports! {
+        "env" => {
+            "random" => func!(random),
+        },
+    };
+    let instance = instantiate(&binary, &import_object).unwrap();
+    let values = instance.dyn_func("$$MAIN$$").unwrap().call(&[]).unwrap();
+    assert_eq!(values[0], Value::I32(105));
+
+    // programs which don't use builtins don't import anything
+    let exp = parse(&lexpr::from_str("(+ 1 2)").unwrap()).unwrap();
+    let module = construct_module_from_prog(&compile_exp(&exp).unwrap()).unwrap();
+    assert_eq!(
+        module.import_count(parity_wasm::elements::ImportCountType::Function),
+        0
+    );
+    // builtins which use strings can't be compiled yet
+    let exp = parse(&lexpr::from_str(r#"(write-file "a.txt" "hello")"#).unwrap()).unwrap();
+    let typed_exp = type_check(&exp).unwrap();
+    let mut state = CodeGenerateState::default();
+    assert_eq!(gen_instr(&typed_exp, &mut state).is_err(), true);
+    let exp = parse(&lexpr::from_str("(null? (command-line))").unwrap()).unwrap();
+    let typed_exp = type_check(&exp).unwrap();
+    let mut state = CodeGenerateState::default();
+    assert_eq!(gen_instr(&typed_exp, &mut state).is_err(), true);
+}
+
+#[test]
+fn test_generate_code_errors() {
+    let exp = parse(&lexpr::from_str(r#"(begin 3 "hello")"#).unwrap()).unwrap();
+    let typed_exp = type_check(&exp).unwrap();
+    let mut state = CodeGenerateState::default();
+    match gen_instr(&typed_exp, &mut state) {
+        Err(CodeGenerateError::UnsupportedConstruct(_message, exp)) => {
+            assert_eq!(*exp.kind, ExprKind::Str(String::from("hello")))
+        }
+        other => panic!("Expected an unsupported construct error, got {:?}", other),
+    }
+
+    // errors in lifted functions are returned too
+    let exp =
+        parse(&lexpr::from_str(r#"(let ((f (lambda () : int (begin "hello" 3)))) (f))"#).unwrap())
+            .unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    match construct_module_from_prog(&prog) {
+        Err(CodeGenerateError::UnsupportedConstruct(_message, exp)) => {
+            assert_eq!(*exp.kind, ExprKind::Str(String::from("hello")))
+        }
+        other => panic!("Expected an unsupported construct error, got {:?}", other),
+    }
+
+    let exp = TypedExpr::new(Type::Int, ExprKind::Id(String::from("x")));
+    let mut state = CodeGenerateState::default();
+    match gen_instr(&exp, &mut state) {
+        Err(CodeGenerateError::InvalidIdentifier(name)) => assert_eq!(name, "x"),
+        other => panic!("Expected an invalid identifier error, got {:?}", other),
+    }
+
+    let exp = TypedExpr::new(
+        Type::Int,
+        ExprKind::TupleGet(TypedExpr::new(Type::Int, ExprKind::Num(3)), 0),
+    );
+    let mut state = CodeGenerateState::default();
+    match gen_instr(&exp, &mut state) {
+        Err(CodeGenerateError::TypeUnknownAtCodegen(_message, typ)) => {
+            assert_eq!(typ, Type::Int)
+        }
+        other => panic!("Expected a type error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_compile_wasm_asm() {
+    let cases = vec![
+        (r#"(wasm-asm "i32.popcnt" : int 255)"#, 8),
+        (r#"(wasm-asm "i32.sub" : int 10 3)"#, 7),
+        // folded instructions
+        (r#"(wasm-asm "(i32.shl (i32.const 3))" : int 5)"#, 40),
+        (
+            r#"(if (wasm-asm "(i32.lt_u (i32.const -1) (i32.const 0))" : bool) 1 2)"#,
+            2,
+        ),
+        // the high bits of a 64-bit product
+        (
+            r#"(wasm-asm "i64.extend_i32_s i64.const 3000000000 i64.mul i64.const 32 i64.shr_s i32.wrap_i64" : int 10)"#,
+            6,
+        ),
+        (r#"(let ((x 5)) (+ x (wasm-asm "i32.mul" : int x x)))"#, 30),
+    ];
+    for (source, expected) in cases {
+        let exp = parse(&lexpr::from_str(source).unwrap()).unwrap();
+        let prog = compile_exp(&exp).unwrap();
+        let binary = parity_wasm::serialize(construct_module_from_prog(&prog).unwrap()).unwrap();
+        let instance = instantiate(&binary, &imports! {}).unwrap();
+        let values = instance.dyn_func("$$MAIN$$").unwrap().call(&[]).unwrap();
+        assert_eq!(values[0], Value::I32(expected), "result of {}", source);
+    }
+
+    // instructions which can't be used, or aren't valid
+    for source in &[
+        r#"(wasm-asm "local.get 0" : int)"#,
+        r#"(wasm-asm "i32.const" : int)"#,
+        r#"(wasm-asm "i32.const 5000000000" : int)"#,
+        r#"(wasm-asm "(3 i32.add)" : int 1 2)"#,
+    ] {
+        let exp = parse(&lexpr::from_str(source).unwrap()).unwrap();
+        let prog = compile_exp(&exp).unwrap();
+        assert_eq!(
+            construct_module_from_prog(&prog).is_err(),
+            true,
+            "{}",
+            source
+        );
+    }
+    // the stack isn't checked until the module is validated
+    let exp = parse(&lexpr::from_str(r#"(wasm-asm "i32.add" : int 1)"#).unwrap()).unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let binary = parity_wasm::serialize(construct_module_from_prog(&prog).unwrap()).unwrap();
+    assert_eq!(instantiate(&binary, &imports! {}).is_err(), true);
+}
+
+#[test]
+fn test_compile_extern() {
+    let exp = lexpr::from_str(
+        r#"
+(begin
+  (declare-extern clamp "math" "clamp" (pure-> int int int int))
+  (declare-extern notify "host" "notify" (-> bool int))
+  (+ (clamp 15 0 10) (notify (< 1 2))))
+"#,
+    )
+    .unwrap();
+    let (expanded, _constants) = macro_expand_with_constants(&exp).unwrap();
+    let prog = compile_exp(&parse(&expanded).unwrap()).unwrap();
+    let module = construct_module_from_prog(&prog).unwrap();
+    assert_eq!(
+        module.import_count(parity_wasm::elements::ImportCountType::Function),
+        2
+    );
+    let binary = parity_wasm::serialize(module).unwrap();
+
+    fn clamp(x: i32, low: i32, high: i32) -> i32 {
+        x.max(low).min(high)
+    }
+    fn notify(flag: i32) -> i32 {
+        flag * 100
+    }
+    let import_object = imports! {
+        "math" => {
+            "clamp" => func!(clamp),
+        },
+        "host" => {
+            "notify" => func!(notify),
+        },
+    };
+    let instance = instantiate(&binary, &import_object).unwrap();
+    let values = instance.dyn_func("$$MAIN$$").unwrap().call(&[]).unwrap();
+    assert_eq!(values[0], Value::I32(110));
+}
+
+#[test]
+fn test_compile_builtin_current_millis() {
+    let exp = parse(
+        &lexpr::from_str(
+            r#"
+(let ((start (current-millis)))
+  (let ((elapsed (- (current-millis) start)))
+    (if (< elapsed 0) 0 elapsed)))
+"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let module = construct_module_from_prog(&prog).unwrap();
+    let binary = parity_wasm::serialize(module).unwrap();
+
+    let start = std::time::Instant::now();
+    let import_object = imports! {
+        "env" => {
+            "current-millis" => func!(move || -> i32 { start.elapsed().as_millis() as i32 }),
+        },
+    };
+    let instance = instantiate(&binary, &import_object).unwrap();
+    let values = instance.dyn_func("$$MAIN$$").unwrap().call(&[]).unwrap();
+    match values[0] {
+        Value::I32(elapsed) => assert!((0..10_000).contains(&elapsed)),
+        _ => panic!("Expected an i32 result."),
+    }
+}
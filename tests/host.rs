@@ -0,0 +1,108 @@
+#![cfg(feature = "host")]
+
+use scheme_to_wasm::compile::compile_exp;
+use scheme_to_wasm::host::{runtime_imports, seed_random, Instance};
+use scheme_to_wasm::interpret::{eval_source, EvalOptions};
+use scheme_to_wasm::parse::parse;
+use scheme_to_wasm::util::format_float;
+use serial_test_derive::serial;
+use wasmer_runtime::{func, imports};
+
+#[test]
+fn test_host_call_main() {
+    let exp = parse(&lexpr::from_str("(let ((x 5)) (* x 3))").unwrap()).unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let instance = Instance::new(&prog, &imports! {}).unwrap();
+    assert_eq!(instance.call::<(), i32>("$$MAIN$$", ()).unwrap(), 15);
+
+    // the return type must match the program's type
+    assert_eq!(instance.call::<(), bool>("$$MAIN$$", ()).is_err(), true);
+    // as must the arguments
+    assert_eq!(
+        instance.call::<(i32,), i32>("$$MAIN$$", (3,)).is_err(),
+        true
+    );
+    // and the function must exist
+    assert_eq!(instance.call::<(), i32>("add", ()).is_err(), true);
+
+    let exp = parse(&lexpr::from_str("(/ (exact->inexact 7) 2.0)").unwrap()).unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let instance = Instance::new(&prog, &imports! {}).unwrap();
+    assert_eq!(instance.call::<(), f32>("$$MAIN$$", ()).unwrap(), 3.5);
+
+    let exp = parse(&lexpr::from_str("(< (random 10) 10)").unwrap()).unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    fn random(n: i32) -> i32 {
+        n / 2
+    }
+    let import_object = imports! {
+        "env" => {
+            "random" => func!(random),
+        },
+    };
+    let instance = Instance::new(&prog, &import_object).unwrap();
+    assert_eq!(instance.call::<(), bool>("$$MAIN$$", ()).unwrap(), true);
+}
+
+#[test]
+fn test_host_bench() {
+    let exp = parse(&lexpr::from_str("(let ((x 5)) (* x 3))").unwrap()).unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let instance = Instance::new(&prog, &imports! {}).unwrap();
+    let report = instance.bench(20).unwrap();
+    assert_eq!(report.runs, 20);
+    assert!(report.median <= report.p95);
+
+    assert_eq!(instance.bench(0).is_err(), true);
+}
+
+#[test]
+#[serial]
+fn test_runtime_imports() {
+    let source = "(+ (expt (sin 1.0) 2.0) (atan2 (log 2.0) (exp (tan (cos 0.5)))))";
+    let exp = parse(&lexpr::from_str(source).unwrap()).unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let instance = Instance::new(&prog, &runtime_imports()).unwrap();
+    let result = instance.call::<(), f32>("$$MAIN$$", ()).unwrap();
+    assert_eq!(
+        eval_source(source, &EvalOptions::default()).unwrap(),
+        format_float(result)
+    );
+
+    let exp = parse(
+        &lexpr::from_str("(and (< (random 10) 10) (and (= (random 1) 0) (>= (current-millis) 0)))")
+            .unwrap(),
+    )
+    .unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let instance = Instance::new(&prog, &runtime_imports()).unwrap();
+    for _ in 0..10 {
+        assert_eq!(instance.call::<(), bool>("$$MAIN$$", ()).unwrap(), true);
+    }
+
+    // random traps on nonpositive bounds, like the interpreter's
+    let exp = parse(&lexpr::from_str("(random 0)").unwrap()).unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let instance = Instance::new(&prog, &runtime_imports()).unwrap();
+    assert_eq!(instance.call::<(), i32>("$$MAIN$$", ()).is_err(), true);
+}
+
+#[test]
+#[serial]
+fn test_runtime_imports_seed() {
+    let source = "(+ (exact->inexact (random 1000)) (random-float))";
+    let exp = parse(&lexpr::from_str(source).unwrap()).unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let instance = Instance::new(&prog, &runtime_imports()).unwrap();
+    seed_random(7);
+    let result = instance.call::<(), f32>("$$MAIN$$", ()).unwrap();
+    seed_random(7);
+    assert_eq!(instance.call::<(), f32>("$$MAIN$$", ()).unwrap(), result);
+
+    // the same seed gives the same numbers as in the interpreter
+    let options = EvalOptions {
+        seed: Some(7),
+        ..EvalOptions::default()
+    };
+    assert_eq!(eval_source(source, &options).unwrap(), format_float(result));
+}
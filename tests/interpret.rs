@@ -0,0 +1,429 @@
+use scheme_to_wasm::compile::compile_exp;
+use scheme_to_wasm::generate_code::construct_module_from_prog;
+use scheme_to_wasm::interpret::{eval, eval_source, EvalOptions};
+use scheme_to_wasm::parse::parse;
+use scheme_to_wasm::read::read;
+use scheme_to_wasm::type_check::type_check;
+use wasmer_runtime::{func, imports, instantiate};
+
+fn eval_str(source: &str) -> String {
+    eval_source(source, &EvalOptions::default()).unwrap()
+}
+
+#[test]
+fn test_eval_values() {
+    let cases = vec![
+        ("(* (+ 3 5) (- 4 2))", "16"),
+        ("(- -2147483648 1)", "2147483647"),
+        ("(if (and (< 1 2) (= 3 3)) 10 20)", "10"),
+        ("(let ((x 1) (y 2)) (let ((x y)) (+ x y)))", "4"),
+        ("(cons 1 (cons 2 (null int)))", "(1 2)"),
+        ("(car (cdr (cons 1 (cons 2 (null int)))))", "2"),
+        ("(/ (exact->inexact 7) 2.0)", "3.5"),
+        ("(- 0.5 1.5)", "-1.0"),
+        ("(inexact->exact (* 2.5 4.0))", "10"),
+        ("(float->int -2.7)", "-2"),
+        ("(round 2.5)", "2.0"),
+        ("(round -3.5)", "-4.0"),
+        ("(floor -2.5)", "-3.0"),
+        ("(ceiling 2.1)", "3.0"),
+        ("(truncate -2.7)", "-2.0"),
+        ("(expt 3 4)", "81"),
+        ("(expt 2 32)", "0"),
+        ("(expt 2.0 -1.0)", "0.5"),
+        ("(sqrt 2.25)", "1.5"),
+        ("(exp 0.0)", "1.0"),
+        ("(log 1.0)", "0.0"),
+        ("(sin 0.0)", "0.0"),
+        ("(cos 0.0)", "1.0"),
+        ("(atan2 1.0 0.0)", "1.5707964"),
+        ("(nan? (/ 0.0 0.0))", "true"),
+        ("(nan? 1.0)", "false"),
+        ("(infinite? (/ -1.0 0.0))", "true"),
+        ("(infinite? 1.0)", "false"),
+        ("(/ 1.0 3.0)", "0.33333334"),
+        ("(* 1000000.0 1000000.0)", "1000000000000.0"),
+        (
+            "(* 4294967296.0 (* 4294967296.0 4294967296.0))",
+            "79228163000000000000000000000.0",
+        ),
+        ("(/ 1.0 -80000000.0)", "-0.0000000125"),
+        ("(/ 1.0 10000000.0)", "0.0000001"),
+        ("(- 0.0 0.0)", "0.0"),
+        ("(* -1.0 0.0)", "-0.0"),
+        ("(/ 0.0 0.0)", "+nan.0"),
+        ("(/ -1.0 0.0)", "-inf.0"),
+        ("(float->string 2.5)", r#""2.5""#),
+        ("(null? (null int))", "true"),
+        (r#"(concat "a" "b")"#, r#""ab""#),
+        (
+            "(make-tuple 1 (make-tuple) true)",
+            "(make-tuple 1 (make-tuple) true)",
+        ),
+        ("(record-ref (make-record (x 1) (y 2)) y)", "2"),
+        (
+            "(let ((t (make-tuple 1 2))) (begin (tuple-set! t 0 5) t))",
+            "(make-tuple 5 2)",
+        ),
+        (
+            "(typecase (inject true (union int bool)) ((int x) x) ((bool b) (if b 1 0)))",
+            "1",
+        ),
+        (
+            r#"
+(unpack (p (pack (make-tuple 3 (lambda ((x : int)) : int (+ x 1)))
+                 int
+                 (exists T0 (tuple T0 (-> T0 int))))
+           T1)
+  ((tuple-ref p 1) (tuple-ref p 0)))
+"#,
+            "4",
+        ),
+        ("(lambda ((x : int)) : int x)", "#<procedure>"),
+        (
+            r#"(format "~a and ~s, ~d~~" "a" "b" 3)"#,
+            r#""a and \"b\", 3~""#,
+        ),
+    ];
+    for (source, expected) in cases {
+        assert_eq!(eval_str(source), expected, "value of {}", source);
+    }
+}
+
+#[test]
+fn test_eval_float_round_trip() {
+    // printed floats read back as the same float, however large or small
+    let cases = vec![
+        "(* 10000000000.0 10000000000.0)",
+        "(/ 1.0 -80000000.0)",
+        "(/ 1.0 3.0)",
+        "(* 3.0 (* 10000000000000000000.0 10000000000000000000.0))",
+    ];
+    for source in cases {
+        let output = eval_str(source);
+        assert_eq!(eval_str(&output), output, "{}", source);
+        let exp = parse(&read(&output).unwrap()).unwrap();
+        assert_eq!(exp.to_string(), output, "{}", source);
+    }
+    assert_eq!(
+        eval_str("(* 10000000000.0 10000000000.0)"),
+        "100000000000000000000.0"
+    );
+}
+
+#[test]
+fn test_eval_closures() {
+    // closures share the variables they capture
+    let source = r#"
+(let ((make-counter (lambda ((start : int)) : (-> int)
+                      (lambda () : int (begin (set! start (+ start 1)) start)))))
+  (let ((a (make-counter 0)) (b (make-counter 10)))
+    (+ (begin (a) (a)) (b))))
+"#;
+    assert_eq!(eval_str(source), "13");
+
+    let source = r#"
+(let ((sub (lambda ((x : int) (y : int)) : int (- x y)))
+      (double (lambda ((x : int)) : int (* x 2))))
+  ((compose double (curry sub 10)) 4))
+"#;
+    assert_eq!(eval_str(source), "12");
+
+    // promises are only evaluated once
+    let source = r#"
+(let ((n 0))
+  (let ((p (delay (begin (set! n (+ n 1)) n))))
+    (+ (force p) (+ (force p) n))))
+"#;
+    assert_eq!(eval_str(source), "3");
+
+    let source = "(let ((i 0) (sum 0)) (begin (while (< i 5) (begin (set! i (+ i 1)) (set! sum (+ sum i)))) sum))";
+    assert_eq!(eval_str(source), "15");
+}
+
+#[test]
+fn test_eval_errors() {
+    let options = EvalOptions::default();
+    let error = eval_source("(/ 1 (- 1 1))", &options).unwrap_err();
+    assert_eq!(error.to_string(), "EvalError: Cannot divide 1 by 0.");
+    let error = eval_source(r#"(assert (< 2 1) "two is less than one")"#, &options).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "EvalError: Assertion failed: two is less than one"
+    );
+    assert_eq!(eval_source("(car (null int))", &options).is_err(), true);
+    let error = eval_source("(inexact->exact 2.5)", &options).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "EvalError: Invalid argument to inexact->exact: 2.5"
+    );
+    let error = eval_source("(float->int (/ 1.0 0.0))", &options).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "EvalError: Invalid argument to float->int: +inf.0"
+    );
+    let error = eval_source("(expt 2 (- 0 1))", &options).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "EvalError: Cannot raise 2 to a negative power -1."
+    );
+    // programs are type checked first
+    assert_eq!(eval_source("(+ 1 true)", &options).is_err(), true);
+}
+
+#[test]
+fn test_eval_string_match() {
+    let source = r#"
+(typecase (string-match "(\\w+)@(\\w+)\\.com" "mail bob@example.com now")
+  (((list string) groups) (car (cdr (cdr groups))))
+  (((tuple) none) "no match"))
+"#;
+    assert_eq!(eval_str(source), r#""example""#);
+    let source = r#"
+(typecase (string-match "^\\d+$" "12a")
+  (((list string) groups) (car groups))
+  (((tuple) none) "no match"))
+"#;
+    assert_eq!(eval_str(source), r#""no match""#);
+    let source = r#"(string-replace "(\\d+)-(\\d+)" "1-2, 30-40" "\\2-\\1")"#;
+    assert_eq!(eval_str(source), r#""2-1, 40-30""#);
+
+    let error = eval_source(r#"(string-replace "a(" "abc" "")"#, &EvalOptions::default());
+    assert_eq!(
+        error.unwrap_err().to_string(),
+        "EvalError: RegexError: Unclosed ( in pattern."
+    );
+}
+
+#[test]
+fn test_eval_bytes() {
+    let cases = vec![
+        (r#"(string->utf8 "hé")"#, "(104 195 169)"),
+        (r#"(hex-encode (string->utf8 "hé"))"#, r#""68c3a9""#),
+        (r#"(hex-decode "68C3a9")"#, "(104 195 169)"),
+        (r#"(hex-decode "6")"#, "(make-tuple)"),
+        (r#"(hex-decode "zz")"#, "(make-tuple)"),
+        (
+            r#"(utf8->string (cons 104 (cons 105 (null int))))"#,
+            r#""hi""#,
+        ),
+        (r#"(utf8->string (cons 195 (null int)))"#, "(make-tuple)"),
+        (r#"(base64-encode (null int))"#, r#""""#),
+        (r#"(base64-encode (string->utf8 "f"))"#, r#""Zg==""#),
+        (r#"(base64-encode (string->utf8 "fo"))"#, r#""Zm8=""#),
+        (
+            r#"(base64-encode (string->utf8 "foobar"))"#,
+            r#""Zm9vYmFy""#,
+        ),
+        (r#"(base64-decode "Zm9vYg==")"#, "(102 111 111 98)"),
+        (r#"(base64-decode "Zm9vYg=")"#, "(make-tuple)"),
+        (r#"(base64-decode "Zg==Zg==")"#, "(make-tuple)"),
+        (r#"(base64-decode "Zm9v!mFy")"#, "(make-tuple)"),
+    ];
+    for (source, expected) in cases {
+        assert_eq!(eval_str(source), expected, "value of {}", source);
+    }
+    let source = r#"
+(typecase (base64-decode (base64-encode (string->utf8 "round trip")))
+  (((list int) bytes)
+   (typecase (utf8->string bytes)
+     ((string s) s)
+     (((tuple) none) "invalid")))
+  (((tuple) none) "invalid"))
+"#;
+    assert_eq!(eval_str(source), r#""round trip""#);
+
+    let error = eval_source(
+        "(hex-encode (cons 256 (null int)))",
+        &EvalOptions::default(),
+    );
+    assert_eq!(
+        error.unwrap_err().to_string(),
+        "EvalError: Expected a byte, found 256"
+    );
+}
+
+#[test]
+fn test_eval_string_indexing() {
+    // strings are indexed by scalar values, whatever their size in UTF-8
+    let cases = vec![
+        (r#"(string-length "héllo")"#, "5"),
+        (r#"(string-length "")"#, "0"),
+        (r#"(string-ref "héllo" 1)"#, r#""é""#),
+        (r#"(string-ref "a🦀b" 2)"#, r#""b""#),
+        (r#"(substring "héllo" 1 3)"#, r#""él""#),
+        (r#"(substring "héllo" 5 5)"#, r#""""#),
+        (r#"(string->list "a🦀")"#, r#"("a" "🦀")"#),
+        (r#"(string->list "")"#, "()"),
+        // a letter followed by a combining accent is two scalar values, but
+        // a single grapheme
+        ("(string-length \"e\u{301}\")", "2"),
+        ("(string-graphemes \"e\u{301}x\")", "(\"e\u{301}\" \"x\")"),
+        (r#"(string-graphemes "🇫🇷👍🏽")"#, r#"("🇫🇷" "👍🏽")"#),
+    ];
+    for (source, expected) in cases {
+        assert_eq!(eval_str(source), expected, "value of {}", source);
+    }
+
+    let options = EvalOptions::default();
+    let error = eval_source(r#"(string-ref "héllo" 5)"#, &options).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "EvalError: Index 5 is out of range for a string of length 5."
+    );
+    assert_eq!(
+        eval_source(r#"(string-ref "abc" -1)"#, &options).is_err(),
+        true
+    );
+    assert_eq!(
+        eval_source(r#"(substring "abc" 2 1)"#, &options).is_err(),
+        true
+    );
+    assert_eq!(
+        eval_source(r#"(substring "abc" 0 4)"#, &options).is_err(),
+        true
+    );
+}
+
+#[test]
+fn test_eval_options() {
+    let options = EvalOptions {
+        trace: true,
+        ..EvalOptions::default()
+    };
+    let output = eval_source("(let ((x 2)) (+ 1 (* x 3)))", &options).unwrap();
+    assert_eq!(
+        output,
+        "(let ((x 2)) (+ 1 (* x 3))) => 7\n  (+ 1 (* x 3)) => 7\n    (* x 3) => 6\n7"
+    );
+
+    let options = EvalOptions {
+        trace: false,
+        args: vec![String::from("one"), String::from("two")],
+        ..EvalOptions::default()
+    };
+    let output = eval_source("(car (cdr (command-line)))", &options).unwrap();
+    assert_eq!(output, r#""two""#);
+}
+
+#[test]
+fn test_eval_random_seed() {
+    let source = "(cons (random 1000000) (cons (random 10) (cons (random 1000000) (null int))))";
+    let options = EvalOptions {
+        seed: Some(42),
+        ..EvalOptions::default()
+    };
+    let output = eval_source(source, &options).unwrap();
+    assert_eq!(eval_source(source, &options).unwrap(), output);
+    let other = EvalOptions {
+        seed: Some(43),
+        ..EvalOptions::default()
+    };
+    assert_ne!(eval_source(source, &other).unwrap(), output);
+
+    let source = "(let ((x (random-float))) (if (and (>= x 0.0) (< x 1.0)) x -1.0))";
+    let output = eval_source(source, &options).unwrap();
+    assert_eq!(eval_source(source, &options).unwrap(), output);
+    assert_ne!(output, "-1.0");
+}
+
+#[test]
+fn test_eval_matches_compiled() {
+    // the interpreter is the reference for what compiled programs compute
+    let sources = vec![
+        "(* (+ 3 5) (- 4 2))",
+        "(let ((x 5)) (begin (set! x (* x x)) (- x 1)))",
+        "(let ((f (lambda ((x : int) (y : int)) : int (- x y)))) ((curry f 10) 3))",
+        "(let ((x 0)) (let ((f (lambda () : int (set! x (+ x 1))))) (begin (f) (f) x)))",
+        "(typecase (inject 3 (union int bool)) ((int x) (+ x 1)) ((bool b) 0))",
+        "(let ((p (delay (+ 1 2)))) (+ (force p) (force p)))",
+        "(record-ref (make-record (x 3) (y 4)) y)",
+        "(inexact->exact (* (+ (exact->inexact 3) 0.5) 2.0))",
+        "(if (< (- 0.5 1.0) 0.0) 1 0)",
+        "(+ (float->int (round 6.5)) (float->int (floor -1.5)))",
+        "(float->int (* (ceiling 1.2) (truncate (int->float -3))))",
+        "(let ((x 7)) (- (expt x 3) (expt -3 x)))",
+        "(let ((x 40000)) (expt x 3))",
+        "(float->int (sqrt 144.0))",
+        "(let ((x 4)) (case (- x 1) ((0 1) 10) ((2 3 4) 20) ((5) 30) (else 40)))",
+        "(let ((x 9)) (case x ((1) 10) ((1000) 20) (else x)))",
+        "(let ((x -1)) (+ (u/ x 16) (u% x 16)))",
+        "(let ((x -1)) (if (u< x 1) 1 (if (u> x 1) 2 3)))",
+    ];
+    for source in sources {
+        let exp = parse(&lexpr::from_str(source).unwrap()).unwrap();
+        let expected = match eval(&type_check(&exp).unwrap()).unwrap() {
+            scheme_to_wasm::interpret::Value::Int(x) => x,
+            val => panic!("Expected an int from {}, found {}", source, val),
+        };
+        let prog = compile_exp(&exp).unwrap();
+        let binary = parity_wasm::serialize(construct_module_from_prog(&prog).unwrap()).unwrap();
+        let instance = instantiate(&binary, &imports! {}).unwrap();
+        let values = instance.dyn_func("$$MAIN$$").unwrap().call(&[]).unwrap();
+        assert_eq!(
+            values[0],
+            wasmer_runtime::Value::I32(expected),
+            "result of {}",
+            source
+        );
+    }
+}
+
+#[test]
+fn test_eval_matches_compiled_floats() {
+    // the host provides the float functions which wasm lacks, computed the
+    // same way as by the interpreter
+    fn float(x: i32) -> f32 {
+        f32::from_bits(x as u32)
+    }
+    fn bits(x: f32) -> i32 {
+        x.to_bits() as i32
+    }
+    fn sin(x: i32) -> i32 {
+        bits(float(x).sin())
+    }
+    fn cos(x: i32) -> i32 {
+        bits(float(x).cos())
+    }
+    fn tan(x: i32) -> i32 {
+        bits(float(x).tan())
+    }
+    fn atan2(y: i32, x: i32) -> i32 {
+        bits(float(y).atan2(float(x)))
+    }
+    let sources = vec![
+        "(+ (* (sin 0.5) (sin 0.5)) (* (cos 0.5) (cos 0.5)))",
+        "(tan (/ 3.1415927 4.0))",
+        "(atan2 -1.0 -1.0)",
+        "(let ((x 3.0) (y 4.0)) (- (sqrt (+ (* x x) (* y y))) (atan2 y x)))",
+        "(nan? (sqrt -1.0))",
+        "(infinite? (/ (sin 1.0) 0.0))",
+        "(if (nan? (/ 0.0 0.0)) (floor -0.5) 1.0)",
+    ];
+    for source in sources {
+        let exp = parse(&lexpr::from_str(source).unwrap()).unwrap();
+        let expected = match eval(&type_check(&exp).unwrap()).unwrap() {
+            scheme_to_wasm::interpret::Value::Float(x) => bits(x),
+            scheme_to_wasm::interpret::Value::Bool(b) => b as i32,
+            val => panic!("Expected a float or bool from {}, found {}", source, val),
+        };
+        let prog = compile_exp(&exp).unwrap();
+        let binary = parity_wasm::serialize(construct_module_from_prog(&prog).unwrap()).unwrap();
+        let import_object = imports! {
+            "env" => {
+                "sin" => func!(sin),
+                "cos" => func!(cos),
+                "tan" => func!(tan),
+                "atan2" => func!(atan2),
+            },
+        };
+        let instance = instantiate(&binary, &import_object).unwrap();
+        let values = instance.dyn_func("$$MAIN$$").unwrap().call(&[]).unwrap();
+        assert_eq!(
+            values[0],
+            wasmer_runtime::Value::I32(expected),
+            "result of {}",
+            source
+        );
+    }
+}
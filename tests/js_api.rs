@@ -0,0 +1,24 @@
+#![cfg(feature = "js-api")]
+
+use scheme_to_wasm::js_api::compile;
+use wasmer_runtime::{imports, instantiate, Value};
+
+#[test]
+fn test_compile() {
+    let output = compile("(let ((x 20)) (+ x 22))").unwrap();
+    assert_eq!(output.typ(), "int");
+    let instance = instantiate(&output.wasm(), &imports! {}).unwrap();
+    let values = instance.dyn_func("$$MAIN$$").unwrap().call(&[]).unwrap();
+    assert_eq!(values[0], Value::I32(42));
+
+    let diagnostics = compile("(if 1 2 3)").unwrap_err();
+    assert_eq!(diagnostics.code(), Some(String::from("T001")));
+    assert_eq!(
+        diagnostics.message(),
+        "TypeCheckError: Predicate of if expression must have type bool, but 1 has type int."
+    );
+
+    let diagnostics = compile("(+ 1").unwrap_err();
+    assert_eq!(diagnostics.code(), None);
+    assert_eq!(diagnostics.message().starts_with("ReadError"), true);
+}
@@ -0,0 +1,171 @@
+use scheme_to_wasm::compile::compile_exp;
+use scheme_to_wasm::explain::error_code;
+use scheme_to_wasm::generate_code::{
+    construct_module_from_prog_with_options, CodeGenerateError, CodeGenerateOptions,
+};
+use scheme_to_wasm::interpret::{eval_source, EvalOptions};
+use scheme_to_wasm::limits::{check_value, CompilerLimits, LimitError};
+use scheme_to_wasm::macro_expand::macro_expand_with_limits;
+use scheme_to_wasm::parse::{parse, parse_with_limits};
+
+#[test]
+fn test_check_value() {
+    let exp = lexpr::from_str("(+ 1 (* 2 (- 3 4)))").unwrap();
+    let limits = CompilerLimits {
+        max_depth: 4,
+        max_nodes: 10,
+        ..CompilerLimits::default()
+    };
+    assert_eq!(check_value(&exp, &limits), Ok(()));
+    let limits = CompilerLimits {
+        max_depth: 3,
+        ..CompilerLimits::default()
+    };
+    assert_eq!(check_value(&exp, &limits), Err(LimitError::TooDeep(3)));
+    let limits = CompilerLimits {
+        max_nodes: 9,
+        ..CompilerLimits::default()
+    };
+    assert_eq!(check_value(&exp, &limits), Err(LimitError::TooManyNodes(9)));
+    assert_eq!(check_value(&exp, &CompilerLimits::default()), Ok(()));
+}
+
+#[test]
+fn test_parse_with_limits() {
+    let limits = CompilerLimits {
+        max_depth: 100,
+        ..CompilerLimits::default()
+    };
+    let depth = 120;
+    let source = format!("{}1{}", "(car ".repeat(depth), ")".repeat(depth));
+    let exp = lexpr::from_str(&source).unwrap();
+    let err = parse_with_limits(&exp, &limits).unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<LimitError>(),
+        Some(&LimitError::TooDeep(100))
+    );
+
+    let exp = lexpr::from_str("(let ((x 1)) (+ x 2))").unwrap();
+    assert_eq!(
+        parse_with_limits(&exp, &limits).unwrap(),
+        parse(&exp).unwrap()
+    );
+    // other errors are still parse errors
+    let exp = lexpr::from_str("(let ((x 1)))").unwrap();
+    assert_eq!(
+        parse_with_limits(&exp, &limits)
+            .unwrap_err()
+            .to_string()
+            .starts_with("ParseError"),
+        true
+    );
+}
+
+#[test]
+fn test_macro_expand_with_limits() {
+    let expand_err = |source: &str, limits: &CompilerLimits| {
+        let exp = lexpr::from_str(source).unwrap();
+        let err = macro_expand_with_limits(&exp, limits).unwrap_err();
+        err.downcast_ref::<LimitError>().cloned()
+    };
+    let limits = CompilerLimits::default();
+
+    // macros that expand into themselves forever
+    let source = "(begin
+  (define-syntax forever (syntax-rules () ((forever x) (+ 1 (forever x)))))
+  (forever 1))";
+    assert_eq!(
+        expand_err(source, &limits),
+        Some(LimitError::TooDeep(limits.max_depth))
+    );
+    let source = "(begin
+  (define-macro (forever x) `(+ 1 (forever ,x)))
+  (forever 1))";
+    assert_eq!(
+        expand_err(source, &limits),
+        Some(LimitError::TooDeep(limits.max_depth))
+    );
+    let source = "(begin
+  (define-syntax spin (syntax-rules () ((spin x) (spin x))))
+  (spin 1))";
+    assert_eq!(
+        expand_err(source, &limits),
+        Some(LimitError::TooDeep(limits.max_depth))
+    );
+
+    // a macro whose output doubles in size at each step
+    let source = "(begin
+  (define-syntax grow (syntax-rules () ((grow x) (grow (+ x x)))))
+  (grow 1))";
+    assert_eq!(
+        expand_err(source, &limits),
+        Some(LimitError::TooManyNodes(limits.max_nodes))
+    );
+
+    let exp = lexpr::from_str(
+        "(begin (define-syntax twice (syntax-rules () ((twice x) (+ x x)))) (twice 1))",
+    )
+    .unwrap();
+    assert_eq!(
+        macro_expand_with_limits(&exp, &limits).unwrap().to_string(),
+        "(begin (+ 1 1))"
+    );
+    let limits = CompilerLimits {
+        max_expansions: 0,
+        ..CompilerLimits::default()
+    };
+    assert_eq!(
+        macro_expand_with_limits(&exp, &limits)
+            .unwrap_err()
+            .downcast_ref::<LimitError>(),
+        Some(&LimitError::TooManyExpansions(0))
+    );
+
+    let source = "(begin
+  (define-syntax forever (syntax-rules () ((forever x) (+ 1 (forever x)))))
+  (forever 1))";
+    let err = eval_source(source, &EvalOptions::default()).unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<LimitError>(),
+        Some(&LimitError::TooDeep(CompilerLimits::default().max_depth))
+    );
+    assert_eq!(error_code(err.as_ref()), Some("C004"));
+}
+
+#[test]
+fn test_output_size_limit() {
+    let exp =
+        parse(&lexpr::from_str("(let ((f (lambda ((x : int)) : int (* x 2)))) (f 21))").unwrap())
+            .unwrap();
+    let prog = compile_exp(&exp).unwrap();
+    let size = parity_wasm::serialize(
+        construct_module_from_prog_with_options(&prog, &CodeGenerateOptions::default()).unwrap(),
+    )
+    .unwrap()
+    .len();
+
+    let options = CodeGenerateOptions {
+        limits: CompilerLimits {
+            max_output_size: size,
+            ..CompilerLimits::default()
+        },
+        ..CodeGenerateOptions::default()
+    };
+    assert_eq!(
+        construct_module_from_prog_with_options(&prog, &options).is_ok(),
+        true
+    );
+    let options = CodeGenerateOptions {
+        limits: CompilerLimits {
+            max_output_size: size - 1,
+            ..CompilerLimits::default()
+        },
+        ..CodeGenerateOptions::default()
+    };
+    match construct_module_from_prog_with_options(&prog, &options) {
+        Err(CodeGenerateError::LimitExceeded(err)) => {
+            assert_eq!(err, LimitError::OutputTooLarge(size - 1))
+        }
+        result => panic!("Expected a limit error, found {:?}", result),
+    }
+}
@@ -0,0 +1,354 @@
+use scheme_to_wasm::interpret::{eval_source, EvalOptions};
+use scheme_to_wasm::macro_expand::{macro_expand, macro_expand_with_constants};
+use scheme_to_wasm::parse::parse;
+use scheme_to_wasm::type_check::type_check;
+use scheme_to_wasm::types::Type;
+
+#[test]
+fn test_macro_expand_simple() {
+    let exp = lexpr::from_str(
+        r#"
+(begin
+  (define-syntax unless
+    (syntax-rules ()
+      ((unless pred body alt) (if pred alt body))))
+  (unless (< 1 2) 10 20))
+"#,
+    )
+    .unwrap();
+    let expanded = macro_expand(&exp).unwrap();
+    assert_eq!(
+        expanded,
+        lexpr::from_str("(begin (if (< 1 2) 20 10))").unwrap()
+    );
+
+    // literals must match exactly
+    let exp = lexpr::from_str(
+        r#"
+(begin
+  (define-syntax pick
+    (syntax-rules (first second)
+      ((pick first a b) a)
+      ((pick second a b) b)))
+  (+ (pick first 1 2) (pick second 3 4)))
+"#,
+    )
+    .unwrap();
+    let expanded = macro_expand(&exp).unwrap();
+    assert_eq!(expanded, lexpr::from_str("(begin (+ 1 4))").unwrap());
+}
+
+#[test]
+fn test_macro_expand_ellipsis() {
+    let exp = lexpr::from_str(
+        r#"
+(begin
+  (define-syntax my-or
+    (syntax-rules ()
+      ((my-or) false)
+      ((my-or e) e)
+      ((my-or e rest ...) (if e true (my-or rest ...)))))
+  (my-or (< 3 1) (< 2 1) (< 1 2)))
+"#,
+    )
+    .unwrap();
+    let expanded = macro_expand(&exp).unwrap();
+    assert_eq!(
+        expanded,
+        lexpr::from_str("(begin (if (< 3 1) true (if (< 2 1) true (< 1 2))))").unwrap()
+    );
+
+    // nested ellipses
+    let exp = lexpr::from_str(
+        r#"
+(begin
+  (define-syntax sum-all
+    (syntax-rules ()
+      ((sum-all (x ...) ...) (+ 0 (+ 0 x ...) ...))))
+  (sum-all (1 2) (3)))
+"#,
+    )
+    .unwrap();
+    let expanded = macro_expand(&exp).unwrap();
+    assert_eq!(
+        expanded,
+        lexpr::from_str("(begin (+ 0 (+ 0 1 2) (+ 0 3)))").unwrap()
+    );
+}
+
+#[test]
+fn test_macro_expand_hygiene() {
+    // the macro's temporary variable does not capture the user's variable
+    let exp = lexpr::from_str(
+        r#"
+(begin
+  (define-syntax swap!
+    (syntax-rules ()
+      ((swap! a b) (let ((tmp a)) (begin (set! a b) (set! b tmp))))))
+  (let ((tmp 1) (other 2))
+    (begin (swap! tmp other) (- tmp other))))
+"#,
+    )
+    .unwrap();
+    let expanded = macro_expand(&exp).unwrap();
+    let typed_exp = type_check(&parse(&expanded).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Int);
+    let expanded_str = expanded.to_string();
+    assert_eq!(expanded_str.contains("(let ((tmp% "), false);
+    assert_eq!(expanded_str.contains("(set! tmp other)"), true);
+    assert_eq!(expanded_str.contains("(set! other tmp%"), true);
+
+    // free identifiers in the template are captured by variables where the
+    // macro is used, rather than referring to those where it is defined
+    let source = r#"
+(begin
+  (define-syntax add-offset
+    (syntax-rules ()
+      ((add-offset x) (+ x offset))))
+  (let ((offset 10))
+    (let ((f (lambda ((offset : int)) : int (add-offset 1))))
+      (f 100))))
+"#;
+    let expanded = macro_expand(&lexpr::from_str(source).unwrap()).unwrap();
+    assert_eq!(expanded.to_string().contains("(+ 1 offset)"), true);
+    assert_eq!(eval_source(source, &EvalOptions::default()).unwrap(), "101");
+}
+
+#[test]
+fn test_macro_expand_sad() {
+    // no rule matches
+    let exp = lexpr::from_str(
+        r#"
+(begin
+  (define-syntax unless
+    (syntax-rules ()
+      ((unless pred body alt) (if pred alt body))))
+  (unless (< 1 2) 10))
+"#,
+    )
+    .unwrap();
+    assert_eq!(macro_expand(&exp).is_err(), true);
+
+    // definitions outside of begin
+    let exp = lexpr::from_str("(define-syntax foo (syntax-rules () ((foo) 1)))").unwrap();
+    assert_eq!(macro_expand(&exp).is_err(), true);
+
+    // pattern variable under an ellipsis used without one
+    let exp = lexpr::from_str(
+        r#"
+(begin
+  (define-syntax bad
+    (syntax-rules ()
+      ((bad x ...) (+ x 1))))
+  (bad 1 2))
+"#,
+    )
+    .unwrap();
+    assert_eq!(macro_expand(&exp).is_err(), true);
+}
+
+#[test]
+fn test_macro_expand_define_macro() {
+    let exp = lexpr::from_str(
+        r#"
+(begin
+  (define-macro (my-unless pred body alt) `(if ,pred ,alt ,body))
+  (my-unless (< 1 2) 10 20))
+"#,
+    )
+    .unwrap();
+    let expanded = macro_expand(&exp).unwrap();
+    assert_eq!(
+        expanded,
+        lexpr::from_str("(begin (if (< 1 2) 20 10))").unwrap()
+    );
+
+    // the body can compute code, here by unrolling a sum
+    let exp = lexpr::from_str(
+        r#"
+(begin
+  (define-macro (sum . exps)
+    (if (null? exps)
+        0
+        (if (null? (cdr exps))
+            (car exps)
+            `(+ ,(car exps) (sum ,@(cdr exps))))))
+  (sum 1 2 3))
+"#,
+    )
+    .unwrap();
+    let expanded = macro_expand(&exp).unwrap();
+    assert_eq!(expanded, lexpr::from_str("(begin (+ 1 (+ 2 3)))").unwrap());
+    let typed_exp = type_check(&parse(&expanded).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Int);
+
+    // gensym creates fresh names
+    let exp = lexpr::from_str(
+        r#"
+(begin
+  (define-macro (double x)
+    (let ((tmp (gensym)))
+      `(let ((,tmp ,x)) (+ ,tmp ,tmp))))
+  (let ((tmp 5)) (double tmp)))
+"#,
+    )
+    .unwrap();
+    let expanded = macro_expand(&exp).unwrap();
+    let typed_exp = type_check(&parse(&expanded).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Int);
+
+    // wrong number of arguments
+    let exp = lexpr::from_str(
+        r#"
+(begin
+  (define-macro (my-unless pred body alt) `(if ,pred ,alt ,body))
+  (my-unless (< 1 2) 10))
+"#,
+    )
+    .unwrap();
+    assert_eq!(macro_expand(&exp).is_err(), true);
+}
+
+#[test]
+fn test_macro_expand_constants() {
+    let exp = lexpr::from_str(
+        r#"
+(begin
+  (define-constant size 4)
+  (define-constant double-size (* size 2))
+  (define-constant big (if (> double-size 5) true false))
+  (if big (+ size double-size) 0))
+"#,
+    )
+    .unwrap();
+    let expanded = macro_expand(&exp).unwrap();
+    assert_eq!(
+        expanded,
+        lexpr::from_str("(begin (if #t (+ 4 8) 0))").unwrap()
+    );
+    let typed_exp = type_check(&parse(&expanded).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Int);
+
+    // values must be known at compile time
+    let exp = lexpr::from_str(
+        r#"
+(let ((x 3))
+  (begin
+    (define-constant y (+ x 1))
+    y))
+"#,
+    )
+    .unwrap();
+    assert_eq!(macro_expand(&exp).is_err(), true);
+
+    // constants can't be rebound or assigned
+    let exp = lexpr::from_str(
+        r#"
+(begin
+  (define-constant size 4)
+  (let ((size 5)) size))
+"#,
+    )
+    .unwrap();
+    assert_eq!(macro_expand(&exp).is_err(), true);
+
+    let exp = lexpr::from_str(
+        r#"
+(begin
+  (define-constant size 4)
+  (set! size 5))
+"#,
+    )
+    .unwrap();
+    assert_eq!(macro_expand(&exp).is_err(), true);
+
+    // strings are constants, but can't be operated on
+    let exp = lexpr::from_str(r#"(begin (define-constant name "app") name)"#).unwrap();
+    assert_eq!(
+        macro_expand(&exp).unwrap(),
+        lexpr::from_str(r#"(begin "app")"#).unwrap()
+    );
+    let exp = lexpr::from_str(r#"(begin (define-constant name (+ "a" "b")) name)"#).unwrap();
+    assert_eq!(macro_expand(&exp).is_err(), true);
+
+    // overflow
+    let exp = lexpr::from_str("(begin (define-constant big (* 65536 65536)) big)").unwrap();
+    assert_eq!(macro_expand(&exp).is_err(), true);
+
+    // float constants are exported as their bits, like floats in compiled code
+    let exp = lexpr::from_str("(begin (define-constant k 1.5) (+ k 1.0))").unwrap();
+    let (expanded, constants) = macro_expand_with_constants(&exp).unwrap();
+    assert_eq!(expanded, lexpr::from_str("(begin (+ 1.5 1.0))").unwrap());
+    assert_eq!(
+        constants.into_iter().collect::<Vec<_>>(),
+        vec![(String::from("k"), 1.5f32.to_bits() as i32)]
+    );
+}
+
+#[test]
+fn test_macro_expand_declare_extern() {
+    let exp = lexpr::from_str(
+        r#"
+(begin
+  (declare-extern log-int "console" "log" (-> int bool))
+  (log-int (+ 1 2)))
+"#,
+    )
+    .unwrap();
+    let expanded = macro_expand(&exp).unwrap();
+    assert_eq!(
+        expanded,
+        lexpr::from_str(r#"(begin (call-extern "console" "log" (-> int bool) (+ 1 2)))"#).unwrap()
+    );
+    let typed_exp = type_check(&parse(&expanded).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Bool);
+
+    // extern functions used as values are wrapped in lambdas
+    let exp = lexpr::from_str(
+        r#"
+(begin
+  (declare-extern log-int "console" "log" (-> int bool))
+  (let ((apply-to-3 (lambda ((f : (-> int bool))) : bool (f 3))))
+    (apply-to-3 log-int)))
+"#,
+    )
+    .unwrap();
+    let expanded = macro_expand(&exp).unwrap();
+    let typed_exp = type_check(&parse(&expanded).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Bool);
+    let callback = expanded.to_vec().unwrap()[1].to_vec().unwrap()[2]
+        .to_vec()
+        .unwrap()[1]
+        .clone();
+    let callback = callback.to_vec().unwrap();
+    assert_eq!(callback[0], lexpr::Value::symbol("lambda"));
+    assert_eq!(
+        callback[4].to_vec().unwrap()[..4],
+        lexpr::from_str(r#"(call-extern "console" "log" (-> int bool))"#)
+            .unwrap()
+            .to_vec()
+            .unwrap()[..]
+    );
+
+    // but only if they have function types
+    let exp = lexpr::from_str(
+        r#"
+(begin
+  (declare-extern log-int "console" "log" int)
+  (let ((f log-int)) 3))
+"#,
+    )
+    .unwrap();
+    assert_eq!(macro_expand(&exp).is_err(), true);
+
+    // module and field must be strings
+    let exp = lexpr::from_str(
+        r#"
+(begin
+  (declare-extern log-int console log (-> int bool))
+  3)
+"#,
+    )
+    .unwrap();
+    assert_eq!(macro_expand(&exp).is_err(), true);
+}
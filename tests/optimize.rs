@@ -0,0 +1,75 @@
+use scheme_to_wasm::optimize::{optimize_exp, run_wasm_opt, OptLevel, WasmOptPreset};
+use scheme_to_wasm::parse::parse;
+use scheme_to_wasm::type_check::type_check;
+
+fn optimize_str(program: &str, level: OptLevel) -> String {
+    let exp = parse(&lexpr::from_str(program).unwrap()).unwrap();
+    let typed_exp = type_check(&exp).unwrap();
+    let opt_exp = optimize_exp(&typed_exp, level).unwrap();
+    format!("{}", opt_exp)
+}
+
+#[test]
+fn test_optimize_constant_folding() {
+    assert_eq!(optimize_str("(+ (* 2 3) 4)", OptLevel::O0), "(+ (* 2 3) 4)");
+    assert_eq!(optimize_str("(+ (* 2 3) 4)", OptLevel::O1), "10");
+    assert_eq!(optimize_str("(and (< 1 2) (= 3 3))", OptLevel::O1), "true");
+    assert_eq!(optimize_str("(if (> 1 2) 5 (- 7 1))", OptLevel::O1), "6");
+    assert_eq!(
+        optimize_str("(let ((x 3)) (+ x (* 2 2)))", OptLevel::O1),
+        "(let ((x 3)) (+ x 4))"
+    );
+
+    // arithmetic wraps around like in wasm
+    assert_eq!(
+        optimize_str("(+ 2147483647 1)", OptLevel::O1),
+        "-2147483648"
+    );
+
+    // unsigned operations treat negative ints as large numbers
+    assert_eq!(optimize_str("(u< -1 1)", OptLevel::O1), "false");
+    assert_eq!(optimize_str("(u/ -2 2)", OptLevel::O1), "2147483647");
+    assert_eq!(optimize_str("(u% -1 10)", OptLevel::O1), "5");
+
+    // trapping operations are left for runtime
+    assert_eq!(optimize_str("(/ 5 (- 1 1))", OptLevel::O1), "(/ 5 0)");
+    assert_eq!(optimize_str("(u% 5 (- 1 1))", OptLevel::O1), "(u% 5 0)");
+}
+
+#[test]
+fn test_optimize_dead_code() {
+    // O1 leaves unused code alone
+    assert_eq!(
+        optimize_str("(let ((x 3) (y 4)) (begin x y))", OptLevel::O1),
+        "(let ((x 3) (y 4)) (begin x y))"
+    );
+    assert_eq!(
+        optimize_str("(let ((x 3) (y 4)) (begin x y))", OptLevel::O2),
+        "(let ((y 4)) y)"
+    );
+
+    // side effects are kept
+    let program = "(let ((x 3)) (begin (set! x 4) x))";
+    let output = optimize_str(program, OptLevel::O2);
+    assert_eq!(output, "(let ((x 3)) (begin (set! x 4) x))");
+
+    // divisions which may trap are kept
+    let program = "(let ((x 0)) (begin (/ 1 x) (u% 1 x) 3))";
+    let output = optimize_str(program, OptLevel::O2);
+    assert_eq!(output, "(let ((x 0)) (begin (/ 1 x) (u% 1 x) 3))");
+    let program = "(let ((x 7)) (let ((y (u/ 7 x)) (z (/ x 2))) 3))";
+    let output = optimize_str(program, OptLevel::O2);
+    assert_eq!(output, "(let ((x 7)) (let ((y (u/ 7 x))) 3))");
+
+    // asserts are kept
+    let program = r#"(begin (assert (> 2 1) "math") 3)"#;
+    let output = optimize_str(program, OptLevel::O2);
+    assert_eq!(output, r#"(begin (assert true "math") 3)"#);
+}
+
+#[test]
+fn test_optimize_wasm_opt_missing() {
+    let binary = vec![0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+    let result = run_wasm_opt(&binary, WasmOptPreset::Size, "not-a-real-wasm-opt");
+    assert_eq!(result.is_err(), true);
+}
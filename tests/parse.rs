@@ -1,7 +1,8 @@
 use im_rc::vector;
+use scheme_to_wasm::common::ExprKind;
 use scheme_to_wasm::parse::parse;
 use scheme_to_wasm::parse::parse_type;
-use scheme_to_wasm::types::Type;
+use scheme_to_wasm::types::{unit_type, Effect, Type};
 
 // We don't currently have any unit tests for specifically validating that
 // expressions are correctly parsed. This could be added, but it is frankly
@@ -15,6 +16,67 @@ use scheme_to_wasm::types::Type;
 fn test_parse_int_bounds() {
     let exp = lexpr::from_str("2147483648").unwrap();
     assert_eq!(parse(&exp).is_err(), true);
+
+    let exp = lexpr::from_str("-2147483649").unwrap();
+    assert_eq!(
+        format!("{}", parse(&exp).unwrap_err()),
+        "ParseError: Number -2147483649 is out of range (must be a 32-bit integer, from -2147483648 to 2147483647)."
+    );
+
+    let exp = lexpr::from_str("-2147483648").unwrap();
+    assert_eq!(parse(&exp).is_ok(), true);
+
+    // numbers with a decimal point are floats
+    let exp = lexpr::from_str("2.5").unwrap();
+    assert_eq!(*parse(&exp).unwrap().kind, ExprKind::Float(2.5));
+}
+
+#[test]
+fn test_parse_reserved_binders() {
+    let exp = lexpr::from_str("(let ((if 3)) if)").unwrap();
+    assert_eq!(
+        format!("{}", parse(&exp).unwrap_err()),
+        "ParseError: if is a reserved word and cannot be used as a variable name."
+    );
+
+    let exp = lexpr::from_str("(lambda ((car : int)) : int car)").unwrap();
+    assert_eq!(parse(&exp).is_err(), true);
+
+    let exp =
+        lexpr::from_str("(typecase (inject 3 (union int bool)) ((int random) 1) ((bool b) 2))")
+            .unwrap();
+    assert_eq!(parse(&exp).is_err(), true);
+
+    // names which merely contain a reserved word are fine
+    let exp = lexpr::from_str("(let ((if-true 3) (car2 4)) (+ if-true car2))").unwrap();
+    assert_eq!(parse(&exp).is_ok(), true);
+}
+
+#[test]
+fn test_parse_display_round_trip() {
+    // printing an expression gives source which parses back to it
+    let sources = vec![
+        "(make-record (x 3) (y true))",
+        "(make-record)",
+        "(record-ref (make-record (x 3)) x)",
+        "(pack 3 int (exists T0 T0))",
+        "(unpack (x (pack 3 int (exists T0 T0)) T1) x)",
+        "(tuple-set! (make-tuple 1 2) 1 3)",
+        "(curry f 1 2)",
+        "(compose f g)",
+        r#"(wasm-asm "i32.const 1 i32.add" : int x)"#,
+        r#"(wasm-asm "memory.size" : int)"#,
+        "(+ 1.5 (exact->inexact 2))",
+        "(inexact->exact -3.0)",
+        "(case x ((1 2) 10) ((-3) 20) (else 30))",
+        "(case x (else 30))",
+    ];
+    for source in sources {
+        let exp = parse(&lexpr::from_str(source).unwrap()).unwrap();
+        let printed = exp.to_string();
+        let reparsed = parse(&lexpr::from_str(&printed).unwrap()).unwrap();
+        assert_eq!(reparsed, exp, "printed {} as {}", source, printed);
+    }
 }
 
 #[test]
@@ -45,7 +107,11 @@ fn test_parse_type_existentials() {
         parse_type(&exp).unwrap(),
         Type::Exists(
             0,
-            Box::new(Type::Func(vector![Type::TypeVar(0)], Box::new(Type::Bool)))
+            Box::new(Type::Func(
+                vector![Type::TypeVar(0)],
+                Box::new(Type::Bool),
+                Effect::Mutates
+            ))
         )
     );
 }
@@ -77,6 +143,21 @@ fn test_parse_type_tuples() {
     );
 }
 
+#[test]
+fn test_parse_type_unit() {
+    // unit is the empty tuple, rather than a separate type
+    let exp = lexpr::from_str("unit").unwrap();
+    assert_eq!(parse_type(&exp).unwrap(), unit_type());
+    let exp = lexpr::from_str("(tuple)").unwrap();
+    assert_eq!(parse_type(&exp).unwrap(), unit_type());
+
+    let exp = lexpr::from_str("(-> int unit)").unwrap();
+    assert_eq!(
+        parse_type(&exp).unwrap(),
+        Type::Func(vector![Type::Int], Box::new(unit_type()), Effect::Mutates)
+    );
+}
+
 #[test]
 fn test_parse_type_records() {
     let exp = lexpr::from_str("(record)").unwrap();
@@ -95,30 +176,60 @@ fn test_parse_type_records() {
             (String::from("init"), Type::Str),
             (
                 String::from("update"),
-                Type::Func(vector![Type::Str], Box::new(Type::Str))
+                Type::Func(vector![Type::Str], Box::new(Type::Str), Effect::Mutates)
             )
         ])
     );
 }
 
+#[test]
+fn test_parse_type_unions() {
+    let exp = lexpr::from_str("(union int string)").unwrap();
+    assert_eq!(
+        parse_type(&exp).unwrap(),
+        Type::Union(vector![Type::Int, Type::Str])
+    );
+
+    let exp = lexpr::from_str("(union int (tuple int bool))").unwrap();
+    assert_eq!(
+        parse_type(&exp).unwrap(),
+        Type::Union(vector![
+            Type::Int,
+            Type::Tuple(vector![Type::Int, Type::Bool])
+        ])
+    );
+
+    // unions must contain at least one type
+    let exp = lexpr::from_str("(union)").unwrap();
+    assert_eq!(parse_type(&exp).is_err(), true);
+
+    // unions cannot contain duplicate types
+    let exp = lexpr::from_str("(union int bool int)").unwrap();
+    assert_eq!(parse_type(&exp).is_err(), true);
+}
+
 #[test]
 fn test_parse_type_functions() {
     let exp = lexpr::from_str("(-> int)").unwrap();
     assert_eq!(
         parse_type(&exp).unwrap(),
-        Type::Func(vector![], Box::new(Type::Int))
+        Type::Func(vector![], Box::new(Type::Int), Effect::Mutates)
     );
 
     let exp = lexpr::from_str("(-> int int)").unwrap();
     assert_eq!(
         parse_type(&exp).unwrap(),
-        Type::Func(vector![Type::Int], Box::new(Type::Int))
+        Type::Func(vector![Type::Int], Box::new(Type::Int), Effect::Mutates)
     );
 
     let exp = lexpr::from_str("(-> string int bool)").unwrap();
     assert_eq!(
         parse_type(&exp).unwrap(),
-        Type::Func(vector![Type::Str, Type::Int], Box::new(Type::Bool))
+        Type::Func(
+            vector![Type::Str, Type::Int],
+            Box::new(Type::Bool),
+            Effect::Mutates
+        )
     );
 
     let exp = lexpr::from_str("(-> (-> int int bool) int int bool)").unwrap();
@@ -126,11 +237,30 @@ fn test_parse_type_functions() {
         parse_type(&exp).unwrap(),
         Type::Func(
             vector![
-                Type::Func(vector![Type::Int, Type::Int], Box::new(Type::Bool)),
+                Type::Func(
+                    vector![Type::Int, Type::Int],
+                    Box::new(Type::Bool),
+                    Effect::Mutates
+                ),
                 Type::Int,
                 Type::Int
             ],
-            Box::new(Type::Bool)
+            Box::new(Type::Bool),
+            Effect::Mutates
         )
     );
 }
+
+#[test]
+fn test_parse_radix_literals() {
+    let exp = lexpr::from_str("#x1F").unwrap();
+    assert_eq!(*parse(&exp).unwrap().kind, ExprKind::Num(31));
+    let exp = lexpr::from_str("#b1010").unwrap();
+    assert_eq!(*parse(&exp).unwrap().kind, ExprKind::Num(10));
+    let exp = lexpr::from_str("#o755").unwrap();
+    assert_eq!(*parse(&exp).unwrap().kind, ExprKind::Num(493));
+    let exp = lexpr::from_str("#x-80000000").unwrap();
+    assert_eq!(*parse(&exp).unwrap().kind, ExprKind::Num(-2147483648));
+    let exp = lexpr::from_str("#xFFFFFFFF").unwrap();
+    assert_eq!(parse(&exp).is_err(), true);
+}
@@ -0,0 +1,44 @@
+use scheme_to_wasm::pretty::{format_source, is_formatted, DEFAULT_WIDTH};
+
+#[test]
+fn test_pretty_short_lines() {
+    // short expressions are put on a single line
+    let formatted = format_source("(let ((x   3))\n  (+ x\n 4))", DEFAULT_WIDTH).unwrap();
+    assert_eq!(formatted, "(let ((x 3)) (+ x 4))\n");
+
+    let formatted = format_source(r#"(concat "a\tb"   "c")"#, DEFAULT_WIDTH).unwrap();
+    assert_eq!(formatted, "(concat \"a\\tb\" \"c\")\n");
+}
+
+#[test]
+fn test_pretty_wrapping() {
+    let source = r#"(let ((add (lambda ((a : int) (b : int)) : int (+ a b)))) (begin (add 1 2) (add (* 3 4) (- 100 99))))"#;
+    let formatted = format_source(source, 40).unwrap();
+    assert_eq!(
+        formatted,
+        r#"(let ((add (lambda ((a : int) (b : int)) : int
+             (+ a b))))
+  (begin
+    (add 1 2)
+    (add (* 3 4) (- 100 99))))
+"#
+    );
+    // formatting is idempotent
+    assert_eq!(format_source(&formatted, 40).unwrap(), formatted);
+
+    let formatted = format_source("(if (< 1 2) (+ 10 20) (* 30 40))", 20).unwrap();
+    assert_eq!(formatted, "(if (< 1 2)\n    (+ 10 20)\n    (* 30 40))\n");
+}
+
+#[test]
+fn test_pretty_check() {
+    assert_eq!(is_formatted("(+ 1 2)\n", DEFAULT_WIDTH).unwrap(), true);
+    assert_eq!(is_formatted("(+  1 2)", DEFAULT_WIDTH).unwrap(), false);
+
+    // comments would be lost, so they're rejected
+    assert_eq!(
+        format_source("(+ 1 ; one\n 2)", DEFAULT_WIDTH).is_err(),
+        true
+    );
+    assert_eq!(format_source("(+ 1 2", DEFAULT_WIDTH).is_err(), true);
+}
@@ -0,0 +1,152 @@
+#![cfg(feature = "fs")]
+
+use scheme_to_wasm::features::FeatureFlags;
+use scheme_to_wasm::optimize::OptLevel;
+use scheme_to_wasm::project::{build_project, write_project, Manifest, ModuleResolver, Target};
+use std::path::{Path, PathBuf};
+use wasmer_runtime::{imports, instantiate, Value};
+
+fn project_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("project")
+}
+
+#[test]
+fn test_parse_manifest() {
+    let root = Path::new("root");
+    let mut features = FeatureFlags::new();
+    features.define("wasi").unwrap();
+    features.define("log-level=2").unwrap();
+    let manifest = Manifest::parse(
+        r#"
+entry = "main.scm"
+files = ["a.scm", "b.scm"]
+target = "wasm"
+opt-level = "O1"
+features = ["wasi", "wasi", "log-level=2"]
+"#,
+        root,
+    )
+    .unwrap();
+    assert_eq!(
+        manifest,
+        Manifest {
+            root: PathBuf::from("root"),
+            entry: PathBuf::from("main.scm"),
+            files: vec![PathBuf::from("a.scm"), PathBuf::from("b.scm")],
+            target: Target::Wasm,
+            opt_level: OptLevel::O1,
+            features,
+        }
+    );
+
+    let manifest = Manifest::parse(r#"entry = "main.scm""#, root).unwrap();
+    assert_eq!(manifest.files, Vec::<PathBuf>::new());
+    assert_eq!(manifest.opt_level, OptLevel::O0);
+
+    for source in &[
+        "files = []",
+        "entry = 3",
+        r#"entry = "main.scm"
+files = "a.scm""#,
+        r#"entry = "main.scm"
+target = "rust""#,
+        r#"entry = "main.scm"
+opt-level = "O3""#,
+        r#"entry = "main.scm"
+output = "main.wasm""#,
+        r#"entry = "main.scm"
+features = "wasi""#,
+        r#"entry = "main.scm"
+features = ["log level=2"]"#,
+        "entry = ",
+    ] {
+        assert_eq!(Manifest::parse(source, root).is_err(), true, "{}", source);
+    }
+}
+
+#[test]
+fn test_build_project() {
+    let manifest = Manifest::read(&project_dir().join("scheme.toml")).unwrap();
+    assert_eq!(manifest.root, project_dir());
+    assert_eq!(manifest.opt_level, OptLevel::O2);
+
+    let module = build_project(&project_dir().join("scheme.toml")).unwrap();
+    let binary = parity_wasm::serialize(module).unwrap();
+    let instance = instantiate(&binary, &imports! {}).unwrap();
+    let values = instance.dyn_func("$$MAIN$$").unwrap().call(&[]).unwrap();
+    assert_eq!(values[0], Value::I32(82));
+
+    let err = build_project(&project_dir().join("missing.toml")).unwrap_err();
+    assert_eq!(
+        err.to_string().starts_with("ProjectError: Could not read"),
+        true
+    );
+}
+
+#[test]
+fn test_write_project() {
+    let manifest = Manifest::read(&project_dir().join("scheme.toml")).unwrap();
+    assert_eq!(
+        manifest.output_path(),
+        project_dir().join(manifest.entry.with_extension("wasm"))
+    );
+
+    let output = std::env::temp_dir().join(format!("project-{}.wasm", std::process::id()));
+    let written = write_project(&project_dir().join("scheme.toml"), Some(&output)).unwrap();
+    assert_eq!(written, output);
+    let binary = std::fs::read(&output).unwrap();
+    std::fs::remove_file(&output).unwrap();
+    let instance = instantiate(&binary, &imports! {}).unwrap();
+    let values = instance.dyn_func("$$MAIN$$").unwrap().call(&[]).unwrap();
+    assert_eq!(values[0], Value::I32(82));
+
+    let missing_dir = std::env::temp_dir().join("missing-dir").join("main.wasm");
+    let err = write_project(&project_dir().join("scheme.toml"), Some(&missing_dir)).unwrap_err();
+    assert_eq!(
+        err.to_string().starts_with("ProjectError: Could not write"),
+        true
+    );
+}
+
+#[test]
+fn test_build_project_with_imports() {
+    let dir = project_dir().join("imports");
+    let mut resolver = ModuleResolver::new(&dir);
+    let mut order = vec![];
+    resolver.resolve(Path::new("main.scm"), &mut order).unwrap();
+    assert_eq!(
+        order,
+        vec![
+            PathBuf::from("macros.scm"),
+            PathBuf::from("lib/math.scm"),
+            PathBuf::from("main.scm")
+        ]
+    );
+
+    let module = build_project(&dir.join("scheme.toml")).unwrap();
+    let binary = parity_wasm::serialize(module).unwrap();
+    let instance = instantiate(&binary, &imports! {}).unwrap();
+    let values = instance.dyn_func("$$MAIN$$").unwrap().call(&[]).unwrap();
+    assert_eq!(values[0], Value::I32(50));
+}
+
+#[test]
+fn test_import_errors() {
+    let dir = project_dir().join("cycle");
+    let err = build_project(&dir.join("scheme.toml")).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "ProjectError: Import cycle: a.scm -> b.scm -> a.scm."
+    );
+
+    let mut resolver = ModuleResolver::new(&dir);
+    let err = resolver
+        .resolve(Path::new("missing.scm"), &mut vec![])
+        .unwrap_err();
+    assert_eq!(
+        err.to_string().starts_with("ProjectError: Could not read"),
+        true
+    );
+}
@@ -0,0 +1,2 @@
+(define-constant base 3)
+(define-constant size (* base 3)) ; constants can refer to earlier constants
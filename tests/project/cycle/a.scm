@@ -0,0 +1,2 @@
+(import b)
+(define-constant a-value 1)
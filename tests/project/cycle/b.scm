@@ -0,0 +1,2 @@
+(import a)
+(define-constant b-value 2)
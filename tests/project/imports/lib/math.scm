@@ -0,0 +1,5 @@
+; both this file and main.scm import macros, which is only included once
+(import macros)
+(define-syntax double
+  (syntax-rules ()
+    ((double x) (+ x x))))
@@ -0,0 +1,2 @@
+(import lib/math macros)
+(double (square 5))
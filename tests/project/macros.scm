@@ -0,0 +1,3 @@
+(define-syntax square
+  (syntax-rules ()
+    ((square x) (* x x))))
@@ -0,0 +1 @@
+(+ (square size) 1)
@@ -0,0 +1,83 @@
+use scheme_to_wasm::parse::parse;
+use scheme_to_wasm::read::read;
+
+#[test]
+fn test_read_block_comments() {
+    let value = read("(+ 1 #| two |# 3)").unwrap();
+    assert_eq!(value, lexpr::from_str("(+ 1 3)").unwrap());
+
+    // block comments can be nested, and span lines
+    let value = read(
+        r#"
+#| outer
+   #| inner |#
+   still a comment |#
+(let ((x 2)) x)
+"#,
+    )
+    .unwrap();
+    assert_eq!(value, lexpr::from_str("(let ((x 2)) x)").unwrap());
+
+    // comment syntax inside strings is left alone
+    let value = read(r##"(concat "#| not |#" "a #; b")"##).unwrap();
+    assert_eq!(
+        value,
+        lexpr::from_str(r##"(concat "#| not |#" "a #; b")"##).unwrap()
+    );
+
+    // and strings inside block comments may contain the end of a comment
+    let value = read(r#"(+ 1 #| a "|#" b |# 2)"#).unwrap();
+    assert_eq!(value, lexpr::from_str("(+ 1 2)").unwrap());
+
+    assert_eq!(read("(+ 1 #| 2 3)").is_err(), true);
+}
+
+#[test]
+fn test_read_datum_comments() {
+    let value = read("(+ 1 #;(* 2 (- 3 4)) 5)").unwrap();
+    assert_eq!(value, lexpr::from_str("(+ 1 5)").unwrap());
+
+    let value = read("(begin #; \"skipped\" #;ignored 4)").unwrap();
+    assert_eq!(value, lexpr::from_str("(begin 4)").unwrap());
+
+    // a datum comment followed by another skips the next two datums
+    let value = read("(list #; #; 1 2 3)").unwrap();
+    assert_eq!(value, lexpr::from_str("(list 3)").unwrap());
+
+    // parens in strings and comments within the datum are not counted
+    let value = read("(f #;(g \")\" ; )\n x) y)").unwrap();
+    assert_eq!(value, lexpr::from_str("(f y)").unwrap());
+
+    // datum comments can be nested within the skipped datum
+    let value = read("(+ 1 #;(foo #;bar baz) 2)").unwrap();
+    assert_eq!(value, lexpr::from_str("(+ 1 2)").unwrap());
+    let value = read("(+ 1 #;(foo #;(bar \")\") #| ) |# baz) 2)").unwrap();
+    assert_eq!(value, lexpr::from_str("(+ 1 2)").unwrap());
+
+    assert_eq!(read("(f #;)").is_err(), true);
+}
+
+#[test]
+fn test_read_radix_literals() {
+    let value = read("(+ #x1F #;#xFF (* #b1010 #o17))").unwrap();
+    assert_eq!(value, lexpr::from_str("(+ 31 (* 10 15))").unwrap());
+}
+
+#[test]
+fn test_read_string_escapes() {
+    let value = read(r#""a\n\t\"\\\x41;\u{3bb}""#).unwrap();
+    assert_eq!(value, lexpr::Value::string("a\n\t\"\\A\u{3bb}"));
+
+    // an escaped backslash followed by u{ is not a unicode escape
+    let value = read(r#""\\u{41}""#).unwrap();
+    assert_eq!(value, lexpr::Value::string("\\u{41}"));
+
+    assert_eq!(read(r#""\u{41""#).is_err(), true);
+    assert_eq!(read(r#""\u{zz}""#).is_err(), true);
+
+    // strings are printed so that they can be read back
+    let exp = parse(&read(r#"(concat "tab\there" "\"quoted\" \\ \u{7}")"#).unwrap()).unwrap();
+    let printed = format!("{}", exp);
+    assert_eq!(printed, r#"(concat "tab\there" "\"quoted\" \\ \x7;")"#);
+    assert_eq!(parse(&read(&printed).unwrap()).unwrap(), exp);
+}
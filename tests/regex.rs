@@ -0,0 +1,71 @@
+use scheme_to_wasm::regex::Regex;
+
+fn captures(pattern: &str, text: &str) -> Option<Vec<String>> {
+    Regex::new(pattern).unwrap().captures(text)
+}
+
+fn replace(pattern: &str, text: &str, replacement: &str) -> String {
+    Regex::new(pattern).unwrap().replace_all(text, replacement)
+}
+
+#[test]
+fn test_regex_captures() {
+    let cases = vec![
+        ("abc", "xabcx", Some(vec!["abc"])),
+        ("a.c", "abc", Some(vec!["abc"])),
+        ("a.c", "a\nc", None),
+        ("[a-c]+", "xxbcay", Some(vec!["bca"])),
+        ("[^a-c ]+", "abc def", Some(vec!["def"])),
+        ("[]a]+", "x]a]", Some(vec!["]a]"])),
+        ("[a-]+", "x-a-", Some(vec!["-a-"])),
+        (r"\d+\s\w+", "no 42 apples", Some(vec!["42 apples"])),
+        (r"\D+", "42 apples", Some(vec![" apples"])),
+        (r"\.", "a.b", Some(vec!["."])),
+        ("^ab", "cab", None),
+        ("ab$", "abc", None),
+        ("^$", "", Some(vec![""])),
+        ("cat|dog", "hotdog", Some(vec!["dog"])),
+        ("(a|b)+c", "xabbac", Some(vec!["abbac", "a"])),
+        ("(a)|(b)", "b", Some(vec!["b", "", "b"])),
+        ("(?:ab)+(c)", "ababc", Some(vec!["ababc", "c"])),
+        // quantifiers are greedy, but backtrack
+        ("a*ab", "aaab", Some(vec!["aaab"])),
+        ("(a*)(a)", "aaa", Some(vec!["aaa", "aa", "a"])),
+        ("colou?r", "color", Some(vec!["color"])),
+        ("a{2}", "aaa", Some(vec!["aa"])),
+        ("a{2,}", "aaaa", Some(vec!["aaaa"])),
+        ("a{1,2}b", "aaab", Some(vec!["aab"])),
+        ("a{3}", "aa", None),
+        ("(a*)*b", "aab", Some(vec!["aab", "aa"])),
+        ("x*", "abc", Some(vec![""])),
+    ];
+    for (pattern, text, expected) in cases {
+        let expected =
+            expected.map(|groups| groups.into_iter().map(String::from).collect::<Vec<_>>());
+        assert_eq!(captures(pattern, text), expected, "{} on {}", pattern, text);
+    }
+}
+
+#[test]
+fn test_regex_replace() {
+    assert_eq!(replace("o", "foo boo", "0"), "f00 b00");
+    assert_eq!(
+        replace(r"(\w+)@(\w+)", "a@b c@d", r"\2 at \1"),
+        "b at a d at c"
+    );
+    assert_eq!(replace("x", "abc", "y"), "abc");
+    assert_eq!(replace(r"\s+", "a  b   c", " "), "a b c");
+    // empty matches are replaced between every character
+    assert_eq!(replace("x*", "abc", "-"), "-a-b-c-");
+    assert_eq!(replace("b*", "abc", "-"), "-a--c-");
+    assert_eq!(replace("a", "aaa", r"\\\0"), r"\a\a\a");
+}
+
+#[test]
+fn test_regex_errors() {
+    for pattern in &[
+        "a(", "a)", "[ab", "*a", "a{2", "a{3,1}", "a{x}", "[z-a]", "\\", "^*",
+    ] {
+        assert_eq!(Regex::new(pattern).is_err(), true, "{}", pattern);
+    }
+}
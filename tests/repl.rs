@@ -0,0 +1,121 @@
+use scheme_to_wasm::interpret::EvalOptions;
+use scheme_to_wasm::repl::{run_repl, Repl};
+
+#[test]
+fn test_repl_eval() {
+    let mut repl = Repl::default();
+    assert_eq!(repl.handle("(+ 1 2)").unwrap(), "3");
+    assert_eq!(repl.handle("  ").unwrap(), "");
+    assert_eq!(repl.handle("(+ 1 true)").is_err(), true);
+}
+
+#[test]
+fn test_repl_type() {
+    let mut repl = Repl::default();
+    assert_eq!(
+        repl.handle(":type (lambda ((x : int)) : bool (> x 0))")
+            .unwrap(),
+        "(pure-> int bool)"
+    );
+    // the expression isn't evaluated
+    assert_eq!(repl.handle(":type (/ 1 (- 1 1))").unwrap(), "int");
+    assert_eq!(repl.handle(":type (+ 1 true)").is_err(), true);
+}
+
+#[test]
+fn test_repl_expand() {
+    let mut repl = Repl::default();
+    assert_eq!(
+        repl.handle(":expand (let* ((x 1) (y x)) (+ x y))").unwrap(),
+        "(let ((x 1)) (let ((y x)) (+ x y)))"
+    );
+    assert_eq!(
+        repl.handle(":expand (begin (define-constant n 3) (* n n))")
+            .unwrap(),
+        "(begin (* 3 3))"
+    );
+    assert_eq!(
+        repl.handle(":explain T001").unwrap_err().to_string(),
+        "ReplError: Unknown command :explain; the commands are :type, :expand, :load and :save."
+    );
+}
+
+#[test]
+fn test_repl_definitions() {
+    let mut repl = Repl::default();
+    assert_eq!(repl.handle("(define-constant n 3)").unwrap(), "");
+    assert_eq!(
+        repl.handle("(define-syntax square (syntax-rules () ((square x) (* x x))))")
+            .unwrap(),
+        ""
+    );
+    assert_eq!(repl.handle("(square n)").unwrap(), "9");
+    assert_eq!(repl.handle(":type (square n)").unwrap(), "int");
+
+    // redefining a name replaces its definition
+    assert_eq!(repl.handle("(define-constant n 4)").unwrap(), "");
+    assert_eq!(repl.handle("(square n)").unwrap(), "16");
+    assert_eq!(repl.definitions().len(), 2);
+
+    // invalid definitions are not kept
+    assert_eq!(
+        repl.handle("(define-syntax bad (lambda (x) x))").is_err(),
+        true
+    );
+    assert_eq!(repl.definitions().len(), 2);
+}
+
+#[cfg(feature = "fs")]
+#[test]
+fn test_repl_load_and_save() {
+    let path = std::env::temp_dir().join(format!("repl-session-{}.scm", std::process::id()));
+    let path = path.to_str().unwrap();
+    let mut repl = Repl::default();
+    repl.handle("(define-constant n 5)").unwrap();
+    repl.handle("(define-macro (twice x) (list '+ x x))")
+        .unwrap();
+    assert_eq!(
+        repl.handle(&format!(":save {}", path)).unwrap(),
+        format!("Saved 2 definitions to {}.", path)
+    );
+
+    let mut loaded = Repl::default();
+    assert_eq!(
+        loaded.handle(&format!(":load {}", path)).unwrap(),
+        format!("Loaded 2 definitions from {}.", path)
+    );
+    assert_eq!(loaded.definitions(), repl.definitions());
+    assert_eq!(loaded.handle("(twice n)").unwrap(), "10");
+
+    std::fs::write(path, "(define-constant m 1)\n(+ m 1)").unwrap();
+    assert_eq!(
+        loaded
+            .handle(&format!(":load {}", path))
+            .unwrap_err()
+            .to_string(),
+        "ReplError: (+ m 1) is not a definition."
+    );
+    assert_eq!(loaded.definitions().len(), 2);
+    std::fs::remove_file(path).unwrap();
+
+    assert_eq!(loaded.handle(":load").is_err(), true);
+    assert_eq!(loaded.handle(&format!(":load {}", path)).is_err(), true);
+}
+
+#[test]
+fn test_run_repl() {
+    let input = "(* 6 7)\n:type true\n(car (null int))\n";
+    let mut output = vec![];
+    run_repl(
+        &mut Repl::new(EvalOptions::default()),
+        input.as_bytes(),
+        &mut output,
+    )
+    .unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let lines = output.lines().collect::<Vec<&str>>();
+    assert_eq!(lines[0], "> 42");
+    assert_eq!(lines[1], "> bool");
+    assert_eq!(lines[2].starts_with("> EvalError"), true);
+    assert_eq!(lines[3], "> ");
+}
@@ -0,0 +1,64 @@
+use scheme_to_wasm::common::ExprKind;
+use scheme_to_wasm::parse::parse;
+use scheme_to_wasm::resolve::{original_name, resolve};
+
+#[test]
+fn test_resolve_unique_names() {
+    let exp = parse(&lexpr::from_str("(let ((x 1)) (let ((x (+ x 1))) x))").unwrap()).unwrap();
+    let resolved = resolve(&exp).unwrap();
+    let (outer, inner, inner_val, body) = match &*resolved.kind {
+        ExprKind::Let(bindings, body) => match &*body.kind {
+            ExprKind::Let(inner_bindings, inner_body) => (
+                bindings[0].0.clone(),
+                inner_bindings[0].0.clone(),
+                inner_bindings[0].1.clone(),
+                inner_body.clone(),
+            ),
+            _ => panic!("Expected a nested let, found {}", body),
+        },
+        _ => panic!("Expected a let, found {}", resolved),
+    };
+    assert_ne!(outer, inner);
+    assert_eq!(original_name(&outer), "x");
+    assert_eq!(original_name(&inner), "x");
+    // the inner binding's value refers to the outer x, and the body to the
+    // inner x
+    assert_eq!(inner_val.to_string(), format!("(+ {} 1)", outer));
+    assert_eq!(body.to_string(), inner);
+}
+
+#[test]
+fn test_resolve_binders() {
+    let exp = parse(
+        &lexpr::from_str(
+            r#"
+(let ((f (lambda ((x : int) (y : int)) : int (begin (set! x y) x))))
+  (typecase (inject 3 (union int bool))
+    ((int x) (f x 1))
+    ((bool x) (if x 1 0))))
+"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let resolved = resolve(&exp).unwrap();
+    let printed = resolved.to_string();
+    // every binder gets its own name
+    for name in &["f", "x", "y"] {
+        assert_eq!(printed.contains(&format!("({} ", name)), false);
+    }
+
+    let exp = parse(&lexpr::from_str("(let ((x 1)) (+ x y))").unwrap()).unwrap();
+    assert_eq!(resolve(&exp).is_err(), true);
+    let exp = parse(&lexpr::from_str("(let ((x 1)) (set! y x))").unwrap()).unwrap();
+    assert_eq!(resolve(&exp).is_err(), true);
+}
+
+#[test]
+fn test_original_name() {
+    assert_eq!(original_name("x.12"), "x");
+    assert_eq!(original_name("x.1.12"), "x.1");
+    assert_eq!(original_name("list->vec"), "list->vec");
+    assert_eq!(original_name("x."), "x.");
+    assert_eq!(original_name("x.y"), "x.y");
+}
@@ -1,8 +1,8 @@
 use im_rc::vector;
 use scheme_to_wasm::common::{ExprKind, TypeEnv};
 use scheme_to_wasm::parse::{parse, parse_type};
-use scheme_to_wasm::type_check::{tc_with_env, type_check};
-use scheme_to_wasm::types::Type;
+use scheme_to_wasm::type_check::{tc_with_env, type_check, unify, TypeMismatch, UnifyError};
+use scheme_to_wasm::types::{unit_type, Effect, Type};
 
 #[test]
 fn test_typecheck_prims() {
@@ -121,6 +121,43 @@ fn test_typecheck_binops_sad() {
     let exp = lexpr::from_str(r#"(or "hello" "world")"#).unwrap();
     let typed_exp = type_check(&parse(&exp).unwrap());
     assert_eq!(typed_exp.is_err(), true);
+
+    let exp = lexpr::from_str("(+ 1 2.0)").unwrap();
+    assert_eq!(
+        type_check(&parse(&exp).unwrap()).unwrap_err().to_string(),
+        "TypeCheckError: Cannot mix ints and floats in (+ 1 2.0); convert with exact->inexact or inexact->exact."
+    );
+
+    let exp = lexpr::from_str("(exact->inexact 2.0)").unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap());
+    assert_eq!(typed_exp.is_err(), true);
+}
+
+#[test]
+fn test_typecheck_floats() {
+    let exp = lexpr::from_str("(* 1.5 2.0)").unwrap();
+    assert_eq!(type_check(&parse(&exp).unwrap()).unwrap().typ, Type::Float);
+
+    let exp = lexpr::from_str("(< 1.5 2.0)").unwrap();
+    assert_eq!(type_check(&parse(&exp).unwrap()).unwrap().typ, Type::Bool);
+
+    let exp = lexpr::from_str("(+ (exact->inexact 3) 0.5)").unwrap();
+    assert_eq!(type_check(&parse(&exp).unwrap()).unwrap().typ, Type::Float);
+
+    let exp = lexpr::from_str("(+ (inexact->exact 3.0) 1)").unwrap();
+    assert_eq!(type_check(&parse(&exp).unwrap()).unwrap().typ, Type::Int);
+
+    let exp = lexpr::from_str("(expt 2 10)").unwrap();
+    assert_eq!(type_check(&parse(&exp).unwrap()).unwrap().typ, Type::Int);
+
+    let exp = lexpr::from_str("(expt (sqrt 2.0) (log (exp 2.0)))").unwrap();
+    assert_eq!(type_check(&parse(&exp).unwrap()).unwrap().typ, Type::Float);
+
+    let exp = lexpr::from_str("(expt 2 0.5)").unwrap();
+    assert_eq!(type_check(&parse(&exp).unwrap()).is_err(), true);
+
+    let exp = lexpr::from_str("(sqrt 4)").unwrap();
+    assert_eq!(type_check(&parse(&exp).unwrap()).is_err(), true);
 }
 
 #[test]
@@ -209,6 +246,11 @@ fn test_typecheck_tuples_happy() {
     let exp = lexpr::from_str(r#"(tuple-ref (make-tuple 3 "hello") 1)"#).unwrap();
     let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
     assert_eq!(typed_exp.typ, Type::Str);
+
+    // key is a constant expression
+    let exp = lexpr::from_str(r#"(tuple-ref (make-tuple 3 "hello" true) (- (* 2 2) 3))"#).unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Str);
 }
 
 #[test]
@@ -218,11 +260,31 @@ fn test_typecheck_tuples_sad() {
     let typed_exp = type_check(&parse(&exp).unwrap());
     assert_eq!(typed_exp.is_err(), true);
 
+    // key is a constant expression, but too large
+    let exp = lexpr::from_str(r#"(tuple-ref (make-tuple 3 "hello") (+ 1 1))"#).unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap());
+    assert_eq!(typed_exp.is_err(), true);
+
     // key is not a number
     let exp = lexpr::from_str(r#"(tuple-ref (make-tuple 3 "hello") true)"#).unwrap();
     let parsed = parse(&exp);
     assert_eq!(parsed.is_err(), true);
 
+    // key is negative
+    let exp = lexpr::from_str(r#"(tuple-ref (make-tuple 3 "hello") (- 0 1))"#).unwrap();
+    let parsed = parse(&exp);
+    assert_eq!(parsed.is_err(), true);
+
+    // key is not a constant
+    let exp = lexpr::from_str(r#"(let ((i 0)) (tuple-ref (make-tuple 3 "hello") i))"#).unwrap();
+    let parsed = parse(&exp);
+    assert_eq!(parsed.is_err(), true);
+
+    // key divides by zero
+    let exp = lexpr::from_str(r#"(tuple-ref (make-tuple 3 "hello") (/ 1 0))"#).unwrap();
+    let parsed = parse(&exp);
+    assert_eq!(parsed.is_err(), true);
+
     // first expression is not a tuple
     let exp = lexpr::from_str(r#"(tuple-ref (cons 3 (null int)) 0)"#).unwrap();
     let typed_exp = type_check(&parse(&exp).unwrap());
@@ -301,6 +363,333 @@ fn test_typecheck_records_sad() {
     let exp = lexpr::from_str(r#"(record-ref "hello" foo)"#).unwrap();
     let typed_exp = type_check(&parse(&exp).unwrap());
     assert_eq!(typed_exp.is_err(), true);
+
+    // closure environments are records, so hand-written closures are checked
+    // too: the env doesn't have the field type the function expects
+    let exp = lexpr::from_str(
+        r#"
+(pack (make-tuple
+       (lambda ((env : (record (a : int))) (x : int)) : int (+ x (record-ref env a)))
+       (make-record (a true)))
+      (record (a : int))
+      (exists T0 (tuple (-> T0 int int) T0)))
+"#,
+    )
+    .unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap());
+    assert_eq!(typed_exp.is_err(), true);
+
+    // and record-ref keys must be identifiers
+    let exp = lexpr::from_str(r#"(record-ref (make-record (num 3)) "num")"#).unwrap();
+    assert_eq!(
+        format!("{}", parse(&exp).unwrap_err()),
+        "ParseError: Record-ref key is not a valid identifier."
+    );
+}
+
+#[test]
+fn test_typecheck_open_records_happy() {
+    // function accepting any record with an int field x
+    let exp = lexpr::from_str(
+        r#"(let ((get-x (lambda ((r : (record (x : int) ... T1))) : int (record-ref r x))))
+             (+ (get-x (make-record (x 3))) (get-x (make-record (y #t) (x 4)))))"#,
+    )
+    .unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Int);
+
+    // row variable is bound to the remaining fields in the return type
+    let exp = lexpr::from_str(
+        r#"(let ((id (lambda ((r : (record (x : int) ... T1))) : (record (x : int) ... T1) r)))
+             (id (make-record (x 3) (y #t))))"#,
+    )
+    .unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(
+        typed_exp.typ,
+        Type::Record(vector![
+            (String::from("x"), Type::Int),
+            (String::from("y"), Type::Bool)
+        ])
+    );
+}
+
+#[test]
+fn test_typecheck_open_records_sad() {
+    // record is missing the field x
+    let exp = lexpr::from_str(
+        r#"(let ((get-x (lambda ((r : (record (x : int) ... T1))) : int (record-ref r x))))
+             (get-x (make-record (y 3))))"#,
+    )
+    .unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap());
+    assert_eq!(typed_exp.is_err(), true);
+
+    // field x has the wrong type
+    let exp = lexpr::from_str(
+        r#"(let ((get-x (lambda ((r : (record (x : int) ... T1))) : int (record-ref r x))))
+             (get-x (make-record (x #t))))"#,
+    )
+    .unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap());
+    assert_eq!(typed_exp.is_err(), true);
+
+    // the same row variable is bound to different fields
+    let exp = lexpr::from_str(
+        r#"(let ((f (lambda ((a : (record (x : int) ... T1)) (b : (record (x : int) ... T1))) : int
+                      (record-ref a x))))
+             (f (make-record (x 1) (y 2)) (make-record (x 1) (z 2))))"#,
+    )
+    .unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap());
+    assert_eq!(typed_exp.is_err(), true);
+
+    // fields outside of the known fields cannot be accessed
+    let exp =
+        lexpr::from_str(r#"(lambda ((r : (record (x : int) ... T1))) : int (record-ref r y))"#)
+            .unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap());
+    assert_eq!(typed_exp.is_err(), true);
+}
+
+#[test]
+fn test_typecheck_subtyping_happy() {
+    // record with extra fields passed where a smaller record is expected
+    let exp = lexpr::from_str(
+        r#"(let ((get-x (lambda ((r : (record (x : int)))) : int (record-ref r x))))
+             (get-x (make-record (y #t) (x 4))))"#,
+    )
+    .unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Int);
+
+    // tuple with extra elements, when tuple width subtyping is enabled
+    let exp = lexpr::from_str(
+        r#"(let ((first (lambda ((t : (tuple int bool))) : int (tuple-ref t 0))))
+             (first (make-tuple 1 #t "extra")))"#,
+    )
+    .unwrap();
+    let env = TypeEnv::new().with_tuple_width_subtyping(true);
+    let typed_exp = tc_with_env(&parse(&exp).unwrap(), &env).unwrap();
+    assert_eq!(typed_exp.typ, Type::Int);
+}
+
+#[test]
+fn test_typecheck_subtyping_sad() {
+    // record with fewer fields than expected
+    let exp = lexpr::from_str(
+        r#"(let ((get-xy (lambda ((r : (record (x : int) (y : int)))) : int (record-ref r x))))
+             (get-xy (make-record (x 4))))"#,
+    )
+    .unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap());
+    assert_eq!(typed_exp.is_err(), true);
+
+    // tuple width subtyping is disabled by default
+    let exp = lexpr::from_str(
+        r#"(let ((first (lambda ((t : (tuple int bool))) : int (tuple-ref t 0))))
+             (first (make-tuple 1 #t "extra")))"#,
+    )
+    .unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap());
+    assert_eq!(typed_exp.is_err(), true);
+
+    // tuple elements do not match the expected prefix
+    let exp = lexpr::from_str(
+        r#"(let ((first (lambda ((t : (tuple int bool))) : int (tuple-ref t 0))))
+             (first (make-tuple 1 2 #t)))"#,
+    )
+    .unwrap();
+    let env = TypeEnv::new().with_tuple_width_subtyping(true);
+    let typed_exp = tc_with_env(&parse(&exp).unwrap(), &env);
+    assert_eq!(typed_exp.is_err(), true);
+}
+
+#[test]
+fn test_typecheck_unions_happy() {
+    // values are injected into unions at call sites
+    let exp = lexpr::from_str(
+        r#"(let ((describe (lambda ((val : (union int string))) : string
+                            (typecase val
+                              ((int n) "number")
+                              ((string s) s)))))
+             (describe 3))"#,
+    )
+    .unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Str);
+
+    // and when returned from functions with a union return type
+    let exp = lexpr::from_str(r#"(lambda ((b : bool)) : (union int bool) b)"#).unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(
+        typed_exp.typ,
+        Type::Func(
+            vector![Type::Bool],
+            Box::new(Type::Union(vector![Type::Int, Type::Bool])),
+            Effect::Pure
+        )
+    );
+
+    // values can also be explicitly injected
+    let exp = lexpr::from_str(
+        r#"(typecase (inject 3 (union bool int))
+             ((bool b) 0)
+             ((int n) (+ n 1)))"#,
+    )
+    .unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Int);
+}
+
+#[test]
+fn test_typecheck_unions_sad() {
+    // value is not one of the types in the union
+    let exp = lexpr::from_str(r#"(inject #t (union int string))"#).unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap());
+    assert_eq!(typed_exp.is_err(), true);
+
+    // typecase on a non-union value
+    let exp = lexpr::from_str(r#"(typecase 3 ((int n) n))"#).unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap());
+    assert_eq!(typed_exp.is_err(), true);
+
+    // typecase is missing a clause
+    let exp = lexpr::from_str(r#"(typecase (inject 3 (union int bool)) ((int n) n))"#).unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap());
+    assert_eq!(typed_exp.is_err(), true);
+
+    // typecase clause type is not in the union
+    let exp = lexpr::from_str(
+        r#"(typecase (inject 3 (union int bool)) ((int n) n) ((bool b) 0) ((string s) 1))"#,
+    )
+    .unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap());
+    assert_eq!(typed_exp.is_err(), true);
+
+    // typecase clauses have different types
+    let exp = lexpr::from_str(r#"(typecase (inject 3 (union int bool)) ((int n) n) ((bool b) b))"#)
+        .unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap());
+    assert_eq!(typed_exp.is_err(), true);
+}
+
+#[test]
+fn test_typecheck_unify_errors() {
+    let expected = parse_type(&lexpr::from_str("(tuple int bool)").unwrap()).unwrap();
+    let found = parse_type(&lexpr::from_str("(tuple int int)").unwrap()).unwrap();
+    assert_eq!(
+        unify(&expected, &found, false, &mut vec![]),
+        Err(UnifyError::Mismatch {
+            expected: expected.clone(),
+            found: found.clone()
+        })
+    );
+
+    let expected = parse_type(&lexpr::from_str("(record (a : int))").unwrap()).unwrap();
+    let found = parse_type(&lexpr::from_str("(record (b : int))").unwrap()).unwrap();
+    assert_eq!(
+        unify(&expected, &found, false, &mut vec![]),
+        Err(UnifyError::MissingField {
+            label: String::from("a"),
+            found: found.clone()
+        })
+    );
+
+    // occurs check
+    let expected = parse_type(&lexpr::from_str("(record ... T0)").unwrap()).unwrap();
+    let found = parse_type(&lexpr::from_str("(record (a : int) ... T0)").unwrap()).unwrap();
+    assert_eq!(
+        unify(&expected, &found, false, &mut vec![]),
+        Err(UnifyError::InfiniteType {
+            var: 0,
+            typ: found.clone()
+        })
+    );
+
+    let exp = lexpr::from_str(
+        r#"(lambda ((f : (-> (record ... T0) int)) (r : (record (a : int) ... T0))) : int
+             (f r))"#,
+    )
+    .unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap());
+    assert_eq!(typed_exp.is_err(), true);
+}
+
+#[test]
+fn test_typecheck_pure_functions() {
+    // pure callbacks can be passed where pure functions are required
+    let exp = lexpr::from_str(
+        r#"(let ((apply (lambda ((f : (pure-> int int)) (x : int)) : int (f x))))
+             (apply (lambda ((n : int)) : int (* n 2)) 3))"#,
+    )
+    .unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Int);
+
+    // pure functions can be passed where any function is allowed
+    let exp = lexpr::from_str(
+        r#"(let ((apply (lambda ((f : (-> int int)) (x : int)) : int (f x))))
+             (apply (lambda ((n : int)) : int (* n 2)) 3))"#,
+    )
+    .unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Int);
+
+    // mutating callbacks can't be passed where pure functions are required
+    let exp = lexpr::from_str(
+        r#"(let ((count 0))
+             (let ((apply (lambda ((f : (pure-> int int)) (x : int)) : int (f x))))
+               (apply (lambda ((n : int)) : int (set! count n)) 3)))"#,
+    )
+    .unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap());
+    assert_eq!(typed_exp.is_err(), true);
+
+    // functions with unknown effects can't be passed either
+    let exp = lexpr::from_str(
+        r#"(let ((apply (lambda ((f : (pure-> int int)) (x : int)) : int (f x))))
+             (lambda ((g : (-> int int))) : int (apply g 3)))"#,
+    )
+    .unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap());
+    assert_eq!(typed_exp.is_err(), true);
+
+    // effects are part of the types of list elements
+    let exp = lexpr::from_str(
+        r#"(let ((count 0))
+             (let ((call-first (lambda ((l : (list (pure-> int)))) : int ((car l)))))
+               (call-first (cons (lambda () : int (set! count 1)) (null (pure-> int))))))"#,
+    )
+    .unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap());
+    assert_eq!(typed_exp.is_err(), true);
+    let exp = lexpr::from_str("(cons (lambda () : int 1) (null (-> int)))").unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(
+        typed_exp.typ,
+        parse_type(&lexpr::from_str("(list (-> int))").unwrap()).unwrap()
+    );
+
+    // and of if expressions, which may mutate if either branch does
+    let exp = lexpr::from_str(
+        r#"(let ((count 0))
+             (lambda ((b : bool)) : (pure-> int)
+               (if b (lambda () : int 1) (lambda () : int (set! count 1)))))"#,
+    )
+    .unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap());
+    assert_eq!(typed_exp.is_err(), true);
+    let exp = lexpr::from_str(
+        r#"(let ((count 0))
+             (if #t (lambda () : int 1) (lambda () : int (set! count 1))))"#,
+    )
+    .unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(
+        typed_exp.typ,
+        parse_type(&lexpr::from_str("(-> int)").unwrap()).unwrap()
+    );
 }
 
 #[test]
@@ -355,6 +744,422 @@ fn test_typecheck_sideeffects_sad() {
     assert_eq!(typed_exp.is_err(), true);
 }
 
+#[test]
+fn test_typecheck_assert() {
+    let exp = lexpr::from_str(r#"(begin (assert (< 1 2) "one is less than two") 3)"#).unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Int);
+
+    // condition is not a bool
+    let exp = lexpr::from_str(r#"(assert (+ 1 2) "not a bool")"#).unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap());
+    assert_eq!(typed_exp.is_err(), true);
+
+    // message is not a string literal
+    let exp = lexpr::from_str("(assert true 3)").unwrap();
+    assert_eq!(parse(&exp).is_err(), true);
+}
+
+#[test]
+fn test_typecheck_while() {
+    let exp = lexpr::from_str("(let ((i 0)) (while (< i 10) (set! i (+ i 1))))").unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, unit_type());
+
+    // unit can be used in annotations
+    let exp = lexpr::from_str(
+        "(lambda ((f : (-> int unit))) : unit (let ((i 0)) (while (< i 3) (f i))))",
+    )
+    .unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(
+        typed_exp.typ,
+        Type::Func(
+            vector![Type::Func(
+                vector![Type::Int],
+                Box::new(unit_type()),
+                Effect::Mutates
+            )],
+            Box::new(unit_type()),
+            Effect::Mutates
+        )
+    );
+
+    // condition is not a bool
+    let exp = lexpr::from_str("(while 1 2)").unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap());
+    assert_eq!(typed_exp.is_err(), true);
+
+    // the loop's value is unit, not the body's value
+    let exp = lexpr::from_str("(+ 1 (while false 2))").unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap());
+    assert_eq!(typed_exp.is_err(), true);
+
+    let exp = lexpr::from_str("(while true)").unwrap();
+    assert_eq!(parse(&exp).is_err(), true);
+}
+
+#[test]
+fn test_typecheck_case() {
+    let exp =
+        lexpr::from_str("(case (+ 1 2) ((1 2) true) ((3 (+ 2 2)) false) (else true))").unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Bool);
+
+    // the key is not an int
+    let exp = lexpr::from_str("(case true ((1) 1) (else 2))").unwrap();
+    let err = type_check(&parse(&exp).unwrap()).unwrap_err();
+    assert_eq!(err.code(), "T001");
+
+    // a clause has a different type from the else clause
+    let exp = lexpr::from_str("(case 1 ((1) 1) ((2) false) (else 2))").unwrap();
+    let err = type_check(&parse(&exp).unwrap()).unwrap_err();
+    assert_eq!(err.code(), "T001");
+
+    // datums must be constant, and appear in only one clause
+    let exp = lexpr::from_str("(let ((x 1)) (case 1 ((x) 1) (else 2)))").unwrap();
+    assert_eq!(parse(&exp).unwrap_err().code(), "P005");
+    let exp = lexpr::from_str("(case 1 (((* 65536 65536)) 1) (else 2))").unwrap();
+    assert_eq!(parse(&exp).unwrap_err().code(), "P005");
+    let exp = lexpr::from_str("(case 1 ((1 2) 1) ((2) 2) (else 3))").unwrap();
+    assert_eq!(parse(&exp).unwrap_err().code(), "P001");
+
+    // clauses need datums, and the else clause is required and comes last
+    for source in &[
+        "(case 1 (() 1) (else 2))",
+        "(case 1 ((1) 1))",
+        "(case 1 (else 2) ((1) 1))",
+        "(case 1 ((1) 1 2) (else 3))",
+        "(case 1)",
+    ] {
+        let exp = lexpr::from_str(source).unwrap();
+        assert_eq!(parse(&exp).is_err(), true, "{}", source);
+    }
+}
+
+#[test]
+fn test_typecheck_tuple_set() {
+    let exp = lexpr::from_str("(let ((t (make-tuple 1 true))) (tuple-set! t 1 false))").unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Bool);
+
+    // the value must have the element's type
+    let exp = lexpr::from_str("(let ((t (make-tuple 1 true))) (tuple-set! t 1 3))").unwrap();
+    assert_eq!(type_check(&parse(&exp).unwrap()).is_err(), true);
+
+    // index out of bounds
+    let exp = lexpr::from_str("(let ((t (make-tuple 1 true))) (tuple-set! t 2 3))").unwrap();
+    assert_eq!(type_check(&parse(&exp).unwrap()).is_err(), true);
+
+    let exp = lexpr::from_str("(tuple-set! 3 0 3)").unwrap();
+    assert_eq!(type_check(&parse(&exp).unwrap()).is_err(), true);
+}
+
+#[test]
+fn test_typecheck_curry() {
+    let exp =
+        lexpr::from_str("(let ((f (lambda ((x : int) (b : bool)) : int (if b x 0)))) (curry f 3))")
+            .unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(
+        typed_exp.typ,
+        Type::Func(vector![Type::Bool], Box::new(Type::Int), Effect::Pure)
+    );
+
+    // all or none of the arguments can be given
+    let exp = lexpr::from_str(
+        "(let ((f (lambda ((x : int) (b : bool)) : int (if b x 0)))) (curry f 3 true))",
+    )
+    .unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(
+        typed_exp.typ,
+        Type::Func(vector![], Box::new(Type::Int), Effect::Pure)
+    );
+    let exp = lexpr::from_str(
+        "(let ((f (lambda ((x : int) (b : bool)) : int (if b x 0)))) ((curry f) 3 true))",
+    )
+    .unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Int);
+
+    // too many arguments
+    let exp = lexpr::from_str(
+        "(let ((f (lambda ((x : int) (b : bool)) : int (if b x 0)))) (curry f 3 true 4))",
+    )
+    .unwrap();
+    assert_eq!(type_check(&parse(&exp).unwrap()).is_err(), true);
+
+    // argument has the wrong type
+    let exp = lexpr::from_str(
+        "(let ((f (lambda ((x : int) (b : bool)) : int (if b x 0)))) (curry f true))",
+    )
+    .unwrap();
+    assert_eq!(type_check(&parse(&exp).unwrap()).is_err(), true);
+
+    // not a function
+    let exp = lexpr::from_str("(curry 3 4)").unwrap();
+    assert_eq!(type_check(&parse(&exp).unwrap()).is_err(), true);
+    let exp = lexpr::from_str("(curry)").unwrap();
+    assert_eq!(parse(&exp).is_err(), true);
+}
+
+#[test]
+fn test_typecheck_compose() {
+    let exp = lexpr::from_str(
+        r#"
+(compose (lambda ((x : int)) : bool (< x 10))
+         (lambda ((s : string) (n : int)) : int n))
+"#,
+    )
+    .unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(
+        typed_exp.typ,
+        Type::Func(
+            vector![Type::Str, Type::Int],
+            Box::new(Type::Bool),
+            Effect::Pure
+        )
+    );
+
+    // result of the inner function doesn't match the outer function
+    let exp = lexpr::from_str(
+        "(compose (lambda ((x : int)) : bool (< x 10)) (lambda ((b : bool)) : bool b))",
+    )
+    .unwrap();
+    assert_eq!(type_check(&parse(&exp).unwrap()).is_err(), true);
+
+    // outer function must take exactly one argument
+    let exp = lexpr::from_str(
+        "(compose (lambda ((x : int) (y : int)) : int x) (lambda ((b : bool)) : int 1))",
+    )
+    .unwrap();
+    assert_eq!(type_check(&parse(&exp).unwrap()).is_err(), true);
+
+    let exp = lexpr::from_str("(compose (lambda ((x : int)) : int x) 3)").unwrap();
+    assert_eq!(type_check(&parse(&exp).unwrap()).is_err(), true);
+}
+
+#[test]
+fn test_typecheck_promises() {
+    let exp = lexpr::from_str("(delay (+ 1 2))").unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Promise(Box::new(Type::Int)));
+
+    let exp = lexpr::from_str("(let ((p (delay (< 1 2)))) (force p))").unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Bool);
+
+    let exp =
+        lexpr::from_str("(lambda ((p : (promise (list int)))) : (list int) (cons 3 (force p)))")
+            .unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap());
+    assert_eq!(typed_exp.is_ok(), true);
+
+    // only promises can be forced
+    let exp = lexpr::from_str("(force 3)").unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap());
+    assert_eq!(typed_exp.is_err(), true);
+}
+
+#[test]
+fn test_typecheck_format() {
+    let exp = lexpr::from_str(r#"(format "x = ~d, y = ~s~%" 3 "four")"#).unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Str);
+
+    // ~a accepts any type, and ~~ doesn't take an argument
+    let exp = lexpr::from_str(r#"(format "~~~a ~a" true (make-tuple 1 2))"#).unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Str);
+
+    // directive doesn't match the type of its argument
+    let exp = lexpr::from_str(r#"(format "~d" "three")"#).unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap());
+    assert_eq!(typed_exp.is_err(), true);
+
+    // wrong number of arguments, or invalid directives
+    let exp = lexpr::from_str(r#"(format "~a and ~a" 1)"#).unwrap();
+    assert_eq!(parse(&exp).is_err(), true);
+    let exp = lexpr::from_str(r#"(format "~q" 1)"#).unwrap();
+    assert_eq!(parse(&exp).is_err(), true);
+    let exp = lexpr::from_str(r#"(format "oops~")"#).unwrap();
+    assert_eq!(parse(&exp).is_err(), true);
+}
+
+#[test]
+fn test_typecheck_division_by_zero() {
+    let exp = lexpr::from_str("(/ 6 (- 2 2))").unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Int);
+
+    let exp = lexpr::from_str("(let ((x 3)) (/ x 0))").unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap());
+    assert_eq!(
+        format!("{}", typed_exp.unwrap_err()),
+        "TypeCheckError: Division by zero in (/ x 0)."
+    );
+}
+
+#[test]
+fn test_typecheck_unsigned() {
+    let exp = lexpr::from_str("(+ (u/ 7 2) (u% -7 2))").unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Int);
+
+    let exp = lexpr::from_str("(and (u< 1 2) (u> -1 2))").unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Bool);
+
+    // there are no unsigned floats
+    let exp = lexpr::from_str("(u/ 1.0 2.0)").unwrap();
+    let err = type_check(&parse(&exp).unwrap()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "TypeCheckError: Argument of u/ must have type int, but 1.0 has type float."
+    );
+
+    let exp = lexpr::from_str("(let ((x 3)) (u% x 0))").unwrap();
+    let err = type_check(&parse(&exp).unwrap()).unwrap_err();
+    assert_eq!(err.code(), "T006");
+    let exp = lexpr::from_str("(let ((x 3)) (u/ x 0))").unwrap();
+    let err = type_check(&parse(&exp).unwrap()).unwrap_err();
+    assert_eq!(err.code(), "T006");
+
+    let exp = lexpr::from_str("(let ((u< 3)) u<)").unwrap();
+    assert_eq!(parse(&exp).is_err(), true);
+}
+
+#[test]
+fn test_typecheck_builtins() {
+    let exp = lexpr::from_str("(+ (random 6) 1)").unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Int);
+
+    let exp = lexpr::from_str("(random true)").unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap());
+    assert_eq!(typed_exp.is_err(), true);
+
+    let exp = lexpr::from_str("(random 1 2)").unwrap();
+    assert_eq!(parse(&exp).is_err(), true);
+
+    let exp =
+        lexpr::from_str("(let ((start (current-millis))) (- (current-millis) start))").unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Int);
+
+    let exp = lexpr::from_str("(current-millis 0)").unwrap();
+    assert_eq!(parse(&exp).is_err(), true);
+
+    let exp = lexpr::from_str("(* (random-float) 2.0)").unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Float);
+
+    let exp = lexpr::from_str(r#"(write-file "copy.txt" (read-file "original.txt"))"#).unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Bool);
+    // files can only be used by the interpreter
+    let env = TypeEnv::new().with_wasm_target(true);
+    let err = tc_with_env(&parse(&exp).unwrap(), &env).unwrap_err();
+    assert_eq!(err.code(), "T008");
+    assert_eq!(
+        err.to_string(),
+        "TypeCheckError: write-file is not supported by the wasm target, only by the interpreter."
+    );
+
+    let exp =
+        lexpr::from_str(r#"(if (null? (command-line)) "none" (car (command-line)))"#).unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Str);
+    let err = tc_with_env(&parse(&exp).unwrap(), &env).unwrap_err();
+    assert_eq!(err.code(), "T008");
+
+    let exp = lexpr::from_str(
+        r#"(typecase (getenv "HOME")
+                    ((string s) s)
+                    (((tuple) none) "unset"))"#,
+    )
+    .unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Str);
+    let err = tc_with_env(&parse(&exp).unwrap(), &env).unwrap_err();
+    assert_eq!(err.code(), "T008");
+
+    let exp = lexpr::from_str(r#"(string-match "a+" (string-replace "b" "abc" "a"))"#).unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(
+        typed_exp.typ,
+        parse_type(&lexpr::from_str("(option (list string))").unwrap()).unwrap()
+    );
+
+    let exp = lexpr::from_str(r#"(hex-decode (base64-encode (string->utf8 "a")))"#).unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(
+        typed_exp.typ,
+        parse_type(&lexpr::from_str("(option (list int))").unwrap()).unwrap()
+    );
+
+    let exp = lexpr::from_str(r#"(string-length (substring (string-ref "abc" 0) 0 1))"#).unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Int);
+    let exp = lexpr::from_str(r#"(string-ref "abc" "0")"#).unwrap();
+    assert_eq!(type_check(&parse(&exp).unwrap()).is_err(), true);
+
+    let exp = lexpr::from_str(r#"(lambda ((x : (option string))) : int 0)"#).unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(
+        typed_exp.typ,
+        parse_type(&lexpr::from_str("(pure-> (union string (tuple)) int)").unwrap()).unwrap()
+    );
+
+    let exp = lexpr::from_str(r#"(write-file "numbers.txt" 123)"#).unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap());
+    assert_eq!(typed_exp.is_err(), true);
+}
+
+#[test]
+fn test_typecheck_extern() {
+    let exp = lexpr::from_str(r#"(call-extern "math" "max" (pure-> int int int) 3 4)"#).unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Int);
+
+    // argument types are checked against the declared signature
+    let exp = lexpr::from_str(r#"(call-extern "math" "max" (pure-> int int int) 3 #t)"#).unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap());
+    assert_eq!(typed_exp.is_err(), true);
+
+    // wrong number of arguments
+    let exp = lexpr::from_str(r#"(call-extern "math" "max" (pure-> int int int) 3)"#).unwrap();
+    assert_eq!(parse(&exp).is_err(), true);
+
+    // only simple values can be passed to the host
+    let exp =
+        lexpr::from_str(r#"(call-extern "lists" "len" (-> (list int) int) (null int))"#).unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap());
+    assert_eq!(typed_exp.is_err(), true);
+}
+
+#[test]
+fn test_typecheck_wasm_asm() {
+    let exp = lexpr::from_str(r#"(wasm-asm "i32.add" : int 3 4)"#).unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Int);
+    let exp = lexpr::from_str(r#"(wasm-asm "i32.eqz" : bool 0)"#).unwrap();
+    let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
+    assert_eq!(typed_exp.typ, Type::Bool);
+
+    // only simple values can be passed to and returned from instructions
+    let exp = lexpr::from_str(r#"(wasm-asm "drop" : int (null int))"#).unwrap();
+    assert_eq!(type_check(&parse(&exp).unwrap()).is_err(), true);
+    let exp = lexpr::from_str(r#"(wasm-asm "nop" : (list int) 3)"#).unwrap();
+    assert_eq!(type_check(&parse(&exp).unwrap()).is_err(), true);
+
+    // the result type must be given
+    let exp = lexpr::from_str(r#"(wasm-asm "i32.add" 3 4)"#).unwrap();
+    assert_eq!(parse(&exp).is_err(), true);
+}
+
 #[test]
 fn test_typecheck_local_scoping() {
     // local variable overrides outer variable
@@ -419,20 +1224,27 @@ fn test_typecheck_if_sad() {
 fn test_typecheck_lambda_happy() {
     let exp = lexpr::from_str("(lambda () : int 3)").unwrap();
     let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
-    assert_eq!(typed_exp.typ, Type::Func(vector![], Box::new(Type::Int)));
+    assert_eq!(
+        typed_exp.typ,
+        Type::Func(vector![], Box::new(Type::Int), Effect::Pure)
+    );
 
     let exp = lexpr::from_str("(lambda ((x : int)) : bool (< x 5))").unwrap();
     let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
     assert_eq!(
         typed_exp.typ,
-        Type::Func(vector![Type::Int], Box::new(Type::Bool))
+        Type::Func(vector![Type::Int], Box::new(Type::Bool), Effect::Pure)
     );
 
     let exp = lexpr::from_str("(lambda ((x : int) (y : int)) : int (* x y))").unwrap();
     let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
     assert_eq!(
         typed_exp.typ,
-        Type::Func(vector![Type::Int, Type::Int], Box::new(Type::Int))
+        Type::Func(
+            vector![Type::Int, Type::Int],
+            Box::new(Type::Int),
+            Effect::Pure
+        )
     );
 
     let exp =
@@ -443,11 +1255,16 @@ fn test_typecheck_lambda_happy() {
         typed_exp.typ,
         Type::Func(
             vector![
-                Type::Func(vector![Type::Int, Type::Int], Box::new(Type::Bool)),
+                Type::Func(
+                    vector![Type::Int, Type::Int],
+                    Box::new(Type::Bool),
+                    Effect::Mutates
+                ),
                 Type::Int,
                 Type::Int
             ],
-            Box::new(Type::Bool)
+            Box::new(Type::Bool),
+            Effect::Mutates
         )
     );
 }
@@ -464,7 +1281,7 @@ fn test_typecheck_nested_lambdas() {
     )
     .unwrap();
     let typed_exp = type_check(&exp).unwrap();
-    let expected = parse_type(&lexpr::from_str("(-> (-> int int) (-> int))").unwrap()).unwrap();
+    let expected = parse_type(&lexpr::from_str("(pure-> (-> int int) (-> int))").unwrap()).unwrap();
     assert_eq!(typed_exp.typ, expected);
 
     let exp = parse(
@@ -478,9 +1295,10 @@ fn test_typecheck_nested_lambdas() {
         .unwrap(),
     )
     .unwrap();
-    let expected =
-        parse_type(&lexpr::from_str("(-> (-> int int int int) (-> int (-> int int)))").unwrap())
-            .unwrap();
+    let expected = parse_type(
+        &lexpr::from_str("(pure-> (-> int int int int) (-> int (-> int int)))").unwrap(),
+    )
+    .unwrap();
     let typed_exp = type_check(&exp).unwrap();
     assert_eq!(typed_exp.typ, expected);
 
@@ -513,7 +1331,7 @@ fn test_typecheck_lambdas_recursive_happy() {
     let typed_exp = type_check(&parse(&exp).unwrap()).unwrap();
     assert_eq!(
         typed_exp.typ,
-        Type::Func(vector![Type::Int], Box::new(Type::Int))
+        Type::Func(vector![Type::Int], Box::new(Type::Int), Effect::Pure)
     );
 }
 
@@ -575,10 +1393,11 @@ fn test_typecheck_apply_hof_happy() {
     .unwrap();
     let map_type = Type::Func(
         vector![
-            Type::Func(vector![Type::Int], Box::new(Type::Int)),
+            Type::Func(vector![Type::Int], Box::new(Type::Int), Effect::Mutates),
             Type::List(Box::new(Type::Int)),
         ],
         Box::new(Type::List(Box::new(Type::Int))),
+        Effect::Mutates,
     ); // (-> (-> int int) (list int) (list int))
     let mut env = TypeEnv::new();
     env = env.add_binding((String::from("map"), map_type.clone()));
@@ -762,3 +1581,73 @@ fn test_typecheck_adt() {
     .unwrap();
     assert_eq!(typed_exp.typ, typ);
 }
+
+#[test]
+fn test_typecheck_mismatch_errors() {
+    // errors for expressions of the wrong type show both types and the
+    // offending expression
+    let cases = vec![
+        (
+            "(if (+ 1 2) 3 4)",
+            "Predicate of if expression must have type bool, but (+ 1 2) has type int.",
+        ),
+        (
+            "(if true 1 false)",
+            "Alternate of if expression (like its consequent) must have type int, but false has type bool.",
+        ),
+        (
+            "(+ 1 (< 1 2))",
+            "Argument of + must have type int, but (< 1 2) has type bool.",
+        ),
+        (
+            "(cons true (cons 1 (null int)))",
+            "Car of cons (the element type of its cdr) must have type int, but true has type bool.",
+        ),
+        (
+            "(let ((f (lambda ((x : int)) : int x))) (f (concat \"a\" \"b\")))",
+            "Argument 0 of function application must have type int, but (concat \"a\" \"b\") has type string.",
+        ),
+        (
+            "(lambda ((x : int)) : bool (* x 2))",
+            "Body of lambda expression (its return type) must have type bool, but (* x 2) has type int.",
+        ),
+    ];
+    for (source, message) in cases {
+        let exp = parse(&lexpr::from_str(source).unwrap()).unwrap();
+        assert_eq!(
+            type_check(&exp).unwrap_err().to_string(),
+            format!("TypeCheckError: {}", message),
+            "{}",
+            source
+        );
+    }
+
+    let exp = parse(&lexpr::from_str("(while 1 2)").unwrap()).unwrap();
+    let err = type_check(&exp).unwrap_err();
+    assert_eq!(
+        err.mismatch_info(),
+        Some(&TypeMismatch {
+            expected: Type::Bool,
+            found: Type::Int,
+            exp: String::from("1"),
+        })
+    );
+
+    // record arguments explain which field doesn't match
+    let exp = parse(
+        &lexpr::from_str(
+            "(let ((f (lambda ((r : (record (x : int)))) : int (record-ref r x)))) (f (make-record (x true))))",
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let err = type_check(&exp).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "TypeCheckError: Argument 0 of function application must have type (record (x : int)), but (make-record (x true)) has type (record (x : bool)). Field x of record argument does not have the expected type int, found bool."
+    );
+
+    // other errors have no types to show
+    let exp = parse(&lexpr::from_str("(tuple-ref 3 0)").unwrap()).unwrap();
+    assert_eq!(type_check(&exp).unwrap_err().mismatch_info(), None);
+}
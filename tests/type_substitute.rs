@@ -1,6 +1,6 @@
 use im_rc::vector;
 use scheme_to_wasm::parse::parse_type;
-use scheme_to_wasm::types::{type_var_substitute, Type};
+use scheme_to_wasm::types::{type_var_substitute, Effect, Type};
 
 #[test]
 fn test_type_var_substitute_idempotent() {
@@ -23,12 +23,20 @@ fn test_type_var_substitute_idempotent() {
     );
 
     // no substitution (function)
-    let typ = Type::Func(vector![Type::Str, Type::Bool], Box::new(Type::Str));
+    let typ = Type::Func(
+        vector![Type::Str, Type::Bool],
+        Box::new(Type::Str),
+        Effect::Mutates,
+    );
     let type_var = 0;
     let replace_with = Type::Bool;
     assert_eq!(
         type_var_substitute(&typ, type_var, &replace_with),
-        Type::Func(vector![Type::Str, Type::Bool], Box::new(Type::Str))
+        Type::Func(
+            vector![Type::Str, Type::Bool],
+            Box::new(Type::Str),
+            Effect::Mutates
+        )
     );
 
     // no substitution (tuple)
@@ -92,12 +100,17 @@ fn test_type_var_substitute_happy() {
     let typ = Type::Func(
         vector![Type::TypeVar(3), Type::Bool],
         Box::new(Type::TypeVar(3)),
+        Effect::Mutates,
     );
     let type_var = 3;
     let replace_with = Type::Int;
     assert_eq!(
         type_var_substitute(&typ, type_var, &replace_with),
-        Type::Func(vector![Type::Int, Type::Bool], Box::new(Type::Int))
+        Type::Func(
+            vector![Type::Int, Type::Bool],
+            Box::new(Type::Int),
+            Effect::Mutates
+        )
     );
 
     // substitution (tuple)